@@ -0,0 +1,692 @@
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+use hypernote_core::contracts::NoteMeta;
+use hypernote_core::export::{export_workspace, ExportOptions};
+use hypernote_core::import::bundle::import_bundle;
+use hypernote_core::import::{import_drafts, scan_markdown};
+use hypernote_core::lock::DataDirLock;
+use hypernote_core::store::default_data_dir;
+use hypernote_core::NoteStore;
+
+const USAGE: &str = "usage: hypernote-cli [--data-dir <dir>] <command> [args]
+
+commands:
+  list [--trash] [--json]           note ids and titles, most recently updated first
+  show <note>                       print a note's text
+  new [--title <title>] [text]      create a note; text is read from stdin when piped
+  append <note> [text]              append text (from stdin when omitted)
+  import <path>... [--json]         create one note per Markdown file, walking directories;
+                                    front matter sets title, tags and dates, and notes
+                                    that already exist are skipped
+  export <note> [--out <file>]      write a note's text to stdout or a file
+  export-workspace <file.zip> [--trash] [--json]
+                                    write every note as Markdown and .yjs into a zip with
+                                    a checksum manifest (trashed notes too with --trash)
+  import-workspace <file.zip> [--json]
+                                    restore an export-workspace zip, merging each note into
+                                    the note of the same id; conflicts are listed
+  trash <note>                      move a note to the trash
+  compact [<note>...] [--force]     re-encode notes (all when none given) and report their
+                                    sizes before and after; --force also drops deleted
+                                    content, which peers that have not caught up can then
+                                    no longer get from this device
+  versions <note> [--json]          saved versions of a note, newest first
+  diff <note> [from] [to] [--json]  unified diff between versions (a number or current);
+                                    defaults to the newest version against the current text
+
+<note> is a note id or any unique prefix of one. Without --data-dir the desktop app's
+data directory is used. Commands that write refuse to run while the app or hypernoted has
+the directory open; the others then read the notes as last saved.";
+
+enum Command {
+    List {
+        trash: bool,
+        json: bool,
+    },
+    Show {
+        note: String,
+    },
+    New {
+        title: Option<String>,
+        text: Option<String>,
+    },
+    Append {
+        note: String,
+        text: Option<String>,
+    },
+    Import {
+        paths: Vec<PathBuf>,
+        json: bool,
+    },
+    Export {
+        note: String,
+        out: Option<PathBuf>,
+    },
+    ExportWorkspace {
+        dest: PathBuf,
+        trash: bool,
+        json: bool,
+    },
+    ImportWorkspace {
+        bundle: PathBuf,
+        json: bool,
+    },
+    Trash {
+        note: String,
+    },
+    Compact {
+        notes: Vec<String>,
+        force: bool,
+    },
+    Versions {
+        note: String,
+        json: bool,
+    },
+    Diff {
+        note: String,
+        from: Option<String>,
+        to: Option<String>,
+        json: bool,
+    },
+}
+
+impl Command {
+    /// Whether the command changes the data directory.
+    fn writes(&self) -> bool {
+        matches!(
+            self,
+            Command::New { .. }
+                | Command::Append { .. }
+                | Command::Import { .. }
+                | Command::ImportWorkspace { .. }
+                | Command::Trash { .. }
+                | Command::Compact { .. }
+        )
+    }
+}
+
+struct Options {
+    data_dir: PathBuf,
+    command: Command,
+}
+
+fn main() {
+    let Options { data_dir, command } = match parse_options(std::env::args().skip(1)) {
+        Ok(value) => value,
+        Err(reason) => {
+            eprintln!("{reason}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    // Writers need the directory to themselves; readers make do with a read-only copy
+    // when the app or hypernoted holds it.
+    let lock = DataDirLock::acquire(&data_dir);
+    let opened = match &lock {
+        Ok(_) => NoteStore::open(&data_dir),
+        Err(reason) if command.writes() => {
+            eprintln!("hypernote-cli: {reason}; quit it first");
+            std::process::exit(1);
+        }
+        Err(_) => NoteStore::mirror(&data_dir),
+    };
+    let store = match opened {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("hypernote-cli: cannot open {}: {e}", data_dir.display());
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(reason) = run(&store, command, &mut std::io::stdout().lock()) {
+        eprintln!("hypernote-cli: {reason}");
+        std::process::exit(1);
+    }
+}
+
+fn run(store: &NoteStore, command: Command, out: &mut dyn Write) -> Result<(), String> {
+    match command {
+        Command::List { trash, json } => {
+            let notes = if trash {
+                store.list_trash()
+            } else {
+                store.list()
+            };
+            print_notes(out, &notes, json);
+        }
+        Command::Show { note } => {
+            let note_id = resolve_note_id(store, &note)?;
+            let note = store.get(&note_id).ok_or("note not found")?;
+            write_out(out, &note.markdown);
+        }
+        Command::New { title, text } => {
+            let text = match text {
+                Some(text) => text,
+                None if !std::io::stdin().is_terminal() => read_stdin()?,
+                None => String::new(),
+            };
+            let meta = create_note(store, title, &text)?;
+            write_out(out, &format!("{}\n", meta.id));
+        }
+        Command::Append { note, text } => {
+            let note_id = resolve_note_id(store, &note)?;
+            let text = match text {
+                Some(text) => text,
+                None => read_stdin()?,
+            };
+            store.append_text(&note_id, &text)?;
+        }
+        Command::Import { paths, json } => {
+            let (drafts, failures) = scan_markdown(&paths);
+            let report = import_drafts(store, drafts, failures, false, |draft| {
+                store.create_from(draft).map(|(meta, _)| meta)
+            });
+            if json {
+                let json = serde_json::to_string_pretty(&report).unwrap_or_default();
+                write_out(out, &format!("{json}\n"));
+            } else {
+                let mut lines = String::new();
+                for note in &report.imported {
+                    let note_id = note.note_id.as_deref().unwrap_or_default();
+                    lines.push_str(&format!("{note_id}\t{}\n", note.path));
+                }
+                for duplicate in &report.duplicates {
+                    let note_id = duplicate.note_id.as_deref().unwrap_or_default();
+                    lines.push_str(&format!("duplicate\t{note_id}\t{}\n", duplicate.path));
+                }
+                write_out(out, &lines);
+            }
+            for failure in &report.failures {
+                eprintln!("hypernote-cli: {}: {}", failure.path, failure.reason);
+            }
+            if !report.failures.is_empty() {
+                return Err(format!("{} file(s) not imported", report.failures.len()));
+            }
+        }
+        Command::Export { note, out: file } => {
+            let note_id = resolve_note_id(store, &note)?;
+            let note = store.get(&note_id).ok_or("note not found")?;
+            match file {
+                Some(path) => std::fs::write(&path, note.markdown)
+                    .map_err(|e| format!("cannot write {}: {e}", path.display()))?,
+                None => write_out(out, &note.markdown),
+            }
+        }
+        Command::ExportWorkspace { dest, trash, json } => {
+            let options = ExportOptions {
+                include_trash: trash,
+                ..Default::default()
+            };
+            let manifest = export_workspace(store, &dest, &options)?;
+            if json {
+                let json = serde_json::to_string_pretty(&manifest).unwrap_or_default();
+                write_out(out, &format!("{json}\n"));
+            } else {
+                write_out(
+                    out,
+                    &format!("{}\t{}\n", manifest.note_count, dest.display()),
+                );
+            }
+        }
+        Command::ImportWorkspace { bundle, json } => {
+            let report = import_bundle(store, &bundle, |meta, state| {
+                store.merge_backup(meta, state)
+            })?;
+            if json {
+                let json = serde_json::to_string_pretty(&report).unwrap_or_default();
+                write_out(out, &format!("{json}\n"));
+            } else {
+                let mut lines = format!(
+                    "{} created, {} merged, {} unchanged\n",
+                    report.created.len(),
+                    report.merged.len(),
+                    report.unchanged.len()
+                );
+                for conflict in &report.conflicts {
+                    lines.push_str(&format!(
+                        "conflict\t{}\t{}\n",
+                        conflict.note_id, conflict.reason
+                    ));
+                }
+                write_out(out, &lines);
+            }
+            for failure in &report.failures {
+                eprintln!("hypernote-cli: {}: {}", failure.path, failure.reason);
+            }
+            if !report.failures.is_empty() {
+                return Err(format!("{} file(s) not restored", report.failures.len()));
+            }
+        }
+        Command::Trash { note } => {
+            let note_id = resolve_note_id(store, &note)?;
+            store.move_to_trash(&note_id)?;
+        }
+        Command::Compact { notes, force } => {
+            let note_ids = match notes.is_empty() {
+                true => store.all_note_ids(),
+                false => notes
+                    .iter()
+                    .map(|note| resolve_note_id(store, note))
+                    .collect::<Result<_, _>>()?,
+            };
+            let mut lines = String::new();
+            for note_id in note_ids {
+                // The peers' acks are the app's or hypernoted's to judge, so only on request.
+                let report = store.compact(&note_id, force)?;
+                lines.push_str(&format!(
+                    "{}\t{}\t{}\n",
+                    report.note_id, report.before_bytes, report.after_bytes
+                ));
+            }
+            write_out(out, &lines);
+        }
+        Command::Versions { note, json } => {
+            let note_id = resolve_note_id(store, &note)?;
+            let versions = store.versions(&note_id);
+            if json {
+                let json = serde_json::to_string_pretty(&versions).unwrap_or_default();
+                write_out(out, &format!("{json}\n"));
+            } else {
+                let lines: String = versions
+                    .iter()
+                    .map(|v| format!("{}\t{}\t{}\n", v.version, v.saved_at, v.title))
+                    .collect();
+                write_out(out, &lines);
+            }
+        }
+        Command::Diff {
+            note,
+            from,
+            to,
+            json,
+        } => {
+            let note_id = resolve_note_id(store, &note)?;
+            let from = match from {
+                Some(from) => parse_version(&from)?,
+                None => Some(
+                    store
+                        .versions(&note_id)
+                        .first()
+                        .ok_or("note has no saved versions")?
+                        .version,
+                ),
+            };
+            let to = to.as_deref().map(parse_version).transpose()?.flatten();
+            let diff = store.diff(&note_id, from, to)?;
+            if json {
+                let json = serde_json::to_string_pretty(&diff).unwrap_or_default();
+                write_out(out, &format!("{json}\n"));
+            } else {
+                write_out(out, &diff.unified);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn create_note(store: &NoteStore, title: Option<String>, text: &str) -> Result<NoteMeta, String> {
+    let mut meta = store.create()?;
+    if !text.is_empty() {
+        meta = store.append_text(&meta.id, text)?;
+    }
+    match title {
+        Some(title) => store.rename(&meta.id, &title),
+        None => Ok(meta),
+    }
+}
+
+/// `3` or `v3` for a saved version, `current` for the note as it is now.
+fn parse_version(raw: &str) -> Result<Option<u32>, String> {
+    if raw == "current" {
+        return Ok(None);
+    }
+    raw.strip_prefix('v')
+        .unwrap_or(raw)
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("not a version: {raw}"))
+}
+
+/// Accepts a full note id or a prefix that matches exactly one note.
+fn resolve_note_id(store: &NoteStore, raw: &str) -> Result<String, String> {
+    let ids = store.note_ids();
+    if ids.iter().any(|id| id == raw) {
+        return Ok(raw.to_string());
+    }
+
+    let mut matches = ids.into_iter().filter(|id| id.starts_with(raw));
+    match (matches.next(), matches.next()) {
+        (Some(id), None) => Ok(id),
+        (Some(_), Some(_)) => Err(format!("note id prefix is ambiguous: {raw}")),
+        _ => Err(format!("note not found: {raw}")),
+    }
+}
+
+fn print_notes(out: &mut dyn Write, notes: &[NoteMeta], json: bool) {
+    if json {
+        let json = serde_json::to_string_pretty(notes).unwrap_or_default();
+        write_out(out, &format!("{json}\n"));
+        return;
+    }
+
+    let lines: String = notes
+        .iter()
+        .map(|note| format!("{}\t{}\n", note.id, note.title))
+        .collect();
+    write_out(out, &lines);
+}
+
+/// Like `print!`, but a closed pipe (`hypernote-cli list | head`) is not a panic.
+fn write_out(out: &mut dyn Write, text: &str) {
+    let _ = out.write_all(text.as_bytes()).and_then(|_| out.flush());
+}
+
+fn read_stdin() -> Result<String, String> {
+    let mut text = String::new();
+    std::io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|e| format!("cannot read stdin: {e}"))?;
+    Ok(text)
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut data_dir = None;
+    let mut force = false;
+    let mut positional = Vec::new();
+    let mut trash = false;
+    let mut json = false;
+    let mut title = None;
+    let mut out = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--data-dir" => {
+                let dir = args
+                    .next()
+                    .ok_or_else(|| "--data-dir needs a value".to_string())?;
+                data_dir = Some(PathBuf::from(dir));
+            }
+            "--title" => {
+                title = Some(
+                    args.next()
+                        .ok_or_else(|| "--title needs a value".to_string())?,
+                );
+            }
+            "--out" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| "--out needs a value".to_string())?;
+                out = Some(PathBuf::from(path));
+            }
+            "--trash" => trash = true,
+            "--force" => force = true,
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("unknown argument: {other}"));
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let name = positional
+        .next()
+        .ok_or_else(|| "missing command".to_string())?;
+    let mut rest: Vec<String> = positional.collect();
+    let note = |rest: &mut Vec<String>| -> Result<String, String> {
+        if rest.is_empty() {
+            return Err(format!("{name} needs a note id"));
+        }
+        Ok(rest.remove(0))
+    };
+
+    let command = match name.as_str() {
+        "list" => Command::List { trash, json },
+        "show" => Command::Show {
+            note: note(&mut rest)?,
+        },
+        "new" => Command::New {
+            title,
+            text: (!rest.is_empty()).then(|| rest.join(" ")),
+        },
+        "append" => Command::Append {
+            note: note(&mut rest)?,
+            text: (!rest.is_empty()).then(|| rest.join(" ")),
+        },
+        "import" if rest.is_empty() => return Err("import needs at least one path".to_string()),
+        "import" => Command::Import {
+            paths: rest.into_iter().map(PathBuf::from).collect(),
+            json,
+        },
+        "export" => Command::Export {
+            note: note(&mut rest)?,
+            out,
+        },
+        "export-workspace" => Command::ExportWorkspace {
+            dest: PathBuf::from(
+                rest.first()
+                    .ok_or_else(|| "export-workspace needs a file".to_string())?,
+            ),
+            trash,
+            json,
+        },
+        "import-workspace" => Command::ImportWorkspace {
+            bundle: PathBuf::from(
+                rest.first()
+                    .ok_or_else(|| "import-workspace needs a file".to_string())?,
+            ),
+            json,
+        },
+        "trash" => Command::Trash {
+            note: note(&mut rest)?,
+        },
+        "compact" => Command::Compact { notes: rest, force },
+        "versions" => Command::Versions {
+            note: note(&mut rest)?,
+            json,
+        },
+        "diff" => {
+            let note = note(&mut rest)?;
+            let mut versions = rest.into_iter();
+            Command::Diff {
+                note,
+                from: versions.next(),
+                to: versions.next(),
+                json,
+            }
+        }
+        other => return Err(format!("unknown command: {other}")),
+    };
+
+    let data_dir = data_dir
+        .or_else(default_data_dir)
+        .ok_or_else(|| "no default data directory; pass --data-dir".to_string())?;
+
+    Ok(Options { data_dir, command })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run_to_string(store: &NoteStore, command: Command) -> Result<String, String> {
+        let mut out = Vec::new();
+        run(store, command, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("hn-cli-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn parses_commands_and_flags_in_any_order() {
+        let options = parse(&["--json", "diff", "61874811", "2", "--data-dir", "/d"]).unwrap();
+        assert_eq!(options.data_dir, PathBuf::from("/d"));
+        assert!(matches!(
+            options.command,
+            Command::Diff { note, from: Some(from), to: None, json: true }
+                if note == "61874811" && from == "2"
+        ));
+
+        let options = parse(&["export", "abc", "--out", "a.md"]).unwrap();
+        assert!(matches!(
+            options.command,
+            Command::Export { note, out: Some(out) } if note == "abc" && out == Path::new("a.md")
+        ));
+
+        let options = parse(&["compact"]).unwrap();
+        assert!(matches!(
+            &options.command,
+            Command::Compact { notes, force: false } if notes.is_empty()
+        ));
+        let options = parse(&["compact", "abc", "--force"]).unwrap();
+        assert!(matches!(
+            &options.command,
+            Command::Compact { notes, force: true } if notes == &["abc"]
+        ));
+        assert!(options.command.writes());
+        assert!(!parse(&["list"]).unwrap().command.writes());
+    }
+
+    #[test]
+    fn rejects_incomplete_commands() {
+        assert_eq!(parse(&[]).err().unwrap(), "missing command");
+        assert!(parse(&["show"]).is_err());
+        assert!(parse(&["import"]).is_err());
+        assert!(parse(&["export-workspace"]).is_err());
+        assert!(parse(&["list", "--verbose"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+    }
+
+    #[test]
+    fn exports_a_note_to_stdout_or_a_file() {
+        let dir = temp_dir();
+        let store = NoteStore::open(dir.join("data")).unwrap();
+        let note = create_note(&store, None, "Standup").unwrap();
+
+        let prefix = note.id[..8].to_string();
+        let export = Command::Export {
+            note: prefix.clone(),
+            out: None,
+        };
+        assert_eq!(run_to_string(&store, export).unwrap(), "Standup");
+
+        let file = dir.join("standup.md");
+        let export = Command::Export {
+            note: prefix,
+            out: Some(file.clone()),
+        };
+        assert_eq!(run_to_string(&store, export).unwrap(), "");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "Standup");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn imports_through_the_markdown_importer() {
+        let dir = temp_dir();
+        let store = NoteStore::open(dir.join("data")).unwrap();
+        let files = dir.join("notes");
+        std::fs::create_dir_all(files.join("work")).unwrap();
+        std::fs::write(
+            files.join("work/standup-2024.md"),
+            "---\ntags: [meetings]\n---\n# Standup\n\nShipped the relay.\n",
+        )
+        .unwrap();
+        std::fs::write(files.join("scratch.md"), "just a thought\n").unwrap();
+
+        let import = Command::Import {
+            paths: vec![files.clone()],
+            json: false,
+        };
+        let lines = run_to_string(&store, import).unwrap();
+        assert_eq!(lines.lines().count(), 2, "{lines}");
+
+        let mut notes = store.list();
+        notes.sort_by(|a, b| a.title.cmp(&b.title));
+        let titles: Vec<&str> = notes.iter().map(|note| note.title.as_str()).collect();
+        // Titles come from the heading or the text, not the file name, and stay derived.
+        assert_eq!(titles, ["Standup", "just a thought"]);
+        assert!(notes.iter().all(|note| !note.title_override));
+        assert_eq!(notes[0].tags, ["meetings"]);
+        assert_eq!(notes[0].folder.as_deref(), Some("work"));
+
+        let again = Command::Import {
+            paths: vec![files],
+            json: false,
+        };
+        let lines = run_to_string(&store, again).unwrap();
+        assert!(
+            lines.lines().all(|line| line.starts_with("duplicate\t")),
+            "{lines}"
+        );
+        assert_eq!(store.list().len(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn diffs_the_newest_version_against_the_current_text() {
+        let dir = temp_dir();
+        let store = NoteStore::open(&dir).unwrap();
+        let note = create_note(&store, None, "first line").unwrap();
+        store.append_text(&note.id, "\nsecond line").unwrap();
+        assert!(!store.versions(&note.id).is_empty());
+
+        let diff = Command::Diff {
+            note: note.id.clone(),
+            from: None,
+            to: None,
+            json: false,
+        };
+        let unified = run_to_string(&store, diff).unwrap();
+        assert!(unified.contains("+second line"), "{unified}");
+
+        let diff = Command::Diff {
+            note: note.id,
+            from: Some("v99".to_string()),
+            to: None,
+            json: false,
+        };
+        assert!(run_to_string(&store, diff).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compacts_notes_and_reports_their_sizes() {
+        let dir = temp_dir();
+        let store = NoteStore::open(&dir).unwrap();
+        let note = create_note(&store, Some("Draft".to_string()), "text").unwrap();
+        for title in ["Second", "Third", "Final"] {
+            store.rename(&note.id, title).unwrap();
+        }
+
+        let compact = Command::Compact {
+            notes: vec![note.id.clone()],
+            force: true,
+        };
+        let report = run_to_string(&store, compact).unwrap();
+        let fields: Vec<&str> = report.trim_end().split('\t').collect();
+        assert_eq!(fields[0], note.id);
+        let before: u64 = fields[1].parse().unwrap();
+        let after: u64 = fields[2].parse().unwrap();
+        assert!(after <= before);
+        assert_eq!(store.get(&note.id).unwrap().markdown, "text");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
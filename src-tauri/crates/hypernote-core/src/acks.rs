@@ -0,0 +1,180 @@
+//! Which peers have caught up with which notes, for deciding when compaction may collect
+//! deleted content.
+//!
+//! A peer is known by the sender id it uses together with where its connection comes from
+//! (see `Node::record_ack`), so no peer can ack for one that connects from elsewhere. Each
+//! `state_vector` it sends is kept with the time it arrived, and peers not heard from for
+//! [`PEER_TTL_MS`] are forgotten. Both live in `<data-dir>/peer-acks.json`, so a peer that
+//! has been offline for a while still holds collection back after a restart.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use yrs::updates::decoder::Decode;
+use yrs::StateVector;
+
+use crate::store::write_atomic;
+
+pub const ACKS_FILE: &str = "peer-acks.json";
+/// How long a peer that sends nothing stays known: 30 days.
+pub const PEER_TTL_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+/// A peer's last contact is saved again only once it is this much newer: an hour.
+const SEEN_SAVE_MS: i64 = 60 * 60 * 1000;
+
+/// A peer's state vector for a note, encoded, and when it arrived.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Ack {
+    vector: Vec<u8>,
+    at: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Peer {
+    /// When the peer last sent a frame.
+    #[serde(default)]
+    seen: i64,
+    /// note id → its last state vector.
+    #[serde(default)]
+    notes: HashMap<String, Ack>,
+}
+
+#[derive(Debug, Default)]
+pub struct PeerAcks {
+    /// Where they are saved; `None` keeps them in memory only.
+    path: Option<PathBuf>,
+    peers: HashMap<String, Peer>,
+}
+
+impl PeerAcks {
+    /// The acks saved in `dir`, or none when there are none yet or they cannot be read.
+    pub fn load(dir: Option<&Path>) -> Self {
+        let path = dir.map(|dir| dir.join(ACKS_FILE));
+        let peers = match path.as_ref().map(fs::read) {
+            Some(Ok(bytes)) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                eprintln!("[hypernote] ignoring unreadable {ACKS_FILE}: {e}");
+                HashMap::new()
+            }),
+            _ => HashMap::new(),
+        };
+        Self { path, peers }
+    }
+
+    /// Notes `peer` as seen at `at` and, with a `vector` for `note_id`, as caught up to it,
+    /// saving the change. Peers not seen for [`PEER_TTL_MS`] before `at` are forgotten.
+    pub fn record(&mut self, peer: &str, ack: Option<(&str, &[u8])>, at: i64) {
+        let known = self.peers.len();
+        self.peers.retain(|_, peer| peer.seen > at - PEER_TTL_MS);
+        let mut changed = self.peers.len() != known;
+
+        let entry = self.peers.entry(peer.to_string()).or_default();
+        changed |= at - entry.seen >= SEEN_SAVE_MS;
+        entry.seen = entry.seen.max(at);
+        if let Some((note_id, vector)) = ack {
+            let ack = Ack {
+                vector: vector.to_vec(),
+                at,
+            };
+            entry.notes.insert(note_id.to_string(), ack);
+            changed = true;
+        }
+        if changed {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let saved = serde_json::to_vec(&self.peers)
+            .map_err(|e| e.to_string())
+            .and_then(|json| write_atomic(path, &json).map_err(|e| e.to_string()));
+        if let Err(reason) = saved {
+            eprintln!("[hypernote] cannot save {ACKS_FILE}: {reason}");
+        }
+    }
+
+    /// Whether a peer seen within [`PEER_TTL_MS`] before `now` is known and every such peer
+    /// has reported, after `changed_at`, a state vector covering `current`.
+    pub fn all_caught_up(
+        &self,
+        note_id: &str,
+        current: &StateVector,
+        changed_at: i64,
+        now: i64,
+    ) -> bool {
+        let mut peers = self
+            .peers
+            .values()
+            .filter(|peer| peer.seen > now - PEER_TTL_MS)
+            .peekable();
+        peers.peek().is_some()
+            && peers.all(|peer| {
+                peer.notes.get(note_id).is_some_and(|ack| {
+                    let Ok(theirs) = StateVector::decode_v1(&ack.vector) else {
+                        return false;
+                    };
+                    ack.at > changed_at
+                        && current
+                            .iter()
+                            .all(|(client, clock)| theirs.get(client) >= *clock)
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, ReadTxn, Text, Transact};
+
+    #[test]
+    fn needs_a_known_peer_and_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("hn-acks-{}", uuid::Uuid::new_v4()));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("content");
+        text.insert(&mut doc.transact_mut(), 0, "draft");
+        let current = doc.transact().state_vector();
+
+        let mut acks = PeerAcks::load(Some(&dir));
+        assert!(!acks.all_caught_up("note-1", &current, 10, 40));
+
+        acks.record("tablet", None, 5);
+        let vector = current.encode_v1();
+        acks.record("laptop", Some(("note-1", &vector)), 20);
+        // The tablet is known but has not caught up.
+        assert!(!acks.all_caught_up("note-1", &current, 10, 40));
+
+        let mut acks = PeerAcks::load(Some(&dir));
+        assert!(!acks.all_caught_up("note-1", &current, 10, 40));
+        acks.record("tablet", Some(("note-1", &vector)), 30);
+        assert!(acks.all_caught_up("note-1", &current, 10, 40));
+        assert!(!acks.all_caught_up("note-1", &current, 20, 40));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn forgets_peers_not_seen_for_a_while() {
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("content");
+        text.insert(&mut doc.transact_mut(), 0, "draft");
+        let current = doc.transact().state_vector();
+        let vector = current.encode_v1();
+
+        let mut acks = PeerAcks::load(None);
+        // Seen once and never again.
+        acks.record("one-off", None, 0);
+        acks.record("laptop", Some(("note-1", &vector)), 20);
+        assert!(!acks.all_caught_up("note-1", &current, 10, 40));
+        assert!(acks.all_caught_up("note-1", &current, 10, PEER_TTL_MS + 1));
+
+        acks.record("laptop", Some(("note-1", &vector)), PEER_TTL_MS + 30);
+        assert!(!acks.peers.contains_key("one-off"));
+        // With every peer gone, nobody vouches for the note.
+        assert!(!acks.all_caught_up("note-1", &current, 10, 3 * PEER_TTL_MS));
+    }
+}
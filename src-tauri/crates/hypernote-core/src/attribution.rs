@@ -0,0 +1,254 @@
+//! Who wrote which part of a note.
+//!
+//! Every Yjs client that edits a note's text claims itself in the note's `authors` map:
+//! the key is the client id in decimal, the value `{ device, edits }` where `device` is the
+//! stable device id the author presents as its peer id in the handshake, and `edits` is a
+//! list of `[clock, at]` checkpoints (client clock, ms timestamp), added at most once per
+//! [`CHECKPOINT_INTERVAL_MS`]. The map syncs with the note, so attribution survives relays
+//! and full-state catch-ups. Text whose client never claimed itself has no author.
+
+use std::collections::HashMap;
+
+use yrs::updates::decoder::Decode;
+use yrs::{Any, Doc, Map, Out, ReadTxn, Snapshot, Text, Transact, TransactionMut, Update};
+
+use crate::contracts::AttributedRange;
+use crate::store::CONTENT_TEXT;
+
+/// Name of the Yjs map from client id to author.
+pub const AUTHORS_MAP: &str = "authors";
+/// Minimum time between two checkpoints of one client; the frontend uses the same.
+pub const CHECKPOINT_INTERVAL_MS: i64 = 60_000;
+
+struct Author {
+    device: String,
+    /// `(clock, at)`, in clock order.
+    edits: Vec<(u64, i64)>,
+}
+
+impl Author {
+    fn from_any(value: &Any) -> Option<Self> {
+        let Any::Map(fields) = value else {
+            return None;
+        };
+        let Some(Any::String(device)) = fields.get("device") else {
+            return None;
+        };
+        let number = |value: &Any| match value {
+            Any::Number(number) => number.as_i64(),
+            _ => None,
+        };
+        let mut edits: Vec<(u64, i64)> = match fields.get("edits") {
+            Some(Any::Array(edits)) => edits
+                .iter()
+                .filter_map(|edit| match edit {
+                    Any::Array(pair) if pair.len() == 2 => {
+                        Some((u64::try_from(number(&pair[0])?).ok()?, number(&pair[1])?))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        edits.sort_by_key(|(clock, _)| *clock);
+        Some(Self {
+            device: device.to_string(),
+            edits,
+        })
+    }
+
+    /// When the character at `clock` was written.
+    fn edited_at(&self, clock: u64) -> Option<i64> {
+        self.edits
+            .iter()
+            .take_while(|(from, _)| *from <= clock)
+            .last()
+            .map(|(_, at)| *at)
+    }
+}
+
+/// `update` plus a claim, for `device` at `at`, of every client in it that `yjs_state` has
+/// no author for.
+///
+/// For updates the backend makes itself with a fresh client (appends, restores).
+pub fn claim_update(
+    yjs_state: &[u8],
+    update: &[u8],
+    device: &str,
+    at: i64,
+) -> Result<Vec<u8>, String> {
+    let clients = Update::decode_v1(update)
+        .map_err(|e| format!("undecodable update: {e}"))?
+        .state_vector_lower();
+
+    let doc = Doc::new();
+    let authors = doc.get_or_insert_map(AUTHORS_MAP);
+    let mut txn = doc.transact_mut();
+    load(&mut txn, yjs_state)?;
+    let before = txn.state_vector();
+    for (client, clock) in clients.iter() {
+        let key = client.to_string();
+        if authors.get(&txn, &key).is_some() {
+            continue;
+        }
+        let mut fields = HashMap::new();
+        fields.insert("device".to_string(), Any::from(device));
+        let edit = Any::from(vec![Any::from(i64::from(*clock)), Any::from(at)]);
+        fields.insert("edits".to_string(), Any::from(vec![edit]));
+        authors.insert(&mut txn, key, Any::from(fields));
+    }
+    let claim = txn.encode_diff_v1(&before);
+    yrs::merge_updates_v1([update, claim.as_slice()]).map_err(|e| e.to_string())
+}
+
+/// The note's text as consecutive ranges, each written by one author at one time.
+pub fn attribute(yjs_state: &[u8]) -> Result<Vec<AttributedRange>, String> {
+    let doc = Doc::new();
+    let content = doc.get_or_insert_text(CONTENT_TEXT);
+    let authors_map = doc.get_or_insert_map(AUTHORS_MAP);
+    let mut txn = doc.transact_mut();
+    load(&mut txn, yjs_state)?;
+
+    let authors: HashMap<u64, Author> = authors_map
+        .iter(&txn)
+        .filter_map(|(client, value)| match value {
+            Out::Any(value) => Some((client.parse().ok()?, Author::from_any(&value)?)),
+            _ => None,
+        })
+        .collect();
+
+    // Against an empty snapshot every visible item counts as added, which exposes its id.
+    let now = txn.snapshot();
+    let chunks = content.diff_range(&mut txn, Some(&now), Some(&Snapshot::default()), |c| c.id);
+
+    let mut ranges: Vec<AttributedRange> = Vec::new();
+    let mut offset = 0;
+    for chunk in chunks {
+        let Out::Any(Any::String(text)) = &chunk.insert else {
+            continue;
+        };
+        let len = text.encode_utf16().count();
+        let Some(id) = chunk.ychange else {
+            push(&mut ranges, offset, offset + len, None, None);
+            offset += len;
+            continue;
+        };
+
+        let author = authors.get(&id.client.get());
+        let start_clock = u64::from(id.clock);
+        let mut cuts: Vec<usize> = author
+            .map(|author| {
+                author
+                    .edits
+                    .iter()
+                    .filter(|(clock, _)| *clock > start_clock)
+                    .map(|(clock, _)| (clock - start_clock) as usize)
+                    .filter(|&cut| cut < len)
+                    .collect()
+            })
+            .unwrap_or_default();
+        cuts.push(len);
+
+        let mut from = 0;
+        for to in cuts {
+            let device = author.map(|author| author.device.clone());
+            let at = author.and_then(|author| author.edited_at(start_clock + from as u64));
+            push(&mut ranges, offset + from, offset + to, device, at);
+            from = to;
+        }
+        offset += len;
+    }
+    Ok(ranges)
+}
+
+fn push(
+    ranges: &mut Vec<AttributedRange>,
+    start: usize,
+    end: usize,
+    author: Option<String>,
+    edited_at: Option<i64>,
+) {
+    if start == end {
+        return;
+    }
+    if let Some(last) = ranges.last_mut() {
+        if last.end == start && last.author == author && last.edited_at == edited_at {
+            last.end = end;
+            return;
+        }
+    }
+    ranges.push(AttributedRange {
+        start,
+        end,
+        author,
+        edited_at,
+    });
+}
+
+fn load(txn: &mut TransactionMut, yjs_state: &[u8]) -> Result<(), String> {
+    if yjs_state.is_empty() {
+        return Ok(());
+    }
+    let update = Update::decode_v1(yjs_state).map_err(|e| format!("undecodable state: {e}"))?;
+    txn.apply_update(update)
+        .map_err(|e| format!("undecodable state: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::append_update;
+
+    fn merge(a: &[u8], b: &[u8]) -> Vec<u8> {
+        yrs::merge_updates_v1([a, b]).expect("merge")
+    }
+
+    #[test]
+    fn attributes_ranges_to_claimed_devices() {
+        let first = append_update(&[], "Hello").unwrap();
+        let first = claim_update(&[], &first, "device-a", 100).unwrap();
+        let second = append_update(&first, ", wörld").unwrap();
+        let state = merge(
+            &first,
+            &claim_update(&first, &second, "device-b", 200).unwrap(),
+        );
+        let unclaimed = append_update(&state, "!").unwrap();
+        let state = merge(&state, &unclaimed);
+
+        let ranges = attribute(&state).unwrap();
+        assert_eq!(ranges.len(), 3);
+        assert_eq!((ranges[0].start, ranges[0].end), (0, 5));
+        assert_eq!(ranges[0].author.as_deref(), Some("device-a"));
+        assert_eq!(ranges[0].edited_at, Some(100));
+        assert_eq!((ranges[1].start, ranges[1].end), (5, 12));
+        assert_eq!(ranges[1].author.as_deref(), Some("device-b"));
+        assert_eq!((ranges[2].author.as_deref(), ranges[2].end), (None, 13));
+    }
+
+    #[test]
+    fn splits_one_client_run_at_its_checkpoints() {
+        let doc = Doc::with_client_id(7);
+        let content = doc.get_or_insert_text(CONTENT_TEXT);
+        let authors = doc.get_or_insert_map(AUTHORS_MAP);
+        let mut txn = doc.transact_mut();
+        content.insert(&mut txn, 0, "morning");
+        content.insert(&mut txn, 7, " evening");
+        let edits = Any::from(vec![
+            Any::from(vec![Any::from(0), Any::from(10)]),
+            Any::from(vec![Any::from(7), Any::from(20)]),
+        ]);
+        let mut fields = HashMap::new();
+        fields.insert("device".to_string(), Any::from("device-a"));
+        fields.insert("edits".to_string(), edits);
+        authors.insert(&mut txn, "7", Any::from(fields));
+        let state = txn.encode_update_v1();
+        drop(txn);
+
+        let ranges = attribute(&state).unwrap();
+        let times: Vec<_> = ranges
+            .iter()
+            .map(|r| (r.start, r.end, r.edited_at))
+            .collect();
+        assert_eq!(times, vec![(0, 7, Some(10)), (7, 15, Some(20))]);
+    }
+}
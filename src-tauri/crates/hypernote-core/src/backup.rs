@@ -0,0 +1,283 @@
+//! Scheduled local backups: timestamped workspace exports (see [`crate::export`]) with
+//! trashed notes and attachments, read back and checked after writing, and thinned out to
+//! a number of daily and weekly copies.
+//!
+//! Settings live in `<data-dir>/backups.json` and are read again before every run, so a
+//! change applies without a restart.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::contracts::BackupInfo;
+use crate::export::{self, ExportOptions, MANIFEST_PATH};
+use crate::import::days_from_civil;
+use crate::store::{write_atomic, NoteStore};
+
+pub const SETTINGS_FILE: &str = "backups.json";
+/// Where backups go unless the settings name a directory.
+pub const BACKUPS_DIR: &str = "backups";
+/// How often the scheduler checks whether a backup is due.
+pub const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const PREFIX: &str = "hypernote-";
+const EXTENSION: &str = ".zip";
+const DAY_MS: i64 = 86_400_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupSettings {
+    /// Off until turned on, so processes sharing a backup directory don't each fill it.
+    pub enabled: bool,
+    /// Directory for the archives, e.g. a NAS mount; `<data-dir>/backups` when unset.
+    pub dir: Option<PathBuf>,
+    pub interval_hours: u64,
+    /// Newest backup of each of the last this many days with one.
+    pub keep_daily: usize,
+    /// Newest backup of each of the last this many weeks (Monday to Sunday, UTC) with one.
+    pub keep_weekly: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            interval_hours: 24,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+impl BackupSettings {
+    /// The settings saved in `data_dir`, or the defaults when there are none.
+    pub fn load(data_dir: &Path) -> Result<Self, String> {
+        let path = data_dir.join(SETTINGS_FILE);
+        match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| format!("unreadable {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("cannot read {}: {e}", path.display())),
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), String> {
+        if self.interval_hours == 0 {
+            return Err("the backup interval must be at least an hour".to_string());
+        }
+        if self.dir.as_ref().is_some_and(|dir| !dir.is_absolute()) {
+            return Err("the backup directory must be an absolute path".to_string());
+        }
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        write_atomic(&data_dir.join(SETTINGS_FILE), &json)
+            .map_err(|e| format!("cannot save backup settings: {e}"))
+    }
+
+    pub fn backup_dir(&self, data_dir: &Path) -> PathBuf {
+        self.dir
+            .clone()
+            .unwrap_or_else(|| data_dir.join(BACKUPS_DIR))
+    }
+}
+
+/// Writes a backup of `store` into `dir` stamped `now`, reads it back to check it against
+/// its manifest and removes it again if it does not match.
+pub fn create_backup(store: &NoteStore, dir: &Path, now: i64) -> Result<BackupInfo, String> {
+    let path = dir.join(file_name(now));
+    let options = ExportOptions {
+        include_trash: true,
+        ..Default::default()
+    };
+    let written = export::export_workspace(store, &path, &options)?;
+    match export::verify_bundle(&path) {
+        Ok(read) if read == written => {}
+        Ok(_) => return Err(discard(&path, "the manifest read back differs")),
+        Err(reason) => return Err(discard(&path, &reason)),
+    }
+    let size = fs::metadata(&path).map_or(0, |meta| meta.len());
+    Ok(info(path, now, size, Some(written.note_count)))
+}
+
+fn discard(path: &Path, reason: &str) -> String {
+    let _ = fs::remove_file(path);
+    format!("backup {} failed verification: {reason}", path.display())
+}
+
+/// Backups in `dir`, newest first.
+pub fn list_backups(dir: &Path) -> Vec<BackupInfo> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<BackupInfo> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let created_at = parse_file_name(&entry.file_name().to_string_lossy())?;
+            let size = entry.metadata().ok()?.len();
+            let path = entry.path();
+            let note_count = note_count(&path);
+            Some(info(path, created_at, size, note_count))
+        })
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    backups
+}
+
+/// The backup in `dir` called `name`; only names [`list_backups`] reports are accepted.
+pub fn find_backup(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    list_backups(dir)
+        .into_iter()
+        .find(|backup| backup.name == name)
+        .map(|backup| PathBuf::from(backup.path))
+        .ok_or_else(|| format!("backup not found: {name}"))
+}
+
+/// Deletes the backups in `dir` that `settings` no longer keeps; the newest always stays.
+/// Returns the names removed.
+pub fn rotate(dir: &Path, settings: &BackupSettings) -> Vec<String> {
+    let backups = list_backups(dir);
+    let times: Vec<i64> = backups.iter().map(|backup| backup.created_at).collect();
+    let keep = kept(&times, settings.keep_daily, settings.keep_weekly);
+    let mut removed = Vec::new();
+    for (backup, keep) in backups.into_iter().zip(keep) {
+        if keep {
+            continue;
+        }
+        match fs::remove_file(&backup.path) {
+            Ok(()) => removed.push(backup.name),
+            Err(e) => eprintln!("[hypernote] cannot remove backup {}: {e}", backup.name),
+        }
+    }
+    removed
+}
+
+/// For backups made at `times`, newest first, whether each is the newest of one of the
+/// last `daily` days or `weekly` weeks that have a backup.
+fn kept(times: &[i64], daily: usize, weekly: usize) -> Vec<bool> {
+    let mut keep = vec![false; times.len()];
+    keep_newest_per(times, daily, &mut keep, |at| at.div_euclid(DAY_MS));
+    // 1970-01-01 was a Thursday; shifting by three days starts weeks on Monday.
+    keep_newest_per(times, weekly, &mut keep, |at| {
+        (at.div_euclid(DAY_MS) + 3).div_euclid(7)
+    });
+    if let Some(newest) = keep.first_mut() {
+        *newest = true;
+    }
+    keep
+}
+
+/// Marks the first of `times` in each of the first `count` periods met.
+fn keep_newest_per(times: &[i64], count: usize, keep: &mut [bool], period_of: fn(i64) -> i64) {
+    let mut last = None;
+    let mut seen = 0;
+    for (i, &at) in times.iter().enumerate() {
+        let period = period_of(at);
+        if last == Some(period) {
+            continue;
+        }
+        last = Some(period);
+        if seen == count {
+            break;
+        }
+        seen += 1;
+        keep[i] = true;
+    }
+}
+
+fn info(path: PathBuf, created_at: i64, size: u64, note_count: Option<usize>) -> BackupInfo {
+    BackupInfo {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        path: path.display().to_string(),
+        created_at,
+        size,
+        note_count,
+    }
+}
+
+/// The manifest's note count, without reading the rest of the archive.
+fn note_count(path: &Path) -> Option<usize> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(path).ok()?).ok()?;
+    let entry = zip.by_name(MANIFEST_PATH).ok()?;
+    let manifest: crate::contracts::ExportManifest = serde_json::from_reader(entry).ok()?;
+    Some(manifest.note_count)
+}
+
+/// `hypernote-20240309T154500Z.zip`.
+fn file_name(at: i64) -> String {
+    let iso = export::iso8601(at);
+    let stamp: String = iso[..19]
+        .chars()
+        .filter(|c| *c != '-' && *c != ':')
+        .collect();
+    format!("{PREFIX}{stamp}Z{EXTENSION}")
+}
+
+fn parse_file_name(name: &str) -> Option<i64> {
+    let stamp = name.strip_prefix(PREFIX)?.strip_suffix(EXTENSION)?;
+    let stamp = stamp.strip_suffix('Z')?;
+    let (date, time) = stamp.split_once('T')?;
+    if date.len() != 8
+        || time.len() != 6
+        || !(date.chars().chain(time.chars())).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let field = |s: &str, at: usize, len: usize| s[at..at + len].parse::<i64>().ok();
+    let days = days_from_civil(field(date, 0, 4)?, field(date, 4, 2)?, field(date, 6, 2)?);
+    let seconds = field(time, 0, 2)? * 3600 + field(time, 2, 2)? * 60 + field(time, 4, 2)?;
+    Some(days * DAY_MS + seconds * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_backup_of_recent_days_and_weeks() {
+        // Newest first: two on day 20, then 19 and 18 of the same week, then 10 and 3.
+        let days = [20.5, 20.1, 19.0, 18.0, 10.0, 3.0];
+        let times: Vec<i64> = days
+            .iter()
+            .map(|day| (day * DAY_MS as f64) as i64)
+            .collect();
+        assert_eq!(kept(&times, 2, 0), [true, false, true, false, false, false]);
+        assert_eq!(kept(&times, 0, 2), [true, false, false, false, true, false]);
+        assert_eq!(kept(&times, 1, 3), [true, false, false, false, true, true]);
+        assert_eq!(
+            kept(&times, 0, 0),
+            [true, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn writes_verifies_lists_and_rotates_backups() {
+        let dir = std::env::temp_dir().join(format!("hn-backup-{}", uuid::Uuid::new_v4()));
+        let store = NoteStore::open(dir.join("data")).unwrap();
+        let note = store.create().unwrap();
+        store.append_text(&note.id, "Keep me").unwrap();
+        let backups = dir.join("nas");
+
+        let first = create_backup(&store, &backups, 1_709_999_100_000).unwrap();
+        assert_eq!(first.name, "hypernote-20240309T154500Z.zip");
+        assert_eq!(first.note_count, Some(1));
+        let next_day = create_backup(&store, &backups, 1_709_999_100_000 + DAY_MS).unwrap();
+        assert_eq!(list_backups(&backups), [next_day.clone(), first.clone()]);
+        assert!(find_backup(&backups, "../data").is_err());
+
+        let settings = BackupSettings {
+            enabled: true,
+            keep_daily: 1,
+            keep_weekly: 0,
+            ..Default::default()
+        };
+        assert!(!BackupSettings::load(&dir).unwrap().enabled);
+        assert_eq!(rotate(&backups, &settings), [first.name]);
+        assert_eq!(list_backups(&backups), [next_day]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
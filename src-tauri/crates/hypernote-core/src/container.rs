@@ -0,0 +1,178 @@
+//! HYPN note container, byte-compatible with `src/lib/contracts/storage.ts`.
+//!
+//! Layout (all integers big-endian):
+//!
+//! ```text
+//! "HYPN" | version u8 | metadata_len u32 | metadata JSON | body_len u64 | Yjs state | crc32 u32
+//! ```
+//!
+//! The checksum covers the metadata bytes followed by the Yjs state.
+
+use crate::contracts::NoteMeta;
+
+const MAGIC: &[u8; 4] = b"HYPN";
+const VERSION: u8 = 1;
+const METADATA_LEN_OFFSET: usize = MAGIC.len() + 1;
+const METADATA_OFFSET: usize = METADATA_LEN_OFFSET + 4;
+const MIN_CONTAINER_SIZE: usize = METADATA_OFFSET + 8 + 4;
+
+#[derive(Debug, Clone)]
+pub struct DecodedNote {
+    pub meta: NoteMeta,
+    pub yjs_state: Vec<u8>,
+}
+
+pub fn encode(meta: &NoteMeta, yjs_state: &[u8]) -> Vec<u8> {
+    let metadata = serde_json::to_vec(meta).expect("note metadata serializes");
+    let mut bytes = Vec::with_capacity(MIN_CONTAINER_SIZE + metadata.len() + yjs_state.len());
+
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&(metadata.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&metadata);
+    bytes.extend_from_slice(&(yjs_state.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(yjs_state);
+    bytes.extend_from_slice(&checksum(&metadata, yjs_state).to_be_bytes());
+
+    bytes
+}
+
+/// Reads only the metadata, without checking the body or checksum.
+pub fn decode_meta(bytes: &[u8]) -> Result<NoteMeta, String> {
+    parse_prefix(bytes).map(|(meta, _)| meta)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<DecodedNote, String> {
+    let (meta, metadata_end) = parse_prefix(bytes)?;
+    let metadata = &bytes[METADATA_OFFSET..metadata_end];
+
+    let mut offset = metadata_end;
+    let body_len = read_u64(bytes, offset).ok_or("invalid container: missing body length")?;
+    offset += 8;
+
+    let body_end = usize::try_from(body_len)
+        .ok()
+        .and_then(|len| offset.checked_add(len))
+        .filter(|end| end.checked_add(4).is_some_and(|total| total <= bytes.len()))
+        .ok_or("invalid container: body overflow")?;
+
+    let yjs_state = &bytes[offset..body_end];
+    let expected = read_u32(bytes, body_end).ok_or("invalid container: body overflow")?;
+    if expected != checksum(metadata, yjs_state) {
+        return Err("invalid container: checksum mismatch".to_string());
+    }
+
+    Ok(DecodedNote {
+        meta,
+        yjs_state: yjs_state.to_vec(),
+    })
+}
+
+fn parse_prefix(bytes: &[u8]) -> Result<(NoteMeta, usize), String> {
+    if bytes.len() < MIN_CONTAINER_SIZE {
+        return Err("invalid container: too short".to_string());
+    }
+
+    if &bytes[..MAGIC.len()] != MAGIC {
+        return Err("invalid container: bad magic".to_string());
+    }
+
+    let version = bytes[MAGIC.len()];
+    if version != VERSION {
+        return Err(format!("unsupported container version: {version}"));
+    }
+
+    let metadata_len = read_u32(bytes, METADATA_LEN_OFFSET).unwrap_or_default() as usize;
+    let metadata_end = METADATA_OFFSET + metadata_len;
+    if metadata_end > bytes.len() {
+        return Err("invalid container: metadata overflow".to_string());
+    }
+
+    let meta = serde_json::from_slice(&bytes[METADATA_OFFSET..metadata_end])
+        .map_err(|_| "invalid container: malformed metadata".to_string())?;
+
+    Ok((meta, metadata_end))
+}
+
+fn checksum(metadata: &[u8], yjs_state: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(metadata);
+    hasher.update(yjs_state);
+    hasher.finalize()
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let raw = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(raw.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let raw = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(raw.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decode_meta, encode};
+    use crate::contracts::NoteMeta;
+
+    fn meta() -> NoteMeta {
+        NoteMeta {
+            id: "note-1".to_string(),
+            title: "Groceries".to_string(),
+            created_at: 1,
+            updated_at: 2,
+            deleted_at: None,
+            body: Some("milk".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_metadata_and_state() {
+        let bytes = encode(&meta(), &[1, 2, 3]);
+        let decoded = decode(&bytes).expect("container decodes");
+
+        assert_eq!(decoded.meta.id, "note-1");
+        assert_eq!(decoded.meta.body.as_deref(), Some("milk"));
+        assert_eq!(decoded.yjs_state, vec![1, 2, 3]);
+        assert_eq!(
+            decode_meta(&bytes).expect("meta decodes").title,
+            "Groceries"
+        );
+    }
+
+    #[test]
+    fn reads_containers_written_by_the_frontend() {
+        // encodeNoteContainer({ id: 'n', title: 't', createdAt: 1, updatedAt: 2, deletedAt: null }, [9])
+        let metadata = br#"{"id":"n","title":"t","createdAt":1,"updatedAt":2,"deletedAt":null}"#;
+        let mut bytes = b"HYPN\x01".to_vec();
+        bytes.extend_from_slice(&(metadata.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(metadata);
+        bytes.extend_from_slice(&1u64.to_be_bytes());
+        bytes.push(9);
+        let mut crc_input = metadata.to_vec();
+        crc_input.push(9);
+        bytes.extend_from_slice(&crc32fast::hash(&crc_input).to_be_bytes());
+
+        let decoded = decode(&bytes).expect("frontend container decodes");
+        assert_eq!(decoded.meta.id, "n");
+        assert_eq!(decoded.meta.body, None);
+        assert_eq!(decoded.yjs_state, vec![9]);
+        assert_eq!(encode(&decoded.meta, &decoded.yjs_state), bytes);
+    }
+
+    #[test]
+    fn rejects_damaged_containers() {
+        let mut bytes = encode(&meta(), &[1, 2, 3]);
+        let last = bytes.len() - 5;
+        bytes[last] ^= 0xff;
+        assert_eq!(
+            decode(&bytes).unwrap_err(),
+            "invalid container: checksum mismatch"
+        );
+
+        assert!(decode(b"HYPN").is_err());
+        assert!(decode(&encode(&meta(), &[1])[..20]).is_err());
+    }
+}
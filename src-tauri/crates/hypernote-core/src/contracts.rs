@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteMeta {
+    pub id: String,
+    pub title: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub deleted_at: Option<i64>,
+    /// Plain-text preview written by the frontend; absent in containers from older builds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Set when the title was chosen by hand; otherwise it follows the note's first heading
+    /// or first line.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub title_override: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Slash-separated path such as `work/clients`; `None` is the top level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// Criteria for `list_notes_filtered`; every field that is set must match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NoteFilter {
+    /// Notes carrying all of these tags.
+    pub tags: Vec<String>,
+    /// Notes in this folder or any folder below it.
+    pub folder: Option<String>,
+    pub pinned: Option<bool>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteDocument {
+    pub meta: NoteMeta,
+    pub yjs_state: Vec<u8>,
+    pub markdown: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub ws_url: String,
+    pub status: String,
+    pub note_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandAck {
+    pub accepted: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerUpdateEvent {
+    pub note_id: String,
+    pub update: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerConnectedEvent {
+    pub peer_id: String,
+    pub addr: String,
+    pub outbound: bool,
+    /// Connection goes through an internet relay rather than straight to one peer.
+    pub relay: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDisconnectedEvent {
+    pub peer_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsMessageEvent {
+    pub peer_id: String,
+    pub payload: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncState {
+    pub enabled: bool,
+    /// Notes edited locally while sync was paused and not yet offered to a peer.
+    pub unsynced_note_ids: Vec<String>,
+    /// Addresses refused for flooding frames or connections, until their ban runs out.
+    pub bans: Vec<PeerBan>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerBan {
+    /// IP address the ban applies to.
+    pub addr: String,
+    pub reason: String,
+    pub banned_at: i64,
+    pub until: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub note_id: String,
+    pub title: String,
+    pub score: f32,
+    /// Text around the first match, in order; matched words have `highlight` set.
+    pub snippet: Vec<SnippetPart>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlight: bool,
+}
+
+/// One `quick_open` result; `title_parts` highlights the matched characters.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickOpenHit {
+    pub note_id: String,
+    pub title: String,
+    pub score: i32,
+    pub title_parts: Vec<SnippetPart>,
+    /// Opened recently in this session.
+    pub recent: bool,
+}
+
+/// One saved version of a note, from `list_note_versions`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteVersion {
+    /// Increases by one per version of the note.
+    pub version: u32,
+    /// When the note last changed before this version was kept.
+    pub saved_at: i64,
+    pub title: String,
+}
+
+/// A span of a note's text written by one author at one time, from `get_note_attribution`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributedRange {
+    /// UTF-16 offsets into the text, as the editor counts them; `end` is exclusive.
+    pub start: usize,
+    pub end: usize,
+    /// Device id of the author, if its client claimed the edit.
+    pub author: Option<String>,
+    pub edited_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffKind {
+    Equal,
+    Insert,
+    Delete,
+    /// A line deleted in one place and inserted unchanged in another.
+    Move,
+}
+
+/// One line of a `diff_note` result. Line numbers are 1-based; a move has both.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+    /// For a deleted or inserted line that replaced another: its text split into words
+    /// marked `equal` or changed.
+    pub words: Vec<DiffWord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffWord {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteDiff {
+    pub lines: Vec<DiffLine>,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub moves: usize,
+    /// The same diff in `diff -u` format, moves as a deletion and an insertion.
+    pub unified: String,
+}
+
+/// A note container that failed validation when the store loaded.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageIssue {
+    pub note_id: String,
+    /// Where the container was found.
+    pub path: String,
+    /// Where it was moved; `None` when it was left in place.
+    pub quarantined_to: Option<String>,
+    pub reason: String,
+    /// Saved version the note was rebuilt from, if any.
+    pub restored_version: Option<u32>,
+    /// Whether updates from the note's log were replayed on top.
+    pub replayed_log: bool,
+    pub detected_at: i64,
+}
+
+/// Outcome of compacting a note's stored Yjs state.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactionReport {
+    pub note_id: String,
+    pub before_bytes: u64,
+    pub after_bytes: u64,
+    /// Whether deleted content was dropped and the smaller state saved, not just re-encoded.
+    pub collected: bool,
+}
+
+/// Outcome of an import, one entry per source file.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    /// Nothing was created; `imported` lists what would have been.
+    pub dry_run: bool,
+    pub imported: Vec<ImportedNote>,
+    /// Files whose title and text match a note that already exists; nothing was created.
+    pub duplicates: Vec<ImportDuplicate>,
+    pub failures: Vec<ImportFailure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedNote {
+    pub path: String,
+    /// `None` in a dry run.
+    pub note_id: Option<String>,
+    pub title: String,
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    /// Names of the files saved under `attachments/<note-id>/`.
+    pub attachments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDuplicate {
+    pub path: String,
+    /// The note the file matches; `None` when that is another file of a dry run.
+    pub note_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportFailure {
+    pub path: String,
+    pub reason: String,
+}
+
+/// `manifest.json` of a workspace export, the shape `workspace-export.ts` writes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifest {
+    pub workspace_id: String,
+    /// ISO 8601, as `Date.toISOString` prints it.
+    pub exported_at: String,
+    pub note_count: usize,
+    pub files: Vec<ExportFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFile {
+    pub path: String,
+    pub size: u64,
+    /// Lower-case hex SHA-256 of the file's bytes.
+    pub sha256: String,
+}
+
+/// Outcome of restoring a workspace export, by note id.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleImportReport {
+    pub workspace_id: String,
+    pub exported_at: String,
+    /// Notes the workspace did not have.
+    pub created: Vec<String>,
+    /// Existing notes that took changes from the bundle.
+    pub merged: Vec<String>,
+    /// Existing notes that already had everything in the bundle.
+    pub unchanged: Vec<String>,
+    /// Merged notes that had also changed here since the bundle's copy.
+    pub conflicts: Vec<BundleConflict>,
+    /// Files that failed their checksum or could not be restored.
+    pub failures: Vec<ImportFailure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleConflict {
+    pub note_id: String,
+    /// The title after merging.
+    pub title: String,
+    pub reason: String,
+}
+
+/// A backup archive, a workspace export written by the backup scheduler.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    /// File name, which `restore_backup` takes.
+    pub name: String,
+    pub path: String,
+    pub created_at: i64,
+    pub size: u64,
+    /// `None` when the archive's manifest cannot be read.
+    pub note_count: Option<usize>,
+}
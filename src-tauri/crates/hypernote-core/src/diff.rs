@@ -0,0 +1,377 @@
+//! Line and word diff between two texts of a note.
+//!
+//! Lines are matched by longest common subsequence. A deleted line that comes back
+//! unchanged elsewhere is reported once, as a move. Remaining runs of deleted lines followed
+//! by inserted ones are paired up as modifications and carry a word diff. [`unified`]
+//! renders the plain line diff in the usual `diff -u` format, moves as a deletion plus an
+//! insertion, so patch tools can read it.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::contracts::{DiffKind, DiffLine, DiffWord, NoteDiff};
+
+/// Lines of context around each hunk in [`unified`].
+pub const CONTEXT_LINES: usize = 3;
+/// Above this many table cells the middle of a diff is reported as replaced wholesale.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Structured diff from `old` to `new`; `from` and `to` name the two sides in `unified`.
+pub fn diff_texts(old: &str, new: &str, from: &str, to: &str) -> NoteDiff {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff(&old_lines, &new_lines);
+
+    // Deleted lines by text, oldest first, to recognize the same line inserted elsewhere.
+    let mut deleted: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for op in &ops {
+        if let Op::Delete(i) = *op {
+            if !old_lines[i].trim().is_empty() {
+                deleted.entry(old_lines[i]).or_default().push_back(i);
+            }
+        }
+    }
+    let mut moved_from: HashMap<usize, usize> = HashMap::new();
+    for op in &ops {
+        if let Op::Insert(j) = *op {
+            if let Some(i) = deleted.get_mut(new_lines[j]).and_then(VecDeque::pop_front) {
+                moved_from.insert(j, i);
+            }
+        }
+    }
+    let moved_old: HashSet<usize> = moved_from.values().copied().collect();
+
+    let mut lines = Vec::with_capacity(ops.len());
+    let mut pending_deletes: VecDeque<usize> = VecDeque::new();
+    for op in &ops {
+        match *op {
+            Op::Equal(i, j) => lines.push(DiffLine {
+                kind: DiffKind::Equal,
+                old_line: Some(i + 1),
+                new_line: Some(j + 1),
+                text: old_lines[i].to_string(),
+                words: Vec::new(),
+            }),
+            Op::Delete(i) if moved_old.contains(&i) => {}
+            Op::Delete(i) => {
+                pending_deletes.push_back(lines.len());
+                lines.push(DiffLine {
+                    kind: DiffKind::Delete,
+                    old_line: Some(i + 1),
+                    new_line: None,
+                    text: old_lines[i].to_string(),
+                    words: Vec::new(),
+                });
+            }
+            Op::Insert(j) => {
+                if let Some(&i) = moved_from.get(&j) {
+                    lines.push(DiffLine {
+                        kind: DiffKind::Move,
+                        old_line: Some(i + 1),
+                        new_line: Some(j + 1),
+                        text: new_lines[j].to_string(),
+                        words: Vec::new(),
+                    });
+                    continue;
+                }
+                let mut line = DiffLine {
+                    kind: DiffKind::Insert,
+                    old_line: None,
+                    new_line: Some(j + 1),
+                    text: new_lines[j].to_string(),
+                    words: Vec::new(),
+                };
+                // Pair with the earliest unpaired deletion of the same run, if any.
+                if let Some(index) = pending_deletes.pop_front() {
+                    let deleted = &mut lines[index];
+                    let (old_words, new_words) = diff_words(&deleted.text, &line.text);
+                    deleted.words = old_words;
+                    line.words = new_words;
+                }
+                lines.push(line);
+            }
+        }
+        if matches!(op, Op::Equal(..)) {
+            pending_deletes.clear();
+        }
+    }
+
+    let count = |kind: DiffKind| lines.iter().filter(|line| line.kind == kind).count();
+    NoteDiff {
+        insertions: count(DiffKind::Insert),
+        deletions: count(DiffKind::Delete),
+        moves: count(DiffKind::Move),
+        unified: render_unified(&old_lines, &new_lines, &ops, from, to),
+        lines,
+    }
+}
+
+/// `diff -u` rendering of the line diff from `old` to `new`, empty when they are equal.
+pub fn unified(old: &str, new: &str, from: &str, to: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    render_unified(
+        &old_lines,
+        &new_lines,
+        &diff(&old_lines, &new_lines),
+        from,
+        to,
+    )
+}
+
+fn render_unified(old: &[&str], new: &[&str], ops: &[Op], from: &str, to: &str) -> String {
+    let changed: Vec<usize> = (0..ops.len())
+        .filter(|&k| !matches!(ops[k], Op::Equal(..)))
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Group changes whose context would overlap into one hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &k in &changed {
+        let start = k.saturating_sub(CONTEXT_LINES);
+        let end = (k + CONTEXT_LINES + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {from}\n+++ {to}\n");
+    for (start, end) in hunks {
+        let ops = &ops[start..end];
+        // Position of the hunk's first line on each side, for the header.
+        let (old_start, new_start) = match ops[0] {
+            Op::Equal(i, j) => (i, j),
+            Op::Delete(i) => (i, first_new(ops).unwrap_or(0)),
+            Op::Insert(j) => (first_old(ops).unwrap_or(0), j),
+        };
+        let old_len = ops.iter().filter(|op| !matches!(op, Op::Insert(_))).count();
+        let new_len = ops.iter().filter(|op| !matches!(op, Op::Delete(_))).count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_len),
+            range(new_start, new_len)
+        ));
+        for op in ops {
+            match *op {
+                Op::Equal(i, _) => out.push_str(&format!(" {}\n", old[i])),
+                Op::Delete(i) => out.push_str(&format!("-{}\n", old[i])),
+                Op::Insert(j) => out.push_str(&format!("+{}\n", new[j])),
+            }
+        }
+    }
+    out
+}
+
+fn first_old(ops: &[Op]) -> Option<usize> {
+    ops.iter().find_map(|op| match *op {
+        Op::Equal(i, _) | Op::Delete(i) => Some(i),
+        Op::Insert(_) => None,
+    })
+}
+
+fn first_new(ops: &[Op]) -> Option<usize> {
+    ops.iter().find_map(|op| match *op {
+        Op::Equal(_, j) | Op::Insert(j) => Some(j),
+        Op::Delete(_) => None,
+    })
+}
+
+/// `start,len` as unified diff headers write it: 1-based, and the line before an empty range.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+/// Word diff of a modified line: the old line's words and the new line's words, each
+/// marked equal or changed.
+fn diff_words(old: &str, new: &str) -> (Vec<DiffWord>, Vec<DiffWord>) {
+    let old_words = words(old);
+    let new_words = words(new);
+    let mut old_out: Vec<DiffWord> = Vec::new();
+    let mut new_out: Vec<DiffWord> = Vec::new();
+    for op in diff(&old_words, &new_words) {
+        match op {
+            Op::Equal(i, _) => {
+                push_word(&mut old_out, DiffKind::Equal, old_words[i]);
+                push_word(&mut new_out, DiffKind::Equal, old_words[i]);
+            }
+            Op::Delete(i) => push_word(&mut old_out, DiffKind::Delete, old_words[i]),
+            Op::Insert(j) => push_word(&mut new_out, DiffKind::Insert, new_words[j]),
+        }
+    }
+    (old_out, new_out)
+}
+
+fn push_word(words: &mut Vec<DiffWord>, kind: DiffKind, text: &str) {
+    match words.last_mut() {
+        Some(last) if last.kind == kind => last.text.push_str(text),
+        _ => words.push(DiffWord {
+            kind,
+            text: text.to_string(),
+        }),
+    }
+}
+
+/// Splits a line into runs of word characters, runs of whitespace and single other chars.
+fn words(line: &str) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<u8> = None;
+    for (at, c) in line.char_indices() {
+        let kind = class(c);
+        if at > start && (prev != Some(kind) || kind == 2) {
+            words.push(&line[start..at]);
+            start = at;
+        }
+        prev = Some(kind);
+    }
+    if start < line.len() {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+/// Shortest edit script from `a` to `b`, in order.
+fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<Op> = (0..prefix).map(|k| Op::Equal(k, k)).collect();
+    if a_mid.len().saturating_mul(b_mid.len()) > MAX_LCS_CELLS {
+        ops.extend((0..a_mid.len()).map(|i| Op::Delete(prefix + i)));
+        ops.extend((0..b_mid.len()).map(|j| Op::Insert(prefix + j)));
+    } else {
+        ops.extend(lcs(a_mid, b_mid).into_iter().map(|op| match op {
+            Op::Equal(i, j) => Op::Equal(prefix + i, prefix + j),
+            Op::Delete(i) => Op::Delete(prefix + i),
+            Op::Insert(j) => Op::Insert(prefix + j),
+        }));
+    }
+    let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+    ops.extend((0..suffix).map(|k| Op::Equal(a_end + k, b_end + k)));
+    ops
+}
+
+fn lcs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    let (n, m) = (a.len(), b.len());
+    // lengths[i][j]: LCS length of a[i..] and b[j..].
+    let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[at(i, j)] = if a[i] == b[j] {
+                lengths[at(i + 1, j + 1)] + 1
+            } else {
+                lengths[at(i + 1, j)].max(lengths[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            ops.push(Op::Equal(i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[at(i + 1, j)] >= lengths[at(i, j + 1)] {
+            ops.push(Op::Delete(i));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(j));
+            j += 1;
+        }
+    }
+    ops.extend((i..n).map(Op::Delete));
+    ops.extend((j..m).map(Op::Insert));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_moves_and_word_changes() {
+        let old = "# Plan\nbuy milk\ncall Ana";
+        let new = "# Plan\nbuy oat milk\ncall Ana";
+        let diff = diff_texts(old, new, "v1", "current");
+
+        let kinds: Vec<_> = diff.lines.iter().map(|line| line.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiffKind::Equal,
+                DiffKind::Delete,
+                DiffKind::Insert,
+                DiffKind::Equal
+            ]
+        );
+        assert_eq!((diff.insertions, diff.deletions, diff.moves), (1, 1, 0));
+        let inserted: Vec<_> = diff.lines[2]
+            .words
+            .iter()
+            .map(|word| (word.kind, word.text.as_str()))
+            .collect();
+        assert_eq!(
+            inserted,
+            vec![
+                (DiffKind::Equal, "buy "),
+                (DiffKind::Insert, "oat "),
+                (DiffKind::Equal, "milk")
+            ]
+        );
+
+        let moved = diff_texts("a\nb\nc\nd", "b\nc\nd\na", "v1", "v2");
+        assert_eq!(moved.moves, 1);
+        let line = moved
+            .lines
+            .iter()
+            .find(|l| l.kind == DiffKind::Move)
+            .unwrap();
+        assert_eq!((line.old_line, line.new_line), (Some(1), Some(4)));
+    }
+
+    #[test]
+    fn renders_unified_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12";
+        let new = "1\ntwo\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13";
+        assert_eq!(
+            unified(old, new, "v1", "v2"),
+            "--- v1\n+++ v2\n@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+        assert_eq!(unified("same", "same", "a", "b"), "");
+        assert_eq!(
+            unified("", "new", "a", "b"),
+            "--- a\n+++ b\n@@ -0,0 +1 @@\n+new\n"
+        );
+    }
+}
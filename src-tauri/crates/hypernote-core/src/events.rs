@@ -0,0 +1,27 @@
+//! Events the core raises for whoever hosts it.
+//!
+//! The app forwards them to the webview under the same names; the daemon logs them.
+
+use serde::Serialize;
+
+pub const PEER_CONNECTED: &str = "hypernote://peer-connected";
+pub const PEER_DISCONNECTED: &str = "hypernote://peer-disconnected";
+pub const PEER_UPDATE: &str = "hypernote://peer-update";
+pub const WS_MESSAGE: &str = "hypernote://ws-message";
+pub const SYNC_STATE_CHANGED: &str = "hypernote://sync-state-changed";
+/// Payload is the note's [`crate::contracts::NoteMeta`] after the change.
+pub const NOTE_META_CHANGED: &str = "hypernote://note-meta-changed";
+/// An update the backend made itself (metadata changes), for the host to save and share.
+pub const NOTE_UPDATE: &str = "hypernote://note-update";
+/// Payload is a [`crate::contracts::StorageIssue`] found when the store loaded.
+pub const STORAGE_ISSUE: &str = "hypernote://storage-issue";
+
+pub trait EventSink: Send + Sync + 'static {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> Result<(), String>;
+}
+
+/// Serializes `payload` and hands it to `sink`.
+pub fn emit<T: Serialize>(sink: &dyn EventSink, event: &str, payload: T) -> Result<(), String> {
+    let payload = serde_json::to_value(payload).map_err(|e| e.to_string())?;
+    sink.emit(event, payload)
+}
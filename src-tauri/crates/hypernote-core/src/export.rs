@@ -0,0 +1,414 @@
+//! Workspace export: one zip holding every note as Markdown next to its raw `.yjs`
+//! container, with a `manifest.json` listing each file's size and SHA-256 in the shape
+//! `src/lib/export/workspace-export.ts` writes.
+//!
+//! ```text
+//! markdown/<title>.md        "# <title>\n\n<text>", as the webview exports it
+//! yjs/<note-id>.yjs          the note's HYPN container, CRDT history and metadata intact
+//! attachments/<note-id>/...  files imported notes refer to
+//! manifest.json              written last
+//! ```
+//!
+//! Trashed notes, when included, go under `trash/` in each of the first two.
+//! [`read_bundle`] reads an export back, checking every file against the manifest.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::container;
+use crate::contracts::{ExportFile, ExportManifest, NoteDocument};
+use crate::import::days_from_civil;
+use crate::store::{NoteStore, ATTACHMENTS_DIR, CONTAINER_EXTENSION, TRASH_DIR};
+
+pub const MANIFEST_PATH: &str = "manifest.json";
+pub const MARKDOWN_DIR: &str = "markdown";
+pub const CONTAINERS_DIR: &str = "yjs";
+/// The id `workspace-export.ts` falls back to.
+pub const DEFAULT_WORKSPACE_ID: &str = "hypernote-workspace";
+const MAX_FILENAME_CHARS: usize = 48;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+    /// Recorded in the manifest; [`DEFAULT_WORKSPACE_ID`] when unset.
+    pub workspace_id: Option<String>,
+    pub include_trash: bool,
+    pub include_attachments: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            workspace_id: None,
+            include_trash: false,
+            include_attachments: true,
+        }
+    }
+}
+
+/// Writes the export zip to `dest`, one note at a time, and returns its manifest. The zip
+/// is built next to `dest` and renamed into place, so `dest` is either the previous file
+/// or a complete export.
+pub fn export_workspace(
+    store: &NoteStore,
+    dest: &Path,
+    options: &ExportOptions,
+) -> Result<ExportManifest, String> {
+    let name = dest
+        .file_name()
+        .ok_or_else(|| format!("not a file path: {}", dest.display()))?;
+    if let Some(parent) = dest
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("cannot create {}: {e}", parent.display()))?;
+    }
+    let mut partial = name.to_os_string();
+    partial.push(".partial");
+    let partial = dest.with_file_name(partial);
+
+    let written = write_bundle(store, &partial, options)
+        .and_then(|manifest| fs::rename(&partial, dest).map(|_| manifest))
+        .map_err(|e| format!("cannot write {}: {e}", dest.display()));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
+fn write_bundle(
+    store: &NoteStore,
+    path: &Path,
+    options: &ExportOptions,
+) -> std::io::Result<ExportManifest> {
+    let exported_at = crate::unix_now_ms();
+    let mut bundle = Bundle {
+        zip: ZipWriter::new(BufWriter::new(File::create(path)?)),
+        options: file_options(exported_at),
+        files: Vec::new(),
+    };
+    let mut ids = if options.include_trash {
+        store.all_note_ids()
+    } else {
+        store.note_ids()
+    };
+    ids.sort();
+
+    let mut names = HashSet::new();
+    let mut note_count = 0;
+    for id in ids {
+        let Some(note) = store.get(&id) else {
+            continue;
+        };
+        let prefix = if note.meta.deleted_at.is_some() {
+            format!("{TRASH_DIR}/")
+        } else {
+            String::new()
+        };
+        let mut base = format!("{prefix}{}", safe_filename(&note.meta.title, &id));
+        if !names.insert(base.clone()) {
+            base = format!("{base}-{id}");
+            names.insert(base.clone());
+        }
+        bundle.add(
+            &format!("{MARKDOWN_DIR}/{base}.md"),
+            markdown_of(&note).as_bytes(),
+        )?;
+        bundle.add(
+            &format!("{CONTAINERS_DIR}/{prefix}{id}.{CONTAINER_EXTENSION}"),
+            &container::encode(&note.meta, &note.yjs_state),
+        )?;
+        if options.include_attachments {
+            if let Some(dir) = store.data_dir() {
+                for (name, file) in attachments(&dir.join(ATTACHMENTS_DIR).join(&id))? {
+                    bundle.add(&format!("{ATTACHMENTS_DIR}/{id}/{name}"), &fs::read(file)?)?;
+                }
+            }
+        }
+        note_count += 1;
+    }
+
+    let manifest = ExportManifest {
+        workspace_id: options
+            .workspace_id
+            .clone()
+            .unwrap_or_else(|| DEFAULT_WORKSPACE_ID.to_string()),
+        exported_at: iso8601(exported_at),
+        note_count,
+        files: bundle.files,
+    };
+    let json = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::other)?;
+    let mut zip = bundle.zip;
+    zip.start_file(MANIFEST_PATH, bundle.options)?;
+    zip.write_all(&json)?;
+    let file = zip.finish()?.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(manifest)
+}
+
+/// Reads the export at `path`, handing each file the manifest lists to `visit` in order:
+/// its bytes, or why they do not match the manifest. Fails when the zip or its manifest
+/// cannot be read.
+pub fn read_bundle(
+    path: &Path,
+    mut visit: impl FnMut(&ExportFile, Result<Vec<u8>, String>),
+) -> Result<ExportManifest, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {e}", path.display()))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| format!("not a zip archive: {e}"))?;
+    let manifest: ExportManifest = {
+        let entry = zip
+            .by_name(MANIFEST_PATH)
+            .map_err(|_| format!("no {MANIFEST_PATH} in the archive"))?;
+        serde_json::from_reader(entry).map_err(|e| format!("unreadable {MANIFEST_PATH}: {e}"))?
+    };
+    for file in &manifest.files {
+        visit(file, read_checked(&mut zip, file));
+    }
+    Ok(manifest)
+}
+
+/// Reads the whole export at `path` back and checks every file against the manifest.
+pub fn verify_bundle(path: &Path) -> Result<ExportManifest, String> {
+    let mut failures = Vec::new();
+    let manifest = read_bundle(path, |file, bytes| {
+        if let Err(reason) = bytes {
+            failures.push(format!("{}: {reason}", file.path));
+        }
+    })?;
+    match failures.is_empty() {
+        true => Ok(manifest),
+        false => Err(failures.join("; ")),
+    }
+}
+
+fn read_checked(zip: &mut ZipArchive<File>, file: &ExportFile) -> Result<Vec<u8>, String> {
+    let entry = zip
+        .by_name(&file.path)
+        .map_err(|_| "missing from the archive".to_string())?;
+    let mut bytes = Vec::new();
+    // One byte past the listed size is enough to tell it is wrong.
+    entry
+        .take(file.size.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("unreadable: {e}"))?;
+    if bytes.len() as u64 != file.size {
+        return Err(format!("size does not match the manifest ({})", file.size));
+    }
+    if sha256_hex(&bytes) != file.sha256 {
+        return Err("checksum does not match the manifest".to_string());
+    }
+    Ok(bytes)
+}
+
+struct Bundle {
+    zip: ZipWriter<BufWriter<File>>,
+    options: SimpleFileOptions,
+    files: Vec<ExportFile>,
+}
+
+impl Bundle {
+    fn add(&mut self, path: &str, bytes: &[u8]) -> std::io::Result<()> {
+        self.zip.start_file(path, self.options)?;
+        self.zip.write_all(bytes)?;
+        self.files.push(ExportFile {
+            path: path.to_string(),
+            size: bytes.len() as u64,
+            sha256: sha256_hex(bytes),
+        });
+        Ok(())
+    }
+}
+
+/// Deflated entries stamped with the export time, in UTC since zip times have no zone.
+fn file_options(at: i64) -> SimpleFileOptions {
+    let (days, ms) = (at.div_euclid(86_400_000), at.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    let seconds = ms / 1000;
+    let modified = zip::DateTime::from_date_and_time(
+        year as u16,
+        month as u8,
+        day as u8,
+        (seconds / 3600) as u8,
+        (seconds / 60 % 60) as u8,
+        (seconds % 60) as u8,
+    )
+    .unwrap_or_default();
+    SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(modified)
+}
+
+/// Files in a note's attachment directory, by name; none when it has no directory.
+fn attachments(dir: &Path) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            ));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `buildMarkdown` in `workspace-export.ts`.
+fn markdown_of(note: &NoteDocument) -> String {
+    format!("# {}\n\n{}", note.meta.title, note.markdown)
+}
+
+/// `safeFilename` in `workspace-export.ts`: lower-case ASCII words joined by `-`.
+pub fn safe_filename(title: &str, fallback: &str) -> String {
+    let base = if title.trim().is_empty() {
+        fallback
+    } else {
+        title.trim()
+    };
+    let name: String = base
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .chars()
+        .filter(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-' || *c == '_')
+        .take(MAX_FILENAME_CHARS)
+        .collect();
+    if name.is_empty() {
+        fallback.to_string()
+    } else {
+        name
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// `2024-03-09T17:05:00.250Z` for a Unix time in milliseconds.
+pub fn iso8601(ms: i64) -> String {
+    let (days, ms) = (ms.div_euclid(86_400_000), ms.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// The inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    debug_assert_eq!(days_from_civil(year, month, day), days - 719_468);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_times_and_file_names_like_the_webview() {
+        assert_eq!(iso8601(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(iso8601(1_709_999_100_250), "2024-03-09T15:45:00.250Z");
+        assert_eq!(
+            safe_filename("  Trip   Plan: Day 1! ", "id"),
+            "trip-plan-day-1"
+        );
+        assert_eq!(safe_filename("日本語", "note-1"), "note-1");
+    }
+
+    #[test]
+    fn exports_markdown_and_containers_with_checksums() {
+        let dir = std::env::temp_dir().join(format!("hn-export-{}", uuid::Uuid::new_v4()));
+        let store = NoteStore::open(dir.join("data")).unwrap();
+        let kept = store.create().unwrap();
+        store.append_text(&kept.id, "Groceries\nmilk").unwrap();
+        store
+            .save_attachment(&kept.id, "list.txt", b"eggs")
+            .unwrap();
+        let trashed = store.create().unwrap();
+        store.append_text(&trashed.id, "Groceries").unwrap();
+        store.move_to_trash(&trashed.id).unwrap();
+
+        let dest = dir.join("out/export.zip");
+        let manifest = export_workspace(&store, &dest, &ExportOptions::default()).unwrap();
+        assert_eq!(manifest.workspace_id, DEFAULT_WORKSPACE_ID);
+        assert_eq!(manifest.note_count, 1);
+        let paths: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        let container = format!("yjs/{}.yjs", kept.id);
+        let attachment = format!("attachments/{}/list.txt", kept.id);
+        assert_eq!(
+            paths,
+            [
+                "markdown/groceries.md",
+                container.as_str(),
+                attachment.as_str()
+            ]
+        );
+
+        let mut zip = zip::ZipArchive::new(File::open(&dest).unwrap()).unwrap();
+        let mut read = |path: &str| {
+            let mut bytes = Vec::new();
+            zip.by_name(path).unwrap().read_to_end(&mut bytes).unwrap();
+            bytes
+        };
+        for file in &manifest.files {
+            assert_eq!(sha256_hex(&read(&file.path)), file.sha256);
+        }
+        assert_eq!(
+            read("markdown/groceries.md"),
+            b"# Groceries\n\nGroceries\nmilk"
+        );
+        let decoded = container::decode(&read(&container)).unwrap();
+        assert_eq!(decoded.yjs_state, store.state(&kept.id).unwrap());
+        let written: ExportManifest = serde_json::from_slice(&read(MANIFEST_PATH)).unwrap();
+        assert_eq!(written, manifest);
+
+        let options = ExportOptions {
+            include_trash: true,
+            ..Default::default()
+        };
+        let manifest = export_workspace(&store, &dest, &options).unwrap();
+        assert_eq!(manifest.note_count, 2);
+        assert!(manifest
+            .files
+            .iter()
+            .any(|file| file.path == format!("yjs/trash/{}.yjs", trashed.id)));
+        assert!(!dir.join("out/export.zip.partial").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
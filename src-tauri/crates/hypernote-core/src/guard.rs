@@ -0,0 +1,237 @@
+//! Admission control for inbound peer connections: a global peer cap, a per-address
+//! connection cap, per-peer frame rate limits and temporary bans for addresses that
+//! flood either.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+use crate::contracts::PeerBan;
+use crate::limits::Limits;
+
+/// Refused connection attempts from one address within [`STRIKE_WINDOW_MS`] that get it
+/// banned.
+pub const BAN_STRIKES: u32 = 20;
+pub const STRIKE_WINDOW_MS: i64 = 60_000;
+
+/// Why [`PeerGuard::admit`] turned a connection away.
+#[derive(Debug, PartialEq)]
+pub enum Refusal {
+    /// The address is serving a ban.
+    Banned,
+    /// Over a cap; the reason is for the log.
+    Refused(String),
+    /// Over a cap once too often; the address is now banned.
+    NewBan(PeerBan),
+}
+
+#[derive(Default)]
+pub struct PeerGuard {
+    /// Open inbound connections per address, handshakes in progress included.
+    connections: HashMap<IpAddr, usize>,
+    /// Refused attempts per address and when their window started.
+    strikes: HashMap<IpAddr, (u32, i64)>,
+    bans: HashMap<IpAddr, PeerBan>,
+}
+
+impl PeerGuard {
+    /// Counts a new inbound connection from `ip`, `peers` being how many peers are
+    /// connected now. Every `Ok` must be paired with a [`PeerGuard::release`].
+    pub fn admit(
+        &mut self,
+        ip: IpAddr,
+        peers: usize,
+        limits: &Limits,
+        now: i64,
+    ) -> Result<(), Refusal> {
+        self.prune(now);
+        if self.banned(ip, now) {
+            return Err(Refusal::Banned);
+        }
+
+        let open = self.connections.get(&ip).copied().unwrap_or(0);
+        let refused = if peers >= limits.max_peers {
+            format!("peer limit reached ({})", limits.max_peers)
+        } else if open >= limits.max_connections_per_ip {
+            format!("too many connections from {ip} ({open})")
+        } else {
+            *self.connections.entry(ip).or_default() += 1;
+            return Ok(());
+        };
+
+        let strikes = self.strikes.entry(ip).or_insert((0, now));
+        if now - strikes.1 > STRIKE_WINDOW_MS {
+            *strikes = (0, now);
+        }
+        strikes.0 += 1;
+        if strikes.0 >= BAN_STRIKES {
+            let reason = format!("{BAN_STRIKES} refused connections within a minute");
+            return Err(Refusal::NewBan(self.ban(ip, &reason, limits, now)));
+        }
+        Err(Refusal::Refused(refused))
+    }
+
+    pub fn release(&mut self, ip: IpAddr) {
+        if let Some(open) = self.connections.get_mut(&ip) {
+            *open -= 1;
+            if *open == 0 {
+                self.connections.remove(&ip);
+            }
+        }
+    }
+
+    /// Refuses `ip` for [`Limits::ban_duration`] from `now`.
+    pub fn ban(&mut self, ip: IpAddr, reason: &str, limits: &Limits, now: i64) -> PeerBan {
+        self.strikes.remove(&ip);
+        let ban = PeerBan {
+            addr: ip.to_string(),
+            reason: reason.to_string(),
+            banned_at: now,
+            until: now + limits.ban_duration.as_millis() as i64,
+        };
+        self.bans.insert(ip, ban.clone());
+        ban
+    }
+
+    /// Bans still in force at `now`, soonest to expire first.
+    pub fn bans(&mut self, now: i64) -> Vec<PeerBan> {
+        self.prune(now);
+        let mut bans: Vec<PeerBan> = self.bans.values().cloned().collect();
+        bans.sort_by_key(|ban| ban.until);
+        bans
+    }
+
+    /// Forgets strike windows and bans that are over at `now`, so addresses that tried once
+    /// don't pile up.
+    fn prune(&mut self, now: i64) {
+        self.strikes
+            .retain(|_, (_, started)| now - *started <= STRIKE_WINDOW_MS);
+        self.bans.retain(|_, ban| ban.until > now);
+    }
+
+    fn banned(&mut self, ip: IpAddr, now: i64) -> bool {
+        match self.bans.get(&ip) {
+            Some(ban) if ban.until > now => true,
+            Some(_) => {
+                self.bans.remove(&ip);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// Token bucket over one peer's incoming frames.
+pub struct FrameRate {
+    tokens: f64,
+    last: Instant,
+}
+
+impl FrameRate {
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            tokens: f64::from(limits.frame_burst),
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token for one frame received at `now`; `false` once the bucket is empty.
+    pub fn allow(&mut self, limits: &Limits, now: Instant) -> bool {
+        let refill = now.saturating_duration_since(self.last).as_secs_f64()
+            * f64::from(limits.max_frames_per_second);
+        self.tokens = (self.tokens + refill).min(f64::from(limits.frame_burst));
+        self.last = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn caps_connections_and_bans_addresses_that_keep_trying() {
+        let limits = Limits {
+            max_connections_per_ip: 2,
+            ..Limits::default()
+        };
+        let ip: IpAddr = "192.168.1.20".parse().unwrap();
+        let other: IpAddr = "192.168.1.21".parse().unwrap();
+        let mut guard = PeerGuard::default();
+
+        assert_eq!(guard.admit(ip, 0, &limits, 0), Ok(()));
+        assert_eq!(guard.admit(ip, 1, &limits, 0), Ok(()));
+        assert!(matches!(
+            guard.admit(ip, 2, &limits, 0),
+            Err(Refusal::Refused(_))
+        ));
+        assert!(matches!(
+            guard.admit(other, limits.max_peers, &limits, 0),
+            Err(Refusal::Refused(_))
+        ));
+        guard.release(ip);
+        assert_eq!(guard.admit(ip, 1, &limits, 0), Ok(()));
+
+        for _ in 1..BAN_STRIKES - 1 {
+            assert!(matches!(
+                guard.admit(ip, 2, &limits, 1),
+                Err(Refusal::Refused(_))
+            ));
+        }
+        let Err(Refusal::NewBan(ban)) = guard.admit(ip, 2, &limits, 1) else {
+            panic!("expected a ban");
+        };
+        assert_eq!(ban.addr, "192.168.1.20");
+        guard.release(ip);
+        assert_eq!(guard.admit(ip, 0, &limits, 2), Err(Refusal::Banned));
+        assert_eq!(guard.bans(2).len(), 1);
+
+        assert_eq!(guard.admit(ip, 0, &limits, ban.until), Ok(()));
+        assert!(guard.bans(ban.until).is_empty());
+    }
+
+    #[test]
+    fn forgets_strikes_once_their_window_is_over() {
+        let limits = Limits {
+            max_peers: 0,
+            ..Limits::default()
+        };
+        let mut guard = PeerGuard::default();
+        for last in 0..=255 {
+            let ip = IpAddr::from([10, 0, 0, last]);
+            assert!(guard.admit(ip, 0, &limits, 0).is_err());
+        }
+        assert_eq!(guard.strikes.len(), 256);
+
+        guard.bans(STRIKE_WINDOW_MS + 1);
+        assert!(guard.strikes.is_empty());
+        let (first, second) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+        let later = STRIKE_WINDOW_MS + 1;
+        assert!(guard.admit(first, 0, &limits, later).is_err());
+        assert_eq!(guard.strikes.len(), 1);
+        let much_later = later + STRIKE_WINDOW_MS + 1;
+        assert!(guard.admit(second, 0, &limits, much_later).is_err());
+        assert_eq!(guard.strikes.len(), 1);
+    }
+
+    #[test]
+    fn frame_rate_allows_a_burst_then_the_sustained_rate() {
+        let limits = Limits {
+            max_frames_per_second: 10,
+            frame_burst: 5,
+            ..Limits::default()
+        };
+        let start = Instant::now();
+        let mut rate = FrameRate::new(&limits);
+        assert!((0..5).all(|_| rate.allow(&limits, start)));
+        assert!(!rate.allow(&limits, start));
+        assert!(rate.allow(&limits, start + Duration::from_millis(100)));
+        assert!(!rate.allow(&limits, start + Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,313 @@
+//! Point-in-time versions of notes.
+//!
+//! When an edit arrives for a note whose newest version is at least
+//! [`SNAPSHOT_INTERVAL_MS`] older than its current state, that state is kept as a new
+//! version before the edit is merged: the note's full Yjs state in a `.yjs` container under
+//! `history/<note-id>/<version>.yjs`. The newest [`MAX_VERSIONS`] per note are kept.
+//!
+//! Restoring never rewinds the document. [`restore_update`] edits the current content to
+//! match the version, as a CRDT update that reaches peers like any other edit.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use hypernote_protocol::is_valid_workspace_id;
+use yrs::updates::decoder::Decode;
+use yrs::{GetString, ReadTxn, Text, Transact, Update};
+
+use crate::container;
+use crate::contracts::{NoteMeta, NoteVersion};
+use crate::store::{container_paths, write_atomic, CONTAINER_EXTENSION, CONTENT_TEXT};
+
+pub const HISTORY_DIR: &str = "history";
+/// Minimum time between two versions of a note, measured by its `updatedAt`.
+pub const SNAPSHOT_INTERVAL_MS: i64 = 5 * 60 * 1000;
+pub const MAX_VERSIONS: usize = 100;
+
+struct Version {
+    info: NoteVersion,
+    /// Kept in memory only when there is no directory to write to.
+    state: Option<(NoteMeta, Vec<u8>)>,
+}
+
+pub struct History {
+    dir: Option<PathBuf>,
+    /// Reads versions another process keeps but never adds any.
+    read_only: bool,
+    /// Versions per note, oldest first; read from disk on first use.
+    notes: Mutex<HashMap<String, Vec<Version>>>,
+}
+
+impl History {
+    pub fn in_memory() -> Self {
+        Self {
+            dir: None,
+            read_only: false,
+            notes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// History kept under `dir`, normally `<data-dir>/history`.
+    pub fn open(dir: PathBuf) -> Self {
+        Self {
+            dir: Some(dir),
+            read_only: false,
+            notes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The versions under `dir` as written by the process that owns it; checkpoints are
+    /// skipped, so version numbers never collide with the owner's.
+    pub fn mirror(dir: PathBuf) -> Self {
+        Self {
+            read_only: true,
+            ..Self::open(dir)
+        }
+    }
+
+    /// Versions of a note, newest first.
+    pub fn list(&self, note_id: &str) -> Vec<NoteVersion> {
+        let Ok(mut notes) = self.notes.lock() else {
+            return Vec::new();
+        };
+        self.versions(&mut notes, note_id)
+            .iter()
+            .rev()
+            .map(|version| version.info.clone())
+            .collect()
+    }
+
+    /// Metadata and Yjs state of one version.
+    pub fn get(&self, note_id: &str, version: u32) -> Result<(NoteMeta, Vec<u8>), String> {
+        let mut notes = self.notes.lock().map_err(|_| "history poisoned")?;
+        let found = self
+            .versions(&mut notes, note_id)
+            .iter()
+            .find(|found| found.info.version == version)
+            .ok_or("version not found")?;
+        self.read(note_id, found)
+    }
+
+    /// Keeps `yjs_state` as a new version of `meta.id` if the newest one is older than
+    /// [`SNAPSHOT_INTERVAL_MS`] or, with `force`, if its content differs from the newest.
+    ///
+    /// Returns the new version, if one was kept.
+    pub fn checkpoint(
+        &self,
+        meta: &NoteMeta,
+        yjs_state: &[u8],
+        force: bool,
+    ) -> Result<Option<NoteVersion>, String> {
+        if self.read_only || yjs_state.is_empty() || !is_valid_workspace_id(&meta.id) {
+            return Ok(None);
+        }
+
+        let mut notes = self.notes.lock().map_err(|_| "history poisoned")?;
+        let versions = self.versions(&mut notes, &meta.id);
+        if let Some(newest) = versions.last() {
+            let unchanged = || match self.read(&meta.id, newest) {
+                Ok((_, newest_state)) => same_state(&newest_state, yjs_state),
+                Err(_) => false,
+            };
+            let recent = meta.updated_at - newest.info.saved_at < SNAPSHOT_INTERVAL_MS;
+            if if force { unchanged() } else { recent } {
+                return Ok(None);
+            }
+        }
+
+        let info = NoteVersion {
+            version: versions.last().map_or(1, |newest| newest.info.version + 1),
+            saved_at: meta.updated_at,
+            title: meta.title.clone(),
+        };
+        let state = match self.version_path(&meta.id, info.version) {
+            Some(path) => {
+                write_atomic(&path, &container::encode(meta, yjs_state))
+                    .map_err(|e| format!("failed to save version: {e}"))?;
+                None
+            }
+            None => Some((meta.clone(), yjs_state.to_vec())),
+        };
+        versions.push(Version {
+            info: info.clone(),
+            state,
+        });
+
+        let excess = versions.len().saturating_sub(MAX_VERSIONS);
+        for dropped in versions.drain(..excess) {
+            if let Some(path) = self.version_path(&meta.id, dropped.info.version) {
+                let _ = fs::remove_file(path);
+            }
+        }
+        Ok(Some(info))
+    }
+
+    fn read(&self, note_id: &str, version: &Version) -> Result<(NoteMeta, Vec<u8>), String> {
+        if let Some(state) = &version.state {
+            return Ok(state.clone());
+        }
+
+        let path = self
+            .version_path(note_id, version.info.version)
+            .ok_or("version not found")?;
+        let bytes = fs::read(&path).map_err(|e| format!("failed to read version: {e}"))?;
+        let decoded = container::decode(&bytes)?;
+        Ok((decoded.meta, decoded.yjs_state))
+    }
+
+    fn versions<'a>(
+        &self,
+        notes: &'a mut HashMap<String, Vec<Version>>,
+        note_id: &str,
+    ) -> &'a mut Vec<Version> {
+        notes
+            .entry(note_id.to_string())
+            .or_insert_with(|| self.read_versions(note_id))
+    }
+
+    fn read_versions(&self, note_id: &str) -> Vec<Version> {
+        let Some(dir) = self.note_dir(note_id) else {
+            return Vec::new();
+        };
+        let mut versions: Vec<Version> = container_paths(&dir)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|path| {
+                let version = path.file_stem()?.to_str()?.parse().ok()?;
+                let meta = container::decode_meta(&fs::read(&path).ok()?).ok()?;
+                Some(Version {
+                    info: NoteVersion {
+                        version,
+                        saved_at: meta.updated_at,
+                        title: meta.title,
+                    },
+                    state: None,
+                })
+            })
+            .collect();
+        versions.sort_by_key(|version| version.info.version);
+        versions
+    }
+
+    fn note_dir(&self, note_id: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        is_valid_workspace_id(note_id).then(|| dir.join(note_id))
+    }
+
+    fn version_path(&self, note_id: &str, version: u32) -> Option<PathBuf> {
+        self.note_dir(note_id)
+            .map(|dir| dir.join(format!("{version}.{CONTAINER_EXTENSION}")))
+    }
+}
+
+/// Whether two states hold the same changes, however they were encoded.
+fn same_state(a: &[u8], b: &[u8]) -> bool {
+    let vector = yrs::encode_state_vector_from_update_v1;
+    matches!((vector(a), vector(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// An update that turns the content of `yjs_state` into `text`.
+///
+/// Only the span between the common prefix and suffix is replaced, by a fresh Yjs client,
+/// so edits peers make meanwhile outside that span survive.
+pub fn restore_update(yjs_state: &[u8], text: &str) -> Result<Vec<u8>, String> {
+    let doc = yrs::Doc::new();
+    let content = doc.get_or_insert_text(CONTENT_TEXT);
+    let mut txn = doc.transact_mut();
+    if !yjs_state.is_empty() {
+        let update = Update::decode_v1(yjs_state).map_err(|e| format!("undecodable state: {e}"))?;
+        txn.apply_update(update)
+            .map_err(|e| format!("undecodable state: {e}"))?;
+    }
+
+    let current = content.get_string(&txn);
+    let prefix: usize = current
+        .chars()
+        .zip(text.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let suffix: usize = current[prefix..]
+        .chars()
+        .rev()
+        .zip(text[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+
+    // Offsets are UTF-8 bytes, the default for a `yrs::Doc`.
+    let before = txn.state_vector();
+    let removed = current.len() - prefix - suffix;
+    if removed > 0 {
+        content.remove_range(&mut txn, prefix as u32, removed as u32);
+    }
+    let inserted = &text[prefix..text.len() - suffix];
+    if !inserted.is_empty() {
+        content.insert(&mut txn, prefix as u32, inserted);
+    }
+    Ok(txn.encode_diff_v1(&before))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{append_update, markdown_of};
+
+    fn meta(updated_at: i64) -> NoteMeta {
+        NoteMeta {
+            id: "note-1".to_string(),
+            title: format!("at {updated_at}"),
+            updated_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_versions_at_most_once_per_interval() {
+        let history = History::in_memory();
+        let kept = |updated_at, state: &[u8], force| {
+            let kept = history.checkpoint(&meta(updated_at), state, force).unwrap();
+            kept.map(|version| version.version)
+        };
+        let state = append_update(&[], "draft").unwrap();
+        let edited = [state.as_slice(), &append_update(&state, "!").unwrap()];
+        let edited = yrs::merge_updates_v1(edited).unwrap();
+
+        assert_eq!(kept(1, &state, false), Some(1));
+        assert_eq!(kept(2, &edited, false), None);
+        assert_eq!(kept(2, &state, true), None, "nothing changed");
+        assert_eq!(kept(2, &edited, true), Some(2));
+        assert_eq!(kept(2 + SNAPSHOT_INTERVAL_MS, &edited, false), Some(3));
+
+        let listed = history.list("note-1");
+        assert_eq!(listed.len(), 3);
+        assert_eq!(listed[0].version, 3);
+        assert_eq!(history.get("note-1", 1).unwrap().0.title, "at 1");
+        assert!(history.get("note-1", 9).is_err());
+    }
+
+    #[test]
+    fn restores_content_while_keeping_concurrent_edits() {
+        let old = append_update(&[], "Plan: café").unwrap();
+        let current =
+            yrs::merge_updates_v1([old.as_slice(), &append_update(&old, " and lunch").unwrap()])
+                .unwrap();
+        let restore = restore_update(&current, "Plan: café").unwrap();
+        // Someone else prepends meanwhile, without having seen the restore.
+        let concurrent = yrs::Doc::new();
+        let prepend = {
+            let text = concurrent.get_or_insert_text(CONTENT_TEXT);
+            let mut txn = concurrent.transact_mut();
+            txn.apply_update(Update::decode_v1(&current).unwrap())
+                .unwrap();
+            let before = txn.state_vector();
+            text.insert(&mut txn, 0, "> ");
+            txn.encode_diff_v1(&before)
+        };
+
+        let merged = yrs::merge_updates_v1([current.as_slice(), &restore, &prepend]).unwrap();
+        assert_eq!(markdown_of(&merged), "> Plan: café");
+    }
+}
@@ -0,0 +1,186 @@
+//! Bear exports: TextBundles, either as `.textbundle` directories or zipped as `.textpack`
+//! files. Each holds the note as `text.md` (or `.markdown`, `.txt`) with its files under
+//! `assets/`. Bear's tags are inline, including the `#multi word#` form.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{
+    draft_from_markdown, file_times, inline_tags, map_links, percent_decode, read_file, read_zip,
+    ImportFailure, NoteDraft, MAX_FILE_BYTES, MAX_TEXT_BYTES,
+};
+
+const TEXT_FILES: [&str; 3] = ["text.md", "text.markdown", "text.txt"];
+const ASSETS_DIR: &str = "assets";
+
+/// Drafts for the bundle at `path`, or for every bundle found under a directory.
+pub fn scan_export(path: &Path) -> (Vec<(PathBuf, NoteDraft)>, Vec<ImportFailure>) {
+    let mut bundles = Vec::new();
+    if let Err(e) = find_bundles(path, &mut bundles) {
+        let failure = ImportFailure {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        };
+        return (Vec::new(), vec![failure]);
+    }
+    if bundles.is_empty() {
+        let failure = ImportFailure {
+            path: path.display().to_string(),
+            reason: "no .textbundle or .textpack found".to_string(),
+        };
+        return (Vec::new(), vec![failure]);
+    }
+
+    let mut drafts = Vec::new();
+    let mut failures = Vec::new();
+    for bundle in bundles {
+        let draft = match bundle_kind(&bundle) {
+            Some(Kind::Pack) => read_pack(&bundle),
+            _ => read_bundle_dir(&bundle),
+        };
+        match draft {
+            Ok(draft) => drafts.push((bundle, draft)),
+            Err(reason) => failures.push(ImportFailure {
+                path: bundle.display().to_string(),
+                reason,
+            }),
+        }
+    }
+    (drafts, failures)
+}
+
+enum Kind {
+    Bundle,
+    Pack,
+}
+
+fn bundle_kind(path: &Path) -> Option<Kind> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "textbundle" => Some(Kind::Bundle),
+        "textpack" => Some(Kind::Pack),
+        _ => None,
+    }
+}
+
+fn find_bundles(path: &Path, found: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if bundle_kind(path).is_some() {
+        found.push(path.to_path_buf());
+        return Ok(());
+    }
+    if !fs::metadata(path)?.is_dir() {
+        return Ok(());
+    }
+    let mut entries: Vec<_> = fs::read_dir(path)?.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if !entry.file_name().to_string_lossy().starts_with('.') {
+            find_bundles(&entry.path(), found)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_bundle_dir(dir: &Path) -> Result<NoteDraft, String> {
+    let text_path = TEXT_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or("bundle has no text file")?;
+    let mut files = HashMap::new();
+    files.insert("text".to_string(), read_file(&text_path, MAX_TEXT_BYTES)?);
+    if let Ok(assets) = fs::read_dir(dir.join(ASSETS_DIR)) {
+        for asset in assets.filter_map(Result::ok) {
+            let name = asset.file_name().to_string_lossy().into_owned();
+            let bytes = read_file(&asset.path(), MAX_FILE_BYTES)
+                .map_err(|reason| format!("{name}: {reason}"))?;
+            files.insert(format!("{ASSETS_DIR}/{name}"), bytes);
+        }
+    }
+    let (created_at, updated_at) = file_times(&text_path)?;
+    bundle_draft(files, created_at, updated_at)
+}
+
+fn read_pack(path: &Path) -> Result<NoteDraft, String> {
+    let mut files = HashMap::new();
+    let mut modified = 0;
+    for entry in read_zip(path)? {
+        // Entries sit under `<name>.textbundle/`.
+        let inner = match entry.path.split_once(".textbundle/") {
+            Some((_, inner)) => inner.to_string(),
+            None => entry.path.clone(),
+        };
+        if TEXT_FILES.contains(&inner.as_str()) {
+            modified = entry.modified;
+            files.insert("text".to_string(), entry.bytes);
+        } else if inner.starts_with(&format!("{ASSETS_DIR}/")) {
+            files.insert(inner, entry.bytes);
+        }
+    }
+    bundle_draft(files, modified, modified)
+}
+
+/// `files` holds the text under `text` and assets under `assets/<name>`.
+fn bundle_draft(
+    mut files: HashMap<String, Vec<u8>>,
+    created_at: i64,
+    updated_at: i64,
+) -> Result<NoteDraft, String> {
+    let text = files.remove("text").ok_or("bundle has no text file")?;
+    let text = String::from_utf8(text).map_err(|_| "not UTF-8 text".to_string())?;
+    let mut draft = NoteDraft {
+        created_at,
+        updated_at,
+        ..draft_from_markdown(&text)
+    }
+    .with_new_id();
+    for tag in inline_tags(&draft.markdown, true) {
+        if !draft.tags.contains(&tag) {
+            draft.tags.push(tag);
+        }
+    }
+
+    let body = std::mem::take(&mut draft.markdown);
+    draft.markdown = map_links(&body, |link| {
+        let target = percent_decode(link.dest);
+        let bytes = files.get(&target)?.clone();
+        let name = target.rsplit('/').next().unwrap_or(&target);
+        let attached = draft.attach(name, bytes);
+        let bang = if link.image { "!" } else { "" };
+        Some(format!("{bang}[{}]({attached})", link.text))
+    });
+    Ok(draft)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_textbundles_with_assets_and_bear_tags() {
+        let root = std::env::temp_dir().join(format!("hn-bear-{}", uuid::Uuid::new_v4()));
+        let bundle = root.join("Recipes.textbundle");
+        fs::create_dir_all(bundle.join("assets")).unwrap();
+        fs::write(bundle.join("info.json"), "{}").unwrap();
+        fs::write(bundle.join("assets/soup.jpg"), "jpg").unwrap();
+        fs::write(
+            bundle.join("text.md"),
+            "# Soup\n#food/dinner #family recipes# ![](assets/soup.jpg)\n",
+        )
+        .unwrap();
+        fs::write(root.join("notes.txt"), "not a bundle").unwrap();
+
+        let (drafts, failures) = scan_export(&root);
+        assert!(failures.is_empty());
+        let (path, draft) = &drafts[0];
+        let id = draft.id.clone().unwrap();
+        assert_eq!(path, &bundle);
+        assert_eq!(draft.title.as_deref(), Some("Soup"));
+        assert_eq!(draft.tags, ["food/dinner", "family recipes"]);
+        assert!(draft
+            .markdown
+            .ends_with(&format!("![](attachments/{id}/soup.jpg)\n")));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
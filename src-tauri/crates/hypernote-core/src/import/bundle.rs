@@ -0,0 +1,223 @@
+//! Workspace exports read back (see [`crate::export`]). Each note's `.yjs` container is
+//! merged into the note of the same id the way a peer's update would be, so edits made
+//! on either side survive. The Markdown copies are skipped and attachments are restored
+//! where the note has none of that name yet.
+
+use std::path::Path;
+
+use crate::container;
+use crate::contracts::{BundleConflict, BundleImportReport, ExportFile, ImportFailure, NoteMeta};
+use crate::export::{read_bundle, CONTAINERS_DIR};
+use crate::store::{compare_states, NoteStore, ATTACHMENTS_DIR, CONTAINER_EXTENSION, TRASH_DIR};
+
+/// Restores the export at `path`. Files failing their checksum are reported and skipped;
+/// `restore` merges a container's state into the store and shares it.
+pub fn import_bundle(
+    store: &NoteStore,
+    path: &Path,
+    mut restore: impl FnMut(&NoteMeta, &[u8]) -> Result<NoteMeta, String>,
+) -> Result<BundleImportReport, String> {
+    let mut report = BundleImportReport::default();
+    let mut containers = 0;
+    let manifest = read_bundle(path, |file, bytes| {
+        let restored = match entry_of(&file.path) {
+            Some(Entry::Container(note_id)) => {
+                containers += 1;
+                bytes.and_then(|bytes| {
+                    restore_note(store, note_id, &bytes, &mut restore, &mut report)
+                })
+            }
+            Some(Entry::Attachment(note_id, name)) => {
+                bytes.and_then(|bytes| restore_attachment(store, note_id, name, &bytes))
+            }
+            None => return,
+        };
+        if let Err(reason) = restored {
+            report.failures.push(failure(path, file, reason));
+        }
+    })?;
+    if containers == 0 {
+        return Err("the archive has no .yjs containers to restore notes from".to_string());
+    }
+    report.workspace_id = manifest.workspace_id;
+    report.exported_at = manifest.exported_at;
+    Ok(report)
+}
+
+enum Entry<'a> {
+    Container(&'a str),
+    Attachment(&'a str, &'a str),
+}
+
+/// `yjs/[trash/]<id>.yjs` and `attachments/<id>/<name>`.
+fn entry_of(path: &str) -> Option<Entry<'_>> {
+    if let Some(rest) = path.strip_prefix(&format!("{CONTAINERS_DIR}/")) {
+        let file = rest.strip_prefix(&format!("{TRASH_DIR}/")).unwrap_or(rest);
+        let note_id = file.strip_suffix(&format!(".{CONTAINER_EXTENSION}"))?;
+        return (!note_id.contains('/')).then_some(Entry::Container(note_id));
+    }
+    let rest = path.strip_prefix(&format!("{ATTACHMENTS_DIR}/"))?;
+    let (note_id, name) = rest.split_once('/')?;
+    Some(Entry::Attachment(note_id, name))
+}
+
+fn restore_note(
+    store: &NoteStore,
+    note_id: &str,
+    bytes: &[u8],
+    restore: &mut impl FnMut(&NoteMeta, &[u8]) -> Result<NoteMeta, String>,
+    report: &mut BundleImportReport,
+) -> Result<(), String> {
+    let decoded = container::decode(bytes)?;
+    if decoded.meta.id != note_id {
+        return Err(format!("container holds note {}", decoded.meta.id));
+    }
+    let before = store.meta(note_id);
+    let (incoming, local) = match (&before, store.state(note_id)) {
+        (Some(_), Some(state)) => compare_states(&state, &decoded.yjs_state)?,
+        _ => (true, false),
+    };
+    if !incoming {
+        report.unchanged.push(note_id.to_string());
+        return Ok(());
+    }
+
+    let meta = restore(&decoded.meta, &decoded.yjs_state)?;
+    let Some(before) = before else {
+        report.created.push(note_id.to_string());
+        return Ok(());
+    };
+    report.merged.push(note_id.to_string());
+    if local {
+        report.conflicts.push(BundleConflict {
+            note_id: note_id.to_string(),
+            reason: conflict_reason(&before, &decoded.meta, &meta),
+            title: meta.title,
+        });
+    }
+    Ok(())
+}
+
+fn conflict_reason(local: &NoteMeta, bundled: &NoteMeta, merged: &NoteMeta) -> String {
+    let mut reason = "edited here and in the archive; both sets of changes were kept".to_string();
+    if local.title != bundled.title {
+        reason.push_str(&format!(
+            "; titled \"{}\" here and \"{}\" in the archive",
+            local.title, bundled.title
+        ));
+    }
+    if local.deleted_at.is_some() != bundled.deleted_at.is_some() {
+        let now = match merged.deleted_at {
+            Some(_) => "now in the trash",
+            None => "now restored",
+        };
+        reason.push_str(&format!("; trashed on one side only, {now}"));
+    }
+    reason
+}
+
+fn restore_attachment(
+    store: &NoteStore,
+    note_id: &str,
+    name: &str,
+    bytes: &[u8],
+) -> Result<(), String> {
+    let exists = store
+        .data_dir()
+        .is_some_and(|dir| dir.join(ATTACHMENTS_DIR).join(note_id).join(name).exists());
+    match exists {
+        true => Ok(()),
+        false => store.save_attachment(note_id, name, bytes),
+    }
+}
+
+fn failure(path: &Path, file: &ExportFile, reason: String) -> ImportFailure {
+    ImportFailure {
+        path: path.join(&file.path).display().to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use super::*;
+    use crate::export::{export_workspace, ExportOptions, MANIFEST_PATH};
+
+    #[test]
+    fn merges_bundled_notes_and_reports_conflicts() {
+        let dir = std::env::temp_dir().join(format!("hn-bundle-{}", uuid::Uuid::new_v4()));
+        let source = NoteStore::open(dir.join("source")).unwrap();
+        let shared = source.create().unwrap();
+        source.append_text(&shared.id, "Plan").unwrap();
+        let fresh = source.create().unwrap();
+        source
+            .append_text(&fresh.id, "Only in the archive")
+            .unwrap();
+
+        // The target has the shared note as it was before the export, then edits it.
+        let target = NoteStore::open(dir.join("target")).unwrap();
+        let state = source.state(&shared.id).unwrap();
+        target.apply_update(&shared.id, &state).unwrap();
+        let bundle = dir.join("export.zip");
+        source.append_text(&shared.id, "\nfrom the source").unwrap();
+        export_workspace(&source, &bundle, &ExportOptions::default()).unwrap();
+        target.append_text(&shared.id, "\nfrom the target").unwrap();
+
+        let restore = |meta: &NoteMeta, state: &[u8]| target.merge_backup(meta, state);
+        let report = import_bundle(&target, &bundle, restore).unwrap();
+        assert_eq!(report.created, [fresh.id.as_str()]);
+        assert_eq!(report.merged, [shared.id.as_str()]);
+        assert_eq!(report.conflicts[0].note_id, shared.id);
+        assert!(report.failures.is_empty());
+        let text = target.get(&shared.id).unwrap().markdown;
+        assert!(text.contains("from the source") && text.contains("from the target"));
+        let restored = target.meta(&fresh.id).unwrap();
+        assert_eq!(
+            restored.created_at,
+            source.meta(&fresh.id).unwrap().created_at
+        );
+
+        // Nothing new the second time round.
+        let restore = |meta: &NoteMeta, state: &[u8]| target.merge_backup(meta, state);
+        let report = import_bundle(&target, &bundle, restore).unwrap();
+        assert_eq!(report.unchanged.len(), 2);
+        assert!(report.merged.is_empty() && report.created.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_files_that_fail_their_checksum() {
+        let dir = std::env::temp_dir().join(format!("hn-bundle-{}", uuid::Uuid::new_v4()));
+        let source = NoteStore::open(dir.join("source")).unwrap();
+        let note = source.create().unwrap();
+        source.append_text(&note.id, "Plan").unwrap();
+        let manifest = export_workspace(&source, &dir.join("good.zip"), &Default::default());
+        let mut manifest = manifest.unwrap();
+
+        // Same files, but the manifest claims other contents for the container.
+        let container = format!("yjs/{}.yjs", note.id);
+        let bad = dir.join("bad.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&bad).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file(&container, options).unwrap();
+        zip.write_all(&container::encode(&note, &[0, 0])).unwrap();
+        manifest.files.retain(|file| file.path == container);
+        zip.start_file(MANIFEST_PATH, options).unwrap();
+        zip.write_all(&serde_json::to_vec(&manifest).unwrap())
+            .unwrap();
+        zip.finish().unwrap();
+
+        let target = NoteStore::in_memory();
+        let restore = |meta: &NoteMeta, state: &[u8]| target.merge_backup(meta, state);
+        let report = import_bundle(&target, &bad, restore).unwrap();
+        assert!(report.created.is_empty());
+        assert!(report.failures[0].path.ends_with(&container));
+        assert!(target.note_ids().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
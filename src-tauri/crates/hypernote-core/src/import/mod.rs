@@ -0,0 +1,821 @@
+//! Import: Markdown files and other apps' exports become notes.
+//!
+//! Each `.md` file becomes one [`NoteDraft`]: YAML front-matter is stripped and its
+//! `title` and `tags` kept, the title otherwise comes from the first heading, and the
+//! file's created/modified times carry over. Files inside a directory land in the note
+//! folder matching their path below it. The [`obsidian`], [`notion`] and [`bear`]
+//! importers build on this with each app's conventions; [`bundle`] restores HyperNote's own
+//! workspace exports.
+
+pub mod bear;
+pub mod bundle;
+pub mod notion;
+pub mod obsidian;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use crate::contracts::{ImportDuplicate, ImportFailure, ImportReport, ImportedNote, NoteMeta};
+use crate::limits::MAX_NOTE_BYTES;
+use crate::store::{title_from_text, NoteStore};
+
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+/// Largest text file read as a note; a note can't hold more.
+pub const MAX_TEXT_BYTES: usize = MAX_NOTE_BYTES;
+/// Largest single file read for an import, attachments and zip entries included.
+pub const MAX_FILE_BYTES: usize = 128 * 1024 * 1024;
+/// Largest uncompressed content read out of one zip, nested zips included.
+pub const MAX_ZIP_BYTES: usize = 1024 * 1024 * 1024;
+
+/// A note to be created, as read from some other format.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NoteDraft {
+    /// Explicit title; `None` derives it from the content like any other note.
+    pub title: Option<String>,
+    pub markdown: String,
+    pub tags: Vec<String>,
+    pub folder: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Id for the note; importers with attachments pick it up front, since `markdown`
+    /// refers to them under `attachments/<id>/`.
+    pub id: Option<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// A file a note refers to, saved next to the notes when the note is created.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Other apps' export formats, read by [`scan_export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportFormat {
+    /// An Obsidian vault directory.
+    Obsidian,
+    /// A Notion "Markdown & CSV" export zip.
+    Notion,
+    /// Bear `.textbundle` / `.textpack` exports, or a directory of them.
+    Bear,
+}
+
+/// Reads the export at `path` into drafts.
+pub fn scan_export(
+    format: ImportFormat,
+    path: &Path,
+) -> (Vec<(PathBuf, NoteDraft)>, Vec<ImportFailure>) {
+    match format {
+        ImportFormat::Obsidian => obsidian::scan_vault(path),
+        ImportFormat::Notion => notion::scan_export(path),
+        ImportFormat::Bear => bear::scan_export(path),
+    }
+}
+
+impl NoteDraft {
+    /// A fresh id, with `markdown` to be written against it.
+    pub fn with_new_id(mut self) -> Self {
+        self.id = Some(uuid::Uuid::new_v4().to_string());
+        self
+    }
+
+    /// Where an attachment named `name` is referenced from `markdown`.
+    pub fn attachment_link(&self, name: &str) -> String {
+        let id = self.id.as_deref().unwrap_or_default();
+        format!("attachments/{id}/{}", name.replace(' ', "%20"))
+    }
+
+    /// Adds `bytes` under a name not yet taken in this note and returns its link.
+    pub fn attach(&mut self, name: &str, bytes: Vec<u8>) -> String {
+        let name = unique_name(name, |taken| {
+            self.attachments
+                .iter()
+                .any(|attachment| attachment.name == taken)
+        });
+        let link = self.attachment_link(&name);
+        self.attachments.push(Attachment { name, bytes });
+        link
+    }
+
+    /// The title the note will show once created.
+    pub fn display_title(&self) -> String {
+        self.title
+            .clone()
+            .unwrap_or_else(|| title_from_text(&self.markdown))
+    }
+}
+
+/// Front-matter fields; scalar values are one-element lists.
+pub type FrontMatter = BTreeMap<String, Vec<String>>;
+
+/// Splits a leading `---` … `---` YAML block off `text`. Only flat `key: value`,
+/// `key: [a, b]` and `key:` followed by `- item` lines are understood; anything else in
+/// the block is dropped.
+pub fn split_front_matter(text: &str) -> (FrontMatter, &str) {
+    let mut fields = FrontMatter::new();
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return (fields, text);
+    };
+
+    let mut offset = 0;
+    let mut key: Option<String> = None;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end();
+        if line == "---" || line == "..." {
+            return (fields, rest[offset..].trim_start_matches(['\r', '\n']));
+        }
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            if let Some(values) = key.as_ref().and_then(|key| fields.get_mut(key)) {
+                values.push(unquote(item).to_string());
+            }
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let values = match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(list) => list
+                .split(',')
+                .map(|item| unquote(item.trim()).to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+            None if value.is_empty() => Vec::new(),
+            None => vec![unquote(value).to_string()],
+        };
+        fields.insert(name.clone(), values);
+        key = Some(name);
+    }
+
+    // No closing line: not front-matter after all.
+    (FrontMatter::new(), text)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+/// Draft for a Markdown document, without times or folder.
+pub fn draft_from_markdown(text: &str) -> NoteDraft {
+    let (front, body) = split_front_matter(text);
+    let title = front
+        .get("title")
+        .and_then(|values| values.first())
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .or_else(|| first_heading(body));
+    let tags = front
+        .get("tags")
+        .or_else(|| front.get("tag"))
+        .map(|values| {
+            values
+                .iter()
+                .flat_map(|value| value.split([',', ' ']))
+                .map(|tag| tag.trim_start_matches('#').to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    NoteDraft {
+        title,
+        markdown: body.to_string(),
+        tags,
+        ..Default::default()
+    }
+}
+
+/// Text of the first ATX heading, outside fenced code blocks.
+fn first_heading(text: &str) -> Option<String> {
+    let mut fenced = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with("```") || line.starts_with("~~~") {
+            fenced = !fenced;
+            continue;
+        }
+        if !fenced && line.starts_with('#') {
+            let title = title_from_text(line);
+            if title != title_from_text("") {
+                return Some(title);
+            }
+        }
+    }
+    None
+}
+
+/// Reads one Markdown file, taking its times from the file system.
+pub fn read_markdown_file(path: &Path, folder: Option<String>) -> Result<NoteDraft, String> {
+    let text = read_text(path)?;
+    let (created_at, updated_at) = file_times(path)?;
+    Ok(NoteDraft {
+        folder,
+        created_at,
+        updated_at,
+        ..draft_from_markdown(&text)
+    })
+}
+
+fn read_text(path: &Path) -> Result<String, String> {
+    let bytes = read_file(path, MAX_TEXT_BYTES)?;
+    String::from_utf8(bytes).map_err(|_| "not UTF-8 text".to_string())
+}
+
+/// The file at `path`, failing without reading past `limit` bytes when it is larger.
+fn read_file(path: &Path, limit: usize) -> Result<Vec<u8>, String> {
+    read_bounded(fs::File::open(path).map_err(|e| e.to_string())?, limit)
+}
+
+fn read_bounded(reader: impl Read, limit: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() > limit {
+        return Err(format!("larger than {} MB", limit / (1024 * 1024)));
+    }
+    Ok(bytes)
+}
+
+/// Creation and modification time of a file, in ms; creation falls back to modification
+/// where the file system doesn't record it.
+fn file_times(path: &Path) -> Result<(i64, i64), String> {
+    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+    let updated_at = metadata.modified().map(unix_ms).unwrap_or_default();
+    let created_at = metadata
+        .created()
+        .map(unix_ms)
+        .unwrap_or(updated_at)
+        .min(updated_at);
+    Ok((created_at, updated_at))
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| MARKDOWN_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Every Markdown file under `root`, sorted, with the folder its path below `root` maps
+/// to. Hidden entries (`.git`, `.obsidian`) and symlinked directories are skipped.
+pub fn markdown_files(root: &Path) -> Result<Vec<(PathBuf, Option<String>)>, String> {
+    files_under(root, &is_markdown)
+}
+
+/// Every file under `root` that `keep` accepts, walked as [`markdown_files`] does.
+fn files_under(
+    root: &Path,
+    keep: &dyn Fn(&Path) -> bool,
+) -> Result<Vec<(PathBuf, Option<String>)>, String> {
+    let mut found = Vec::new();
+    walk(root, root, keep, &mut found)
+        .map_err(|e| format!("cannot read {}: {e}", root.display()))?;
+    Ok(found)
+}
+
+fn walk(
+    root: &Path,
+    dir: &Path,
+    keep: &dyn Fn(&Path) -> bool,
+    found: &mut Vec<(PathBuf, Option<String>)>,
+) -> std::io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(root, &path, keep, found)?;
+        } else if keep(&path) {
+            found.push((path.clone(), folder_of(root, &path)));
+        }
+    }
+    Ok(())
+}
+
+/// `root/work/clients/a.md` → `work/clients`.
+pub fn folder_of(root: &Path, path: &Path) -> Option<String> {
+    let parent = path.parent()?.strip_prefix(root).ok()?;
+    let folder = parent
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    crate::organize::normalize_folder(&folder)
+}
+
+/// Reads the Markdown files and directories in `paths` into drafts. A file named
+/// directly goes to the top level; files found in a directory keep their subfolders.
+pub fn scan_markdown(paths: &[PathBuf]) -> (Vec<(PathBuf, NoteDraft)>, Vec<ImportFailure>) {
+    let mut drafts = Vec::new();
+    let mut failures = Vec::new();
+    let mut fail = |path: &Path, reason: String| {
+        failures.push(ImportFailure {
+            path: path.display().to_string(),
+            reason,
+        })
+    };
+
+    for path in paths {
+        let files = match fs::metadata(path) {
+            Err(e) => {
+                fail(path, e.to_string());
+                continue;
+            }
+            Ok(metadata) if metadata.is_dir() => match markdown_files(path) {
+                Ok(files) => files,
+                Err(reason) => {
+                    fail(path, reason);
+                    continue;
+                }
+            },
+            Ok(_) if is_markdown(path) => vec![(path.clone(), None)],
+            Ok(_) => {
+                fail(path, "not a Markdown file".to_string());
+                continue;
+            }
+        };
+        for (file, folder) in files {
+            match read_markdown_file(&file, folder) {
+                Ok(draft) => drafts.push((file, draft)),
+                Err(reason) => fail(&file, reason),
+            }
+        }
+    }
+
+    (drafts, failures)
+}
+
+/// An existing note with the same title and text as `draft`.
+pub fn find_duplicate(store: &NoteStore, draft: &NoteDraft) -> Option<String> {
+    let title = draft.display_title();
+    let text = draft.markdown.trim();
+    store
+        .list()
+        .into_iter()
+        .filter(|meta| meta.title == title)
+        .find(|meta| {
+            store
+                .get(&meta.id)
+                .is_some_and(|note| note.markdown.trim() == text)
+        })
+        .map(|meta| meta.id)
+}
+
+/// Creates a note for each draft with `create`, skipping those that duplicate a note
+/// already in `store` or an earlier draft. A dry run creates nothing and reports what
+/// would have been created.
+pub fn import_drafts(
+    store: &NoteStore,
+    drafts: Vec<(PathBuf, NoteDraft)>,
+    failures: Vec<ImportFailure>,
+    dry_run: bool,
+    mut create: impl FnMut(&NoteDraft) -> Result<NoteMeta, String>,
+) -> ImportReport {
+    let mut report = ImportReport {
+        failures,
+        dry_run,
+        ..Default::default()
+    };
+    // (title, text) of each draft taken so far → the note it became.
+    let mut taken: HashMap<(String, String), Option<String>> = HashMap::new();
+    for (path, draft) in drafts {
+        let path = path.display().to_string();
+        let key = (draft.display_title(), draft.markdown.trim().to_string());
+        let existing = match taken.get(&key) {
+            Some(note_id) => Some(note_id.clone()),
+            None => find_duplicate(store, &draft).map(Some),
+        };
+        if let Some(note_id) = existing {
+            report.duplicates.push(ImportDuplicate { path, note_id });
+            continue;
+        }
+
+        let created = match dry_run {
+            true => Ok(None),
+            false => create(&draft).map(Some),
+        };
+        match created {
+            Ok(meta) => {
+                let note_id = meta.as_ref().map(|meta| meta.id.clone());
+                taken.insert(key, note_id.clone());
+                report.imported.push(ImportedNote {
+                    path,
+                    note_id,
+                    title: meta
+                        .as_ref()
+                        .map_or_else(|| draft.display_title(), |m| m.title.clone()),
+                    folder: match meta {
+                        Some(meta) => meta.folder,
+                        None => draft
+                            .folder
+                            .as_deref()
+                            .and_then(crate::organize::normalize_folder),
+                    },
+                    tags: draft.tags.clone(),
+                    attachments: draft.attachments.iter().map(|a| a.name.clone()).collect(),
+                });
+            }
+            Err(reason) => report.failures.push(ImportFailure { path, reason }),
+        }
+    }
+    report
+}
+
+/// `name`, or `name-2.ext`, `name-3.ext`… if `taken` says it is in use.
+fn unique_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let name = Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| !name.is_empty() && name != "..")
+        .unwrap_or_else(|| "attachment".to_string());
+    if !taken(&name) {
+        return name;
+    }
+    let path = Path::new(&name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| format!("{stem}-{n}{ext}"))
+        .find(|candidate| !taken(candidate))
+        .unwrap_or(name)
+}
+
+/// Tags written inline as `#tag` or `#nested/tag`, outside code. With `closed`, Bear's
+/// `#multi word tag#` form is read too.
+pub fn inline_tags(text: &str, closed: bool) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut fenced = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~") {
+            fenced = !fenced;
+            continue;
+        }
+        if fenced {
+            continue;
+        }
+        let chars: Vec<char> = line.chars().collect();
+        let mut in_code = false;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '`' {
+                in_code = !in_code;
+            }
+            let starts = c == '#' && !in_code && (i == 0 || chars[i - 1].is_whitespace());
+            if !starts {
+                i += 1;
+                continue;
+            }
+            let tag_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '/');
+            let run = chars[i + 1..].iter().take_while(|&&c| tag_char(c)).count();
+            let mut tag: String = chars[i + 1..i + 1 + run].iter().collect();
+            let mut end = i + 1 + run;
+            if closed && run > 0 {
+                let rest: String = chars[i + 1..].iter().collect();
+                if let Some(close) = rest.find('#') {
+                    let inner = &rest[..close];
+                    let after = rest[close + 1..].chars().next();
+                    if inner.contains(' ')
+                        && !inner.ends_with(' ')
+                        && after.is_none_or(char::is_whitespace)
+                    {
+                        tag = inner.to_string();
+                        end = i + 1 + inner.chars().count() + 1;
+                    }
+                }
+            }
+            let tag = tag.trim_end_matches('/');
+            if !tag.is_empty() && !tag.chars().all(|c| c.is_ascii_digit()) {
+                let tag = tag.to_string();
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            i = end.max(i + 1);
+        }
+    }
+    tags
+}
+
+/// A Markdown link or image in a note's text.
+pub struct Link<'a> {
+    pub image: bool,
+    pub text: &'a str,
+    pub dest: &'a str,
+}
+
+/// `text` with every `[text](dest)` / `![text](dest)` passed through `map`; `None` keeps
+/// the link as written.
+pub fn map_links(text: &str, mut map: impl FnMut(&Link) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        let image = rest[..open].ends_with('!');
+        let parsed = rest[open + 1..].find("](").and_then(|close| {
+            let label = &rest[open + 1..open + 1 + close];
+            let after = &rest[open + 1 + close + 2..];
+            let end = after.find(')')?;
+            (!label.contains(['\n', '[', ']'])).then_some((
+                label,
+                &after[..end],
+                open + 1 + close + 2 + end + 1,
+            ))
+        });
+        let Some((label, dest, end)) = parsed else {
+            out.push_str(&rest[..open + 1]);
+            rest = &rest[open + 1..];
+            continue;
+        };
+        let link = Link {
+            image,
+            text: label,
+            dest: dest.trim(),
+        };
+        let start = if image { open - 1 } else { open };
+        out.push_str(&rest[..start]);
+        match map(&link) {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `%20` and other percent escapes in a link decoded.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// A file read out of a zip archive.
+pub struct ZipEntry {
+    /// Path inside the archive, `/`-separated.
+    pub path: String,
+    pub bytes: Vec<u8>,
+    /// Modification time recorded in the archive, in ms (the zip's local time read as UTC).
+    pub modified: i64,
+}
+
+/// Every file in the zip at `path`. Zips nested in it, as Notion splits large exports,
+/// are read in place of the inner zip file. Files over [`MAX_FILE_BYTES`] (Markdown over
+/// [`MAX_TEXT_BYTES`]) or more than [`MAX_ZIP_BYTES`] in all fail the read.
+pub fn read_zip(path: &Path) -> Result<Vec<ZipEntry>, String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let (mut entries, mut budget) = (Vec::new(), MAX_ZIP_BYTES);
+    read_zip_from(file, &mut entries, 0, &mut budget)?;
+    Ok(entries)
+}
+
+/// `budget` is what is left to read of [`MAX_ZIP_BYTES`].
+fn read_zip_from(
+    reader: impl Read + Seek,
+    out: &mut Vec<ZipEntry>,
+    depth: u8,
+    budget: &mut usize,
+) -> Result<(), String> {
+    let mut archive =
+        zip::ZipArchive::new(reader).map_err(|e| format!("not a zip archive: {e}"))?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(|e| e.to_string())?;
+        // Paths that would escape the archive root are dropped.
+        let Some(path) = file.enclosed_name() else {
+            continue;
+        };
+        if file.is_dir() {
+            continue;
+        }
+        let path = path
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let modified = file.last_modified().map(zip_time_ms).unwrap_or_default();
+        let limit = match is_markdown(Path::new(&path)) {
+            true => MAX_TEXT_BYTES,
+            false => MAX_FILE_BYTES,
+        };
+        // Sizes in the archive are the sender's word; `take` holds either limit regardless.
+        let bytes = read_bounded(&mut file, limit.min(*budget)).map_err(|reason| {
+            match limit > *budget {
+                true => format!("more than {} MB in all", MAX_ZIP_BYTES / (1024 * 1024)),
+                false => format!("{path}: {reason}"),
+            }
+        })?;
+        *budget -= bytes.len();
+        if depth == 0 && path.to_ascii_lowercase().ends_with(".zip") {
+            drop(file);
+            read_zip_from(Cursor::new(bytes), out, depth + 1, budget)?;
+            continue;
+        }
+        out.push(ZipEntry {
+            path,
+            bytes,
+            modified,
+        });
+    }
+    Ok(())
+}
+
+fn zip_time_ms(time: zip::DateTime) -> i64 {
+    let days = days_from_civil(
+        i64::from(time.year()),
+        i64::from(time.month()),
+        i64::from(time.day()),
+    );
+    let seconds =
+        i64::from(time.hour()) * 3600 + i64::from(time.minute()) * 60 + i64::from(time.second());
+    (days * 86_400 + seconds) * 1000
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_titles_and_tags_from_front_matter_or_headings() {
+        let draft = draft_from_markdown(
+            "---\ntitle: \"Trip plan\"\ntags: [travel, '#2024']\n---\n\nDay one\n",
+        );
+        assert_eq!(draft.title.as_deref(), Some("Trip plan"));
+        assert_eq!(draft.tags, ["travel", "2024"]);
+        assert_eq!(draft.markdown, "Day one\n");
+
+        let draft = draft_from_markdown("---\ntags:\n  - a\n  - b\n---\nintro\n\n## Goals\n");
+        assert_eq!(draft.title.as_deref(), Some("Goals"));
+        assert_eq!(draft.tags, ["a", "b"]);
+
+        let draft = draft_from_markdown("---\nnot closed\n# Heading");
+        assert_eq!(draft.title.as_deref(), Some("Heading"));
+        assert!(draft.markdown.starts_with("---"));
+    }
+
+    #[test]
+    fn imports_folders_and_reports_duplicates_and_failures() {
+        let dir = std::env::temp_dir().join(format!("hn-import-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("work/clients")).unwrap();
+        fs::create_dir_all(dir.join(".obsidian")).unwrap();
+        fs::write(dir.join("top.md"), "# Top\nbody").unwrap();
+        fs::write(dir.join("work/clients/acme.md"), "# Acme\nnotes").unwrap();
+        fs::write(dir.join("work/copy.md"), "# Top\nbody").unwrap();
+        fs::write(dir.join(".obsidian/app.md"), "hidden").unwrap();
+        fs::write(dir.join("bad.md"), [0xff, 0xfe]).unwrap();
+        fs::write(dir.join("image.png"), "png").unwrap();
+
+        let store = NoteStore::in_memory();
+        let paths = [dir.clone(), dir.join("image.png"), dir.join("missing.md")];
+        let (drafts, failures) = scan_markdown(&paths);
+        let report = import_drafts(&store, drafts, failures, false, |draft| {
+            store.create_from(draft).map(|(meta, _)| meta)
+        });
+
+        let imported: Vec<_> = report
+            .imported
+            .iter()
+            .map(|note| (note.title.as_str(), note.folder.as_deref()))
+            .collect();
+        assert_eq!(imported, [("Top", None), ("Acme", Some("work/clients"))]);
+        assert_eq!(report.duplicates.len(), 1);
+        assert!(report.duplicates[0].path.ends_with("copy.md"));
+        assert_eq!(report.duplicates[0].note_id, report.imported[0].note_id);
+        let failed: Vec<_> = report.failures.iter().map(|f| f.reason.as_str()).collect();
+        assert_eq!(failed.len(), 3);
+        assert!(failed.contains(&"not a Markdown file"));
+        assert!(failed.contains(&"not UTF-8 text"));
+
+        let acme_id = report.imported[1].note_id.as_deref().unwrap();
+        let acme = store.get(acme_id).unwrap();
+        assert_eq!(acme.markdown, "# Acme\nnotes");
+        let modified = fs::metadata(dir.join("work/clients/acme.md"))
+            .and_then(|m| m.modified())
+            .map(unix_ms)
+            .unwrap();
+        assert_eq!(acme.meta.updated_at, modified);
+        assert!(acme.meta.created_at <= modified);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_create_nothing_and_imports_save_attachments() {
+        let dir = std::env::temp_dir().join(format!("hn-import-{}", uuid::Uuid::new_v4()));
+        let store = NoteStore::open(&dir).unwrap();
+        let mut draft = draft_from_markdown(
+            "# Chart
+",
+        )
+        .with_new_id();
+        let link = draft.attach("chart.png", b"png".to_vec());
+        draft.markdown.push_str(&format!("![]({link})\n"));
+        let drafts = vec![
+            (PathBuf::from("a.md"), draft.clone()),
+            (PathBuf::from("b.md"), draft),
+        ];
+
+        let create = |draft: &NoteDraft| store.create_from(draft).map(|(meta, _)| meta);
+        let report = import_drafts(&store, drafts.clone(), Vec::new(), true, create);
+        assert!(report.dry_run);
+        assert_eq!(report.imported[0].note_id, None);
+        assert_eq!(report.imported[0].attachments, ["chart.png"]);
+        assert_eq!(report.duplicates[0].note_id, None);
+        assert!(store.list().is_empty());
+
+        let report = import_drafts(&store, drafts, Vec::new(), false, create);
+        let note_id = report.imported[0].note_id.clone().unwrap();
+        assert_eq!(
+            report.duplicates[0].note_id.as_deref(),
+            Some(note_id.as_str())
+        );
+        let saved = dir.join(format!("attachments/{note_id}/chart.png"));
+        assert_eq!(fs::read(saved).unwrap(), b"png");
+        assert!(store.get(&note_id).unwrap().markdown.contains(&link));
+
+        drop(store);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stops_reading_zips_past_their_limits() {
+        use std::io::Write;
+
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        archive.start_file("inner.zip", options).unwrap();
+        let mut inner = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        inner.start_file("zeros.bin", options).unwrap();
+        inner.write_all(&vec![0; 64 * 1024]).unwrap();
+        let inner = inner.finish().unwrap().into_inner();
+        archive.write_all(&inner).unwrap();
+        let archive = archive.finish().unwrap().into_inner();
+
+        let mut entries = Vec::new();
+        let mut budget = 64 * 1024 + inner.len();
+        read_zip_from(Cursor::new(&archive), &mut entries, 0, &mut budget).unwrap();
+        assert_eq!((entries[0].path.as_str(), budget), ("zeros.bin", 0));
+
+        let mut budget = 64 * 1024;
+        let error = read_zip_from(Cursor::new(&archive), &mut Vec::new(), 0, &mut budget);
+        assert_eq!(error.unwrap_err(), "more than 1024 MB in all");
+        assert!(read_bounded(&b"twelve bytes"[..], 11).is_err());
+        assert_eq!(read_bounded(&b"twelve bytes"[..], 12).unwrap().len(), 12);
+    }
+}
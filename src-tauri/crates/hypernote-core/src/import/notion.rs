@@ -0,0 +1,275 @@
+//! Notion "Markdown & CSV" export zips. Pages are `<title> <32-hex id>.md`, subpages sit
+//! in a folder named like their parent page, and each database is a `<name> <id>.csv`
+//! next to a folder holding one page per row.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{
+    draft_from_markdown, map_links, percent_decode, read_zip, ImportFailure, NoteDraft, ZipEntry,
+};
+
+/// Drafts for every page and database in the export zip at `path`. Page and folder names
+/// lose their Notion ids, links between pages become their text, files the export
+/// includes become attachments and each database becomes a note holding its table.
+pub fn scan_export(path: &Path) -> (Vec<(PathBuf, NoteDraft)>, Vec<ImportFailure>) {
+    let entries = match read_zip(path) {
+        Ok(entries) => entries,
+        Err(reason) => {
+            let failure = ImportFailure {
+                path: path.display().to_string(),
+                reason,
+            };
+            return (Vec::new(), vec![failure]);
+        }
+    };
+    let by_path: HashMap<&str, &ZipEntry> = entries
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect();
+
+    let mut drafts = Vec::new();
+    let mut failures = Vec::new();
+    for entry in &entries {
+        let lower = entry.path.to_ascii_lowercase();
+        let draft = if lower.ends_with(".md") {
+            page_draft(entry, &by_path)
+        } else if lower.ends_with(".csv") {
+            // Newer exports write each database twice; `_all` has every row.
+            let all = format!("{}_all.csv", &entry.path[..entry.path.len() - 4]);
+            if by_path.contains_key(all.as_str()) {
+                continue;
+            }
+            database_draft(entry)
+        } else {
+            continue;
+        };
+        let source = path.join(&entry.path);
+        match draft {
+            Ok(draft) => drafts.push((source, draft)),
+            Err(reason) => failures.push(ImportFailure {
+                path: source.display().to_string(),
+                reason,
+            }),
+        }
+    }
+    (drafts, failures)
+}
+
+fn page_draft(entry: &ZipEntry, by_path: &HashMap<&str, &ZipEntry>) -> Result<NoteDraft, String> {
+    let text = std::str::from_utf8(&entry.bytes).map_err(|_| "not UTF-8 text".to_string())?;
+    let text = text.trim_start_matches('\u{feff}');
+    let (dir, file) = entry.path.rsplit_once('/').unwrap_or(("", &entry.path));
+    let mut draft = NoteDraft {
+        folder: folder_of(dir),
+        created_at: entry.modified,
+        updated_at: entry.modified,
+        ..draft_from_markdown(text)
+    }
+    .with_new_id();
+    if draft.title.is_none() {
+        draft.title = Some(strip_id(file.strip_suffix(".md").unwrap_or(file)).to_string());
+    }
+    if let Some(tags) = property(&draft.markdown, "Tags") {
+        draft.tags = tags
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+    }
+
+    let body = std::mem::take(&mut draft.markdown);
+    draft.markdown = map_links(&body, |link| {
+        if link.dest.contains("://") || link.dest.starts_with("mailto:") {
+            return None;
+        }
+        let target = percent_decode(link.dest);
+        let target = match dir {
+            "" => target,
+            dir => format!("{dir}/{target}"),
+        };
+        let target = normalize(&target);
+        if target.to_ascii_lowercase().ends_with(".md") || target.ends_with(".csv") {
+            return Some(link.text.to_string());
+        }
+        let file = by_path.get(target.as_str())?;
+        let name = target.rsplit('/').next().unwrap_or(&target);
+        let attached = draft.attach(name, file.bytes.clone());
+        let bang = if link.image { "!" } else { "" };
+        Some(format!("{bang}[{}]({attached})", link.text))
+    });
+    Ok(draft)
+}
+
+/// The value of a `Key: value` property line in the block under a page's title.
+fn property<'a>(markdown: &'a str, key: &str) -> Option<&'a str> {
+    markdown
+        .lines()
+        .skip_while(|line| line.trim().is_empty() || line.starts_with('#'))
+        .take_while(|line| !line.trim().is_empty())
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .map(str::trim)
+}
+
+fn database_draft(entry: &ZipEntry) -> Result<NoteDraft, String> {
+    let text = std::str::from_utf8(&entry.bytes).map_err(|_| "not UTF-8 text".to_string())?;
+    let rows = parse_csv(text.trim_start_matches('\u{feff}'));
+    let (dir, file) = entry.path.rsplit_once('/').unwrap_or(("", &entry.path));
+    let stem = file.strip_suffix(".csv").unwrap_or(file);
+    let title = strip_id(stem.strip_suffix("_all").unwrap_or(stem)).to_string();
+
+    let mut markdown = format!("# {title}\n\n");
+    if let Some(header) = rows.first() {
+        let columns = header.len();
+        let row = |cells: &[String]| {
+            let cells: Vec<String> = (0..columns)
+                .map(|i| {
+                    cells
+                        .get(i)
+                        .map(|cell| cell.replace('|', "\\|").replace('\n', "<br>"))
+                        .unwrap_or_default()
+                })
+                .collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        markdown.push_str(&row(header));
+        markdown.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+        for cells in &rows[1..] {
+            markdown.push_str(&row(cells));
+        }
+    }
+
+    Ok(NoteDraft {
+        title: Some(title),
+        markdown,
+        folder: folder_of(dir),
+        created_at: entry.modified,
+        updated_at: entry.modified,
+        ..Default::default()
+    })
+}
+
+/// RFC 4180 rows: quoted fields may hold commas, newlines and `""` escapes.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Folder for pages under `dir`, with Notion ids and the export's wrapping folder gone.
+fn folder_of(dir: &str) -> Option<String> {
+    let folder = dir
+        .split('/')
+        .filter(|part| !part.starts_with("Export-"))
+        .map(strip_id)
+        .collect::<Vec<_>>()
+        .join("/");
+    crate::organize::normalize_folder(&folder)
+}
+
+/// `Meeting notes 0a1b…(32 hex)` → `Meeting notes`.
+fn strip_id(name: &str) -> &str {
+    let Some(split) = name.len().checked_sub(32) else {
+        return name;
+    };
+    match name.get(split..) {
+        Some(id) if id.chars().all(|c| c.is_ascii_hexdigit()) => name[..split].trim_end(),
+        _ => name,
+    }
+}
+
+/// `a/b/../c/./d` → `a/c/d`.
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn reads_pages_databases_and_files_from_the_zip() {
+        let path = std::env::temp_dir().join(format!("hn-notion-{}.zip", uuid::Uuid::new_v4()));
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let mut add = |name: &str, bytes: &[u8]| {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(bytes).unwrap();
+        };
+        add(
+            &format!("Work {ID}.md"),
+            format!(
+                "# Work\n\nTags: planning, q3\n\nSee [Tasks](Work%20{ID}/Tasks%20{ID}.csv) \
+                 and ![chart](Work%20{ID}/chart.png).\n"
+            )
+            .as_bytes(),
+        );
+        add(&format!("Work {ID}/chart.png"), b"png");
+        add(
+            &format!("Work {ID}/Tasks {ID}_all.csv"),
+            b"\xef\xbb\xbfName,Notes\nShip it,\"a, b\"\n",
+        );
+        add(&format!("Work {ID}/Tasks {ID}.csv"), b"Name\nShip it\n");
+        zip.finish().unwrap();
+
+        let (drafts, failures) = scan_export(&path);
+        assert!(failures.is_empty());
+        assert_eq!(drafts.len(), 2);
+        let page = &drafts[0].1;
+        let id = page.id.clone().unwrap();
+        assert_eq!(page.title.as_deref(), Some("Work"));
+        assert_eq!(page.tags, ["planning", "q3"]);
+        assert!(page.markdown.ends_with(&format!(
+            "See Tasks and ![chart](attachments/{id}/chart.png).\n"
+        )));
+        assert_eq!(page.attachments[0].name, "chart.png");
+
+        let tasks = &drafts[1].1;
+        assert_eq!(tasks.title.as_deref(), Some("Tasks"));
+        assert_eq!(tasks.folder.as_deref(), Some("Work"));
+        assert_eq!(
+            tasks.markdown,
+            "# Tasks\n\n| Name | Notes |\n| --- | --- |\n| Ship it | a, b |\n"
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+//! Obsidian vaults: a directory of Markdown files named after their notes, linked with
+//! `[[wiki-links]]`, tagged inline or in front-matter, and embedding attachments kept
+//! anywhere in the vault (usually an attachments folder).
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{
+    draft_from_markdown, file_times, files_under, inline_tags, map_links, percent_decode,
+    read_file, read_text, split_front_matter, ImportFailure, NoteDraft, MAX_FILE_BYTES,
+};
+
+/// Drafts for every note in the vault at `root`. Titles are file names unless the
+/// front-matter sets one; wiki-links become their display text and embedded or linked
+/// files in the vault become attachments.
+pub fn scan_vault(root: &Path) -> (Vec<(PathBuf, NoteDraft)>, Vec<ImportFailure>) {
+    let failure = |reason: String| ImportFailure {
+        path: root.display().to_string(),
+        reason,
+    };
+    if !root.is_dir() {
+        return (
+            Vec::new(),
+            vec![failure("not a vault directory".to_string())],
+        );
+    }
+    let files = match files_under(root, &|_| true) {
+        Ok(files) => files,
+        Err(reason) => return (Vec::new(), vec![failure(reason)]),
+    };
+    let vault = Vault::new(root, files.iter().map(|(path, _)| path.clone()).collect());
+
+    let mut drafts = Vec::new();
+    let mut failures = Vec::new();
+    for (path, folder) in files
+        .into_iter()
+        .filter(|(path, _)| super::is_markdown(path))
+    {
+        match vault.draft(&path, folder) {
+            Ok(draft) => drafts.push((path, draft)),
+            Err(reason) => failures.push(ImportFailure {
+                path: path.display().to_string(),
+                reason,
+            }),
+        }
+    }
+    (drafts, failures)
+}
+
+struct Vault {
+    root: PathBuf,
+    /// Lower-cased file name → files with that name, for links by name alone.
+    by_name: HashMap<String, Vec<PathBuf>>,
+}
+
+impl Vault {
+    fn new(root: &Path, files: Vec<PathBuf>) -> Self {
+        let mut by_name: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for file in files {
+            if let Some(name) = file.file_name() {
+                let name = name.to_string_lossy().to_lowercase();
+                by_name.entry(name).or_default().push(file);
+            }
+        }
+        Self {
+            root: root.to_path_buf(),
+            by_name,
+        }
+    }
+
+    fn draft(&self, path: &Path, folder: Option<String>) -> Result<NoteDraft, String> {
+        let text = read_text(path)?;
+        let (created_at, updated_at) = file_times(path)?;
+        let (front, _) = split_front_matter(&text);
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        let mut draft = NoteDraft {
+            folder,
+            created_at,
+            updated_at,
+            ..draft_from_markdown(&text)
+        }
+        .with_new_id();
+        // Obsidian names a note by its file; headings are just content.
+        if !front.contains_key("title") {
+            draft.title = stem;
+        }
+        for tag in inline_tags(&draft.markdown, false) {
+            if !draft.tags.contains(&tag) {
+                draft.tags.push(tag);
+            }
+        }
+
+        let body = std::mem::take(&mut draft.markdown);
+        let dir = path.parent().unwrap_or(&self.root);
+        // Plain links first, so the links embeds turn into are not resolved again.
+        let body = map_links(&body, |link| {
+            if is_remote(link.dest) {
+                return None;
+            }
+            let file = self.resolve(dir, &percent_decode(link.dest))?;
+            let bytes = read_file(&file, MAX_FILE_BYTES).ok()?;
+            let name = file.file_name()?.to_string_lossy().into_owned();
+            let target = draft.attach(&name, bytes);
+            let bang = if link.image { "!" } else { "" };
+            Some(format!("{bang}[{}]({target})", link.text))
+        });
+        let body = convert_wiki_links(&body, |target| {
+            let file = self.resolve(dir, target)?;
+            let bytes = read_file(&file, MAX_FILE_BYTES).ok()?;
+            let name = file.file_name()?.to_string_lossy().into_owned();
+            let link = draft.attach(&name, bytes);
+            Some(format!("![{name}]({link})"))
+        });
+        draft.markdown = body;
+        Ok(draft)
+    }
+
+    /// The vault file `target` points at: relative to the note, to the vault root, or by
+    /// file name alone. Notes are not attachments.
+    fn resolve(&self, dir: &Path, target: &str) -> Option<PathBuf> {
+        let target = target.trim();
+        if target.is_empty() || target.contains("..") {
+            return None;
+        }
+        let file = [dir.join(target), self.root.join(target)]
+            .into_iter()
+            .find(|file| file.is_file())
+            .or_else(|| {
+                let name = Path::new(target)
+                    .file_name()?
+                    .to_string_lossy()
+                    .to_lowercase();
+                self.by_name.get(&name)?.first().cloned()
+            })?;
+        (!super::is_markdown(&file)).then_some(file)
+    }
+}
+
+fn is_remote(dest: &str) -> bool {
+    dest.contains("://") || dest.starts_with("mailto:") || dest.starts_with('#')
+}
+
+/// `[[Note]]`, `[[folder/Note|alias]]` and `[[Note#Heading]]` become their display text.
+/// `![[file]]` embeds go to `embed` first, and fall back to display text when it has
+/// nothing.
+fn convert_wiki_links(text: &str, mut embed: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find("[[") {
+        let Some(close) = rest[open + 2..].find("]]") else {
+            break;
+        };
+        let inner = &rest[open + 2..open + 2 + close];
+        let is_embed = rest[..open].ends_with('!');
+        let start = if is_embed { open - 1 } else { open };
+        out.push_str(&rest[..start]);
+        rest = &rest[open + 2 + close + 2..];
+
+        let (target, alias) = match inner.split_once('|') {
+            Some((target, alias)) => (target, Some(alias)),
+            None => (inner, None),
+        };
+        if is_embed {
+            if let Some(replacement) = embed(target) {
+                out.push_str(&replacement);
+                continue;
+            }
+        }
+        out.push_str(&display_text(target, alias));
+    }
+    out.push_str(rest);
+    out
+}
+
+fn display_text(target: &str, alias: Option<&str>) -> String {
+    if let Some(alias) = alias.map(str::trim).filter(|alias| !alias.is_empty()) {
+        return alias.to_string();
+    }
+    let (note, section) = match target.split_once('#') {
+        Some((note, section)) => (note, Some(section.trim_start_matches('^'))),
+        None => (target, None),
+    };
+    let note = note.rsplit('/').next().unwrap_or(note);
+    let note = note.strip_suffix(".md").unwrap_or(note).trim();
+    match section.filter(|section| !section.is_empty()) {
+        Some(section) if note.is_empty() => section.to_string(),
+        Some(section) => format!("{note} > {section}"),
+        None => note.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn converts_links_tags_and_attachments() {
+        let root = std::env::temp_dir().join(format!("hn-vault-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("Projects")).unwrap();
+        fs::create_dir_all(root.join("Attachments")).unwrap();
+        fs::create_dir_all(root.join(".obsidian")).unwrap();
+        fs::write(root.join("Attachments/chart.png"), "png").unwrap();
+        fs::write(root.join(".obsidian/workspace.json"), "{}").unwrap();
+        fs::write(
+            root.join("Projects/Launch.md"),
+            "---\ntags: [work]\n---\n# Plan\nSee [[Budget|the budget]] and [[Notes/Ideas#Later]].\n\
+             ![[chart.png]] ![[Missing.png]] #launch/q3 `#code`\n",
+        )
+        .unwrap();
+
+        let (drafts, failures) = scan_vault(&root);
+        assert!(failures.is_empty());
+        assert_eq!(drafts.len(), 1);
+        let draft = &drafts[0].1;
+        let id = draft.id.clone().unwrap();
+        assert_eq!(draft.title.as_deref(), Some("Launch"));
+        assert_eq!(draft.folder.as_deref(), Some("Projects"));
+        assert_eq!(draft.tags, ["work", "launch/q3"]);
+        assert_eq!(
+            draft.markdown,
+            format!(
+                "# Plan\nSee the budget and Ideas > Later.\n\
+                 ![chart.png](attachments/{id}/chart.png) Missing.png #launch/q3 `#code`\n"
+            )
+        );
+        assert_eq!(draft.attachments[0].bytes, b"png");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
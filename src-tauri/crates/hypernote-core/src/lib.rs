@@ -0,0 +1,43 @@
+//! HyperNote backend core, shared by the desktop app and the `hypernoted` daemon.
+//!
+//! A [`Node`] owns a [`NoteStore`], the LAN WebSocket server on port 4747, mDNS
+//! advertisement/discovery and outbound peer and relay links. Hosts plug in an
+//! [`EventSink`] to receive peer and sync events.
+
+pub mod acks;
+pub mod attribution;
+pub mod backup;
+pub mod container;
+pub mod contracts;
+pub mod diff;
+pub mod events;
+pub mod export;
+pub mod guard;
+pub mod history;
+pub mod import;
+pub mod limits;
+pub mod lock;
+pub mod manifest;
+pub mod meta_map;
+pub mod net;
+mod node;
+pub mod organize;
+pub mod quick_open;
+pub mod search;
+pub mod store;
+pub mod sync;
+pub mod wal;
+
+pub use events::EventSink;
+pub use limits::Limits;
+pub use net::{share_target, SYNC_PORT};
+pub use node::{Node, NodeConfig, SYNC_PAUSED_REASON};
+pub use store::NoteStore;
+
+pub fn unix_now_ms() -> i64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
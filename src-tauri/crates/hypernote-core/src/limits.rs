@@ -0,0 +1,236 @@
+//! Size and connection limits that keep one oversized note or misbehaving peer from
+//! exhausting memory or sockets.
+//!
+//! WebSocket messages are capped on their own, well below the note limit: payloads larger
+//! than a message holds travel as chunks (see [`hypernote_protocol::Chunk`]) that the
+//! receiver joins up to the update limit.
+//!
+//! The app reads overrides from `<data-dir>/limits.json`; `hypernoted` takes flags.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use hypernote_protocol::MAX_CHUNK_BYTES;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+pub const LIMITS_FILE: &str = "limits.json";
+
+/// Maximum size per note file, CRDT state included, as `docs/SPEC.md` sets it.
+pub const MAX_NOTE_BYTES: usize = 50 * 1024 * 1024;
+/// Largest WebSocket message, whatever the note limit.
+pub const MAX_WS_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+/// Largest single WebSocket frame; longer messages arrive fragmented.
+pub const MAX_WS_FRAME_BYTES: usize = 16 * 1024 * 1024;
+/// Room for the envelope fields around a frame's payload.
+const ENVELOPE_BYTES: usize = 64 * 1024;
+/// Concurrent peers, as `docs/SPEC.md` sets it.
+pub const MAX_PEERS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest single Yjs update accepted from the frontend or a peer.
+    pub max_update_bytes: usize,
+    /// Largest merged state a note may grow to.
+    pub max_note_bytes: usize,
+    pub max_ws_message_bytes: usize,
+    pub max_ws_frame_bytes: usize,
+    /// Connected peers, inbound and outbound together.
+    pub max_peers: usize,
+    /// Inbound connections open at once from one IP address.
+    pub max_connections_per_ip: usize,
+    /// Sustained frames per second accepted from one inbound peer.
+    pub max_frames_per_second: u32,
+    /// Frames an inbound peer may send in a burst, e.g. a full catch-up after `hello`.
+    pub frame_burst: u32,
+    /// How long an address that flooded frames or connections is refused.
+    pub ban_duration: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::for_note_bytes(MAX_NOTE_BYTES)
+    }
+}
+
+impl Limits {
+    /// Limits for notes of up to `max_note_bytes`. A single update may carry a whole note,
+    /// as full-state catch-ups do, split over as many messages as it takes.
+    pub fn for_note_bytes(max_note_bytes: usize) -> Self {
+        Self {
+            max_update_bytes: max_note_bytes,
+            max_note_bytes,
+            max_ws_message_bytes: MAX_WS_MESSAGE_BYTES,
+            max_ws_frame_bytes: MAX_WS_FRAME_BYTES,
+            max_peers: MAX_PEERS,
+            max_connections_per_ip: 4,
+            max_frames_per_second: 200,
+            frame_burst: 2000,
+            ban_duration: Duration::from_secs(10 * 60),
+        }
+    }
+
+    /// The defaults with the overrides saved in `data_dir`, if any.
+    pub fn load(data_dir: &Path) -> Result<Self, String> {
+        let path = data_dir.join(LIMITS_FILE);
+        let settings: LimitSettings = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| format!("unreadable {}: {e}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("cannot read {}: {e}", path.display())),
+        };
+        settings.apply(Self::default())
+    }
+
+    /// Payload bytes per chunk, so that a chunk's encoding fits in one message.
+    pub fn chunk_bytes(&self) -> usize {
+        let fits = self.max_ws_message_bytes.saturating_sub(ENVELOPE_BYTES) / 4;
+        MAX_CHUNK_BYTES.min(fits).max(1)
+    }
+
+    pub fn ws_config(&self) -> WebSocketConfig {
+        WebSocketConfig::default()
+            .max_message_size(Some(self.max_ws_message_bytes))
+            .max_frame_size(Some(self.max_ws_frame_bytes))
+    }
+
+    pub fn check_update(&self, len: usize) -> Result<(), String> {
+        match len > self.max_update_bytes {
+            true => Err(too_large("update", len, self.max_update_bytes)),
+            false => Ok(()),
+        }
+    }
+
+    pub fn check_note(&self, len: usize) -> Result<(), String> {
+        match len > self.max_note_bytes {
+            true => Err(too_large("note", len, self.max_note_bytes)),
+            false => Ok(()),
+        }
+    }
+}
+
+/// `limits.json`: sizes in MB, each optional.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LimitSettings {
+    pub max_note_mb: Option<usize>,
+    pub max_message_mb: Option<usize>,
+    pub max_peers: Option<usize>,
+}
+
+impl LimitSettings {
+    /// `limits` with these overrides; every other limit is left as it was.
+    pub fn apply(&self, mut limits: Limits) -> Result<Limits, String> {
+        if let Some(bytes) = bytes(self.max_note_mb, "maxNoteMb")? {
+            limits.max_update_bytes = bytes;
+            limits.max_note_bytes = bytes;
+        }
+        if let Some(bytes) = bytes(self.max_message_mb, "maxMessageMb")? {
+            limits.max_ws_message_bytes = bytes;
+            limits.max_ws_frame_bytes = limits.max_ws_frame_bytes.min(bytes);
+        }
+        match self.max_peers {
+            Some(0) => return Err("maxPeers must be positive".to_string()),
+            Some(peers) => limits.max_peers = peers,
+            None => {}
+        }
+        Ok(limits)
+    }
+}
+
+/// `megabytes` in bytes, refusing zero and sizes past `usize`.
+fn bytes(megabytes: Option<usize>, name: &str) -> Result<Option<usize>, String> {
+    match megabytes {
+        None => Ok(None),
+        Some(0) => Err(format!("{name} must be positive")),
+        Some(megabytes) => megabytes
+            .checked_mul(1024 * 1024)
+            .map(Some)
+            .ok_or_else(|| format!("{name} is too large")),
+    }
+}
+
+fn too_large(what: &str, len: usize, limit: usize) -> String {
+    format!(
+        "{what} too large: {} exceeds the {} limit",
+        megabytes(len),
+        megabytes(limit)
+    )
+}
+
+fn megabytes(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_messages_apart_from_the_note_limit() {
+        let limits = Limits::default();
+        assert_eq!(limits.max_update_bytes, MAX_NOTE_BYTES);
+        assert_eq!(limits.max_ws_message_bytes, MAX_WS_MESSAGE_BYTES);
+        assert_eq!(
+            Limits::for_note_bytes(500 * 1024 * 1024).max_ws_message_bytes,
+            MAX_WS_MESSAGE_BYTES
+        );
+        assert!(4 * limits.chunk_bytes() + ENVELOPE_BYTES <= limits.max_ws_message_bytes);
+        assert_eq!(
+            limits.check_note(60 * 1024 * 1024).unwrap_err(),
+            "note too large: 60.0 MB exceeds the 50.0 MB limit"
+        );
+        assert!(Limits::for_note_bytes(10).check_update(11).is_err());
+        assert!(limits.check_update(MAX_NOTE_BYTES).is_ok());
+    }
+    #[test]
+    fn applies_saved_overrides() {
+        let settings = LimitSettings {
+            max_note_mb: Some(5),
+            max_message_mb: Some(1),
+            max_peers: Some(3),
+        };
+        let limits = settings.apply(Limits::default()).unwrap();
+        assert_eq!(limits.max_note_bytes, 5 * 1024 * 1024);
+        assert_eq!(limits.max_ws_message_bytes, 1024 * 1024);
+        assert!(4 * limits.chunk_bytes() + ENVELOPE_BYTES <= 1024 * 1024);
+        assert_eq!(limits.max_peers, 3);
+
+        let zero = LimitSettings {
+            max_peers: Some(0),
+            ..Default::default()
+        };
+        assert!(zero.apply(Limits::default()).is_err());
+        let huge = LimitSettings {
+            max_note_mb: Some(usize::MAX),
+            ..Default::default()
+        };
+        assert_eq!(
+            huge.apply(Limits::default()).unwrap_err(),
+            "maxNoteMb is too large"
+        );
+
+        // The note limit leaves limits set elsewhere alone.
+        let custom = Limits {
+            max_connections_per_ip: 1,
+            frame_burst: 10,
+            ..Limits::default()
+        };
+        let note_only = LimitSettings {
+            max_note_mb: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            note_only.apply(custom).unwrap(),
+            Limits {
+                max_update_bytes: 5 * 1024 * 1024,
+                max_note_bytes: 5 * 1024 * 1024,
+                ..custom
+            }
+        );
+
+        let dir = std::env::temp_dir().join(format!("hn-limits-{}", uuid::Uuid::new_v4()));
+        assert_eq!(Limits::load(&dir).unwrap(), Limits::default());
+    }
+}
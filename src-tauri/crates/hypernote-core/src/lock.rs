@@ -0,0 +1,58 @@
+//! Exclusive use of a data directory.
+//!
+//! The app, `hypernoted` and `hypernote-cli` each hold an OS file lock on
+//! `<data-dir>/hypernote.lock` while they write there, so two processes never persist the
+//! same notes or share a device id at once. The lock goes away with the process, crashes
+//! included, so there is no stale lock to clean up.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::Path;
+
+pub const LOCK_FILE: &str = "hypernote.lock";
+
+/// Held for as long as the data directory is in use; dropping it releases the lock.
+#[derive(Debug)]
+pub struct DataDirLock {
+    _file: File,
+}
+
+impl DataDirLock {
+    /// Takes the lock on `dir`, creating the directory if needed. Fails when another
+    /// process holds it.
+    pub fn acquire(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {e}", dir.display()))?;
+        let path = dir.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| format!("cannot open {}: {e}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Self { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(format!(
+                "{} is in use by another HyperNote process (the app, hypernoted or \
+                 hypernote-cli)",
+                dir.display()
+            )),
+            Err(TryLockError::Error(e)) => Err(format!("cannot lock {}: {e}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_holder_at_a_time() {
+        let dir = std::env::temp_dir().join(format!("hn-lock-{}", uuid::Uuid::new_v4()));
+        let held = DataDirLock::acquire(&dir).unwrap();
+        let refused = DataDirLock::acquire(&dir).unwrap_err();
+        assert!(refused.contains("in use"), "{refused}");
+
+        drop(held);
+        DataDirLock::acquire(&dir).expect("free again once released");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
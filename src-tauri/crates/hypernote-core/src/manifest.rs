@@ -0,0 +1,198 @@
+//! Workspace manifest: which notes exist, synced between peers like a note.
+//!
+//! A Yjs document with one map, `notes`, from note id to `{ title, createdAt, deletedAt }`.
+//! The store keeps it current as notes are created, retitled and trashed, and peers merge
+//! each other's copies under the reserved note id [`MANIFEST_NOTE_ID`]. A fresh device thus
+//! learns every note from the first peer it meets, including notes created while it was
+//! offline, and can ask for the ones it lacks. Entries are never removed: a trashed note
+//! stays listed as a tombstone with `deletedAt` set.
+
+use std::collections::HashMap;
+
+use yrs::updates::decoder::Decode;
+use yrs::{Any, Doc, Map, MapRef, Out, ReadTxn, StateVector, Transact, Update};
+
+use crate::contracts::NoteMeta;
+use crate::store::EMPTY_UPDATE;
+
+pub use hypernote_protocol::MANIFEST_NOTE_ID;
+
+const NOTES_MAP: &str = "notes";
+
+/// What the manifest knows about one note.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub title: String,
+    pub created_at: i64,
+    pub deleted_at: Option<i64>,
+}
+
+impl ManifestEntry {
+    pub fn of(meta: &NoteMeta) -> Self {
+        Self {
+            title: meta.title.clone(),
+            created_at: meta.created_at,
+            deleted_at: meta.deleted_at,
+        }
+    }
+
+    /// Metadata standing in for a note whose content has not arrived yet.
+    pub fn placeholder(&self, note_id: &str) -> NoteMeta {
+        NoteMeta {
+            id: note_id.to_string(),
+            title: self.title.clone(),
+            created_at: self.created_at,
+            updated_at: self.created_at,
+            deleted_at: self.deleted_at,
+            ..Default::default()
+        }
+    }
+
+    fn to_any(&self) -> Any {
+        let mut fields = HashMap::new();
+        fields.insert("title".to_string(), Any::from(self.title.as_str()));
+        fields.insert("createdAt".to_string(), Any::from(self.created_at));
+        fields.insert("deletedAt".to_string(), Any::from(self.deleted_at));
+        Any::from(fields)
+    }
+
+    fn from_any(value: &Any) -> Option<Self> {
+        let Any::Map(fields) = value else {
+            return None;
+        };
+        let number = |key: &str| match fields.get(key) {
+            Some(Any::Number(number)) => number.as_i64(),
+            _ => None,
+        };
+        let title = match fields.get("title") {
+            Some(Any::String(title)) => title.to_string(),
+            _ => String::new(),
+        };
+        Some(Self {
+            title,
+            created_at: number("createdAt")?,
+            deleted_at: number("deletedAt"),
+        })
+    }
+}
+
+pub struct Manifest {
+    doc: Doc,
+    notes: MapRef,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        let doc = Doc::new();
+        let notes = doc.get_or_insert_map(NOTES_MAP);
+        Self { doc, notes }
+    }
+}
+
+impl Manifest {
+    pub fn from_state(state: &[u8]) -> Result<Self, String> {
+        let manifest = Self::default();
+        manifest.apply(state)?;
+        Ok(manifest)
+    }
+
+    /// Merges a peer's manifest update.
+    pub fn apply(&self, update: &[u8]) -> Result<(), String> {
+        let update = Update::decode_v1(update).map_err(|e| format!("undecodable update: {e}"))?;
+        self.doc
+            .transact_mut()
+            .apply_update(update)
+            .map_err(|e| format!("undecodable update: {e}"))
+    }
+
+    /// Writes `meta`'s entry if it differs from the recorded one; returns whether it did.
+    pub fn record(&self, meta: &NoteMeta) -> bool {
+        let entry = ManifestEntry::of(meta);
+        if self.entry(&meta.id).as_ref() == Some(&entry) {
+            return false;
+        }
+        let mut txn = self.doc.transact_mut();
+        self.notes
+            .insert(&mut txn, meta.id.as_str(), entry.to_any());
+        true
+    }
+
+    pub fn entry(&self, note_id: &str) -> Option<ManifestEntry> {
+        let txn = self.doc.transact();
+        match self.notes.get(&txn, note_id)? {
+            Out::Any(value) => ManifestEntry::from_any(&value),
+            _ => None,
+        }
+    }
+
+    pub fn entries(&self) -> Vec<(String, ManifestEntry)> {
+        let txn = self.doc.transact();
+        self.notes
+            .iter(&txn)
+            .filter_map(|(note_id, value)| match value {
+                Out::Any(value) => {
+                    ManifestEntry::from_any(&value).map(|entry| (note_id.to_string(), entry))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Full state as one update.
+    pub fn state(&self) -> Vec<u8> {
+        self.doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default())
+    }
+
+    pub fn state_vector(&self) -> StateVector {
+        self.doc.transact().state_vector()
+    }
+
+    /// Changes made since `state_vector`, if any.
+    pub fn diff(&self, state_vector: &StateVector) -> Option<Vec<u8>> {
+        let diff = self.doc.transact().encode_diff_v1(state_vector);
+        (diff != EMPTY_UPDATE).then_some(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(id: &str, title: &str, deleted_at: Option<i64>) -> NoteMeta {
+        NoteMeta {
+            id: id.to_string(),
+            title: title.to_string(),
+            created_at: 1,
+            updated_at: 2,
+            deleted_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merges_entries_and_tombstones_from_both_sides() {
+        let ours = Manifest::default();
+        let theirs = Manifest::default();
+        assert!(ours.record(&meta("a", "Plan", None)));
+        assert!(!ours.record(&meta("a", "Plan", None)));
+        theirs.record(&meta("b", "Offline note", None));
+        theirs.record(&meta("a", "Plan", Some(9)));
+
+        let since = ours.state_vector();
+        ours.apply(&theirs.state()).expect("merge");
+        theirs.apply(&ours.state()).expect("merge");
+        assert!(ours.diff(&since).is_some());
+
+        let mut entries = ours.entries();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].1.title, "Offline note");
+        assert_eq!(ours.entry("a"), theirs.entry("a"));
+        assert!(Manifest::from_state(&ours.state())
+            .unwrap()
+            .entry("b")
+            .is_some());
+    }
+}
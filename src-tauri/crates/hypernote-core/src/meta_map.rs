@@ -0,0 +1,199 @@
+//! Note metadata kept in the note's own Yjs document.
+//!
+//! Title overrides, trash state, tags, pin, folder and color live in a map named `meta`
+//! next to the editor's `content` text, so they travel in ordinary `update` frames and
+//! merge like the text does. Writes to different keys all survive; concurrent writes to
+//! the same key resolve the same way on every peer (Yjs keeps the higher client id's).
+//! A key that was never written leaves the value from the note's container alone, so
+//! notes saved before the map existed keep their metadata.
+
+use yrs::updates::decoder::Decode;
+use yrs::{Any, Doc, GetString, Map, Out, ReadTxn, Transact, TransactionMut, Update};
+
+use crate::contracts::NoteMeta;
+use crate::store::{title_from_text, BODY_PREVIEW_CHARS, CONTENT_TEXT};
+
+/// Name of the Yjs map holding a note's metadata.
+pub const META_MAP: &str = "meta";
+
+const TITLE: &str = "title";
+const DELETED_AT: &str = "deletedAt";
+const TAGS: &str = "tags";
+const PINNED: &str = "pinned";
+const FOLDER: &str = "folder";
+const COLOR: &str = "color";
+
+/// One metadata field set to a new value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaChange {
+    /// A manual title; `None` derives the title from the content again.
+    Title(Option<String>),
+    Tags(Vec<String>),
+    Pinned(bool),
+    Folder(Option<String>),
+    Color(Option<String>),
+    /// Moved to the trash at the given time; `None` restores the note.
+    Deleted(Option<i64>),
+}
+
+impl MetaChange {
+    /// Tags, pin, folder or color: the fields `meta` frames carry.
+    pub fn is_organization(&self) -> bool {
+        matches!(
+            self,
+            Self::Tags(_) | Self::Pinned(_) | Self::Folder(_) | Self::Color(_)
+        )
+    }
+}
+
+/// An update recording `change` on top of `yjs_state`.
+///
+/// Like [`crate::store::append_update`], the write comes from a fresh Yjs client so it
+/// merges with whatever peers did concurrently.
+pub fn change_update(yjs_state: &[u8], change: &MetaChange) -> Result<Vec<u8>, String> {
+    let doc = Doc::new();
+    let map = doc.get_or_insert_map(META_MAP);
+    let mut txn = doc.transact_mut();
+    load(&mut txn, yjs_state).map_err(|e| format!("undecodable state: {e}"))?;
+
+    let (key, value) = match change {
+        MetaChange::Title(title) => (TITLE, Any::from(title.clone())),
+        MetaChange::Tags(tags) => (TAGS, Any::from(tags.clone())),
+        MetaChange::Pinned(pinned) => (PINNED, Any::from(*pinned)),
+        MetaChange::Folder(folder) => (FOLDER, Any::from(folder.clone())),
+        MetaChange::Color(color) => (COLOR, Any::from(color.clone())),
+        MetaChange::Deleted(at) => (DELETED_AT, Any::from(*at)),
+    };
+
+    let before = txn.state_vector();
+    map.insert(&mut txn, key, value);
+    Ok(txn.encode_diff_v1(&before))
+}
+
+/// Fills `meta` from `yjs_state` and returns the note's text.
+///
+/// Sets the body preview, every metadata key present in the map, and the derived title
+/// unless a manual one is in force.
+pub fn project(meta: &mut NoteMeta, yjs_state: &[u8]) -> String {
+    let doc = Doc::new();
+    let content = doc.get_or_insert_text(CONTENT_TEXT);
+    let map = doc.get_or_insert_map(META_MAP);
+    let mut txn = doc.transact_mut();
+    if load(&mut txn, yjs_state).is_err() {
+        return String::new();
+    }
+
+    for (key, value) in map.iter(&txn) {
+        if let Out::Any(value) = value {
+            set_field(meta, key, &value);
+        }
+    }
+
+    let text = content.get_string(&txn);
+    meta.body = Some(text.chars().take(BODY_PREVIEW_CHARS).collect());
+    if !meta.title_override {
+        meta.title = title_from_text(&text);
+    }
+    text
+}
+
+fn set_field(meta: &mut NoteMeta, key: &str, value: &Any) {
+    match key {
+        TITLE => match value {
+            Any::String(title) => {
+                meta.title = title.to_string();
+                meta.title_override = true;
+            }
+            _ => meta.title_override = false,
+        },
+        DELETED_AT => {
+            meta.deleted_at = match value {
+                Any::Number(at) => at.as_i64(),
+                _ => None,
+            }
+        }
+        TAGS => {
+            meta.tags = match value {
+                Any::Array(tags) => tags
+                    .iter()
+                    .filter_map(|tag| match tag {
+                        Any::String(tag) => Some(tag.to_string()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            }
+        }
+        PINNED => meta.pinned = matches!(value, Any::Bool(true)),
+        FOLDER => meta.folder = as_string(value),
+        COLOR => meta.color = as_string(value),
+        _ => {}
+    }
+}
+
+fn as_string(value: &Any) -> Option<String> {
+    match value {
+        Any::String(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn load(txn: &mut TransactionMut, yjs_state: &[u8]) -> Result<(), String> {
+    if yjs_state.is_empty() {
+        return Ok(());
+    }
+    let update = Update::decode_v1(yjs_state).map_err(|e| e.to_string())?;
+    txn.apply_update(update).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(state: &[u8]) -> NoteMeta {
+        let mut meta = NoteMeta::default();
+        project(&mut meta, state);
+        meta
+    }
+
+    fn merge(a: &[u8], b: &[u8]) -> Vec<u8> {
+        yrs::merge_updates_v1([a, b]).expect("merge")
+    }
+
+    #[test]
+    fn concurrent_changes_converge_in_either_order() {
+        let base = change_update(&[], &MetaChange::Pinned(false)).expect("change");
+        let ours = change_update(&base, &MetaChange::Folder(Some("work".into()))).unwrap();
+        let ours_title = change_update(&base, &MetaChange::Title(Some("Plan".into()))).unwrap();
+        let theirs = change_update(&base, &MetaChange::Title(Some("Trip".into()))).unwrap();
+
+        let ab = merge(&merge(&merge(&base, &ours), &ours_title), &theirs);
+        let ba = merge(&merge(&merge(&base, &theirs), &ours_title), &ours);
+        let (ab, ba) = (meta(&ab), meta(&ba));
+        assert_eq!(ab.folder.as_deref(), Some("work"));
+        assert!(ab.title_override);
+        assert_eq!(ab.title, ba.title);
+        assert_eq!(ab.folder, ba.folder);
+    }
+
+    #[test]
+    fn unset_keys_keep_the_stored_values() {
+        let mut legacy = NoteMeta {
+            title: "Old title".into(),
+            title_override: true,
+            tags: vec!["kept".into()],
+            ..Default::default()
+        };
+        let state = change_update(&[], &MetaChange::Deleted(Some(42))).unwrap();
+        project(&mut legacy, &state);
+        assert_eq!(legacy.title, "Old title");
+        assert_eq!(legacy.tags, vec!["kept".to_string()]);
+        assert_eq!(legacy.deleted_at, Some(42));
+
+        let restored = change_update(&state, &MetaChange::Deleted(None)).unwrap();
+        let reset = change_update(&merge(&state, &restored), &MetaChange::Title(None)).unwrap();
+        project(&mut legacy, &merge(&merge(&state, &restored), &reset));
+        assert_eq!(legacy.deleted_at, None);
+        assert!(!legacy.title_override);
+    }
+}
@@ -0,0 +1,276 @@
+//! Join-target parsing and local address helpers.
+
+/// TCP port of the LAN WebSocket server, also advertised over mDNS.
+pub const SYNC_PORT: u16 = 4747;
+
+/// What the app shows as its invite: `ws://<lan-ip>:4747`.
+pub fn share_target() -> String {
+    let host = detect_local_ipv4_host()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(default_share_host);
+    format!("ws://{host}:{SYNC_PORT}")
+}
+
+pub fn local_hostname() -> String {
+    std::process::Command::new("hostname")
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "hypernote-host".to_string())
+}
+
+pub fn normalize_join_target(raw: &str) -> Result<String, String> {
+    let target = raw.trim();
+    if target.is_empty() {
+        return Err("target is empty".to_string());
+    }
+
+    if let Some(without_scheme) = target.strip_prefix("ws://") {
+        return parse_host_port_target(without_scheme, true);
+    }
+
+    if target.contains("://") {
+        return Err("only ws:// scheme is supported".to_string());
+    }
+
+    parse_host_port_target(target, false)
+}
+
+/// Recognises relay targets (`wss://host[:port]/<workspace-id>` or
+/// `ws://host:port/<workspace-id>`, with the `?secret=<secret>` of the
+/// workspace) and returns the URL to dial.
+///
+/// Returns `None` for plain LAN targets so they fall through to `normalize_join_target`.
+pub fn parse_relay_target(raw: &str) -> Option<Result<String, String>> {
+    let target = raw.trim();
+    let (scheme, rest) = if let Some(rest) = target.strip_prefix("wss://") {
+        ("wss", rest)
+    } else if let Some(rest) = target.strip_prefix("ws://") {
+        ("ws", rest)
+    } else {
+        return None;
+    };
+
+    // `ws://host:port` without a path is a direct LAN peer.
+    let (authority, path) = match rest.split_once('/') {
+        Some((authority, path)) if scheme == "wss" || !path.is_empty() => (authority, path),
+        None if scheme == "wss" => (rest, ""),
+        _ => return None,
+    };
+
+    Some(build_relay_url(scheme, authority, path))
+}
+
+fn build_relay_url(scheme: &str, authority: &str, path: &str) -> Result<String, String> {
+    if authority.is_empty() || authority.starts_with(':') {
+        return Err("host is missing".to_string());
+    }
+
+    if authority
+        .chars()
+        .any(|ch| ch.is_whitespace() || matches!(ch, '?' | '#' | '@'))
+    {
+        return Err("target contains unsupported characters".to_string());
+    }
+
+    if let Some((_, port_raw)) = authority.rsplit_once(':') {
+        if !matches!(port_raw.parse::<u16>(), Ok(port) if port > 0) {
+            return Err("port must be between 1 and 65535".to_string());
+        }
+    }
+
+    let (path, secret) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(relay_secret(query)?)),
+        None => (path, None),
+    };
+    let workspace_id = path.trim_end_matches('/');
+    if !hypernote_protocol::is_valid_workspace_id(workspace_id) {
+        return Err("relay target must end with /<workspace-id>".to_string());
+    }
+
+    match secret {
+        Some(secret) => Ok(format!(
+            "{scheme}://{authority}/{workspace_id}?secret={secret}"
+        )),
+        None => Ok(format!("{scheme}://{authority}/{workspace_id}")),
+    }
+}
+
+fn relay_secret(query: &str) -> Result<&str, String> {
+    let secret = query
+        .strip_prefix("secret=")
+        .ok_or_else(|| "the only relay parameter is ?secret=<secret>".to_string())?;
+    let url_safe = |ch: char| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.' | '~');
+    if secret.is_empty() || !secret.chars().all(url_safe) {
+        return Err("the relay secret may use letters, digits, '-', '_', '.' and '~'".to_string());
+    }
+    Ok(secret)
+}
+
+/// A relay URL without its secret, for logs and the peer list.
+pub fn redact_relay_url(url: &str) -> &str {
+    url.split_once('?').map_or(url, |(base, _)| base)
+}
+
+fn parse_host_port_target(value: &str, require_port: bool) -> Result<String, String> {
+    if value.is_empty() {
+        return Err("target is empty".to_string());
+    }
+
+    if value
+        .chars()
+        .any(|ch| ch.is_whitespace() || matches!(ch, '/' | '?' | '#'))
+    {
+        return Err("target contains unsupported characters".to_string());
+    }
+
+    if let Some((host, port_raw)) = value.rsplit_once(':') {
+        if host.is_empty() {
+            return Err("host is missing".to_string());
+        }
+
+        if host.contains(':') {
+            return Err("target host format is invalid".to_string());
+        }
+
+        let port = match port_raw.parse::<u16>() {
+            Ok(value) if value > 0 => value,
+            _ => return Err("port must be between 1 and 65535".to_string()),
+        };
+
+        return Ok(format!("{host}:{port}"));
+    }
+
+    if require_port {
+        return Err("target must be ws://host:port".to_string());
+    }
+
+    Ok(format!("{value}:{SYNC_PORT}"))
+}
+
+pub fn is_self_join_target(addr: &str) -> bool {
+    let (host, port_raw) = match addr.rsplit_once(':') {
+        Some(value) => value,
+        None => return false,
+    };
+
+    let port = match port_raw.parse::<u16>() {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+
+    if port != SYNC_PORT {
+        return false;
+    }
+
+    let host_lower = host.to_ascii_lowercase();
+    if matches!(
+        host_lower.as_str(),
+        "localhost" | "127.0.0.1" | "0.0.0.0" | "::1"
+    ) {
+        return true;
+    }
+
+    let local_host = local_hostname().to_ascii_lowercase();
+    if host_lower == local_host || host_lower == format!("{local_host}.local") {
+        return true;
+    }
+
+    if let Some(local_ip) = detect_local_ipv4_host() {
+        if host == local_ip.to_string() {
+            return true;
+        }
+    }
+
+    false
+}
+
+pub fn detect_local_ipv4_host() -> Option<std::net::Ipv4Addr> {
+    use std::net::{IpAddr, UdpSocket};
+
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_loopback() => Some(ip),
+        _ => None,
+    }
+}
+
+pub fn default_share_host() -> String {
+    let host = local_hostname();
+    if host.contains('.') {
+        host
+    } else {
+        format!("{host}.local")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_self_join_target, normalize_join_target, parse_relay_target, redact_relay_url};
+
+    #[test]
+    fn blocks_loopback_self_join_targets() {
+        assert!(is_self_join_target("localhost:4747"));
+        assert!(is_self_join_target("127.0.0.1:4747"));
+        assert!(!is_self_join_target("127.0.0.1:4748"));
+    }
+
+    #[test]
+    fn normalizes_host_without_port() {
+        let normalized = normalize_join_target("peer-host").expect("normalize should succeed");
+        assert_eq!(normalized, "peer-host:4747");
+    }
+
+    #[test]
+    fn routes_relay_targets_separately_from_lan_targets() {
+        assert!(parse_relay_target("ws://peer-host:4747").is_none());
+        assert!(parse_relay_target("peer-host").is_none());
+
+        let relay = parse_relay_target(" wss://relay.example.com/team-notes/ ")
+            .expect("wss target is a relay")
+            .expect("relay target should parse");
+        assert_eq!(relay, "wss://relay.example.com/team-notes");
+
+        let local = parse_relay_target("ws://localhost:4848/team-notes")
+            .expect("ws target with a path is a relay")
+            .expect("relay target should parse");
+        assert_eq!(local, "ws://localhost:4848/team-notes");
+    }
+
+    #[test]
+    fn keeps_the_relay_secret_out_of_the_displayed_url() {
+        let relay = parse_relay_target("wss://relay.example.com/team-notes?secret=s3cret-value")
+            .expect("wss target is a relay")
+            .expect("relay target should parse");
+        assert_eq!(
+            relay,
+            "wss://relay.example.com/team-notes?secret=s3cret-value"
+        );
+        assert_eq!(
+            redact_relay_url(&relay),
+            "wss://relay.example.com/team-notes"
+        );
+
+        assert!(parse_relay_target("wss://relay.example.com/team?key=x")
+            .expect("wss target is a relay")
+            .is_err());
+        assert!(
+            parse_relay_target("wss://relay.example.com/team?secret=a b")
+                .expect("wss target is a relay")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_relay_targets_without_workspace_id() {
+        assert!(parse_relay_target("wss://relay.example.com")
+            .expect("wss target is a relay")
+            .is_err());
+        assert!(parse_relay_target("wss://relay.example.com/a/b")
+            .expect("wss target is a relay")
+            .is_err());
+    }
+}
@@ -0,0 +1,1804 @@
+//! A HyperNote instance on the network: note store, WS server, mDNS and peer links.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hypernote_protocol::{FrameType, MetaPayload, Reassembly, WsFrame};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::StateVector;
+
+use crate::acks::PeerAcks;
+use crate::backup::{self, BackupSettings, BACKUP_CHECK_INTERVAL};
+use crate::contracts::{
+    AttributedRange, BackupInfo, BundleImportReport, CommandAck, CompactionReport, ExportManifest,
+    ImportReport, NoteDiff, NoteDocument, NoteFilter, NoteMeta, NoteVersion, PeerConnectedEvent,
+    PeerDisconnectedEvent, PeerInfo, PeerUpdateEvent, QuickOpenHit, SearchHit, StorageIssue,
+    SyncState, TagCount, WsMessageEvent,
+};
+use crate::events::{self, EventSink};
+use crate::export::{self, ExportOptions};
+use crate::guard::{FrameRate, PeerGuard, Refusal};
+use crate::import::{self, ImportFormat, NoteDraft};
+use crate::limits::Limits;
+use crate::manifest::MANIFEST_NOTE_ID;
+use crate::meta_map::MetaChange;
+use crate::net::{
+    is_self_join_target, local_hostname, normalize_join_target, parse_relay_target,
+    redact_relay_url,
+};
+use crate::organize;
+use crate::store::NoteStore;
+use crate::{sync, unix_now_ms, SYNC_PORT};
+
+// ---------------------------------------------------------------------------
+// Node state
+// ---------------------------------------------------------------------------
+
+pub const SYNC_PAUSED_REASON: &str = "sync paused";
+/// `error` frame code for updates and messages over the size limits.
+pub const TOO_LARGE: &str = "TOO_LARGE";
+/// `error` frame code sent before closing a peer that exceeded the frame rate.
+pub const RATE_LIMITED: &str = "RATE_LIMITED";
+/// Length of the recent-open history that boosts `quick_open` results.
+const RECENT_NOTES_LIMIT: usize = 50;
+/// How often the background job looks for notes to compact.
+pub const COMPACTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Stored states smaller than this are left to manual `compact_note` runs.
+pub const COMPACT_MIN_BYTES: usize = 256 * 1024;
+
+/// peerId → channel to send outgoing WS commands to that peer.
+type WsPeers = Arc<Mutex<HashMap<String, UnboundedSender<WsPeerCommand>>>>;
+
+/// How a WS connection came to be; relay links carry frames from many remote instances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PeerLink {
+    Inbound,
+    Outbound,
+    Relay,
+}
+
+#[derive(Debug, Clone)]
+enum WsPeerCommand {
+    Text(String),
+    Close { reason: Option<String> },
+}
+
+/// An inbound connection counted against the per-address cap until dropped.
+struct Admission {
+    node: Arc<Node>,
+    ip: IpAddr,
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        if let Ok(mut guard) = self.node.guard.lock() {
+            guard.release(self.ip);
+        }
+    }
+}
+
+pub struct NodeConfig {
+    /// Stable identity for this instance (UUID, generated at startup).
+    pub peer_id: String,
+    pub store: NoteStore,
+    /// Answer peers from the store instead of leaving sync decisions to a frontend.
+    pub serve_sync: bool,
+    /// Size limits for updates, notes and WebSocket messages; also applied to `store`.
+    pub limits: Limits,
+}
+
+pub struct Node {
+    store: NoteStore,
+    peers: Mutex<HashMap<String, PeerInfo>>,
+    /// Shared (Arc) so connection tasks can clone it cheaply.
+    ws_peers: WsPeers,
+    peer_id: String,
+    serve_sync: bool,
+    limits: Limits,
+    events: Arc<dyn EventSink>,
+    /// Runtime the network tasks run on; commands may be called from threads outside it.
+    runtime: Handle,
+    /// Running mDNS daemon, kept so shutdown can unregister the service record.
+    mdns: Mutex<Option<mdns_sd::ServiceDaemon>>,
+    /// Flipped to `true` to stop the WS accept loop.
+    ws_shutdown: watch::Sender<bool>,
+    /// Whether the WS server and mDNS are running. Toggled by `set_sync_enabled`.
+    sync_enabled: AtomicBool,
+    /// Notes edited while sync was paused, handed to the frontend for catch-up on resume.
+    unsynced_note_ids: Mutex<HashSet<String>>,
+    /// Note ids opened this session, most recent first, for `quick_open`.
+    recent_note_ids: Mutex<VecDeque<String>>,
+    /// Peers heard from and the state vectors they reported, for deciding whether
+    /// compaction may collect.
+    acks: Mutex<PeerAcks>,
+    /// Inbound connection counts and bans per address.
+    guard: Mutex<PeerGuard>,
+}
+
+impl Node {
+    /// Reports each [`StorageIssue`] the store found while loading as an event.
+    pub fn new(config: NodeConfig, events: Arc<dyn EventSink>, runtime: Handle) -> Arc<Self> {
+        for issue in config.store.storage_issues() {
+            let _ = events::emit(&*events, events::STORAGE_ISSUE, issue);
+        }
+        let store = &config.store;
+        let acks = PeerAcks::load(store.data_dir().filter(|_| !store.is_read_only()));
+        Arc::new(Self {
+            store: config.store.with_limits(config.limits),
+            limits: config.limits,
+            peers: Default::default(),
+            ws_peers: Arc::new(Mutex::new(HashMap::new())),
+            peer_id: config.peer_id,
+            serve_sync: config.serve_sync,
+            events,
+            runtime,
+            mdns: Mutex::new(None),
+            ws_shutdown: watch::channel(false).0,
+            sync_enabled: AtomicBool::new(true),
+            unsynced_note_ids: Mutex::new(HashSet::new()),
+            recent_note_ids: Mutex::new(VecDeque::new()),
+            acks: Mutex::new(acks),
+            guard: Mutex::new(PeerGuard::default()),
+        })
+    }
+
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    pub fn store(&self) -> &NoteStore {
+        &self.store
+    }
+
+    // -----------------------------------------------------------------------
+    // Notes
+    // -----------------------------------------------------------------------
+
+    pub fn create_note(&self) -> Result<NoteMeta, String> {
+        let before = self.store.manifest_vector();
+        let meta = self.store.create()?;
+        self.share_manifest_since(&before);
+        Ok(meta)
+    }
+
+    /// Creates one note per Markdown file in `paths`, walking directories; see
+    /// [`import::scan_markdown`].
+    pub fn import_markdown(&self, paths: &[PathBuf]) -> ImportReport {
+        let (drafts, failures) = import::scan_markdown(paths);
+        import::import_drafts(&self.store, drafts, failures, false, |draft| {
+            self.create_from(draft)
+        })
+    }
+
+    /// Imports another app's export at `path`; a dry run only reports what it would create.
+    pub fn import_export(&self, format: ImportFormat, path: &Path, dry_run: bool) -> ImportReport {
+        let (drafts, failures) = import::scan_export(format, path);
+        import::import_drafts(&self.store, drafts, failures, dry_run, |draft| {
+            self.create_from(draft)
+        })
+    }
+
+    /// Writes every note as Markdown plus its `.yjs` container into the zip at `dest`.
+    pub fn export_workspace(
+        &self,
+        dest: &Path,
+        options: &ExportOptions,
+    ) -> Result<ExportManifest, String> {
+        export::export_workspace(&self.store, dest, options)
+    }
+
+    /// Restores a workspace export, merging each note into the one of the same id.
+    pub fn import_workspace_bundle(&self, path: &Path) -> Result<BundleImportReport, String> {
+        import::bundle::import_bundle(&self.store, path, |meta, state| {
+            self.merge_backup(meta, state)
+        })
+    }
+
+    /// Merges a note's state from a backup and shares it like a local edit.
+    fn merge_backup(&self, meta: &NoteMeta, yjs_state: &[u8]) -> Result<NoteMeta, String> {
+        let before = self.store.manifest_vector();
+        let merged = self.store.merge_backup(meta, yjs_state)?;
+        if !self.sync_enabled.load(Ordering::SeqCst) {
+            if let Ok(mut unsynced) = self.unsynced_note_ids.lock() {
+                unsynced.insert(meta.id.clone());
+            }
+        }
+        self.share_update(&meta.id, yjs_state.to_vec());
+        self.share_manifest_since(&before);
+        Ok(merged)
+    }
+
+    /// Creates a note from `draft` and shares it like a local edit.
+    fn create_from(&self, draft: &NoteDraft) -> Result<NoteMeta, String> {
+        let before = self.store.manifest_vector();
+        let (meta, update) = self.store.create_from(draft)?;
+        if !self.sync_enabled.load(Ordering::SeqCst) {
+            if let Ok(mut unsynced) = self.unsynced_note_ids.lock() {
+                unsynced.insert(meta.id.clone());
+            }
+        }
+        self.share_update(&meta.id, update);
+        self.share_manifest_since(&before);
+        Ok(meta)
+    }
+
+    pub fn open_note(&self, note_id: &str) -> Result<NoteDocument, String> {
+        let note = self
+            .store
+            .get(note_id)
+            .ok_or_else(|| format!("note not found: {note_id}"))?;
+        self.record_note_opened(note_id);
+        Ok(note)
+    }
+
+    /// Containers of every note outside the trash, or in it with `trash`, for a frontend
+    /// that loads notes from the backend rather than from disk.
+    pub fn note_containers(&self, trash: bool) -> Vec<Vec<u8>> {
+        let metas = match trash {
+            true => self.store.list_trash(),
+            false => self.store.list(),
+        };
+        metas
+            .iter()
+            .filter_map(|meta| self.store.container(&meta.id))
+            .collect()
+    }
+
+    pub fn note_container(&self, note_id: &str) -> Result<Vec<u8>, String> {
+        self.store
+            .container(note_id)
+            .ok_or_else(|| format!("note not found: {note_id}"))
+    }
+
+    /// Merges a container the frontend saved; see [`NoteStore::merge_container`].
+    pub fn save_note_container(&self, bytes: &[u8]) -> CommandAck {
+        let before = self.store.manifest_vector();
+        match self.store.merge_container(bytes) {
+            Ok(meta) => {
+                self.share_manifest_since(&before);
+                if let Some(meta) = meta {
+                    self.announce_meta(meta);
+                }
+                CommandAck {
+                    accepted: true,
+                    reason: None,
+                }
+            }
+            Err(reason) => CommandAck {
+                accepted: false,
+                reason: Some(reason),
+            },
+        }
+    }
+
+    /// Deletes a trashed note from this device; see [`NoteStore::purge`].
+    pub fn purge_note(&self, note_id: &str) -> CommandAck {
+        match self.store.purge(note_id) {
+            Ok(()) => CommandAck {
+                accepted: true,
+                reason: None,
+            },
+            Err(reason) => CommandAck {
+                accepted: false,
+                reason: Some(reason),
+            },
+        }
+    }
+
+    /// Moves `note_id` to the front of the recent-open history.
+    pub fn record_note_opened(&self, note_id: &str) {
+        let Ok(mut recent) = self.recent_note_ids.lock() else {
+            return;
+        };
+        recent.retain(|id| id != note_id);
+        recent.push_front(note_id.to_string());
+        recent.truncate(RECENT_NOTES_LIMIT);
+    }
+
+    pub fn apply_local_edit(&self, note_id: &str, update: &[u8]) -> CommandAck {
+        if !self.sync_enabled.load(Ordering::SeqCst) {
+            if let Ok(mut unsynced) = self.unsynced_note_ids.lock() {
+                unsynced.insert(note_id.to_string());
+            }
+        }
+
+        match self.apply_update(note_id, update) {
+            Ok(()) => CommandAck {
+                accepted: true,
+                reason: None,
+            },
+            Err(reason) => CommandAck {
+                accepted: false,
+                reason: Some(reason),
+            },
+        }
+    }
+
+    pub fn apply_peer_update(&self, note_id: &str, update: Vec<u8>) -> CommandAck {
+        if note_id == MANIFEST_NOTE_ID {
+            let applied = self
+                .limits
+                .check_update(update.len())
+                .and_then(|_| self.store.apply_manifest_update(&update));
+            return match applied {
+                Ok(()) => CommandAck {
+                    accepted: true,
+                    reason: None,
+                },
+                Err(reason) => CommandAck {
+                    accepted: false,
+                    reason: Some(reason),
+                },
+            };
+        }
+
+        if let Err(reason) = self.apply_update(note_id, &update) {
+            return CommandAck {
+                accepted: false,
+                reason: Some(reason),
+            };
+        }
+
+        let payload = PeerUpdateEvent {
+            note_id: note_id.to_string(),
+            update,
+        };
+
+        if let Err(error) = events::emit(&*self.events, events::PEER_UPDATE, payload) {
+            return CommandAck {
+                accepted: false,
+                reason: Some(format!("emit failed: {error}")),
+            };
+        }
+
+        CommandAck {
+            accepted: true,
+            reason: None,
+        }
+    }
+
+    /// Sets a manual title, or with `None` goes back to deriving it from the content.
+    pub fn set_note_title(&self, note_id: &str, title: Option<&str>) -> CommandAck {
+        self.change_meta(note_id, MetaChange::Title(title.map(str::to_string)))
+    }
+
+    pub fn set_note_tags(&self, note_id: &str, tags: &[String]) -> CommandAck {
+        self.change_meta(note_id, MetaChange::Tags(tags.to_vec()))
+    }
+
+    pub fn set_note_pinned(&self, note_id: &str, pinned: bool) -> CommandAck {
+        self.change_meta(note_id, MetaChange::Pinned(pinned))
+    }
+
+    pub fn set_note_color(&self, note_id: &str, color: Option<&str>) -> CommandAck {
+        self.change_meta(note_id, MetaChange::Color(color.map(str::to_string)))
+    }
+
+    pub fn move_note_to_folder(&self, note_id: &str, folder: Option<&str>) -> CommandAck {
+        self.change_meta(note_id, MetaChange::Folder(folder.map(str::to_string)))
+    }
+
+    pub fn list_tags(&self) -> Vec<TagCount> {
+        self.store.tags()
+    }
+
+    pub fn list_notes_filtered(&self, filter: &NoteFilter) -> Vec<NoteMeta> {
+        self.store.list_filtered(filter)
+    }
+
+    /// Takes a peer's `meta` frame: each field that differs from a newer copy is recorded
+    /// like a local change, so the update reaches the note file and the other peers.
+    pub fn apply_peer_meta(&self, note_id: &str, payload: &MetaPayload) -> CommandAck {
+        let Some(meta) = self.store.meta(note_id) else {
+            return CommandAck {
+                accepted: false,
+                reason: Some("note not found".to_string()),
+            };
+        };
+        for change in organize::payload_changes(&meta, payload) {
+            let ack = self.change_meta(note_id, change);
+            if !ack.accepted {
+                return ack;
+            }
+        }
+        CommandAck {
+            accepted: true,
+            reason: None,
+        }
+    }
+
+    /// Records `change` as a CRDT update and shares it like an edit.
+    ///
+    /// The update goes to the host as `NOTE_UPDATE` (the app saves it and relays it to
+    /// approved peers) and, when this node serves sync itself, straight to every peer,
+    /// followed by a `meta` frame when tags, pin, folder or color changed.
+    fn change_meta(&self, note_id: &str, change: MetaChange) -> CommandAck {
+        let organization = change.is_organization();
+        let ack = self.share_change(note_id, |store| store.change_meta(note_id, change));
+        if ack.accepted && organization && self.serve_sync {
+            if let Some(meta) = self.store.meta(note_id) {
+                self.broadcast(sync::meta_frame(&meta, &self.peer_id).to_json());
+            }
+        }
+        ack
+    }
+
+    /// Makes a change with `make` and shares the update it returns like an edit.
+    fn share_change(
+        &self,
+        note_id: &str,
+        make: impl FnOnce(&NoteStore) -> Result<(NoteMeta, Vec<u8>), String>,
+    ) -> CommandAck {
+        let before = self.store.manifest_vector();
+        let (meta, update) = match make(&self.store) {
+            Ok(value) => value,
+            Err(reason) => {
+                return CommandAck {
+                    accepted: false,
+                    reason: Some(reason),
+                }
+            }
+        };
+
+        if !self.sync_enabled.load(Ordering::SeqCst) {
+            if let Ok(mut unsynced) = self.unsynced_note_ids.lock() {
+                unsynced.insert(note_id.to_string());
+            }
+        }
+        self.share_update(note_id, update);
+        self.share_manifest_since(&before);
+        self.announce_meta(meta);
+        CommandAck {
+            accepted: true,
+            reason: None,
+        }
+    }
+
+    /// Hands an update this node made to the host as `NOTE_UPDATE` and, when serving sync
+    /// itself, to every peer.
+    fn share_update(&self, note_id: &str, update: Vec<u8>) {
+        if self.serve_sync {
+            let frame = WsFrame::binary(FrameType::Update, note_id, &self.peer_id, update.clone());
+            self.broadcast(frame.to_json());
+        }
+
+        let payload = PeerUpdateEvent {
+            note_id: note_id.to_string(),
+            update,
+        };
+        let _ = events::emit(&*self.events, events::NOTE_UPDATE, payload);
+    }
+
+    fn share_manifest_since(&self, before: &StateVector) {
+        if let Some(update) = self.store.manifest_diff(before) {
+            self.share_update(MANIFEST_NOTE_ID, update);
+        }
+    }
+
+    /// Containers found damaged at startup, and how their notes were recovered.
+    pub fn list_storage_issues(&self) -> Vec<StorageIssue> {
+        self.store.storage_issues()
+    }
+
+    /// Asks every connected peer for whatever of `note_id` this device lacks, e.g. after its
+    /// container was quarantined. Replies arrive as ordinary updates.
+    pub fn request_note_from_peers(&self, note_id: &str) -> CommandAck {
+        if !hypernote_protocol::is_valid_workspace_id(note_id) {
+            return CommandAck {
+                accepted: false,
+                reason: Some("invalid note id".to_string()),
+            };
+        }
+        if self.ws_peers.lock().map_or(true, |peers| peers.is_empty()) {
+            return CommandAck {
+                accepted: false,
+                reason: Some("no peers connected".to_string()),
+            };
+        }
+
+        let vector = self
+            .store
+            .state(note_id)
+            .and_then(|state| yrs::encode_state_vector_from_update_v1(&state).ok())
+            .unwrap_or_else(|| StateVector::default().encode_v1());
+        let frame = WsFrame::binary(FrameType::StateVector, note_id, &self.peer_id, vector);
+        self.broadcast(frame.to_json())
+    }
+
+    /// Compacts a note's stored state. Deleted content is collected only once there is a
+    /// known peer and every one has reported a state vector covering the note's current
+    /// state since its last change; deletions leave state vectors as they are, hence the time.
+    pub fn compact_note(&self, note_id: &str) -> Result<CompactionReport, String> {
+        self.store
+            .compact(note_id, self.acknowledged_by_all(note_id))
+    }
+
+    /// Runs [`Self::compact_note`] every [`COMPACTION_INTERVAL`] on notes of at least
+    /// [`COMPACT_MIN_BYTES`] that changed size since their last run. A mirroring store
+    /// leaves that to the process that owns the directory.
+    pub fn start_compaction(self: &Arc<Self>) {
+        if self.store.is_read_only() {
+            return;
+        }
+        let node = Arc::clone(self);
+        std::thread::spawn(move || {
+            let mut compacted: HashMap<String, usize> = HashMap::new();
+            loop {
+                std::thread::sleep(COMPACTION_INTERVAL);
+                for note_id in node.store.all_note_ids() {
+                    let size = node.store.state(&note_id).map_or(0, |state| state.len());
+                    if size < COMPACT_MIN_BYTES || compacted.get(&note_id) == Some(&size) {
+                        continue;
+                    }
+                    match node.compact_note(&note_id) {
+                        Ok(report) => {
+                            eprintln!(
+                                "[hypernote] compacted {note_id}: {} -> {} bytes",
+                                report.before_bytes, report.after_bytes
+                            );
+                            compacted.insert(note_id, report.after_bytes as usize);
+                        }
+                        Err(reason) => eprintln!("[hypernote] cannot compact {note_id}: {reason}"),
+                    }
+                }
+            }
+        });
+    }
+
+    /// Checks every [`BACKUP_CHECK_INTERVAL`] whether the newest backup is older than the
+    /// configured interval, and if so writes a new one and rotates the old ones out.
+    pub fn start_backups(self: &Arc<Self>) {
+        let node = Arc::clone(self);
+        std::thread::spawn(move || loop {
+            if let Err(reason) = node.backup_if_due() {
+                eprintln!("[hypernote] backup failed: {reason}");
+            }
+            std::thread::sleep(BACKUP_CHECK_INTERVAL);
+        });
+    }
+
+    fn backup_if_due(&self) -> Result<(), String> {
+        let data_dir = self.store.data_dir().ok_or("no data directory")?;
+        let settings = BackupSettings::load(data_dir)?;
+        // A mirror shares its directory with the process that owns it, which backs it up.
+        if !settings.enabled || self.store.is_read_only() {
+            return Ok(());
+        }
+        let dir = settings.backup_dir(data_dir);
+        let now = unix_now_ms();
+        let interval = settings.interval_hours.max(1) as i64 * 3_600_000;
+        let newest = backup::list_backups(&dir)
+            .first()
+            .map(|info| info.created_at);
+        if newest.is_some_and(|at| now - at < interval) {
+            return Ok(());
+        }
+        let info = backup::create_backup(&self.store, &dir, now)?;
+        let notes = info.note_count.unwrap_or_default();
+        eprintln!("[hypernote] backed up {notes} note(s) to {}", info.path);
+        for name in backup::rotate(&dir, &settings) {
+            eprintln!("[hypernote] removed old backup {name}");
+        }
+        Ok(())
+    }
+
+    pub fn backup_settings(&self) -> Result<BackupSettings, String> {
+        BackupSettings::load(self.store.data_dir().ok_or("no data directory")?)
+    }
+
+    pub fn set_backup_settings(&self, settings: &BackupSettings) -> CommandAck {
+        let saved = match self.store.data_dir() {
+            Some(data_dir) => settings.save(data_dir),
+            None => Err("no data directory".to_string()),
+        };
+        match saved {
+            Ok(()) => CommandAck {
+                accepted: true,
+                reason: None,
+            },
+            Err(reason) => CommandAck {
+                accepted: false,
+                reason: Some(reason),
+            },
+        }
+    }
+
+    /// Backups in the configured directory, newest first.
+    pub fn list_backups(&self) -> Vec<BackupInfo> {
+        let Some(data_dir) = self.store.data_dir() else {
+            return Vec::new();
+        };
+        match BackupSettings::load(data_dir) {
+            Ok(settings) => backup::list_backups(&settings.backup_dir(data_dir)),
+            Err(reason) => {
+                eprintln!("[hypernote] {reason}");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Merges the backup called `name` back in, as [`Self::import_workspace_bundle`] does.
+    pub fn restore_backup(&self, name: &str) -> Result<BundleImportReport, String> {
+        let data_dir = self.store.data_dir().ok_or("no data directory")?;
+        let dir = BackupSettings::load(data_dir)?.backup_dir(data_dir);
+        self.import_workspace_bundle(&backup::find_backup(&dir, name)?)
+    }
+
+    /// Rejects `update` frames whose payload is over the update limit.
+    fn check_frame(&self, frame: &WsFrame) -> Result<(), String> {
+        match frame.frame_type {
+            FrameType::Update => frame
+                .binary_payload()
+                .map_or(Ok(()), |update| self.limits.check_update(update.len())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Notes the sender of `frame`, received `at` over a connection from `origin`, as a known
+    /// peer and keeps the state vector it carries, if any. Peers are known by sender id and
+    /// origin together, so an id used from elsewhere counts as another peer.
+    fn record_ack(&self, frame: &WsFrame, origin: &str, at: i64) {
+        if frame.sender_id == self.peer_id {
+            return;
+        }
+        let vector = match frame.frame_type {
+            FrameType::StateVector => frame
+                .binary_payload()
+                .ok()
+                .filter(|vector| StateVector::decode_v1(vector).is_ok()),
+            _ => None,
+        };
+        let Ok(mut acks) = self.acks.lock() else {
+            return;
+        };
+        let ack = vector
+            .as_deref()
+            .map(|vector| (frame.note_id.as_str(), vector));
+        acks.record(&format!("{}@{origin}", frame.sender_id), ack, at);
+    }
+
+    fn acknowledged_by_all(&self, note_id: &str) -> bool {
+        let (Some(state), Some(meta)) = (self.store.state(note_id), self.store.meta(note_id))
+        else {
+            return false;
+        };
+        let Some(current) = yrs::encode_state_vector_from_update_v1(&state)
+            .ok()
+            .and_then(|vector| StateVector::decode_v1(&vector).ok())
+        else {
+            return false;
+        };
+        self.acks
+            .lock()
+            .is_ok_and(|acks| acks.all_caught_up(note_id, &current, meta.updated_at, unix_now_ms()))
+    }
+
+    /// Full workspace manifest, sent to a peer once it is approved.
+    pub fn workspace_manifest(&self) -> Vec<u8> {
+        self.store.manifest_state()
+    }
+
+    /// Notes the manifest lists that this device has no content for yet.
+    pub fn list_missing_notes(&self) -> Vec<String> {
+        self.store.missing_note_ids()
+    }
+
+    /// Merges `update` and announces the metadata when a title, organization field or
+    /// trash state moved, whether from an edit or from a peer's metadata change.
+    fn apply_update(&self, note_id: &str, update: &[u8]) -> Result<(), String> {
+        let before = self.store.meta(note_id);
+        let manifest_before = self.store.manifest_vector();
+        let meta = self.store.apply_update(note_id, update)?;
+        self.share_manifest_since(&manifest_before);
+        if !before.is_some_and(|before| same_meta_fields(&before, &meta)) {
+            self.announce_meta(meta);
+        }
+        Ok(())
+    }
+
+    fn announce_meta(&self, meta: NoteMeta) {
+        let _ = events::emit(&*self.events, events::NOTE_META_CHANGED, meta);
+    }
+
+    pub fn list_notes(&self) -> Vec<NoteMeta> {
+        self.store.list()
+    }
+
+    /// Saved versions of a note, newest first.
+    pub fn list_note_versions(&self, note_id: &str) -> Vec<NoteVersion> {
+        self.store.versions(note_id)
+    }
+
+    pub fn open_note_version(&self, note_id: &str, version: u32) -> Result<NoteDocument, String> {
+        self.store.version(note_id, version)
+    }
+
+    /// Line and word diff between two versions of a note, `None` being its current content.
+    pub fn diff_note(
+        &self,
+        note_id: &str,
+        from: Option<u32>,
+        to: Option<u32>,
+    ) -> Result<NoteDiff, String> {
+        self.store.diff(note_id, from, to)
+    }
+
+    /// Who wrote each part of a note, as device ids; see [`crate::attribution`].
+    pub fn get_note_attribution(&self, note_id: &str) -> Result<Vec<AttributedRange>, String> {
+        self.store.attribution(note_id)
+    }
+
+    /// Brings a note's content back to `version` with a new update, shared like an edit.
+    pub fn restore_note_version(&self, note_id: &str, version: u32) -> CommandAck {
+        self.share_change(note_id, |store| store.restore_version(note_id, version))
+    }
+
+    pub fn search_notes(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.store.search(query, limit)
+    }
+
+    pub fn quick_open(&self, query: &str, limit: usize) -> Vec<QuickOpenHit> {
+        let recent: Vec<String> = self
+            .recent_note_ids
+            .lock()
+            .map(|recent| recent.iter().cloned().collect())
+            .unwrap_or_default();
+        self.store.quick_open(query, limit, &recent)
+    }
+
+    pub fn delete_note_to_trash(&self, note_id: &str) -> CommandAck {
+        self.change_meta(note_id, MetaChange::Deleted(Some(unix_now_ms())))
+    }
+
+    pub fn restore_note(&self, note_id: &str) -> CommandAck {
+        self.change_meta(note_id, MetaChange::Deleted(None))
+    }
+
+    pub fn list_peers(&self) -> Vec<PeerInfo> {
+        let peers = match self.peers.lock() {
+            Ok(value) => value,
+            Err(_) => return Vec::new(),
+        };
+
+        peers.values().cloned().collect()
+    }
+
+    // -----------------------------------------------------------------------
+    // Peer messaging
+    // -----------------------------------------------------------------------
+
+    pub fn broadcast(&self, payload: String) -> CommandAck {
+        let ws_peers = match self.ws_peers.lock() {
+            Ok(value) => value,
+            Err(_) => {
+                return CommandAck {
+                    accepted: false,
+                    reason: Some("ws_peers poisoned".to_string()),
+                }
+            }
+        };
+
+        let mut failed = 0usize;
+        for tx in ws_peers.values() {
+            if tx.send(WsPeerCommand::Text(payload.clone())).is_err() {
+                failed += 1;
+            }
+        }
+
+        CommandAck {
+            accepted: true,
+            reason: if failed > 0 {
+                Some(format!("{failed} peer(s) unreachable"))
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn send_to_peer(&self, peer_id: &str, payload: String) -> CommandAck {
+        self.command_peer(peer_id, WsPeerCommand::Text(payload))
+    }
+
+    pub fn disconnect_peer(&self, peer_id: &str, reason: Option<String>) -> CommandAck {
+        self.command_peer(peer_id, WsPeerCommand::Close { reason })
+    }
+
+    fn command_peer(&self, peer_id: &str, command: WsPeerCommand) -> CommandAck {
+        let ws_peers = match self.ws_peers.lock() {
+            Ok(value) => value,
+            Err(_) => {
+                return CommandAck {
+                    accepted: false,
+                    reason: Some("ws_peers poisoned".to_string()),
+                }
+            }
+        };
+
+        if let Some(tx) = ws_peers.get(peer_id) {
+            let ok = tx.send(command).is_ok();
+            CommandAck {
+                accepted: ok,
+                reason: if ok {
+                    None
+                } else {
+                    Some("channel closed".to_string())
+                },
+            }
+        } else {
+            CommandAck {
+                accepted: false,
+                reason: Some(format!("peer not found: {peer_id}")),
+            }
+        }
+    }
+
+    /// Dials a LAN peer (`host[:port]`, `ws://host:port`) or a relay workspace URL.
+    pub fn join(self: &Arc<Self>, target: &str) -> CommandAck {
+        if !self.sync_enabled.load(Ordering::SeqCst) {
+            return CommandAck {
+                accepted: false,
+                reason: Some(SYNC_PAUSED_REASON.to_string()),
+            };
+        }
+
+        if self.connected_peers() >= self.limits.max_peers {
+            return CommandAck {
+                accepted: false,
+                reason: Some(format!("peer limit reached ({})", self.limits.max_peers)),
+            };
+        }
+
+        if let Some(relay) = parse_relay_target(target) {
+            let url = match relay {
+                Ok(value) => value,
+                Err(reason) => {
+                    return CommandAck {
+                        accepted: false,
+                        reason: Some(reason),
+                    }
+                }
+            };
+
+            let node = Arc::clone(self);
+            self.runtime.spawn(async move {
+                node.connect_to_relay_ws(url).await;
+            });
+
+            return CommandAck {
+                accepted: true,
+                reason: None,
+            };
+        }
+
+        let addr = match normalize_join_target(target) {
+            Ok(value) => value,
+            Err(reason) => {
+                return CommandAck {
+                    accepted: false,
+                    reason: Some(reason),
+                }
+            }
+        };
+
+        if is_self_join_target(&addr) {
+            return CommandAck {
+                accepted: false,
+                reason: Some("cannot join your own workspace target".to_string()),
+            };
+        }
+
+        let node = Arc::clone(self);
+        self.runtime.spawn(async move {
+            node.connect_to_peer_ws(addr).await;
+        });
+
+        CommandAck {
+            accepted: true,
+            reason: None,
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Sync pause / resume
+    // -----------------------------------------------------------------------
+
+    /// Turns LAN networking on or off without quitting.
+    ///
+    /// Pausing stops the accept loop and mDNS and closes every peer; local edits keep
+    /// landing in the note store and are remembered in `unsynced_note_ids`. Resuming
+    /// restarts both services and hands those note ids to the frontend, which offers
+    /// them to the next approved peer as full state plus a state vector.
+    pub fn set_sync_enabled(self: &Arc<Self>, enabled: bool) -> CommandAck {
+        if self.sync_enabled.swap(enabled, Ordering::SeqCst) == enabled {
+            return CommandAck {
+                accepted: true,
+                reason: None,
+            };
+        }
+
+        if enabled {
+            self.start_sync_services();
+        } else {
+            drop(self.stop_sync_services());
+            self.close_all_peers(SYNC_PAUSED_REASON);
+        }
+
+        let unsynced_note_ids = if enabled {
+            self.unsynced_note_ids
+                .lock()
+                .map(|mut unsynced| unsynced.drain().collect())
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        let state = SyncState {
+            enabled,
+            unsynced_note_ids,
+            bans: self.bans(),
+        };
+        if let Err(error) = events::emit(&*self.events, events::SYNC_STATE_CHANGED, state) {
+            return CommandAck {
+                accepted: true,
+                reason: Some(format!("emit failed: {error}")),
+            };
+        }
+
+        CommandAck {
+            accepted: true,
+            reason: None,
+        }
+    }
+
+    pub fn sync_state(&self) -> SyncState {
+        SyncState {
+            enabled: self.sync_enabled.load(Ordering::SeqCst),
+            unsynced_note_ids: self
+                .unsynced_note_ids
+                .lock()
+                .map(|unsynced| unsynced.iter().cloned().collect())
+                .unwrap_or_default(),
+            bans: self.bans(),
+        }
+    }
+
+    fn bans(&self) -> Vec<crate::contracts::PeerBan> {
+        self.guard
+            .lock()
+            .map(|mut guard| guard.bans(unix_now_ms()))
+            .unwrap_or_default()
+    }
+
+    fn connected_peers(&self) -> usize {
+        self.ws_peers.lock().map(|peers| peers.len()).unwrap_or(0)
+    }
+
+    /// Counts an inbound connection from `ip` against the limits; `None` turns it away.
+    fn admit(self: &Arc<Self>, ip: IpAddr) -> Option<Admission> {
+        let peers = self.connected_peers();
+        let refusal = {
+            let mut guard = self.guard.lock().ok()?;
+            guard.admit(ip, peers, &self.limits, unix_now_ms()).err()
+        };
+        match refusal {
+            None => {
+                return Some(Admission {
+                    node: Arc::clone(self),
+                    ip,
+                })
+            }
+            Some(Refusal::Banned) => {}
+            Some(Refusal::Refused(reason)) => {
+                eprintln!("[hypernote] refused connection from {ip}: {reason}");
+            }
+            Some(Refusal::NewBan(ban)) => {
+                eprintln!("[hypernote] banned {ip}: {}", ban.reason);
+                self.announce_sync_state();
+            }
+        }
+        None
+    }
+
+    fn ban(&self, ip: IpAddr, reason: &str) {
+        if let Ok(mut guard) = self.guard.lock() {
+            guard.ban(ip, reason, &self.limits, unix_now_ms());
+        }
+        eprintln!("[hypernote] banned {ip}: {reason}");
+        self.announce_sync_state();
+    }
+
+    fn announce_sync_state(&self) {
+        let _ = events::emit(&*self.events, events::SYNC_STATE_CHANGED, self.sync_state());
+    }
+
+    /// Starts the WS accept loop and mDNS advertisement/discovery.
+    pub fn start_sync_services(self: &Arc<Self>) {
+        // Subscribe before spawning so a pause issued right after cannot be missed.
+        let shutdown_rx = self.ws_shutdown.subscribe();
+
+        let node = Arc::clone(self);
+        self.runtime.spawn(async move {
+            node.run_ws_server(shutdown_rx).await;
+        });
+
+        // mdns-sd uses sync channels, so discovery gets its own OS thread.
+        let node = Arc::clone(self);
+        std::thread::spawn(move || {
+            node.run_mdns();
+        });
+    }
+
+    /// Stops the accept loop and withdraws the mDNS record. The goodbye packets go out on
+    /// a blocking task; await the handle to be sure they were sent.
+    pub fn stop_sync_services(&self) -> tokio::task::JoinHandle<()> {
+        let _ = self.ws_shutdown.send(true);
+        self.unregister_mdns()
+    }
+
+    // -----------------------------------------------------------------------
+    // WebSocket connection handler (shared by server-accept and client-connect)
+    // -----------------------------------------------------------------------
+
+    async fn handle_ws_connection<S>(
+        self: Arc<Self>,
+        ws: tokio_tungstenite::WebSocketStream<S>,
+        addr: String,
+        link: PeerLink,
+    ) where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::{
+            protocol::{
+                frame::{coding::CloseCode, CloseFrame},
+                Message,
+            },
+            Error as WsError, Utf8Bytes,
+        };
+
+        let peer_id = uuid::Uuid::new_v4().to_string();
+        let (mut sink, mut stream) = ws.split();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<WsPeerCommand>();
+
+        if self.serve_sync {
+            let hello = sync::hello(&self.store, &self.peer_id);
+            let _ = tx.send(WsPeerCommand::Text(hello.to_json()));
+            let manifest = sync::manifest(&self.store, &self.peer_id);
+            let _ = tx.send(WsPeerCommand::Text(manifest.to_json()));
+        }
+
+        // Register peer sender — Arc<Mutex<...>> is 'static, safe across awaits.
+        if let Ok(mut peers) = self.ws_peers.lock() {
+            peers.insert(peer_id.clone(), tx.clone());
+        }
+
+        if let Ok(mut peers) = self.peers.lock() {
+            peers.insert(
+                peer_id.clone(),
+                PeerInfo {
+                    peer_id: peer_id.clone(),
+                    ws_url: if link == PeerLink::Relay {
+                        redact_relay_url(&addr).to_string()
+                    } else {
+                        format!("ws://{addr}")
+                    },
+                    status: "CONNECTED".to_string(),
+                    note_ids: Vec::new(),
+                },
+            );
+        }
+
+        let _ = events::emit(
+            &*self.events,
+            events::PEER_CONNECTED,
+            PeerConnectedEvent {
+                peer_id: peer_id.clone(),
+                addr: redact_relay_url(&addr).to_string(),
+                outbound: link != PeerLink::Inbound,
+                relay: link == PeerLink::Relay,
+            },
+        );
+
+        // Forward outgoing messages to the WS sink in a background task, splitting payloads
+        // too large for one message.
+        let chunk_bytes = self.limits.chunk_bytes();
+        let mut sink_task = self.runtime.spawn(async move {
+            'send: while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    WsPeerCommand::Text(msg) => {
+                        let frame = (msg.len() > chunk_bytes)
+                            .then(|| WsFrame::parse(&msg).ok())
+                            .flatten();
+                        let pieces = match frame {
+                            Some(frame) => frame
+                                .split(chunk_bytes)
+                                .iter()
+                                .map(WsFrame::to_json)
+                                .collect(),
+                            None => vec![msg],
+                        };
+                        for piece in pieces {
+                            if sink.send(Message::Text(piece.into())).await.is_err() {
+                                break 'send;
+                            }
+                        }
+                    }
+                    WsPeerCommand::Close { reason } => {
+                        let close_reason =
+                            reason.unwrap_or_else(|| "peer disconnected".to_string());
+                        let _ = sink
+                            .send(Message::Close(Some(CloseFrame {
+                                code: CloseCode::Normal,
+                                reason: Utf8Bytes::from(close_reason),
+                            })))
+                            .await;
+                        break;
+                    }
+                }
+            }
+        });
+
+        // Read incoming messages and hand them to the host. Inbound peers are held to the
+        // frame rate; relays and peers we dialled are trusted.
+        let inbound_ip = (link == PeerLink::Inbound)
+            .then(|| addr.parse::<SocketAddr>().ok())
+            .flatten()
+            .map(|addr| addr.ip());
+        let mut rate = (link == PeerLink::Inbound).then(|| FrameRate::new(&self.limits));
+        // Acks count for the peer at the other end: a direct link speaks for the first sender
+        // id it uses, a relay for every member of the workspace.
+        let origin = match (link, inbound_ip) {
+            (PeerLink::Relay, _) => redact_relay_url(&addr).to_string(),
+            (_, Some(ip)) => ip.to_string(),
+            _ => addr.clone(),
+        };
+        let mut sender: Option<String> = None;
+        let mut reassembly = Reassembly::default();
+        let mut rejected = None;
+        while let Some(msg_result) = stream.next().await {
+            if let Some(rate) = rate.as_mut() {
+                if !rate.allow(&self.limits, Instant::now()) {
+                    let reason = format!(
+                        "more than {} frames per second",
+                        self.limits.max_frames_per_second
+                    );
+                    if let Some(ip) = inbound_ip {
+                        self.ban(ip, &reason);
+                    }
+                    rejected = Some((RATE_LIMITED, reason));
+                    break;
+                }
+            }
+            match msg_result {
+                Ok(Message::Text(mut text)) => {
+                    let frame = match WsFrame::parse(&text) {
+                        Ok(frame) if frame.chunk().is_some() => {
+                            match reassembly.push(frame, self.limits.max_update_bytes) {
+                                Ok(Some(frame)) => {
+                                    text = frame.to_json().into();
+                                    Some(frame)
+                                }
+                                Ok(None) => continue,
+                                Err(reason) => {
+                                    let error = WsFrame::error(&self.peer_id, TOO_LARGE, &reason);
+                                    let _ = tx.send(WsPeerCommand::Text(error.to_json()));
+                                    continue;
+                                }
+                            }
+                        }
+                        frame => frame.ok(),
+                    };
+                    if let Some(Err(reason)) = frame.as_ref().map(|frame| self.check_frame(frame)) {
+                        let error = WsFrame::error(&self.peer_id, TOO_LARGE, &reason);
+                        let _ = tx.send(WsPeerCommand::Text(error.to_json()));
+                        continue;
+                    }
+                    if let Some(frame) = &frame {
+                        let sender = sender.get_or_insert_with(|| frame.sender_id.clone());
+                        if link == PeerLink::Relay || *sender == frame.sender_id {
+                            self.record_ack(frame, &origin, unix_now_ms());
+                        }
+                    }
+                    if let Some(frame) = frame.filter(|_| self.serve_sync) {
+                        for reply in sync::respond(&self.store, &self.peer_id, &frame) {
+                            let _ = tx.send(WsPeerCommand::Text(reply.to_json()));
+                        }
+                    }
+
+                    let _ = events::emit(
+                        &*self.events,
+                        events::WS_MESSAGE,
+                        WsMessageEvent {
+                            peer_id: peer_id.clone(),
+                            payload: text.to_string(),
+                        },
+                    );
+                }
+                // Over the message or frame limit: the rest of the stream can't be trusted.
+                Err(WsError::Capacity(e)) => {
+                    rejected = Some((TOO_LARGE, e.to_string()));
+                    break;
+                }
+                Ok(Message::Close(_)) | Err(_) => break,
+                _ => {}
+            }
+        }
+
+        if let Some((code, reason)) = &rejected {
+            let error = WsFrame::error(&self.peer_id, code, reason);
+            let _ = tx.send(WsPeerCommand::Text(error.to_json()));
+            let _ = tx.send(WsPeerCommand::Close {
+                reason: Some(reason.clone()),
+            });
+            let _ = tokio::time::timeout(Duration::from_secs(1), &mut sink_task).await;
+        }
+        sink_task.abort();
+
+        // Deregister peer.
+        if let Ok(mut peers) = self.ws_peers.lock() {
+            peers.remove(&peer_id);
+        }
+
+        if let Ok(mut peers) = self.peers.lock() {
+            peers.remove(&peer_id);
+        }
+
+        let _ = events::emit(
+            &*self.events,
+            events::PEER_DISCONNECTED,
+            PeerDisconnectedEvent { peer_id },
+        );
+    }
+
+    // -----------------------------------------------------------------------
+    // WebSocket server (TCP 4747)
+    // -----------------------------------------------------------------------
+
+    async fn run_ws_server(self: Arc<Self>, mut shutdown_rx: watch::Receiver<bool>) {
+        use tokio::net::TcpListener;
+
+        let bind_addr = format!("0.0.0.0:{SYNC_PORT}");
+        // A just-paused server may still be releasing the port when sync is resumed.
+        let mut bind_attempts = 0;
+        let listener = loop {
+            match TcpListener::bind(&bind_addr).await {
+                Ok(l) => break l,
+                Err(e) if bind_attempts < WS_BIND_RETRIES => {
+                    bind_attempts += 1;
+                    eprintln!("[hypernote] WS server bind failed on {SYNC_PORT}, retrying: {e}");
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+                Err(e) => {
+                    eprintln!("[hypernote] WS server bind failed on {SYNC_PORT}: {e}");
+                    return;
+                }
+            }
+        };
+
+        eprintln!("[hypernote] WS server listening on {bind_addr}");
+
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown_rx.changed() => break,
+            };
+
+            match accepted {
+                Ok((stream, addr)) => {
+                    // Dropping the stream closes connections over the limits unanswered.
+                    let Some(admission) = self.admit(addr.ip()) else {
+                        continue;
+                    };
+                    let node = Arc::clone(&self);
+                    let addr_str = addr.to_string();
+                    self.runtime.spawn(async move {
+                        let _admission = admission;
+                        let config = Some(node.limits.ws_config());
+                        match tokio_tungstenite::accept_async_with_config(stream, config).await {
+                            Ok(ws) => {
+                                node.handle_ws_connection(ws, addr_str, PeerLink::Inbound)
+                                    .await
+                            }
+                            Err(e) => eprintln!("[hypernote] WS handshake error: {e}"),
+                        }
+                    });
+                }
+                Err(e) => eprintln!("[hypernote] WS accept error: {e}"),
+            }
+        }
+
+        eprintln!("[hypernote] WS server stopped");
+    }
+
+    // -----------------------------------------------------------------------
+    // WebSocket client (connects to a discovered peer)
+    // -----------------------------------------------------------------------
+
+    async fn connect_to_peer_ws(self: Arc<Self>, addr: String) {
+        let url = format!("ws://{addr}/");
+
+        let config = Some(self.limits.ws_config());
+        match tokio_tungstenite::connect_async_with_config(&url, config, false).await {
+            Ok((ws, _)) => {
+                self.handle_ws_connection(ws, addr, PeerLink::Outbound)
+                    .await
+            }
+            Err(e) => eprintln!("[hypernote] WS connect to {addr} failed: {e}"),
+        }
+    }
+
+    async fn connect_to_relay_ws(self: Arc<Self>, url: String) {
+        let config = Some(self.limits.ws_config());
+        match tokio_tungstenite::connect_async_with_config(&url, config, false).await {
+            Ok((ws, _)) => self.handle_ws_connection(ws, url, PeerLink::Relay).await,
+            Err(e) => eprintln!(
+                "[hypernote] relay connect to {} failed: {e}",
+                redact_relay_url(&url)
+            ),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // mDNS registration + peer discovery
+    // -----------------------------------------------------------------------
+
+    fn run_mdns(self: Arc<Self>) {
+        use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+        let mdns = match ServiceDaemon::new() {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("[hypernote] mDNS daemon start failed: {e}");
+                return;
+            }
+        };
+
+        let host = format!("{}.local.", local_hostname());
+        let service_info = ServiceInfo::new(
+            MDNS_SERVICE_TYPE,
+            &self.peer_id,
+            &host,
+            "", // IP: auto-detect from bound interfaces
+            SYNC_PORT,
+            None,
+        );
+
+        // Install under the lock and re-check the toggle there: a pause that ran before this
+        // point found no daemon to unregister, and a resume may already have started another.
+        let installed = match self.mdns.lock() {
+            Ok(mut slot) if slot.is_none() && self.sync_enabled.load(Ordering::SeqCst) => {
+                *slot = Some(mdns.clone());
+                true
+            }
+            _ => false,
+        };
+        if !installed {
+            let _ = mdns.shutdown();
+            return;
+        }
+
+        match service_info {
+            Ok(info) => {
+                if let Err(e) = mdns.register(info) {
+                    eprintln!("[hypernote] mDNS register failed: {e}");
+                } else {
+                    eprintln!(
+                        "[hypernote] mDNS registered as {}",
+                        mdns_fullname(&self.peer_id)
+                    );
+                }
+            }
+            Err(e) => eprintln!("[hypernote] mDNS ServiceInfo build failed: {e}"),
+        }
+
+        let receiver = match mdns.browse(MDNS_SERVICE_TYPE) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("[hypernote] mDNS browse failed: {e}");
+                return;
+            }
+        };
+
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok(ServiceEvent::ServiceResolved(info)) => {
+                    let name = info.get_fullname();
+
+                    // Skip self.
+                    if name.contains(&self.peer_id) {
+                        continue;
+                    }
+
+                    for addr in info.get_addresses() {
+                        let ws_addr = format!("{addr}:{SYNC_PORT}");
+                        let node = Arc::clone(&self);
+                        self.runtime.spawn(async move {
+                            node.connect_to_peer_ws(ws_addr).await;
+                        });
+                    }
+                }
+                Ok(ServiceEvent::ServiceRemoved(_, name)) => {
+                    eprintln!("[hypernote] mDNS peer removed: {name}");
+                }
+                Ok(ServiceEvent::SearchStopped(_)) => break,
+                Ok(_) => {}
+                Err(_) if receiver.is_disconnected() => break,
+                Err(_) => {
+                    // Timeout — keep looping.
+                }
+            }
+        }
+
+        eprintln!("[hypernote] mDNS browse stopped");
+    }
+
+    fn unregister_mdns(&self) -> tokio::task::JoinHandle<()> {
+        let daemon = match self.mdns.lock() {
+            Ok(mut slot) => slot.take(),
+            Err(_) => None,
+        };
+        let fullname = mdns_fullname(&self.peer_id);
+
+        // Waiting for the daemon's answer blocks, so keep it off the runtime's workers.
+        self.runtime.spawn_blocking(move || {
+            let Some(daemon) = daemon else {
+                return;
+            };
+
+            // Unregister sends goodbye packets so peers drop us now rather than after the TTL.
+            match daemon.unregister(&fullname) {
+                Ok(status) => {
+                    let _ = status.recv_timeout(Duration::from_secs(1));
+                }
+                Err(e) => eprintln!("[hypernote] mDNS unregister failed: {e}"),
+            }
+
+            if let Err(e) = daemon.shutdown() {
+                eprintln!("[hypernote] mDNS shutdown failed: {e}");
+            }
+        })
+    }
+
+    // -----------------------------------------------------------------------
+    // Shutdown
+    // -----------------------------------------------------------------------
+
+    /// Sends a close frame with `reason` to every connected peer.
+    pub fn close_all_peers(&self, reason: &str) {
+        if let Ok(peers) = self.ws_peers.lock() {
+            for tx in peers.values() {
+                let _ = tx.send(WsPeerCommand::Close {
+                    reason: Some(reason.to_string()),
+                });
+            }
+        }
+    }
+
+    /// Resolves once every peer connection has finished closing.
+    pub async fn wait_for_peers_drained(&self) {
+        loop {
+            let drained = self
+                .ws_peers
+                .lock()
+                .map(|peers| peers.is_empty())
+                .unwrap_or(true);
+            if drained {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+const WS_BIND_RETRIES: u32 = 5;
+const MDNS_SERVICE_TYPE: &str = "_hypernote._tcp.local.";
+
+/// Compares what note lists show; `updated_at` and the body preview move on every edit.
+fn same_meta_fields(a: &NoteMeta, b: &NoteMeta) -> bool {
+    a.title == b.title
+        && a.title_override == b.title_override
+        && a.tags == b.tags
+        && a.pinned == b.pinned
+        && a.folder == b.folder
+        && a.color == b.color
+        && a.deleted_at == b.deleted_at
+}
+
+fn mdns_fullname(peer_id: &str) -> String {
+    format!("{peer_id}.{MDNS_SERVICE_TYPE}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures_util::{SinkExt, StreamExt};
+    use hypernote_protocol::{FrameType, WsFrame};
+    use tokio_tungstenite::tungstenite::protocol::{Message, Role};
+    use tokio_tungstenite::WebSocketStream;
+    use yrs::updates::encoder::Encode;
+    use yrs::{ReadTxn, Text, Transact};
+
+    use super::{Node, NodeConfig, PeerLink, RATE_LIMITED, TOO_LARGE};
+    use crate::events::{self, EventSink};
+    use crate::limits::Limits;
+    use crate::store::NoteStore;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl EventSink for Recorder {
+        fn emit(&self, event: &str, _payload: serde_json::Value) -> Result<(), String> {
+            self.0.lock().unwrap().push(event.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn headless_node_greets_peers_and_keeps_their_edits() {
+        let recorder = Arc::new(Recorder::default());
+        let node = Node::new(
+            NodeConfig {
+                peer_id: "daemon".to_string(),
+                store: NoteStore::in_memory(),
+                serve_sync: true,
+                limits: Limits::default(),
+            },
+            recorder.clone(),
+            tokio::runtime::Handle::current(),
+        );
+
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let server = WebSocketStream::from_raw_socket(local, Role::Server, None).await;
+        let mut client = WebSocketStream::from_raw_socket(remote, Role::Client, None).await;
+        let connection = tokio::spawn(Arc::clone(&node).handle_ws_connection(
+            server,
+            "test".to_string(),
+            PeerLink::Inbound,
+        ));
+
+        let Message::Text(greeting) = client.next().await.expect("hello").expect("frame") else {
+            panic!("expected a text frame");
+        };
+        let greeting = WsFrame::parse(&greeting).expect("hello parses");
+        assert_eq!(greeting.frame_type, FrameType::Hello);
+        assert_eq!(greeting.sender_id, "daemon");
+
+        let update = {
+            let doc = yrs::Doc::new();
+            let content = doc.get_or_insert_text("content");
+            let mut txn = doc.transact_mut();
+            content.insert(&mut txn, 0, "from the app");
+            txn.encode_update_v1()
+        };
+        let frame = WsFrame::binary(FrameType::Update, "note-1", "app", update);
+        client
+            .send(Message::Text(frame.to_json().into()))
+            .await
+            .expect("client sends");
+        client.close(None).await.expect("client closes");
+        connection.await.expect("connection ends");
+
+        let note = node.open_note("note-1").expect("update was stored");
+        assert_eq!(note.markdown, "from the app");
+        assert!(node.list_peers().is_empty());
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                events::PEER_CONNECTED,
+                events::WS_MESSAGE,
+                events::PEER_DISCONNECTED
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn mdns_stays_off_when_sync_was_paused_before_it_started() {
+        let node = Node::new(
+            NodeConfig {
+                peer_id: "paused".to_string(),
+                store: NoteStore::in_memory(),
+                serve_sync: false,
+                limits: Limits::default(),
+            },
+            Arc::new(Recorder::default()),
+            tokio::runtime::Handle::current(),
+        );
+        node.set_sync_enabled(false);
+
+        // The discovery thread got scheduled only after the pause found nothing to stop.
+        tokio::task::spawn_blocking({
+            let node = Arc::clone(&node);
+            move || node.run_mdns()
+        })
+        .await
+        .expect("mDNS thread returns");
+        assert!(node.mdns.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_updates_are_refused_with_a_reason() {
+        let node = Node::new(
+            NodeConfig {
+                peer_id: "daemon".to_string(),
+                store: NoteStore::in_memory(),
+                serve_sync: true,
+                limits: Limits::for_note_bytes(64),
+            },
+            Arc::new(Recorder::default()),
+            tokio::runtime::Handle::current(),
+        );
+        let update = {
+            let doc = yrs::Doc::new();
+            let content = doc.get_or_insert_text("content");
+            let mut txn = doc.transact_mut();
+            content.insert(&mut txn, 0, &"x".repeat(200));
+            txn.encode_update_v1()
+        };
+
+        let ack = node.apply_local_edit("note-1", &update);
+        assert!(!ack.accepted);
+        assert!(ack.reason.unwrap().starts_with("update too large"));
+
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let server = WebSocketStream::from_raw_socket(local, Role::Server, None).await;
+        let mut client = WebSocketStream::from_raw_socket(remote, Role::Client, None).await;
+        let connection = tokio::spawn(Arc::clone(&node).handle_ws_connection(
+            server,
+            "test".to_string(),
+            PeerLink::Inbound,
+        ));
+        client.next().await.expect("hello").expect("frame");
+
+        let frame = WsFrame::binary(FrameType::Update, "note-1", "app", update);
+        client
+            .send(Message::Text(frame.to_json().into()))
+            .await
+            .expect("client sends");
+        // Skip the greeting's manifest update.
+        let reply = loop {
+            let Message::Text(text) = client.next().await.expect("reply").expect("frame") else {
+                panic!("expected a text frame");
+            };
+            let frame = WsFrame::parse(&text).expect("reply parses");
+            if frame.frame_type == FrameType::Error {
+                break frame;
+            }
+        };
+        assert_eq!(reply.payload["code"], TOO_LARGE);
+
+        client.close(None).await.expect("client closes");
+        connection.await.expect("connection ends");
+        assert!(node.open_note("note-1").is_err());
+    }
+
+    #[tokio::test]
+    async fn flooding_peers_are_cut_off_and_banned() {
+        let node = Node::new(
+            NodeConfig {
+                peer_id: "daemon".to_string(),
+                store: NoteStore::in_memory(),
+                serve_sync: false,
+                limits: Limits {
+                    max_frames_per_second: 1,
+                    frame_burst: 3,
+                    ..Limits::default()
+                },
+            },
+            Arc::new(Recorder::default()),
+            tokio::runtime::Handle::current(),
+        );
+
+        let (local, remote) = tokio::io::duplex(64 * 1024);
+        let server = WebSocketStream::from_raw_socket(local, Role::Server, None).await;
+        let mut client = WebSocketStream::from_raw_socket(remote, Role::Client, None).await;
+        let connection = tokio::spawn(Arc::clone(&node).handle_ws_connection(
+            server,
+            "10.0.0.9:50000".to_string(),
+            PeerLink::Inbound,
+        ));
+        for _ in 0..10 {
+            if client.send(Message::Text("{}".into())).await.is_err() {
+                break;
+            }
+        }
+
+        let Message::Text(reply) = client.next().await.expect("reply").expect("frame") else {
+            panic!("expected a text frame");
+        };
+        let reply = WsFrame::parse(&reply).expect("reply parses");
+        assert_eq!(reply.payload["code"], RATE_LIMITED);
+        connection.await.expect("connection ends");
+
+        let bans = node.sync_state().bans;
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].addr, "10.0.0.9");
+        assert!(node.admit("10.0.0.9".parse().unwrap()).is_none());
+        assert!(node.admit("10.0.0.10".parse().unwrap()).is_some());
+    }
+
+    #[tokio::test]
+    async fn metadata_changes_go_out_as_note_updates() {
+        let recorder = Arc::new(Recorder::default());
+        let node = Node::new(
+            NodeConfig {
+                peer_id: "app".to_string(),
+                store: NoteStore::in_memory(),
+                serve_sync: false,
+                limits: Limits::default(),
+            },
+            recorder.clone(),
+            tokio::runtime::Handle::current(),
+        );
+        let meta = node.create_note().expect("note");
+
+        assert!(node.delete_note_to_trash(&meta.id).accepted);
+        assert!(node.list_notes().is_empty());
+        assert!(node.restore_note(&meta.id).accepted);
+        assert_eq!(node.list_notes().len(), 1);
+        assert!(!node.set_note_color(&meta.id, Some("red; x")).accepted);
+        // Creating shares the manifest; each change then shares the note and the manifest.
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                events::NOTE_UPDATE,
+                events::NOTE_UPDATE,
+                events::NOTE_UPDATE,
+                events::NOTE_META_CHANGED,
+                events::NOTE_UPDATE,
+                events::NOTE_UPDATE,
+                events::NOTE_META_CHANGED
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn collects_deleted_content_once_every_known_peer_caught_up() {
+        let node = Node::new(
+            NodeConfig {
+                peer_id: "daemon".to_string(),
+                store: NoteStore::in_memory(),
+                serve_sync: true,
+                limits: Limits::default(),
+            },
+            Arc::new(Recorder::default()),
+            tokio::runtime::Handle::current(),
+        );
+        let doc = yrs::Doc::new();
+        let content = doc.get_or_insert_text("content");
+        let edit = |change: &dyn Fn(&mut yrs::TransactionMut)| {
+            let mut txn = doc.transact_mut();
+            let before = txn.state_vector();
+            change(&mut txn);
+            txn.encode_diff_v1(&before)
+        };
+        // Acks arrive at the note's last change, or just after it.
+        let ack = |node: &Node, origin: &str, after: i64| {
+            let vector = doc.transact().state_vector().encode_v1();
+            let frame = WsFrame::binary(FrameType::StateVector, "note-1", "laptop", vector);
+            let changed_at = node.store.meta("note-1").unwrap().updated_at;
+            node.record_ack(&frame, origin, changed_at + after);
+        };
+
+        node.apply_peer_update("note-1", edit(&|txn| content.insert(txn, 0, "scratch")));
+        assert!(!node.compact_note("note-1").expect("compacts").collected);
+        ack(&node, "192.168.1.20", 0);
+        node.apply_peer_update("note-1", edit(&|txn| content.remove_range(txn, 0, 7)));
+        assert!(!node.compact_note("note-1").expect("compacts").collected);
+
+        // The laptop's id used from another address is another peer, not the laptop.
+        ack(&node, "10.0.0.66", 1);
+        assert!(!node.compact_note("note-1").expect("compacts").collected);
+
+        ack(&node, "192.168.1.20", 1);
+        assert!(node.compact_note("note-1").expect("compacts").collected);
+    }
+}
@@ -0,0 +1,260 @@
+//! Tags, pinning, folders and colors on note metadata.
+//!
+//! Values are normalized before they are stored so filters and tag counts compare like with
+//! like: tags lose a leading `#` and duplicates (case-insensitively), folders become
+//! `a/b` paths without empty segments. The values themselves are stored in the note's
+//! `meta` map; see [`crate::meta_map`]. Peers also exchange them in `meta` frames, which
+//! are applied as changes to that map.
+
+use std::collections::HashMap;
+
+use hypernote_protocol::MetaPayload;
+
+use crate::contracts::{NoteFilter, NoteMeta, TagCount};
+use crate::meta_map::MetaChange;
+
+pub const MAX_TAGS: usize = 32;
+pub const MAX_TAG_CHARS: usize = 64;
+const MAX_COLOR_CHARS: usize = 32;
+
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag: String = tag
+            .trim()
+            .trim_start_matches('#')
+            .trim()
+            .chars()
+            .take(MAX_TAG_CHARS)
+            .collect();
+        if tag.is_empty() || normalized.iter().any(|seen| same_tag(seen, &tag)) {
+            continue;
+        }
+        normalized.push(tag);
+        if normalized.len() == MAX_TAGS {
+            break;
+        }
+    }
+    normalized
+}
+
+/// `" work / clients/ "` → `work/clients`; blank paths mean the top level.
+pub fn normalize_folder(folder: &str) -> Option<String> {
+    let path = folder
+        .split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    (!path.is_empty()).then_some(path)
+}
+
+/// Accepts CSS-like tokens (`#f5a623`, `amber`); blank clears the color.
+pub fn normalize_color(color: &str) -> Result<Option<String>, String> {
+    let color = color.trim();
+    if color.is_empty() {
+        return Ok(None);
+    }
+    let valid = color.len() <= MAX_COLOR_CHARS
+        && color
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '#' | '-'));
+    if !valid {
+        return Err(format!("invalid color: {color}"));
+    }
+    Ok(Some(color.to_string()))
+}
+
+pub fn matches(meta: &NoteMeta, filter: &NoteFilter) -> bool {
+    let has_tags = filter
+        .tags
+        .iter()
+        .all(|wanted| meta.tags.iter().any(|tag| same_tag(tag, wanted)));
+    let in_folder = match filter.folder.as_deref().and_then(normalize_folder) {
+        Some(wanted) => meta.folder.as_deref().is_some_and(|folder| {
+            folder == wanted
+                || folder
+                    .strip_prefix(&wanted)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        }),
+        None => true,
+    };
+
+    has_tags
+        && in_folder
+        && filter.pinned.is_none_or(|pinned| meta.pinned == pinned)
+        && filter
+            .color
+            .as_deref()
+            .is_none_or(|color| meta.color.as_deref() == Some(color))
+}
+
+/// Every tag in use with its note count, most used first.
+pub fn tag_counts<'a>(metas: impl IntoIterator<Item = &'a NoteMeta>) -> Vec<TagCount> {
+    let mut counts: HashMap<String, TagCount> = HashMap::new();
+    for meta in metas {
+        for tag in &meta.tags {
+            counts
+                .entry(tag.to_lowercase())
+                .or_insert_with(|| TagCount {
+                    tag: tag.clone(),
+                    count: 0,
+                })
+                .count += 1;
+        }
+    }
+
+    let mut counts: Vec<TagCount> = counts.into_values().collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+    counts
+}
+
+pub fn to_payload(meta: &NoteMeta) -> MetaPayload {
+    MetaPayload {
+        tags: meta.tags.clone(),
+        pinned: meta.pinned,
+        folder: meta.folder.clone(),
+        color: meta.color.clone(),
+        changed_at: meta.updated_at,
+    }
+}
+
+/// The changes that bring `meta` to a peer's copy, if that copy is newer; only fields
+/// that differ once normalized, so an echo of our own values changes nothing.
+pub fn payload_changes(meta: &NoteMeta, payload: &MetaPayload) -> Vec<MetaChange> {
+    if payload.changed_at <= meta.updated_at {
+        return Vec::new();
+    }
+
+    let mut changes = Vec::new();
+    let tags = normalize_tags(&payload.tags);
+    if tags != meta.tags {
+        changes.push(MetaChange::Tags(tags));
+    }
+    if payload.pinned != meta.pinned {
+        changes.push(MetaChange::Pinned(payload.pinned));
+    }
+    let folder = payload.folder.as_deref().and_then(normalize_folder);
+    if folder != meta.folder {
+        changes.push(MetaChange::Folder(folder));
+    }
+    let color = payload
+        .color
+        .as_deref()
+        .and_then(|color| normalize_color(color).ok().flatten());
+    if color != meta.color {
+        changes.push(MetaChange::Color(color));
+    }
+    changes
+}
+
+fn same_tag(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn normalizes_tags_folders_and_colors() {
+        assert_eq!(
+            normalize_tags(&tags(&[" #Work", "work", "", "#", "client-a"])),
+            tags(&["Work", "client-a"])
+        );
+        assert_eq!(
+            normalize_folder(" work / clients/ ").as_deref(),
+            Some("work/clients")
+        );
+        assert_eq!(normalize_folder(" / "), None);
+        assert_eq!(
+            normalize_color(" #f5a623 "),
+            Ok(Some("#f5a623".to_string()))
+        );
+        assert_eq!(normalize_color(""), Ok(None));
+        assert!(normalize_color("red; x").is_err());
+    }
+
+    #[test]
+    fn filters_by_tags_folder_pin_and_color() {
+        let meta = NoteMeta {
+            tags: tags(&["Work", "urgent"]),
+            pinned: true,
+            folder: Some("work/clients".to_string()),
+            color: Some("amber".to_string()),
+            ..Default::default()
+        };
+
+        let filter = |value: serde_json::Value| -> NoteFilter {
+            serde_json::from_value(value).expect("filter")
+        };
+        assert!(matches(&meta, &filter(serde_json::json!({}))));
+        assert!(matches(
+            &meta,
+            &filter(serde_json::json!({ "tags": ["work"], "folder": "work", "pinned": true }))
+        ));
+        assert!(!matches(
+            &meta,
+            &filter(serde_json::json!({ "folder": "wor" }))
+        ));
+        assert!(!matches(
+            &meta,
+            &filter(serde_json::json!({ "tags": ["work", "later"] }))
+        ));
+        assert!(!matches(
+            &meta,
+            &filter(serde_json::json!({ "color": "teal" }))
+        ));
+    }
+
+    #[test]
+    fn counts_tags_case_insensitively() {
+        let first = NoteMeta {
+            tags: tags(&["work"]),
+            ..Default::default()
+        };
+        let second = NoteMeta {
+            tags: tags(&["Work", "home"]),
+            ..Default::default()
+        };
+        let counts = tag_counts([&first, &second]);
+        assert_eq!(counts[0].count, 2);
+        assert_eq!(counts[1].tag, "home");
+    }
+
+    #[test]
+    fn takes_only_the_differing_fields_of_a_newer_peer_copy() {
+        let meta = NoteMeta {
+            tags: tags(&["work"]),
+            updated_at: 10,
+            ..Default::default()
+        };
+        let mut payload = to_payload(&meta);
+        payload.changed_at = 20;
+        assert!(payload_changes(&meta, &payload).is_empty());
+
+        payload.tags = tags(&["#work", "home"]);
+        payload.folder = Some(" clients/ ".to_string());
+        assert_eq!(
+            payload_changes(&meta, &payload),
+            [
+                MetaChange::Tags(tags(&["work", "home"])),
+                MetaChange::Folder(Some("clients".to_string()))
+            ]
+        );
+        payload.changed_at = 10;
+        assert!(payload_changes(&meta, &payload).is_empty());
+    }
+
+    #[test]
+    fn loads_metadata_written_before_these_fields_existed() {
+        let old = r#"{"id":"note-1","title":"Old","createdAt":1,"updatedAt":2,"deletedAt":null}"#;
+        let meta: NoteMeta = serde_json::from_str(old).expect("old metadata loads");
+        assert!(meta.tags.is_empty() && !meta.pinned && meta.folder.is_none());
+        assert!(!serde_json::to_string(&meta).unwrap().contains("tags"));
+    }
+}
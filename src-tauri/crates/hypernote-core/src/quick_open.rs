@@ -0,0 +1,286 @@
+//! Fuzzy "go to note" over titles.
+//!
+//! A query matches a title when its characters appear in order (whitespace in the query is
+//! ignored), as in most editors' quick-open. Each title is scored on its best alignment:
+//! consecutive characters and word starts score high, skipped characters cost a little, and
+//! recently opened notes get a boost so the palette surfaces them first. Scoring is
+//! O(query × title) per note, a few milliseconds for 10k notes in release builds.
+
+use crate::contracts::{NoteMeta, QuickOpenHit, SnippetPart};
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_CONSECUTIVE: i32 = 12;
+const BONUS_WORD_START: i32 = 10;
+const BONUS_TITLE_START: i32 = 8;
+/// Cost per skipped title character, capped per gap so one long gap is not fatal.
+const PENALTY_GAP: i32 = 1;
+const MAX_GAP_PENALTY: i32 = 8;
+/// Boost for the most recently opened note; older entries get proportionally less.
+const RECENT_BOOST: i32 = 40;
+
+/// Ranks `notes` for `query`. `recent` lists note ids most recent first.
+///
+/// An empty query returns recently opened notes first, then the rest by last update.
+pub fn rank<'a>(
+    query: &str,
+    notes: impl IntoIterator<Item = &'a NoteMeta>,
+    recent: &[String],
+    limit: usize,
+) -> Vec<QuickOpenHit> {
+    if limit == 0 {
+        return Vec::new();
+    }
+
+    let pattern: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    let mut scored: Vec<(i32, &NoteMeta, Vec<usize>)> = Vec::new();
+    let mut title_chars = Vec::new();
+    for note in notes {
+        title_chars.clear();
+        title_chars.extend(note.title.chars());
+
+        let (score, positions) = if pattern.is_empty() {
+            (0, Vec::new())
+        } else {
+            match fuzzy_match(&pattern, &title_chars) {
+                Some(found) => found,
+                None => continue,
+            }
+        };
+        scored.push((score + recent_boost(recent, &note.id), note, positions));
+    }
+
+    let order = |a: &(i32, &NoteMeta, Vec<usize>), b: &(i32, &NoteMeta, Vec<usize>)| {
+        b.0.cmp(&a.0)
+            .then_with(|| b.1.updated_at.cmp(&a.1.updated_at))
+            .then_with(|| a.1.id.cmp(&b.1.id))
+    };
+    if scored.len() > limit {
+        scored.select_nth_unstable_by(limit - 1, order);
+        scored.truncate(limit);
+    }
+    scored.sort_unstable_by(order);
+
+    scored
+        .into_iter()
+        .map(|(score, note, positions)| QuickOpenHit {
+            note_id: note.id.clone(),
+            title: note.title.clone(),
+            score,
+            title_parts: highlight(&note.title, &positions),
+            recent: recent.contains(&note.id),
+        })
+        .collect()
+}
+
+/// Best score and matched char positions of `pattern` (lowercase) in `title`, if it matches.
+///
+/// Dynamic programming over (pattern char, title position): each cell keeps the best
+/// score for matching the pattern so far with its last char at that position.
+fn fuzzy_match(pattern: &[char], title: &[char]) -> Option<(i32, Vec<usize>)> {
+    const NONE: i32 = i32::MIN / 2;
+    let (m, n) = (pattern.len(), title.len());
+    if m > n {
+        return None;
+    }
+
+    let lower: Vec<char> = title
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    // Most titles do not match at all; reject them before allocating the table.
+    let mut rest = lower.iter();
+    if !pattern.iter().all(|p| rest.any(|c| c == p)) {
+        return None;
+    }
+
+    let mut score = vec![NONE; m * n];
+    let mut from = vec![usize::MAX; m * n];
+    // Running best of the previous row up to each position, for gaps past the cap.
+    let mut prefix_best: Vec<(i32, usize)> = vec![(NONE, usize::MAX); n];
+
+    for i in 0..m {
+        if i > 0 {
+            let prev = &score[(i - 1) * n..i * n];
+            let mut best = (NONE, usize::MAX);
+            for (k, &value) in prev.iter().enumerate() {
+                if value > best.0 {
+                    best = (value, k);
+                }
+                prefix_best[k] = best;
+            }
+        }
+
+        for j in i..n {
+            if lower[j] != pattern[i] {
+                continue;
+            }
+            let mut bonus = SCORE_MATCH;
+            if is_word_start(title, j) {
+                bonus += BONUS_WORD_START;
+            }
+            if j == 0 {
+                bonus += BONUS_TITLE_START;
+            }
+
+            let cell = i * n + j;
+            if i == 0 {
+                score[cell] = bonus;
+                continue;
+            }
+
+            let prev = (i - 1) * n;
+            let mut best = (NONE, usize::MAX);
+            if j > 0 && score[prev + j - 1] > NONE {
+                best = (score[prev + j - 1] + BONUS_CONSECUTIVE, j - 1);
+            }
+            let capped = (MAX_GAP_PENALTY / PENALTY_GAP) as usize;
+            let near = j.saturating_sub(capped + 1);
+            for k in near..j.saturating_sub(1) {
+                let gap = (j - k - 1) as i32;
+                let value = score[prev + k] - (gap * PENALTY_GAP).min(MAX_GAP_PENALTY);
+                if score[prev + k] > NONE && value > best.0 {
+                    best = (value, k);
+                }
+            }
+            if near > 0 {
+                let (value, k) = prefix_best[near - 1];
+                if value > NONE && value - MAX_GAP_PENALTY > best.0 {
+                    best = (value - MAX_GAP_PENALTY, k);
+                }
+            }
+
+            if best.0 > NONE {
+                score[cell] = best.0 + bonus;
+                from[cell] = best.1;
+            }
+        }
+    }
+
+    let last = (m - 1) * n;
+    let (end, best) = (0..n)
+        .map(|j| (j, score[last + j]))
+        .filter(|&(_, value)| value > NONE)
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))?;
+
+    let mut positions = vec![0; m];
+    let mut j = end;
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i * n + j];
+    }
+    Some((best, positions))
+}
+
+/// Start of the title, after a separator, or a lower→upper camel-case step.
+fn is_word_start(title: &[char], pos: usize) -> bool {
+    let Some(&prev) = pos.checked_sub(1).and_then(|p| title.get(p)) else {
+        return true;
+    };
+    let c = title[pos];
+    !prev.is_alphanumeric() || (prev.is_lowercase() && c.is_uppercase())
+}
+
+fn recent_boost(recent: &[String], note_id: &str) -> i32 {
+    let Some(rank) = recent.iter().position(|id| id == note_id) else {
+        return 0;
+    };
+    let len = recent.len() as i32;
+    RECENT_BOOST * (len - rank as i32) / len
+}
+
+/// Splits `title` into runs, highlighting the chars at `positions` (sorted).
+fn highlight(title: &str, positions: &[usize]) -> Vec<SnippetPart> {
+    let mut parts: Vec<SnippetPart> = Vec::new();
+    let mut matched = positions.iter().peekable();
+    for (i, c) in title.chars().enumerate() {
+        let highlight = matched.next_if_eq(&&i).is_some();
+        match parts.last_mut() {
+            Some(part) if part.highlight == highlight => part.text.push(c),
+            _ => parts.push(SnippetPart {
+                text: c.to_string(),
+                highlight,
+            }),
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(id: &str, title: &str, updated_at: i64) -> NoteMeta {
+        NoteMeta {
+            id: id.to_string(),
+            title: title.to_string(),
+            created_at: 0,
+            updated_at,
+            ..Default::default()
+        }
+    }
+
+    fn ids(hits: &[QuickOpenHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.note_id.as_str()).collect()
+    }
+
+    #[test]
+    fn prefers_word_starts_and_tight_matches() {
+        let notes = [
+            note("scattered", "Some random topic notes", 3),
+            note("initials", "Release Train Notes", 2),
+            note("exact", "rtn", 1),
+            note("none", "Groceries", 4),
+        ];
+
+        let hits = rank("rtn", &notes, &[], 10);
+        assert_eq!(ids(&hits), vec!["exact", "initials", "scattered"]);
+
+        let parts: Vec<(&str, bool)> = hits[1]
+            .title_parts
+            .iter()
+            .map(|part| (part.text.as_str(), part.highlight))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("R", true),
+                ("elease ", false),
+                ("T", true),
+                ("rain ", false),
+                ("N", true),
+                ("otes", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn recent_notes_rise_and_lead_an_empty_query() {
+        let notes = [
+            note("a", "Meeting notes", 3),
+            note("b", "Meeting notes", 2),
+            note("c", "Unrelated", 1),
+        ];
+        let recent = vec!["b".to_string()];
+
+        assert_eq!(ids(&rank("meet", &notes, &recent, 10)), vec!["b", "a"]);
+        assert_eq!(ids(&rank("", &notes, &recent, 10)), vec!["b", "a", "c"]);
+        assert!(rank("", &notes, &recent, 10)[0].recent);
+    }
+
+    #[test]
+    fn limits_results_over_a_large_workspace() {
+        let notes: Vec<NoteMeta> = (0..10_000)
+            .map(|i| note(&format!("note-{i}"), &format!("Daily log {i}"), i))
+            .collect();
+
+        let hits = rank("dl 99", &notes, &[], 5);
+        assert_eq!(hits.len(), 5);
+        assert_eq!(hits[0].note_id, "note-9999");
+        assert!(rank("zzz", &notes, &[], 5).is_empty());
+    }
+}
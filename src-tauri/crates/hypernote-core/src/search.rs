@@ -0,0 +1,563 @@
+//! Full-text search over note text.
+//!
+//! An in-memory inverted index with token positions, kept current by the note store on
+//! every accepted update. Latin-script text is split into lowercase words; Korean, Chinese
+//! and Japanese runs become overlapping character bigrams, so `회의` finds `회의록을`
+//! without a dictionary.
+//!
+//! Query syntax: words must all match (AND), `"quoted words"` must appear in sequence,
+//! `word*` matches by prefix, and the last word is always matched as a prefix so results
+//! follow typing. Hits are ranked with BM25, with a boost when the title matches too.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::contracts::{SearchHit, SnippetPart};
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+/// Extra weight for a clause that also matches the title, relative to its body score.
+const TITLE_BOOST: f32 = 1.5;
+/// Upper bound on the index terms a single prefix may expand to.
+const MAX_PREFIX_EXPANSION: usize = 64;
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+const SNIPPET_MAX_CHARS: usize = 200;
+
+#[derive(Default)]
+pub struct SearchIndex {
+    /// term → note id → positions of that term in the note.
+    postings: BTreeMap<String, HashMap<String, Vec<u32>>>,
+    notes: HashMap<String, IndexedNote>,
+    total_tokens: u64,
+}
+
+struct IndexedNote {
+    title: String,
+    text: String,
+    /// Distinct terms, so removal touches only the postings this note is in.
+    terms: Vec<String>,
+    len: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    term: String,
+    /// Byte range in the source text.
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Clause {
+    Term { term: String, prefix: bool },
+    Phrase(Vec<String>),
+}
+
+impl SearchIndex {
+    /// Replaces whatever was indexed for `note_id`.
+    pub fn upsert(&mut self, note_id: &str, title: &str, text: &str) {
+        self.remove(note_id);
+
+        let tokens = tokenize(text);
+        let mut positions: HashMap<&str, Vec<u32>> = HashMap::new();
+        for (position, token) in tokens.iter().enumerate() {
+            positions
+                .entry(token.term.as_str())
+                .or_default()
+                .push(position as u32);
+        }
+
+        for (term, term_positions) in &positions {
+            self.postings
+                .entry((*term).to_string())
+                .or_default()
+                .insert(note_id.to_string(), term_positions.clone());
+        }
+
+        self.total_tokens += tokens.len() as u64;
+        self.notes.insert(
+            note_id.to_string(),
+            IndexedNote {
+                title: title.to_string(),
+                text: text.to_string(),
+                terms: positions.keys().map(|term| (*term).to_string()).collect(),
+                len: tokens.len() as u32,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, note_id: &str) {
+        let Some(note) = self.notes.remove(note_id) else {
+            return;
+        };
+
+        for term in &note.terms {
+            if let Some(notes) = self.postings.get_mut(term) {
+                notes.remove(note_id);
+                if notes.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.total_tokens -= u64::from(note.len);
+    }
+
+    /// Best `limit` notes matching every clause of `query`, highest score first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() || self.notes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: Option<HashMap<&str, f32>> = None;
+        for clause in &clauses {
+            let matches = self.match_clause(clause);
+            let idf = self.idf(matches.len());
+
+            let mut next = HashMap::new();
+            for (note_id, tf) in matches {
+                let previous = match &scores {
+                    Some(scores) => match scores.get(note_id) {
+                        Some(score) => *score,
+                        None => continue,
+                    },
+                    None => 0.0,
+                };
+                let note = &self.notes[note_id];
+                let mut score = idf * self.bm25(tf, note.len);
+                if clause_matches_title(clause, &note.title) {
+                    score += idf * TITLE_BOOST;
+                }
+                next.insert(note_id, previous + score);
+            }
+            scores = Some(next);
+        }
+
+        let mut ranked: Vec<(&str, f32)> = scores.unwrap_or_default().into_iter().collect();
+        ranked.sort_by(|left, right| right.1.total_cmp(&left.1).then(left.0.cmp(right.0)));
+        ranked.truncate(limit);
+
+        ranked
+            .into_iter()
+            .map(|(note_id, score)| {
+                let note = &self.notes[note_id];
+                SearchHit {
+                    note_id: note_id.to_string(),
+                    title: note.title.clone(),
+                    score,
+                    snippet: snippet(&note.text, &clauses),
+                }
+            })
+            .collect()
+    }
+
+    /// Note id → number of times the clause occurs in it.
+    fn match_clause<'a>(&'a self, clause: &Clause) -> HashMap<&'a str, u32> {
+        let mut matches: HashMap<&str, u32> = HashMap::new();
+
+        match clause {
+            Clause::Term { term, prefix } => {
+                for notes in self.expand(term, *prefix) {
+                    for (note_id, positions) in notes {
+                        *matches.entry(note_id.as_str()).or_default() += positions.len() as u32;
+                    }
+                }
+            }
+            Clause::Phrase(terms) => {
+                let Some(postings) = terms
+                    .iter()
+                    .map(|term| self.postings.get(term))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return matches;
+                };
+
+                for (note_id, starts) in postings[0] {
+                    let rest: Option<Vec<HashSet<u32>>> = postings[1..]
+                        .iter()
+                        .map(|notes| {
+                            notes
+                                .get(note_id)
+                                .map(|positions| positions.iter().copied().collect())
+                        })
+                        .collect();
+                    let Some(rest) = rest else {
+                        continue;
+                    };
+
+                    let count = starts
+                        .iter()
+                        .filter(|start| {
+                            rest.iter().enumerate().all(|(offset, positions)| {
+                                positions.contains(&(**start + offset as u32 + 1))
+                            })
+                        })
+                        .count() as u32;
+                    if count > 0 {
+                        matches.insert(note_id.as_str(), count);
+                    }
+                }
+            }
+        }
+
+        matches
+    }
+
+    /// Postings of `term`, or of every index term starting with it.
+    fn expand(&self, term: &str, prefix: bool) -> Vec<&HashMap<String, Vec<u32>>> {
+        if !prefix {
+            return self.postings.get(term).into_iter().collect();
+        }
+
+        self.postings
+            .range(term.to_string()..)
+            .take_while(|(candidate, _)| candidate.starts_with(term))
+            .take(MAX_PREFIX_EXPANSION)
+            .map(|(_, notes)| notes)
+            .collect()
+    }
+
+    fn idf(&self, matching_notes: usize) -> f32 {
+        let total = self.notes.len() as f32;
+        let matching = matching_notes as f32;
+        ((total - matching + 0.5) / (matching + 0.5) + 1.0).ln()
+    }
+
+    fn bm25(&self, tf: u32, note_len: u32) -> f32 {
+        let tf = tf as f32;
+        let average_len = (self.total_tokens as f32 / self.notes.len() as f32).max(1.0);
+        let norm = 1.0 - BM25_B + BM25_B * note_len as f32 / average_len;
+        tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm)
+    }
+}
+
+fn is_cjk(ch: char) -> bool {
+    matches!(ch as u32,
+        0x1100..=0x11FF     // Hangul Jamo
+        | 0x3040..=0x30FF   // Hiragana, Katakana
+        | 0x3130..=0x318F   // Hangul Compatibility Jamo
+        | 0x3400..=0x4DBF   // CJK Extension A
+        | 0x4E00..=0x9FFF   // CJK Unified Ideographs
+        | 0xAC00..=0xD7A3   // Hangul Syllables
+        | 0xF900..=0xFAFF) // CJK Compatibility Ideographs
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word_start: Option<usize> = None;
+    let mut cjk_run: Vec<(usize, char)> = Vec::new();
+
+    let flush_word = |tokens: &mut Vec<Token>, start: &mut Option<usize>, end: usize| {
+        if let Some(start) = start.take() {
+            tokens.push(Token {
+                term: text[start..end].to_lowercase(),
+                start,
+                end,
+            });
+        }
+    };
+    let flush_cjk = |tokens: &mut Vec<Token>, run: &mut Vec<(usize, char)>| {
+        if let [(start, ch)] = run.as_slice() {
+            tokens.push(Token {
+                term: ch.to_string(),
+                start: *start,
+                end: start + ch.len_utf8(),
+            });
+        }
+        for pair in run.windows(2) {
+            let (start, first) = pair[0];
+            let (second_start, second) = pair[1];
+            tokens.push(Token {
+                term: format!("{first}{second}"),
+                start,
+                end: second_start + second.len_utf8(),
+            });
+        }
+        run.clear();
+    };
+
+    for (offset, ch) in text.char_indices() {
+        if is_cjk(ch) {
+            flush_word(&mut tokens, &mut word_start, offset);
+            cjk_run.push((offset, ch));
+        } else if ch.is_alphanumeric() {
+            flush_cjk(&mut tokens, &mut cjk_run);
+            word_start.get_or_insert(offset);
+        } else {
+            flush_word(&mut tokens, &mut word_start, offset);
+            flush_cjk(&mut tokens, &mut cjk_run);
+        }
+    }
+    flush_word(&mut tokens, &mut word_start, text.len());
+    flush_cjk(&mut tokens, &mut cjk_run);
+
+    tokens
+}
+
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+
+    for (index, segment) in query.split('"').enumerate() {
+        let quoted = index % 2 == 1;
+        if quoted {
+            let terms: Vec<String> = tokenize(segment).into_iter().map(|t| t.term).collect();
+            match terms.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term {
+                    term: terms[0].clone(),
+                    prefix: false,
+                }),
+                _ => clauses.push(Clause::Phrase(terms)),
+            }
+            continue;
+        }
+
+        for word in segment.split_whitespace() {
+            let starred = word.ends_with('*');
+            let terms: Vec<String> = tokenize(word.trim_end_matches('*'))
+                .into_iter()
+                .map(|t| t.term)
+                .collect();
+            match terms.len() {
+                0 => {}
+                1 => {
+                    // A lone CJK character is indexed only inside bigrams.
+                    let single_cjk = terms[0].chars().count() == 1 && terms[0].chars().all(is_cjk);
+                    clauses.push(Clause::Term {
+                        term: terms[0].clone(),
+                        prefix: starred || single_cjk,
+                    });
+                }
+                _ => clauses.push(Clause::Phrase(terms)),
+            }
+        }
+    }
+
+    // Search-as-you-type: the word being typed is probably incomplete.
+    let typing = !query.ends_with(char::is_whitespace) && !query.ends_with('"');
+    if typing {
+        if let Some(Clause::Term { prefix, .. }) = clauses.last_mut() {
+            *prefix = true;
+        }
+    }
+
+    clauses
+}
+
+fn clause_terms(clause: &Clause) -> Vec<(&str, bool)> {
+    match clause {
+        Clause::Term { term, prefix } => vec![(term.as_str(), *prefix)],
+        Clause::Phrase(terms) => terms.iter().map(|term| (term.as_str(), false)).collect(),
+    }
+}
+
+fn term_matches(token: &str, term: &str, prefix: bool) -> bool {
+    if prefix {
+        token.starts_with(term)
+    } else {
+        token == term
+    }
+}
+
+fn clause_matches_title(clause: &Clause, title: &str) -> bool {
+    let tokens = tokenize(title);
+    clause_terms(clause).iter().all(|(term, prefix)| {
+        tokens
+            .iter()
+            .any(|token| term_matches(&token.term, term, *prefix))
+    })
+}
+
+/// Window of `text` around the first match, split into highlighted and plain parts.
+fn snippet(text: &str, clauses: &[Clause]) -> Vec<SnippetPart> {
+    let terms: Vec<(&str, bool)> = clauses.iter().flat_map(clause_terms).collect();
+    let mut spans: Vec<(usize, usize)> = tokenize(text)
+        .into_iter()
+        .filter(|token| {
+            terms
+                .iter()
+                .any(|(term, prefix)| term_matches(&token.term, term, *prefix))
+        })
+        .map(|token| (token.start, token.end))
+        .collect();
+
+    // Overlapping CJK bigrams merge into one highlighted run.
+    spans.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    let first = merged.first().map_or(0, |span| span.0);
+    let window_start = char_boundary_before(text, first, SNIPPET_CONTEXT_CHARS);
+    let window_end = char_boundary_after(text, window_start, SNIPPET_MAX_CHARS);
+
+    let mut parts = Vec::new();
+    let push = |parts: &mut Vec<SnippetPart>, text: &str, highlight: bool| {
+        if !text.is_empty() {
+            parts.push(SnippetPart {
+                text: text.replace('\n', " "),
+                highlight,
+            });
+        }
+    };
+
+    if window_start > 0 {
+        push(&mut parts, "…", false);
+    }
+    let mut cursor = window_start;
+    for (start, end) in merged {
+        if start >= window_end {
+            break;
+        }
+        let start = start.max(cursor);
+        let end = end.min(window_end);
+        push(&mut parts, &text[cursor..start], false);
+        push(&mut parts, &text[start..end], true);
+        cursor = end;
+    }
+    push(&mut parts, &text[cursor..window_end], false);
+    if window_end < text.len() {
+        push(&mut parts, "…", false);
+    }
+
+    parts
+}
+
+fn char_boundary_before(text: &str, offset: usize, chars: usize) -> usize {
+    text[..offset]
+        .char_indices()
+        .rev()
+        .nth(chars.saturating_sub(1))
+        .map_or(0, |(index, _)| index)
+}
+
+fn char_boundary_after(text: &str, offset: usize, chars: usize) -> usize {
+    text[offset..]
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(index, _)| offset + index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_query, tokenize, Clause, SearchIndex};
+
+    fn terms(text: &str) -> Vec<String> {
+        tokenize(text).into_iter().map(|token| token.term).collect()
+    }
+
+    fn hit_ids(index: &SearchIndex, query: &str) -> Vec<String> {
+        index
+            .search(query, 10)
+            .into_iter()
+            .map(|hit| hit.note_id)
+            .collect()
+    }
+
+    #[test]
+    fn splits_words_and_cjk_bigrams() {
+        assert_eq!(terms("Sync v2, done!"), vec!["sync", "v2", "done"]);
+        assert_eq!(terms("회의록을 정리"), vec!["회의", "의록", "록을", "정리"]);
+        assert_eq!(terms("API 문서"), vec!["api", "문서"]);
+        assert_eq!(terms("가"), vec!["가"]);
+    }
+
+    #[test]
+    fn parses_phrases_prefixes_and_the_word_being_typed() {
+        assert_eq!(
+            parse_query("\"release notes\" sync* dra"),
+            vec![
+                Clause::Phrase(vec!["release".into(), "notes".into()]),
+                Clause::Term {
+                    term: "sync".into(),
+                    prefix: true
+                },
+                Clause::Term {
+                    term: "dra".into(),
+                    prefix: true
+                },
+            ]
+        );
+        assert_eq!(
+            parse_query("draft "),
+            vec![Clause::Term {
+                term: "draft".into(),
+                prefix: false
+            }]
+        );
+    }
+
+    #[test]
+    fn requires_every_clause_and_ranks_denser_notes_first() {
+        let mut index = SearchIndex::default();
+        index.upsert("a", "Sync", "sync sync sync protocol");
+        index.upsert(
+            "b",
+            "Notes",
+            "sync notes and a long tail of other words here",
+        );
+        index.upsert("c", "Other", "protocol only");
+
+        assert_eq!(hit_ids(&index, "sync "), vec!["a", "b"]);
+        assert_eq!(hit_ids(&index, "sync protocol "), vec!["a"]);
+        assert!(hit_ids(&index, "missing ").is_empty());
+    }
+
+    #[test]
+    fn matches_phrases_only_in_order() {
+        let mut index = SearchIndex::default();
+        index.upsert("a", "", "release notes for march");
+        index.upsert("b", "", "notes about the release");
+
+        assert_eq!(hit_ids(&index, "\"release notes\""), vec!["a"]);
+    }
+
+    #[test]
+    fn finds_korean_words_inside_inflected_forms() {
+        let mut index = SearchIndex::default();
+        index.upsert("a", "", "오늘 회의록을 공유합니다");
+        index.upsert("b", "", "회계 보고서");
+
+        assert_eq!(hit_ids(&index, "회의록"), vec!["a"]);
+        let mut single = hit_ids(&index, "회");
+        single.sort();
+        assert_eq!(single, vec!["a", "b"]);
+        assert_eq!(hit_ids(&index, "\"회의록을 공유\""), vec!["a"]);
+    }
+
+    #[test]
+    fn updates_and_removals_take_effect_immediately() {
+        let mut index = SearchIndex::default();
+        index.upsert("a", "", "first draft");
+        index.upsert("a", "", "final version");
+
+        assert!(hit_ids(&index, "draft ").is_empty());
+        assert_eq!(hit_ids(&index, "final "), vec!["a"]);
+
+        index.remove("a");
+        assert!(hit_ids(&index, "final ").is_empty());
+    }
+
+    #[test]
+    fn highlights_matches_in_a_snippet() {
+        let mut index = SearchIndex::default();
+        let long_intro = "intro ".repeat(30);
+        index.upsert("a", "", &format!("{long_intro}the sync engine"));
+
+        let hit = index.search("sync", 1).pop().expect("hit");
+        assert_eq!(
+            hit.snippet.first().map(|part| part.text.as_str()),
+            Some("…")
+        );
+        let highlighted: Vec<_> = hit
+            .snippet
+            .iter()
+            .filter(|part| part.highlight)
+            .map(|part| part.text.as_str())
+            .collect();
+        assert_eq!(highlighted, vec!["sync"]);
+    }
+}
@@ -0,0 +1,1747 @@
+//! Note store.
+//!
+//! Holds every note's merged Yjs state in memory. Opened on a data directory it uses the
+//! same layout in every process (`notes/<id>.yjs`, `trash/<id>.yjs`, HYPN containers)
+//! and keeps each note's container current: accepted updates go to the [`Wal`] first and
+//! reach the container when the log is compacted. Every change also refreshes the note's
+//! entry in the full-text [`SearchIndex`].
+//!
+//! Metadata changes are CRDT updates to the note's `meta` map (see [`crate::meta_map`]);
+//! trashed notes stay loaded with `deletedAt` set and live under `trash/` on disk. The
+//! store also keeps the workspace [`Manifest`] current and saves it as `workspace.yjs`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use hypernote_protocol::is_valid_workspace_id;
+use yrs::updates::decoder::Decode;
+use yrs::{GetString, ReadTxn, StateVector, Text, Transact, Update};
+
+use crate::attribution;
+use crate::container;
+use crate::contracts::{
+    AttributedRange, CompactionReport, NoteDiff, NoteDocument, NoteFilter, NoteMeta, NoteVersion,
+    QuickOpenHit, SearchHit, StorageIssue, TagCount,
+};
+use crate::diff;
+use crate::history::{self, History, HISTORY_DIR};
+use crate::import::NoteDraft;
+use crate::limits::Limits;
+use crate::manifest::{Manifest, MANIFEST_NOTE_ID};
+use crate::meta_map::{self, MetaChange};
+use crate::organize;
+use crate::quick_open;
+use crate::search::SearchIndex;
+use crate::unix_now_ms;
+use crate::wal::{self, Wal, COMPACT_BYTES, WAL_DIR};
+
+pub const NOTES_DIR: &str = "notes";
+pub const TRASH_DIR: &str = "trash";
+pub const CONTAINER_EXTENSION: &str = "yjs";
+/// Name of the Yjs text the editor binds to (`doc.getText('content')`).
+pub const CONTENT_TEXT: &str = "content";
+pub const DEFAULT_TITLE: &str = "Untitled";
+pub const MAX_TITLE_CHARS: usize = 200;
+/// Length of the plain-text `body` preview kept in metadata, as the frontend writes it.
+pub const BODY_PREVIEW_CHARS: usize = 500;
+/// Same identifier as `tauri.conf.json`, so the default matches the app's `appDataDir()`.
+pub const APP_IDENTIFIER: &str = "com.hypernote.app";
+/// Directory name of the daemon's default data directory.
+pub const DAEMON_DIR: &str = "hypernoted";
+
+/// Manifest file in the data directory, written only by the store that owns it.
+pub const MANIFEST_FILE: &str = "workspace.yjs";
+/// File holding this device's id, which is also its peer id and the author of its edits.
+/// Only the owner of the directory creates it.
+pub const DEVICE_ID_FILE: &str = "device-id";
+/// Where containers that fail validation at load are moved.
+pub const QUARANTINE_DIR: &str = "quarantine";
+/// Files imported notes refer to, one directory per note.
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// lib0 v1 encoding of an update with no structs and no deletions.
+pub const EMPTY_UPDATE: &[u8] = &[0, 0];
+
+pub struct NoteStore {
+    /// Held only while reading or changing memory, never across disk IO.
+    notes: Mutex<HashMap<String, NoteDocument>>,
+    /// One lock per note, held while its log and container are written; taken before
+    /// `notes`.
+    writing: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// Data directory holding `notes/` and `trash/`; `None` keeps everything in memory.
+    dir: Option<PathBuf>,
+    /// Read `dir` but never write it: another process holds the directory's lock.
+    read_only: bool,
+    index: Mutex<SearchIndex>,
+    manifest: Mutex<Manifest>,
+    history: History,
+    device_id: Option<String>,
+    /// Update log of a writable data directory.
+    wal: Option<Wal>,
+    /// Containers found damaged at load.
+    issues: Mutex<Vec<StorageIssue>>,
+    limits: Limits,
+}
+
+impl NoteStore {
+    pub fn in_memory() -> Self {
+        Self {
+            notes: Mutex::new(HashMap::new()),
+            writing: Mutex::new(HashMap::new()),
+            dir: None,
+            read_only: false,
+            index: Mutex::new(SearchIndex::default()),
+            manifest: Mutex::new(Manifest::default()),
+            history: History::in_memory(),
+            device_id: None,
+            wal: None,
+            issues: Mutex::new(Vec::new()),
+            limits: Limits::default(),
+        }
+    }
+
+    /// Opens (or creates) a data directory and loads every container under `notes/` and `trash/`.
+    ///
+    /// Containers that fail validation are moved to `quarantine/` and their notes rebuilt
+    /// from the newest saved version, if any (see [`Self::storage_issues`]). Updates logged
+    /// but not yet compacted when the directory was last used are replayed and compacted.
+    pub fn open(data_dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = data_dir.into();
+        fs::create_dir_all(dir.join(NOTES_DIR))?;
+        Self::load(dir, false)
+    }
+
+    /// Loads a data directory that another writer owns and keeps it current in memory only.
+    ///
+    /// Notes that appear on disk later are picked up the first time an update names them.
+    /// Damaged containers are reported and recovered in memory, but left in place. Nothing
+    /// is written: no versions, manifest or device id.
+    pub fn mirror(data_dir: impl Into<PathBuf>) -> io::Result<Self> {
+        Self::load(data_dir.into(), true)
+    }
+
+    fn load(dir: PathBuf, read_only: bool) -> io::Result<Self> {
+        let mut paths = Vec::new();
+        for sub in [TRASH_DIR, NOTES_DIR] {
+            match container_paths(&dir.join(sub)) {
+                Ok(found) => paths.extend(found),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let history = match read_only {
+            true => History::mirror(dir.join(HISTORY_DIR)),
+            false => History::open(dir.join(HISTORY_DIR)),
+        };
+        let mut notes = HashMap::new();
+        let mut issues = Vec::new();
+        for path in paths {
+            match read_container(&path) {
+                Ok(note) => {
+                    notes.insert(note.meta.id.clone(), note);
+                }
+                Err(reason) => {
+                    eprintln!("[hypernote] damaged container {}: {reason}", path.display());
+                    let Some(issue) = quarantine(&dir, &path, reason, read_only) else {
+                        continue;
+                    };
+                    if let Some((version, note)) = newest_version(&history, &issue.note_id) {
+                        if !read_only {
+                            let sub = match note.meta.deleted_at {
+                                Some(_) => TRASH_DIR,
+                                None => NOTES_DIR,
+                            };
+                            let path = container_path(&dir.join(sub), &issue.note_id);
+                            if let Err(e) =
+                                write_atomic(&path, &container::encode(&note.meta, &note.yjs_state))
+                            {
+                                eprintln!("[hypernote] failed to restore {}: {e}", path.display());
+                            }
+                        }
+                        notes.insert(issue.note_id.clone(), note);
+                        issues.push(StorageIssue {
+                            restored_version: Some(version),
+                            ..issue
+                        });
+                    } else {
+                        issues.push(issue);
+                    }
+                }
+            }
+        }
+
+        let mut index = SearchIndex::default();
+        for note in notes.values() {
+            if note.meta.deleted_at.is_none() {
+                let text = markdown_of(&note.yjs_state);
+                index.upsert(&note.meta.id, &note.meta.title, &text);
+            }
+        }
+
+        let manifest = match fs::read(dir.join(MANIFEST_FILE)) {
+            Ok(state) => Manifest::from_state(&state).unwrap_or_else(|reason| {
+                eprintln!("[hypernote] rebuilding {MANIFEST_FILE}: {reason}");
+                Manifest::default()
+            }),
+            Err(_) => Manifest::default(),
+        };
+        let mut changed = false;
+        for note in notes.values() {
+            changed |= manifest.record(&note.meta);
+        }
+
+        let wal = match read_only {
+            true => None,
+            false => Some(Wal::open(dir.join(WAL_DIR))?),
+        };
+        let store = Self {
+            notes: Mutex::new(notes),
+            writing: Mutex::new(HashMap::new()),
+            history,
+            device_id: load_device_id(&dir, read_only),
+            issues: Mutex::new(issues),
+            limits: Limits::default(),
+            wal,
+            dir: Some(dir),
+            read_only,
+            index: Mutex::new(index),
+            manifest: Mutex::new(manifest),
+        };
+        if changed {
+            if let Ok(manifest) = store.manifest.lock() {
+                store.save_manifest(&manifest);
+            }
+        }
+        store.replay_logs();
+        Ok(store)
+    }
+
+    /// The same store, rejecting updates and notes larger than `limits` allow.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Whether this store only mirrors a data directory another process writes.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Stable id of this device, kept in the data directory; `None` for in-memory stores.
+    pub fn device_id(&self) -> Option<&str> {
+        self.device_id.as_deref()
+    }
+
+    pub fn create(&self) -> Result<NoteMeta, String> {
+        let now = unix_now_ms();
+        let meta = NoteMeta {
+            id: uuid::Uuid::new_v4().to_string(),
+            title: DEFAULT_TITLE.to_string(),
+            created_at: now,
+            updated_at: now,
+            ..Default::default()
+        };
+
+        self.write(&meta, &[], false)?;
+        if let Ok(mut index) = self.index.lock() {
+            index.upsert(&meta.id, &meta.title, "");
+        }
+        self.record_in_manifest(&meta);
+        let mut notes = self.notes.lock().map_err(|_| "note store poisoned")?;
+        notes.insert(
+            meta.id.clone(),
+            NoteDocument {
+                meta: meta.clone(),
+                yjs_state: Vec::new(),
+                markdown: String::new(),
+            },
+        );
+
+        Ok(meta)
+    }
+
+    /// The note with `markdown` filled in from its current Yjs state.
+    pub fn get(&self, note_id: &str) -> Option<NoteDocument> {
+        let mut note = self.notes.lock().ok()?.get(note_id).cloned()?;
+        note.markdown = markdown_of(&note.yjs_state);
+        Some(note)
+    }
+
+    /// Metadata of every note outside the trash, most recently updated first.
+    ///
+    /// Includes notes only the manifest knows of, with placeholder metadata, until their
+    /// content arrives.
+    pub fn list(&self) -> Vec<NoteMeta> {
+        let mut metas = self.metas(|meta| meta.deleted_at.is_none());
+        let entries = match self.manifest.lock() {
+            Ok(manifest) => manifest.entries(),
+            Err(_) => Vec::new(),
+        };
+        metas.extend(
+            entries
+                .into_iter()
+                .filter(|(id, entry)| entry.deleted_at.is_none() && !self.is_loaded(id))
+                .map(|(id, entry)| entry.placeholder(&id)),
+        );
+        metas.sort_by_key(|note| std::cmp::Reverse(note.updated_at));
+        metas
+    }
+
+    /// Ids of notes outside the trash.
+    pub fn note_ids(&self) -> Vec<String> {
+        self.metas(|meta| meta.deleted_at.is_none())
+            .into_iter()
+            .map(|meta| meta.id)
+            .collect()
+    }
+
+    /// Ids of every note, trashed ones included, so deletions reach peers too.
+    pub fn all_note_ids(&self) -> Vec<String> {
+        self.notes
+            .lock()
+            .map(|notes| notes.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn metas(&self, keep: impl Fn(&NoteMeta) -> bool) -> Vec<NoteMeta> {
+        self.notes
+            .lock()
+            .map(|notes| {
+                notes
+                    .values()
+                    .map(|note| &note.meta)
+                    .filter(|meta| keep(meta))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Merges `update` into a note, creating the note if this is the first we hear of it.
+    ///
+    /// The update is in the log, or the container, by the time this returns.
+    pub fn apply_update(&self, note_id: &str, update: &[u8]) -> Result<NoteMeta, String> {
+        self.merge(note_id, update, unix_now_ms(), None, false)
+    }
+
+    /// Merges updates left in the log by an earlier run, then compacts them.
+    fn replay_logs(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        for (note_id, records) in wal::read_logs(&dir.join(WAL_DIR)) {
+            let at = records.iter().map(|(at, _)| *at).max().unwrap_or_default();
+            let replayed =
+                yrs::merge_updates_v1(records.iter().map(|(_, update)| update.as_slice()))
+                    .map_err(|e| format!("undecodable update: {e}"))
+                    .and_then(|update| self.merge(&note_id, &update, at, None, true));
+            match replayed {
+                Ok(_) => {
+                    if let Ok(mut issues) = self.issues.lock() {
+                        for issue in issues.iter_mut().filter(|issue| issue.note_id == note_id) {
+                            issue.replayed_log = true;
+                        }
+                    }
+                }
+                Err(reason) => {
+                    eprintln!("[hypernote] failed to replay update log of {note_id}: {reason}")
+                }
+            }
+        }
+    }
+
+    /// Containers found damaged when the store loaded, and how each note was recovered.
+    pub fn storage_issues(&self) -> Vec<StorageIssue> {
+        self.issues
+            .lock()
+            .map(|issues| issues.clone())
+            .unwrap_or_default()
+    }
+
+    /// Merges `update`, made at `at`. A note it creates starts from the metadata in `seed`,
+    /// or is dated `at`.
+    /// Outside a replay it is appended to the log, and the container rewritten only once
+    /// the log is due for compaction.
+    fn merge(
+        &self,
+        note_id: &str,
+        update: &[u8],
+        at: i64,
+        seed: Option<&NoteMeta>,
+        replaying: bool,
+    ) -> Result<NoteMeta, String> {
+        // Ids become file names, so hold them to the same alphabet as workspace ids.
+        if !is_valid_workspace_id(note_id) || note_id == MANIFEST_NOTE_ID {
+            return Err("note id is not storable".to_string());
+        }
+        if !replaying {
+            self.limits.check_update(update.len())?;
+        }
+
+        let writing = self.writing(note_id)?;
+        let _writing = writing.lock().map_err(|_| "note store poisoned")?;
+        let loaded = self
+            .notes
+            .lock()
+            .map_err(|_| "note store poisoned")?
+            .get(note_id)
+            .cloned();
+        let existing = loaded.or_else(|| self.read_from_disk(note_id));
+        let existing = existing.as_ref();
+        let merged = match existing.map(|note| note.yjs_state.as_slice()) {
+            Some(state) if !state.is_empty() => yrs::merge_updates_v1([state, update]),
+            _ => yrs::merge_updates_v1([update]),
+        }
+        .map_err(|e| format!("undecodable update: {e}"))?;
+        // A note already over the limit may still take updates that don't grow it.
+        let previous = existing.map_or(0, |note| note.yjs_state.len());
+        if !replaying && merged.len() > previous {
+            self.limits.check_note(merged.len())?;
+        }
+        // Only an update that is going to be kept earns the content before it a version.
+        if let Some(note) = existing {
+            if let Err(reason) = self.history.checkpoint(&note.meta, &note.yjs_state, false) {
+                eprintln!("[hypernote] no version kept for {note_id}: {reason}");
+            }
+        }
+
+        let mut meta = match (existing, seed) {
+            (Some(note), _) => note.meta.clone(),
+            (None, Some(seed)) => NoteMeta {
+                id: note_id.to_string(),
+                ..seed.clone()
+            },
+            (None, None) => NoteMeta {
+                id: note_id.to_string(),
+                title: DEFAULT_TITLE.to_string(),
+                created_at: at,
+                updated_at: at,
+                ..Default::default()
+            },
+        };
+        meta.updated_at = at;
+        let was_deleted = meta.deleted_at.is_some();
+        let text = meta_map::project(&mut meta, &merged);
+        let moved = was_deleted != meta.deleted_at.is_some();
+        let created = existing.is_none();
+
+        let logged = match &self.wal {
+            Some(wal) if !replaying => Some(
+                wal.append(note_id, update, at)
+                    .map_err(|e| format!("log write failed: {e}"))?,
+            ),
+            _ => None,
+        };
+        // New notes and moves between `notes/` and `trash/` go to disk at once, so other
+        // readers of the directory find the file where it belongs.
+        if logged.is_none_or(|len| created || moved || len >= COMPACT_BYTES) {
+            self.write(&meta, &merged, moved)?;
+            self.clear_log(note_id);
+        }
+        if let Ok(mut index) = self.index.lock() {
+            match meta.deleted_at {
+                Some(_) => index.remove(note_id),
+                None => index.upsert(note_id, &meta.title, &text),
+            }
+        }
+        self.record_in_manifest(&meta);
+        self.notes
+            .lock()
+            .map_err(|_| "note store poisoned")?
+            .insert(
+                note_id.to_string(),
+                NoteDocument {
+                    meta: meta.clone(),
+                    yjs_state: merged,
+                    markdown: String::new(),
+                },
+            );
+
+        Ok(meta)
+    }
+
+    /// Creates a note from `draft`, dated as the draft says. Returns its metadata and full
+    /// state, which peers need to get the note.
+    pub fn create_from(&self, draft: &NoteDraft) -> Result<(NoteMeta, Vec<u8>), String> {
+        let mut state = self.claimed(&[], append_update(&[], &draft.markdown)?)?;
+        let title = draft
+            .title
+            .clone()
+            .filter(|title| *title != title_from_text(&draft.markdown));
+        let changes = [
+            title.map(|title| MetaChange::Title(Some(title))),
+            (!draft.tags.is_empty()).then(|| MetaChange::Tags(draft.tags.clone())),
+            draft
+                .folder
+                .clone()
+                .map(|folder| MetaChange::Folder(Some(folder))),
+        ];
+        for change in changes.into_iter().flatten() {
+            let update = meta_map::change_update(&state, &normalized(change)?)?;
+            state = yrs::merge_updates_v1([state.as_slice(), &update])
+                .map_err(|e| format!("undecodable update: {e}"))?;
+        }
+
+        let note_id = draft
+            .id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        for attachment in &draft.attachments {
+            self.save_attachment(&note_id, &attachment.name, &attachment.bytes)?;
+        }
+        let now = unix_now_ms();
+        let updated_at = match draft.updated_at {
+            0 => now,
+            at => at,
+        };
+        let created_at = match draft.created_at {
+            0 => updated_at,
+            at => at,
+        };
+        let seed = NoteMeta {
+            title: DEFAULT_TITLE.to_string(),
+            created_at,
+            ..Default::default()
+        };
+        let meta = self.merge(&note_id, &state, updated_at, Some(&seed), false)?;
+        Ok((meta, state))
+    }
+
+    /// Merges a note's state from a backup into the note of the same id. A note the store
+    /// lacks comes back with the backup's metadata and times; an existing one takes the
+    /// backup's changes as a new edit.
+    pub fn merge_backup(&self, meta: &NoteMeta, yjs_state: &[u8]) -> Result<NoteMeta, String> {
+        let (at, seed) = match self.meta(&meta.id).is_some() {
+            true => (unix_now_ms(), None),
+            false => (meta.updated_at, Some(meta)),
+        };
+        self.merge(&meta.id, yjs_state, at, seed, false)
+    }
+
+    /// Merges a container the app's frontend saved. Its edits normally reached the store
+    /// as updates already, so this returns `None` unless the container adds something,
+    /// e.g. a note the frontend just created.
+    pub fn merge_container(&self, bytes: &[u8]) -> Result<Option<NoteMeta>, String> {
+        let decoded = container::decode(bytes)?;
+        if let Some(state) = self.state(&decoded.meta.id) {
+            let (incoming, _) = compare_states(&state, &decoded.yjs_state)?;
+            if !incoming {
+                return Ok(None);
+            }
+        }
+        self.merge_backup(&decoded.meta, &decoded.yjs_state)
+            .map(Some)
+    }
+
+    /// A note's container as it would be written now, trashed notes included.
+    pub fn container(&self, note_id: &str) -> Option<Vec<u8>> {
+        let notes = self.notes.lock().ok()?;
+        let note = notes.get(note_id)?;
+        Some(container::encode(&note.meta, &note.yjs_state))
+    }
+
+    /// Deletes a trashed note from this device for good: its container, log and entry.
+    /// Peers keep their copies, and the manifest keeps it listed as deleted.
+    pub fn purge(&self, note_id: &str) -> Result<(), String> {
+        let writing = self.writing(note_id)?;
+        let _writing = writing.lock().map_err(|_| "note store poisoned")?;
+        match self.meta(note_id) {
+            Some(meta) if meta.deleted_at.is_some() => {}
+            Some(_) => return Err("only notes in the trash can be deleted".to_string()),
+            None => return Err("note not found".to_string()),
+        }
+        if let Some(dir) = self.dir.as_ref().filter(|_| !self.read_only) {
+            remove_if_exists(&container_path(&dir.join(TRASH_DIR), note_id))
+                .map_err(|e| format!("delete failed: {e}"))?;
+        }
+        self.clear_log(note_id);
+        self.notes
+            .lock()
+            .map_err(|_| "note store poisoned")?
+            .remove(note_id);
+        Ok(())
+    }
+
+    /// Writes a file a note refers to as `attachments/<note-id>/<name>`. Attachments are
+    /// not synced; they stay on the device that imported them.
+    pub fn save_attachment(&self, note_id: &str, name: &str, bytes: &[u8]) -> Result<(), String> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("attachments need a data directory")?;
+        let file_name = Path::new(name)
+            .file_name()
+            .filter(|file_name| *file_name == name)
+            .ok_or_else(|| format!("bad attachment name: {name}"))?;
+        if !is_valid_workspace_id(note_id) {
+            return Err("note id is not storable".to_string());
+        }
+        let path = dir.join(ATTACHMENTS_DIR).join(note_id).join(file_name);
+        fs::create_dir_all(path.parent().unwrap_or(dir))
+            .and_then(|_| write_atomic(&path, bytes))
+            .map_err(|e| format!("cannot save attachment {name}: {e}"))
+    }
+
+    /// Appends `text` to the end of a note as a CRDT update of its own.
+    pub fn append_text(&self, note_id: &str, text: &str) -> Result<NoteMeta, String> {
+        let state = self.state(note_id).unwrap_or_default();
+        let update = self.claimed(&state, append_update(&state, text)?)?;
+        self.apply_update(note_id, &update)
+    }
+
+    /// Ranges of a note's text with the device that wrote each and when.
+    pub fn attribution(&self, note_id: &str) -> Result<Vec<AttributedRange>, String> {
+        let state = match self.state(note_id) {
+            Some(state) => state,
+            None => {
+                self.read_from_disk(note_id)
+                    .ok_or("note not found")?
+                    .yjs_state
+            }
+        };
+        attribution::attribute(&state)
+    }
+
+    /// `update`, made by a fresh client on top of `yjs_state`, with that client attributed to
+    /// this device.
+    fn claimed(&self, yjs_state: &[u8], update: Vec<u8>) -> Result<Vec<u8>, String> {
+        match &self.device_id {
+            Some(device) => attribution::claim_update(yjs_state, &update, device, unix_now_ms()),
+            None => Ok(update),
+        }
+    }
+
+    /// Records `change` in the note's `meta` map as a CRDT update of its own.
+    ///
+    /// Values are normalized first. Returns the resulting metadata and the update, which
+    /// peers need to converge on the same change.
+    pub fn change_meta(
+        &self,
+        note_id: &str,
+        change: MetaChange,
+    ) -> Result<(NoteMeta, Vec<u8>), String> {
+        let change = normalized(change)?;
+        let state = {
+            let mut notes = self.notes.lock().map_err(|_| "note store poisoned")?;
+            if !notes.contains_key(note_id) {
+                let note = self.read_from_disk(note_id).ok_or("note not found")?;
+                notes.insert(note_id.to_string(), note);
+            }
+            notes[note_id].yjs_state.clone()
+        };
+        let update = meta_map::change_update(&state, &change)?;
+        let meta = self.apply_update(note_id, &update)?;
+        Ok((meta, update))
+    }
+
+    /// Saved versions of a note, newest first.
+    pub fn versions(&self, note_id: &str) -> Vec<NoteVersion> {
+        self.history.list(note_id)
+    }
+
+    /// A note as it was at `version`, with the metadata it had then.
+    pub fn version(&self, note_id: &str, version: u32) -> Result<NoteDocument, String> {
+        let (meta, yjs_state) = self.history.get(note_id, version)?;
+        Ok(NoteDocument {
+            meta,
+            markdown: markdown_of(&yjs_state),
+            yjs_state,
+        })
+    }
+
+    /// Diff of a note's content from version `from` to version `to`; `None` on either side
+    /// stands for the current content.
+    pub fn diff(
+        &self,
+        note_id: &str,
+        from: Option<u32>,
+        to: Option<u32>,
+    ) -> Result<NoteDiff, String> {
+        let text = |version: Option<u32>| match version {
+            Some(version) => self.version(note_id, version).map(|note| note.markdown),
+            None => self
+                .get(note_id)
+                .or_else(|| self.read_from_disk(note_id))
+                .map(|note| markdown_of(&note.yjs_state))
+                .ok_or_else(|| "note not found".to_string()),
+        };
+        let label = |version: Option<u32>| match version {
+            Some(version) => format!("{note_id}@v{version}"),
+            None => format!("{note_id}@current"),
+        };
+        Ok(diff::diff_texts(
+            &text(from)?,
+            &text(to)?,
+            &label(from),
+            &label(to),
+        ))
+    }
+
+    /// Brings a note's content back to `version` as a CRDT update of its own.
+    ///
+    /// The current state is kept as a version first, so a restore can be undone the same
+    /// way. Metadata such as tags and title overrides stays as it is now.
+    pub fn restore_version(
+        &self,
+        note_id: &str,
+        version: u32,
+    ) -> Result<(NoteMeta, Vec<u8>), String> {
+        let (_, old_state) = self.history.get(note_id, version)?;
+        let (meta, state) = {
+            let mut notes = self.notes.lock().map_err(|_| "note store poisoned")?;
+            if !notes.contains_key(note_id) {
+                let note = self.read_from_disk(note_id).ok_or("note not found")?;
+                notes.insert(note_id.to_string(), note);
+            }
+            (
+                notes[note_id].meta.clone(),
+                notes[note_id].yjs_state.clone(),
+            )
+        };
+        self.history.checkpoint(&meta, &state, true)?;
+
+        let update = history::restore_update(&state, &markdown_of(&old_state))?;
+        let update = self.claimed(&state, update)?;
+        let meta = self.apply_update(note_id, &update)?;
+        Ok((meta, update))
+    }
+
+    /// Sets a title by hand; content edits no longer change it until [`Self::reset_title`].
+    pub fn rename(&self, note_id: &str, title: &str) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Title(Some(title.to_string())))
+    }
+
+    /// Drops a manual title and derives it from the content again.
+    pub fn reset_title(&self, note_id: &str) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Title(None))
+    }
+
+    pub fn set_tags(&self, note_id: &str, tags: &[String]) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Tags(tags.to_vec()))
+    }
+
+    pub fn set_pinned(&self, note_id: &str, pinned: bool) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Pinned(pinned))
+    }
+
+    /// Moves a note into `folder` (`a/b`); `None` or a blank path means the top level.
+    pub fn move_to_folder(&self, note_id: &str, folder: Option<&str>) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Folder(folder.map(str::to_string)))
+    }
+
+    pub fn set_color(&self, note_id: &str, color: Option<&str>) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Color(color.map(str::to_string)))
+    }
+
+    /// Stamps `deletedAt`, which also moves the note's container into `trash/`.
+    pub fn move_to_trash(&self, note_id: &str) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Deleted(Some(unix_now_ms())))
+    }
+
+    /// Clears `deletedAt` and moves the container back under `notes/`.
+    pub fn restore(&self, note_id: &str) -> Result<NoteMeta, String> {
+        self.changed(note_id, MetaChange::Deleted(None))
+    }
+
+    fn changed(&self, note_id: &str, change: MetaChange) -> Result<NoteMeta, String> {
+        self.change_meta(note_id, change).map(|(meta, _)| meta)
+    }
+
+    /// Notes matching `filter`, pinned first, then most recently updated.
+    pub fn list_filtered(&self, filter: &NoteFilter) -> Vec<NoteMeta> {
+        let mut metas: Vec<NoteMeta> = self
+            .list()
+            .into_iter()
+            .filter(|meta| organize::matches(meta, filter))
+            .collect();
+        metas.sort_by_key(|meta| (!meta.pinned, std::cmp::Reverse(meta.updated_at)));
+        metas
+    }
+
+    pub fn tags(&self) -> Vec<TagCount> {
+        organize::tag_counts(&self.list())
+    }
+
+    pub fn meta(&self, note_id: &str) -> Option<NoteMeta> {
+        let notes = self.notes.lock().ok()?;
+        notes.get(note_id).map(|note| note.meta.clone())
+    }
+
+    /// Metadata of trashed notes, most recently deleted first.
+    pub fn list_trash(&self) -> Vec<NoteMeta> {
+        let mut metas = self.metas(|meta| meta.deleted_at.is_some());
+        metas.sort_by_key(|note| std::cmp::Reverse(note.deleted_at));
+        metas
+    }
+
+    /// Merged Yjs state of a note.
+    pub fn state(&self, note_id: &str) -> Option<Vec<u8>> {
+        let notes = self.notes.lock().ok()?;
+        notes
+            .get(note_id)
+            .map(|note| note.yjs_state.clone())
+            .filter(|state| !state.is_empty())
+    }
+
+    /// Changes to a note that a peer with `state_vector` has not seen, if any.
+    pub fn missing(&self, note_id: &str, state_vector: &[u8]) -> Option<Vec<u8>> {
+        let state = self.state(note_id)?;
+        let diff = yrs::diff_updates_v1(&state, state_vector).ok()?;
+        (diff != EMPTY_UPDATE).then_some(diff)
+    }
+
+    /// Full manifest state, for a peer that has never seen it.
+    pub fn manifest_state(&self) -> Vec<u8> {
+        self.manifest
+            .lock()
+            .map(|manifest| manifest.state())
+            .unwrap_or_else(|_| EMPTY_UPDATE.to_vec())
+    }
+
+    pub fn manifest_vector(&self) -> StateVector {
+        self.manifest
+            .lock()
+            .map(|manifest| manifest.state_vector())
+            .unwrap_or_default()
+    }
+
+    /// Manifest changes made since `state_vector`, if any.
+    pub fn manifest_diff(&self, state_vector: &StateVector) -> Option<Vec<u8>> {
+        self.manifest.lock().ok()?.diff(state_vector)
+    }
+
+    /// Merges a peer's manifest update.
+    pub fn apply_manifest_update(&self, update: &[u8]) -> Result<(), String> {
+        let manifest = self.manifest.lock().map_err(|_| "manifest poisoned")?;
+        manifest.apply(update)?;
+        self.save_manifest(&manifest);
+        Ok(())
+    }
+
+    /// Notes the manifest lists outside the trash whose content this store lacks.
+    pub fn missing_note_ids(&self) -> Vec<String> {
+        let entries = match self.manifest.lock() {
+            Ok(manifest) => manifest.entries(),
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .into_iter()
+            .filter(|(id, entry)| entry.deleted_at.is_none() && !self.is_loaded(id))
+            .filter(|(id, _)| self.read_from_disk(id).is_none())
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn is_loaded(&self, note_id: &str) -> bool {
+        self.notes
+            .lock()
+            .map(|notes| notes.contains_key(note_id))
+            .unwrap_or(false)
+    }
+
+    fn record_in_manifest(&self, meta: &NoteMeta) {
+        if let Ok(manifest) = self.manifest.lock() {
+            if manifest.record(meta) {
+                self.save_manifest(&manifest);
+            }
+        }
+    }
+
+    fn save_manifest(&self, manifest: &Manifest) {
+        let Some(dir) = self.dir.as_ref().filter(|_| !self.read_only) else {
+            return;
+        };
+        if let Err(e) = write_atomic(&dir.join(MANIFEST_FILE), &manifest.state()) {
+            eprintln!("[hypernote] failed to save {MANIFEST_FILE}: {e}");
+        }
+    }
+
+    /// Ranked full-text matches for `query`; see [`crate::search`] for the syntax.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.index
+            .lock()
+            .map(|index| index.search(query, limit))
+            .unwrap_or_default()
+    }
+
+    /// Fuzzy title matches for `query`; `recent` lists note ids most recently opened first.
+    pub fn quick_open(&self, query: &str, limit: usize, recent: &[String]) -> Vec<QuickOpenHit> {
+        self.notes
+            .lock()
+            .map(|notes| {
+                let live = notes
+                    .values()
+                    .map(|note| &note.meta)
+                    .filter(|meta| meta.deleted_at.is_none());
+                quick_open::rank(query, live, recent, limit)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Re-encodes a note's state, dropping the content of deleted text and overwritten
+    /// values with `collect`, and rewrites its container, folding in its log.
+    ///
+    /// Item ids and lengths survive, so peers holding older states still merge with the
+    /// result, text they inserted next to collected content included. Collect only once
+    /// every peer has caught up, since none can get the deleted text from here afterwards.
+    /// The old encoding is kept if the new one is not smaller, and then nothing counts as
+    /// collected.
+    pub fn compact(&self, note_id: &str, collect: bool) -> Result<CompactionReport, String> {
+        if self.read_only {
+            return Err("notes in this directory are written by another process".to_string());
+        }
+        let writing = self.writing(note_id)?;
+        let _writing = writing.lock().map_err(|_| "note store poisoned")?;
+        let note = self.loaded(note_id).ok_or("note not found")?;
+        let before = note.yjs_state.len();
+        let compacted = compact_state(&note.yjs_state, collect)?;
+        let smaller = compacted.len() < before;
+        let yjs_state = match smaller {
+            true => compacted,
+            false => note.yjs_state,
+        };
+        self.write(&note.meta, &yjs_state, false)?;
+        self.clear_log(note_id);
+        let after = yjs_state.len();
+        if let Some(note) = self
+            .notes
+            .lock()
+            .map_err(|_| "note store poisoned")?
+            .get_mut(note_id)
+        {
+            note.yjs_state = yjs_state;
+        }
+        Ok(CompactionReport {
+            note_id: note_id.to_string(),
+            before_bytes: before as u64,
+            after_bytes: after as u64,
+            collected: collect && smaller,
+        })
+    }
+
+    /// Folds every note's logged updates into its container, e.g. before the process exits.
+    pub fn compact_all(&self) {
+        let Some(wal) = &self.wal else {
+            return;
+        };
+        for note_id in wal.note_ids() {
+            let Ok(writing) = self.writing(&note_id) else {
+                return;
+            };
+            let Ok(_writing) = writing.lock() else {
+                continue;
+            };
+            let Some(note) = self.loaded(&note_id) else {
+                continue;
+            };
+            match self.write(&note.meta, &note.yjs_state, false) {
+                Ok(()) => self.clear_log(&note_id),
+                Err(reason) => eprintln!("[hypernote] failed to compact {note_id}: {reason}"),
+            }
+        }
+    }
+
+    /// The lock held while `note_id`'s log and container are written.
+    fn writing(&self, note_id: &str) -> Result<Arc<Mutex<()>>, String> {
+        let mut writing = self.writing.lock().map_err(|_| "note store poisoned")?;
+        Ok(writing.entry(note_id.to_string()).or_default().clone())
+    }
+
+    /// A copy of the note as held in memory.
+    fn loaded(&self, note_id: &str) -> Option<NoteDocument> {
+        self.notes.lock().ok()?.get(note_id).cloned()
+    }
+
+    /// Drops a note's log after its container was written; callers hold its `writing` lock.
+    fn clear_log(&self, note_id: &str) {
+        if let Some(wal) = &self.wal {
+            if let Err(e) = wal.clear(note_id) {
+                eprintln!("[hypernote] failed to clear update log of {note_id}: {e}");
+            }
+        }
+    }
+
+    fn read_from_disk(&self, note_id: &str) -> Option<NoteDocument> {
+        let dir = self.dir.as_ref()?;
+        [NOTES_DIR, TRASH_DIR]
+            .into_iter()
+            .find_map(|sub| read_container(&container_path(&dir.join(sub), note_id)).ok())
+    }
+
+    /// Writes the container under `trash/` or `notes/`; `moved` removes the other copy.
+    fn write(&self, meta: &NoteMeta, yjs_state: &[u8], moved: bool) -> Result<(), String> {
+        let Some(dir) = self.dir.as_ref().filter(|_| !self.read_only) else {
+            return Ok(());
+        };
+
+        let (to, from) = match meta.deleted_at {
+            Some(_) => (TRASH_DIR, NOTES_DIR),
+            None => (NOTES_DIR, TRASH_DIR),
+        };
+        write_atomic(
+            &container_path(&dir.join(to), &meta.id),
+            &container::encode(meta, yjs_state),
+        )
+        .and_then(|_| match moved {
+            true => remove_if_exists(&container_path(&dir.join(from), &meta.id)),
+            false => Ok(()),
+        })
+        .map_err(|e| format!("write failed: {e}"))
+    }
+}
+
+impl Drop for NoteStore {
+    fn drop(&mut self) {
+        self.compact_all();
+    }
+}
+
+/// Moves a damaged container out of the way, into `quarantine/<note-id>.<time>.yjs`, unless
+/// the store only mirrors the directory. `None` if the file name is not a note id.
+fn quarantine(dir: &Path, path: &Path, reason: String, read_only: bool) -> Option<StorageIssue> {
+    let note_id = path.file_stem()?.to_str()?.to_string();
+    if !is_valid_workspace_id(&note_id) {
+        return None;
+    }
+
+    let detected_at = unix_now_ms();
+    let quarantined_to = (!read_only)
+        .then(|| {
+            let to = dir
+                .join(QUARANTINE_DIR)
+                .join(format!("{note_id}.{detected_at}.{CONTAINER_EXTENSION}"));
+            fs::create_dir_all(dir.join(QUARANTINE_DIR))
+                .and_then(|_| fs::rename(path, &to))
+                .map(|_| to)
+                .map_err(|e| eprintln!("[hypernote] failed to quarantine {}: {e}", path.display()))
+                .ok()
+        })
+        .flatten();
+    Some(StorageIssue {
+        note_id,
+        path: path.display().to_string(),
+        quarantined_to: quarantined_to.map(|to| to.display().to_string()),
+        reason,
+        restored_version: None,
+        replayed_log: false,
+        detected_at,
+    })
+}
+
+/// The newest saved version of a note, as a document with the metadata it had then.
+fn newest_version(history: &History, note_id: &str) -> Option<(u32, NoteDocument)> {
+    let newest = history.list(note_id).first()?.version;
+    let (meta, yjs_state) = history.get(note_id, newest).ok()?;
+    Some((
+        newest,
+        NoteDocument {
+            meta,
+            yjs_state,
+            markdown: String::new(),
+        },
+    ))
+}
+
+/// Whether `incoming` has changes `local` lacks, and the other way round.
+pub(crate) fn compare_states(local: &[u8], incoming: &[u8]) -> Result<(bool, bool), String> {
+    let decode =
+        |state: &[u8]| Update::decode_v1(state).map_err(|e| format!("undecodable note state: {e}"));
+    let (local, incoming) = (decode(local)?, decode(incoming)?);
+    Ok((adds(&incoming, &local), adds(&local, &incoming)))
+}
+
+/// Whether `update` inserts or deletes anything `base` has not.
+fn adds(update: &Update, base: &Update) -> bool {
+    let known = base.state_vector();
+    update
+        .state_vector()
+        .iter()
+        .any(|(client, clock)| *clock > known.get(client))
+        || !update.delete_set().diff(base.delete_set()).is_empty()
+}
+
+fn read_container(path: &Path) -> Result<NoteDocument, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let decoded = container::decode(&bytes)?;
+    Ok(NoteDocument {
+        meta: decoded.meta,
+        yjs_state: decoded.yjs_state,
+        markdown: String::new(),
+    })
+}
+
+/// `change` with its value trimmed and validated the way every write stores it.
+fn normalized(change: MetaChange) -> Result<MetaChange, String> {
+    Ok(match change {
+        MetaChange::Title(title) => MetaChange::Title(
+            title.map(|title| title.trim().chars().take(MAX_TITLE_CHARS).collect()),
+        ),
+        MetaChange::Tags(tags) => MetaChange::Tags(organize::normalize_tags(&tags)),
+        MetaChange::Folder(folder) => {
+            MetaChange::Folder(folder.as_deref().and_then(organize::normalize_folder))
+        }
+        MetaChange::Color(Some(color)) => MetaChange::Color(organize::normalize_color(&color)?),
+        change => change,
+    })
+}
+
+/// Plain text of the editor content held in a merged Yjs state.
+pub fn markdown_of(yjs_state: &[u8]) -> String {
+    let Ok(update) = Update::decode_v1(yjs_state) else {
+        return String::new();
+    };
+
+    let doc = yrs::Doc::new();
+    let content = doc.get_or_insert_text(CONTENT_TEXT);
+    let mut txn = doc.transact_mut();
+    if txn.apply_update(update).is_err() {
+        return String::new();
+    }
+    content.get_string(&txn)
+}
+
+/// `yjs_state` loaded into a fresh document and encoded again, which merges adjacent items;
+/// with `collect` the content of deleted items is garbage-collected on the way.
+pub fn compact_state(yjs_state: &[u8], collect: bool) -> Result<Vec<u8>, String> {
+    let doc = yrs::Doc::with_options(yrs::Options {
+        skip_gc: !collect,
+        ..Default::default()
+    });
+    if !yjs_state.is_empty() {
+        let update = Update::decode_v1(yjs_state).map_err(|e| format!("undecodable state: {e}"))?;
+        doc.transact_mut()
+            .apply_update(update)
+            .map_err(|e| format!("undecodable state: {e}"))?;
+    }
+    let txn = doc.transact();
+    Ok(txn.encode_state_as_update_v1(&StateVector::default()))
+}
+
+/// An update that appends `text` after the current content of `yjs_state`.
+///
+/// The edit is made by a fresh Yjs client, so it merges with concurrent edits from
+/// anywhere else instead of overwriting them.
+pub fn append_update(yjs_state: &[u8], text: &str) -> Result<Vec<u8>, String> {
+    let doc = yrs::Doc::new();
+    let content = doc.get_or_insert_text(CONTENT_TEXT);
+    let mut txn = doc.transact_mut();
+    if !yjs_state.is_empty() {
+        let update = Update::decode_v1(yjs_state).map_err(|e| format!("undecodable state: {e}"))?;
+        txn.apply_update(update)
+            .map_err(|e| format!("undecodable state: {e}"))?;
+    }
+
+    let before = txn.state_vector();
+    let end = content.len(&txn);
+    content.insert(&mut txn, end, text);
+    Ok(txn.encode_diff_v1(&before))
+}
+
+/// Title derived from content: the first non-blank line with any heading marks removed,
+/// or "Untitled". The frontend's `titleFromText` follows the same rule.
+pub fn title_from_text(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let title = heading_text(line).unwrap_or(line);
+    if title.is_empty() {
+        DEFAULT_TITLE.to_string()
+    } else {
+        title.chars().take(MAX_TITLE_CHARS).collect()
+    }
+}
+
+/// Text of an ATX heading line (`## Plan ##` → `Plan`).
+fn heading_text(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = line.get(level..)?;
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let rest = rest.trim();
+    // A closing run of `#` counts only when separated by a space, so `# C#` keeps its `#`.
+    let without_closing = rest.trim_end_matches('#');
+    if without_closing.is_empty() || without_closing.ends_with([' ', '\t']) {
+        return Some(without_closing.trim_end());
+    }
+    Some(rest)
+}
+
+/// The saved device id, created on first use unless `read_only`, where a missing one is
+/// replaced by an id for this run only.
+fn load_device_id(dir: &Path, read_only: bool) -> Option<String> {
+    let path = dir.join(DEVICE_ID_FILE);
+    if let Ok(id) = fs::read_to_string(&path) {
+        let id = id.trim();
+        if is_valid_workspace_id(id) {
+            return Some(id.to_string());
+        }
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    if read_only {
+        return Some(id);
+    }
+    match write_atomic(&path, id.as_bytes()) {
+        Ok(()) => Some(id),
+        Err(e) => {
+            eprintln!("[hypernote] failed to save {DEVICE_ID_FILE}: {e}");
+            None
+        }
+    }
+}
+
+/// Where the desktop app keeps `notes/` and `trash/` on this machine.
+pub fn default_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+/// Where `hypernoted` keeps its notes unless told otherwise: its own directory, so the
+/// daemon is a peer of the app on the same machine rather than a second writer of its files.
+pub fn default_daemon_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(DAEMON_DIR))
+}
+
+pub fn container_path(dir: &Path, note_id: &str) -> PathBuf {
+    dir.join(format!("{note_id}.{CONTAINER_EXTENSION}"))
+}
+
+/// Every `*.yjs` file directly under `dir`.
+pub fn container_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some(CONTAINER_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Writes through a temp file and renames it over `path`, so readers never see half a file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension(format!("{CONTAINER_EXTENSION}.tmp"));
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(tmp, path)?;
+    // Make the rename itself durable; directories can't be opened for this on Windows.
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, ReadTxn, StateVector, Text, Transact};
+
+    use super::{
+        container_path, markdown_of, read_container, title_from_text, NoteStore, DEFAULT_TITLE,
+        DEVICE_ID_FILE, MANIFEST_FILE, NOTES_DIR, TRASH_DIR,
+    };
+    use crate::container;
+    use crate::contracts::NoteMeta;
+    use crate::history::HISTORY_DIR;
+    use crate::manifest::Manifest;
+    use crate::wal::{self, WAL_DIR};
+
+    fn edit(doc: &Doc, text: &str) -> Vec<u8> {
+        let content = doc.get_or_insert_text("content");
+        let mut txn = doc.transact_mut();
+        let before = txn.state_vector();
+        let len = content.len(&txn);
+        content.insert(&mut txn, len, text);
+        txn.encode_diff_v1(&before)
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("hypernote-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn merges_incremental_updates_instead_of_replacing_state() {
+        let store = NoteStore::in_memory();
+        let doc = Doc::new();
+        store
+            .apply_update("note-1", &edit(&doc, "hello"))
+            .expect("first edit");
+        store
+            .apply_update("note-1", &edit(&doc, " world"))
+            .expect("second edit");
+
+        let note = store.get("note-1").expect("note exists");
+        assert_eq!(note.markdown, "hello world");
+        assert_eq!(note.meta.title, "hello world");
+        assert!(store.apply_update("note-1", &[0xff, 0xff]).is_err());
+        assert!(store.apply_update("../escape", &edit(&doc, "!")).is_err());
+    }
+
+    #[test]
+    fn appends_as_an_update_that_merges_with_concurrent_edits() {
+        let store = NoteStore::in_memory();
+        let doc = Doc::new();
+        store
+            .apply_update("note-1", &edit(&doc, "log:"))
+            .expect("edit");
+
+        let appended = store.append_text("note-1", " build ok").expect("append");
+        assert_eq!(appended.body.as_deref(), Some("log: build ok"));
+
+        // An editor that never saw the append keeps typing; both edits survive.
+        store
+            .apply_update("note-1", &edit(&doc, "!"))
+            .expect("edit");
+        let text = store.get("note-1").expect("note exists").markdown;
+        assert!(text.starts_with("log:") && text.contains(" build ok") && text.contains('!'));
+
+        let renamed = store.rename("note-1", "Build log").expect("rename");
+        assert_eq!(renamed.title, "Build log");
+    }
+
+    #[test]
+    fn answers_state_vectors_with_only_the_missing_changes() {
+        let store = NoteStore::in_memory();
+        let doc = Doc::new();
+        store
+            .apply_update("note-1", &edit(&doc, "shared"))
+            .expect("edit");
+
+        let up_to_date = doc.transact().state_vector().encode_v1();
+        assert_eq!(store.missing("note-1", &up_to_date), None);
+
+        let empty = StateVector::default().encode_v1();
+        let diff = store
+            .missing("note-1", &empty)
+            .expect("a fresh peer misses everything");
+        assert_eq!(markdown_of(&diff), "shared");
+    }
+
+    #[test]
+    fn persists_containers_and_reloads_them() {
+        let dir = temp_dir("store");
+        let store = NoteStore::open(&dir).expect("store opens");
+        let doc = Doc::new();
+        store
+            .apply_update("note-1", &edit(&doc, "kept"))
+            .expect("edit");
+        store
+            .apply_update("note-2", &edit(&Doc::new(), "binned"))
+            .expect("edit");
+        store.move_to_trash("note-2").expect("trash");
+        let remote = Manifest::default();
+        remote.record(&NoteMeta {
+            id: "note-3".into(),
+            title: "Elsewhere".into(),
+            created_at: 5,
+            ..Default::default()
+        });
+        store
+            .apply_manifest_update(&remote.state())
+            .expect("manifest");
+
+        assert!(!container_path(&dir.join(NOTES_DIR), "note-2").exists());
+        assert!(container_path(&dir.join(TRASH_DIR), "note-2").exists());
+
+        let reopened = NoteStore::open(&dir).expect("store reopens");
+        assert_eq!(reopened.note_ids(), vec!["note-1".to_string()]);
+        assert_eq!(
+            reopened.get("note-1").expect("note reloads").markdown,
+            "kept"
+        );
+        assert_eq!(reopened.list_trash()[0].id, "note-2");
+        assert_eq!(reopened.missing_note_ids(), vec!["note-3".to_string()]);
+        assert!(reopened.list().iter().any(|meta| meta.title == "Elsewhere"));
+
+        reopened.restore("note-2").expect("restore");
+        assert!(container_path(&dir.join(NOTES_DIR), "note-2").exists());
+        assert!(!container_path(&dir.join(TRASH_DIR), "note-2").exists());
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    #[test]
+    fn keeps_versions_on_disk_and_restores_them_as_updates() {
+        let dir = temp_dir("history");
+        let store = NoteStore::open(&dir).expect("store opens");
+        let doc = Doc::new();
+        store
+            .apply_update("note-1", &edit(&doc, "first draft"))
+            .expect("edit");
+        assert!(store.versions("note-1").is_empty());
+        // A rejected update leaves no version behind.
+        assert!(store.apply_update("note-1", &[0xff, 0xff]).is_err());
+        assert!(store.versions("note-1").is_empty());
+        store
+            .apply_update("note-1", &edit(&doc, ", then more"))
+            .expect("edit");
+        assert_eq!(store.versions("note-1")[0].version, 1);
+        assert_eq!(store.version("note-1", 1).unwrap().markdown, "first draft");
+
+        let (meta, update) = store.restore_version("note-1", 1).expect("restore");
+        assert_eq!(meta.title, "first draft");
+        assert_eq!(markdown_of(&store.state("note-1").unwrap()), "first draft");
+
+        // The editor's copy reaches the same content through the shared update.
+        let editor = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let caught_up = yrs::merge_updates_v1([editor.as_slice(), &update]).unwrap();
+        assert_eq!(markdown_of(&caught_up), "first draft");
+
+        let reopened = NoteStore::open(&dir).expect("store reopens");
+        let versions = reopened.versions("note-1");
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            reopened.version("note-1", 2).unwrap().markdown,
+            "first draft, then more"
+        );
+        let diff = reopened.diff("note-1", Some(2), None).expect("diff");
+        assert_eq!((diff.insertions, diff.deletions), (1, 1));
+        assert!(diff
+            .unified
+            .contains("-first draft, then more\n+first draft\n"));
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    #[test]
+    fn attributes_backend_edits_to_a_stable_device_id() {
+        let dir = temp_dir("device");
+        let store = NoteStore::open(&dir).expect("store opens");
+        let device = store.device_id().expect("device id").to_string();
+        store
+            .apply_update("note-1", &edit(&Doc::new(), "typed"))
+            .expect("edit");
+        store.append_text("note-1", " appended").expect("append");
+
+        let ranges = store.attribution("note-1").expect("attribution");
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].author, None);
+        assert_eq!((ranges[1].start, ranges[1].end), (5, 14));
+        assert_eq!(ranges[1].author.as_deref(), Some(device.as_str()));
+
+        let reopened = NoteStore::open(&dir).expect("store reopens");
+        assert_eq!(reopened.device_id(), Some(device.as_str()));
+        assert!(NoteStore::in_memory().device_id().is_none());
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    #[test]
+    fn titles_follow_the_first_line_until_renamed() {
+        let store = NoteStore::in_memory();
+        let doc = Doc::new();
+        let meta = store
+            .apply_update("note-1", &edit(&doc, "\n## Trip plan ##\nday one"))
+            .expect("edit");
+        assert_eq!(meta.title, "Trip plan");
+        assert_eq!(title_from_text("# C#"), "C#");
+        assert_eq!(title_from_text("#hashtag"), "#hashtag");
+        assert_eq!(title_from_text("  \n"), DEFAULT_TITLE);
+
+        let renamed = store.rename("note-1", "Lisbon").expect("rename");
+        assert!(renamed.title_override);
+        let meta = store
+            .apply_update("note-1", &edit(&doc, "\nday two"))
+            .expect("edit");
+        assert_eq!(meta.title, "Lisbon");
+
+        let reset = store.reset_title("note-1").expect("reset");
+        assert_eq!(reset.title, "Trip plan");
+        assert!(!reset.title_override);
+    }
+
+    #[test]
+    fn replays_logged_updates_after_a_crash() {
+        let dir = temp_dir("wal");
+        let store = NoteStore::open(&dir).expect("store opens");
+        let doc = Doc::new();
+        store
+            .apply_update("note-1", &edit(&doc, "saved"))
+            .expect("edit");
+        store
+            .apply_update("note-1", &edit(&doc, " and logged"))
+            .expect("edit");
+        let container = container_path(&dir.join(NOTES_DIR), "note-1");
+        let on_disk = read_container(&container).expect("container");
+        assert_eq!(markdown_of(&on_disk.yjs_state), "saved");
+        // Dies without compacting.
+        std::mem::forget(store);
+
+        let reopened = NoteStore::open(&dir).expect("store reopens");
+        assert_eq!(reopened.get("note-1").unwrap().markdown, "saved and logged");
+        let on_disk = read_container(&container).expect("container");
+        assert_eq!(markdown_of(&on_disk.yjs_state), "saved and logged");
+        assert!(wal::read_logs(&dir.join(WAL_DIR)).is_empty());
+
+        reopened
+            .apply_update("note-1", &edit(&doc, "!"))
+            .expect("edit");
+        drop(reopened);
+        let on_disk = read_container(&container).expect("container");
+        assert_eq!(markdown_of(&on_disk.yjs_state), "saved and logged!");
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    #[test]
+    fn merges_from_many_threads_without_losing_updates() {
+        let dir = temp_dir("threads");
+        let store = NoteStore::open(&dir).expect("store opens");
+        std::thread::scope(|scope| {
+            for writer in 0..4 {
+                let store = &store;
+                scope.spawn(move || {
+                    let doc = Doc::new();
+                    for _ in 0..50 {
+                        let note_id = format!("note-{}", writer % 2);
+                        store
+                            .apply_update(&note_id, &edit(&doc, "x"))
+                            .expect("edit");
+                    }
+                });
+            }
+        });
+        compact_and_check(&store);
+        drop(store);
+
+        let reopened = NoteStore::open(&dir).expect("store reopens");
+        compact_and_check(&reopened);
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    fn compact_and_check(store: &NoteStore) {
+        for note_id in ["note-0", "note-1"] {
+            store.compact(note_id, false).expect("compact");
+            assert_eq!(store.get(note_id).unwrap().markdown.len(), 100);
+        }
+    }
+
+    #[test]
+    fn quarantines_damaged_containers_and_recovers_them() {
+        let dir = temp_dir("quarantine");
+        let store = NoteStore::open(&dir).expect("store opens");
+        let doc = Doc::new();
+        let meta = store
+            .apply_update("note-1", &edit(&doc, "draft"))
+            .expect("edit");
+        let state = store.state("note-1").unwrap();
+        store
+            .history
+            .checkpoint(&meta, &state, true)
+            .expect("version");
+        store
+            .apply_update("note-1", &edit(&doc, " and more"))
+            .expect("edit");
+        std::mem::forget(store);
+
+        let container = container_path(&dir.join(NOTES_DIR), "note-1");
+        let mut bytes = std::fs::read(&container).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&container, bytes).unwrap();
+
+        let reopened = NoteStore::open(&dir).expect("store reopens");
+        let issues = reopened.storage_issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].note_id, "note-1");
+        assert_eq!(issues[0].reason, "invalid container: checksum mismatch");
+        assert_eq!(issues[0].restored_version, Some(1));
+        assert!(issues[0].replayed_log);
+        let quarantined = issues[0].quarantined_to.as_ref().expect("quarantined");
+        assert!(std::path::Path::new(quarantined).exists());
+
+        assert_eq!(reopened.get("note-1").unwrap().markdown, "draft and more");
+        let on_disk = read_container(&container).expect("rewritten");
+        assert_eq!(markdown_of(&on_disk.yjs_state), "draft and more");
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    #[test]
+    fn compacts_deleted_content_without_breaking_merges() {
+        use yrs::updates::decoder::Decode;
+
+        let store = NoteStore::in_memory();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text(super::CONTENT_TEXT);
+        let stale = {
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, &"draft ".repeat(2000));
+            text.insert(&mut txn, 12000, "keep");
+            txn.encode_update_v1()
+        };
+        store.apply_update("note-1", &stale).expect("edit");
+        let deletion = {
+            let mut txn = doc.transact_mut();
+            let before = txn.state_vector();
+            text.remove_range(&mut txn, 0, 12000);
+            txn.encode_diff_v1(&before)
+        };
+        store.apply_update("note-1", &deletion).expect("edit");
+
+        let report = store.compact("note-1", true).expect("compact");
+        assert!(report.after_bytes * 10 < report.before_bytes, "{report:?}");
+        assert!(report.collected);
+        assert_eq!(store.get("note-1").unwrap().markdown, "keep");
+        let again = store.compact("note-1", true).expect("compact");
+        assert!(!again.collected, "{again:?}");
+
+        // A peer that never saw the deletion edits next to the collected text and
+        // reconnects; its edits survive.
+        let peer = Doc::new();
+        let peer_text = peer.get_or_insert_text(super::CONTENT_TEXT);
+        let late = {
+            let mut txn = peer.transact_mut();
+            txn.apply_update(yrs::Update::decode_v1(&stale).unwrap())
+                .unwrap();
+            let before = txn.state_vector();
+            peer_text.insert(&mut txn, 6, "x");
+            peer_text.insert(&mut txn, 12005, "!");
+            txn.encode_diff_v1(&before)
+        };
+        store.apply_update("note-1", &late).expect("late edit");
+        assert_eq!(store.get("note-1").unwrap().markdown, "xkeep!");
+    }
+
+    #[test]
+    fn merges_frontend_containers_and_purges_trashed_notes() {
+        let dir = temp_dir("frontend");
+        let store = NoteStore::open(&dir).expect("store opens");
+        let doc = Doc::new();
+        let meta = NoteMeta {
+            id: "note-1".to_string(),
+            title: "Draft".to_string(),
+            created_at: 5,
+            updated_at: 5,
+            ..Default::default()
+        };
+        let first = edit(&doc, "draft");
+        let saved = container::encode(&meta, &first);
+        assert_eq!(
+            store.merge_container(&saved).unwrap().unwrap().created_at,
+            5
+        );
+
+        // Edits arrive as updates first; the later save adds nothing.
+        store
+            .apply_update("note-1", &edit(&doc, " two"))
+            .expect("edit");
+        let state = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        assert!(store
+            .merge_container(&container::encode(&meta, &state))
+            .unwrap()
+            .is_none());
+        let loaded = container::decode(&store.container("note-1").unwrap()).unwrap();
+        assert_eq!(markdown_of(&loaded.yjs_state), "draft two");
+
+        assert!(store.purge("note-1").is_err(), "only from the trash");
+        store.move_to_trash("note-1").expect("trash");
+        store.purge("note-1").expect("purge");
+        assert!(store.meta("note-1").is_none());
+        assert!(!container_path(&dir.join(TRASH_DIR), "note-1").exists());
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    #[test]
+    fn mirrors_a_directory_without_writing_and_indexes_it() {
+        let dir = temp_dir("mirror");
+        let owner = NoteStore::open(&dir).expect("store opens");
+        let doc = Doc::new();
+        owner
+            .apply_update("note-1", &edit(&doc, "quarterly plan"))
+            .expect("edit");
+
+        let mirror = NoteStore::mirror(&dir).expect("mirror opens");
+        assert_eq!(mirror.search("quarterly", 5)[0].note_id, "note-1");
+
+        // Written by the owner after the mirror loaded; picked up on its first update.
+        let late = Doc::new();
+        owner
+            .apply_update("note-2", &edit(&late, "late arrival"))
+            .expect("edit");
+        mirror
+            .apply_update("note-2", &edit(&late, " noted"))
+            .expect("edit");
+        assert_eq!(
+            mirror.get("note-2").expect("loaded").markdown,
+            "late arrival noted"
+        );
+
+        let on_disk = std::fs::read(container_path(&dir.join(NOTES_DIR), "note-1")).unwrap();
+        mirror
+            .apply_update("note-1", &edit(&doc, " review"))
+            .expect("edit");
+        let after = std::fs::read(container_path(&dir.join(NOTES_DIR), "note-1")).unwrap();
+        assert_eq!(after, on_disk, "a mirror never writes");
+        assert_eq!(mirror.search("\"plan review\"", 5).len(), 1);
+
+        mirror.move_to_trash("note-1").expect("trash");
+        assert!(mirror.search("quarterly", 5).is_empty());
+        assert!(container_path(&dir.join(NOTES_DIR), "note-1").exists());
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+
+    #[test]
+    fn a_mirror_keeps_no_versions_manifest_or_device_id() {
+        let dir = temp_dir("mirror-files");
+        let owner = NoteStore::open(&dir).expect("store opens");
+        let doc = Doc::new();
+        owner
+            .apply_update("note-1", &edit(&doc, "draft"))
+            .expect("edit");
+        drop(owner);
+        std::fs::remove_file(dir.join(DEVICE_ID_FILE)).expect("no device id yet");
+        let manifest = std::fs::read(dir.join(MANIFEST_FILE)).expect("manifest");
+
+        let mirror = NoteStore::mirror(&dir).expect("mirror opens");
+        assert!(mirror.device_id().is_some());
+        mirror.rename("note-1", "Renamed").expect("rename");
+        mirror
+            .apply_update("note-1", &edit(&doc, " and more"))
+            .expect("edit");
+        mirror
+            .apply_update("note-2", &edit(&Doc::new(), "new"))
+            .expect("edit");
+
+        assert!(
+            !dir.join(HISTORY_DIR).exists(),
+            "a mirror keeps no versions"
+        );
+        assert!(!dir.join(DEVICE_ID_FILE).exists());
+        assert_eq!(std::fs::read(dir.join(MANIFEST_FILE)).unwrap(), manifest);
+
+        std::fs::remove_dir_all(dir).expect("cleanup");
+    }
+}
@@ -0,0 +1,299 @@
+//! Backend-driven sync for hosts without a frontend.
+//!
+//! In the app the webview decides what to send (join approval, selected note). A headless
+//! node has no such UI, so it greets every peer, merges every update it receives into its
+//! store and answers state vectors itself.
+
+use hypernote_protocol::{FrameType, HelloPayload, MetaPayload, WsFrame};
+use serde::Deserialize;
+use yrs::updates::encoder::Encode;
+use yrs::StateVector;
+
+use crate::contracts::NoteMeta;
+use crate::manifest::MANIFEST_NOTE_ID;
+use crate::organize;
+use crate::store::NoteStore;
+
+/// `error` frame code for an update the store would not take, e.g. one that would grow
+/// the note past its size limit.
+pub const UPDATE_REJECTED: &str = "UPDATE_REJECTED";
+
+/// Greeting sent as soon as a connection opens. Listing every stored note as open, trashed
+/// ones included, makes app peers push their copy of whichever of those they have selected;
+/// their state vectors let a relay mailbox answer with only what the store lacks.
+pub fn hello(store: &NoteStore, sender_id: &str) -> WsFrame {
+    let open_note_ids = store.all_note_ids();
+    let state_vectors = open_note_ids
+        .iter()
+        .filter_map(|note_id| {
+            let state = store.state(note_id)?;
+            let vector = yrs::encode_state_vector_from_update_v1(&state).ok()?;
+            Some((note_id.clone(), vector))
+        })
+        .collect();
+    let payload = HelloPayload {
+        open_note_ids,
+        state_vectors,
+    };
+    WsFrame::new(
+        FrameType::Hello,
+        "",
+        sender_id,
+        serde_json::to_value(payload).unwrap_or_default(),
+    )
+}
+
+/// The store's full workspace manifest, sent right after the greeting.
+pub fn manifest(store: &NoteStore, sender_id: &str) -> WsFrame {
+    WsFrame::binary(
+        FrameType::Update,
+        MANIFEST_NOTE_ID,
+        sender_id,
+        store.manifest_state(),
+    )
+}
+
+/// A `meta` frame with the tags, pin, folder and color of `meta`.
+pub fn meta_frame(meta: &NoteMeta, sender_id: &str) -> WsFrame {
+    let payload = serde_json::to_value(organize::to_payload(meta)).unwrap_or_default();
+    WsFrame::new(FrameType::Meta, meta.id.clone(), sender_id, payload)
+}
+
+/// Applies `frame` to `store` and returns the frames to send back to its sender.
+///
+/// For every note the peer has open and the store knows, the peer gets the changes missing
+/// from the state vector it sent for the note (the full state if it sent none) plus the
+/// store's state vector so it can reply with whatever the store lacks.
+pub fn respond(store: &NoteStore, sender_id: &str, frame: &WsFrame) -> Vec<WsFrame> {
+    match frame.frame_type {
+        FrameType::Update if frame.note_id == MANIFEST_NOTE_ID => {
+            if let Err(reason) = frame
+                .binary_payload()
+                .and_then(|update| store.apply_manifest_update(&update))
+            {
+                eprintln!("[hypernote] dropped manifest update: {reason}");
+                return Vec::new();
+            }
+            // Ask for every listed note we lack; an empty vector means "send everything".
+            let empty = StateVector::default().encode_v1();
+            store
+                .missing_note_ids()
+                .into_iter()
+                .map(|note_id| {
+                    WsFrame::binary(FrameType::StateVector, note_id, sender_id, empty.clone())
+                })
+                .collect()
+        }
+        FrameType::Update => match frame
+            .binary_payload()
+            .and_then(|update| store.apply_update(&frame.note_id, &update))
+        {
+            Ok(_) => Vec::new(),
+            Err(reason) => {
+                eprintln!("[hypernote] dropped update for {}: {reason}", frame.note_id);
+                let message = format!("{}: {reason}", frame.note_id);
+                vec![WsFrame::error(sender_id, UPDATE_REJECTED, &message)]
+            }
+        },
+        FrameType::Hello => {
+            let hello = frame.hello_payload();
+            hello
+                .open_note_ids
+                .into_iter()
+                .filter_map(|note_id| store.state(&note_id).map(|state| (note_id, state)))
+                .flat_map(|(note_id, state)| {
+                    let vector = yrs::encode_state_vector_from_update_v1(&state).ok();
+                    let update = match hello.state_vectors.get(&note_id) {
+                        Some(theirs) => store.missing(&note_id, theirs),
+                        None => Some(state),
+                    };
+                    let update = update.map(|update| {
+                        WsFrame::binary(FrameType::Update, note_id.clone(), sender_id, update)
+                    });
+                    let vector = vector.map(|vector| {
+                        WsFrame::binary(FrameType::StateVector, note_id, sender_id, vector)
+                    });
+                    update.into_iter().chain(vector)
+                })
+                .collect()
+        }
+        FrameType::StateVector => frame
+            .binary_payload()
+            .ok()
+            .and_then(|vector| store.missing(&frame.note_id, &vector))
+            .map(|update| {
+                WsFrame::binary(FrameType::Update, frame.note_id.clone(), sender_id, update)
+            })
+            .into_iter()
+            .collect(),
+        FrameType::Meta => {
+            if let Err(reason) = apply_meta(store, &frame.note_id, &frame.payload) {
+                eprintln!("[hypernote] dropped meta for {}: {reason}", frame.note_id);
+            }
+            Vec::new()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Records a newer peer copy of a note's organization as changes to its `meta` map.
+fn apply_meta(store: &NoteStore, note_id: &str, payload: &serde_json::Value) -> Result<(), String> {
+    let payload =
+        MetaPayload::deserialize(payload).map_err(|e| format!("invalid meta payload: {e}"))?;
+    let meta = store.meta(note_id).ok_or("note not found")?;
+    for change in organize::payload_changes(&meta, &payload) {
+        store.change_meta(note_id, change)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hypernote_protocol::{FrameType, WsFrame};
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, ReadTxn, Text, Transact};
+
+    use super::{hello, manifest, meta_frame, respond};
+    use crate::meta_map::MetaChange;
+    use crate::store::{markdown_of, NoteStore};
+
+    fn update(text: &str) -> Vec<u8> {
+        let doc = Doc::new();
+        let content = doc.get_or_insert_text("content");
+        let mut txn = doc.transact_mut();
+        content.insert(&mut txn, 0, text);
+        txn.encode_update_v1()
+    }
+
+    #[test]
+    fn stores_updates_and_greets_with_every_note() {
+        let store = NoteStore::in_memory();
+        let frame = WsFrame::binary(FrameType::Update, "note-1", "app", update("hi"));
+        assert!(respond(&store, "daemon", &frame).is_empty());
+
+        assert_eq!(store.get("note-1").expect("note stored").markdown, "hi");
+        assert_eq!(hello(&store, "daemon").payload["openNoteIds"][0], "note-1");
+    }
+
+    #[test]
+    fn carries_metadata_and_deletions_in_update_frames() {
+        let alice = NoteStore::in_memory();
+        let bob = NoteStore::in_memory();
+        let first = update("hi");
+        for store in [&alice, &bob] {
+            store.apply_update("note-1", &first).expect("edit");
+        }
+
+        let (_, tagged) = alice
+            .change_meta("note-1", MetaChange::Tags(vec!["#work".to_string()]))
+            .expect("tags");
+        let (_, trashed) = bob
+            .change_meta("note-1", MetaChange::Deleted(Some(7)))
+            .expect("trash");
+        for (to, change) in [(&bob, tagged), (&alice, trashed)] {
+            let frame = WsFrame::binary(FrameType::Update, "note-1", "peer", change);
+            assert!(respond(to, "daemon", &frame).is_empty());
+        }
+
+        for store in [&alice, &bob] {
+            let meta = store.meta("note-1").expect("note");
+            assert_eq!(meta.tags, vec!["work".to_string()]);
+            assert_eq!(meta.deleted_at, Some(7));
+            assert!(store.list().is_empty());
+        }
+        assert_eq!(hello(&alice, "daemon").payload["openNoteIds"][0], "note-1");
+    }
+
+    #[test]
+    fn applies_newer_meta_frames_as_meta_map_changes() {
+        let alice = NoteStore::in_memory();
+        let bob = NoteStore::in_memory();
+        let first = update("hi");
+        for store in [&alice, &bob] {
+            store.apply_update("note-1", &first).expect("edit");
+        }
+        alice
+            .change_meta("note-1", MetaChange::Pinned(true))
+            .expect("pin");
+        let (meta, _) = alice
+            .change_meta("note-1", MetaChange::Folder(Some("work".to_string())))
+            .expect("move");
+
+        // Stamped after bob's copy even when both fall in the same millisecond.
+        let mut frame = meta_frame(&meta, "alice");
+        let changed_at = bob.meta("note-1").unwrap().updated_at + 1;
+        frame.payload["changedAt"] = changed_at.into();
+        assert!(respond(&bob, "daemon", &frame).is_empty());
+        let meta = bob.meta("note-1").expect("note");
+        assert!(meta.pinned);
+        assert_eq!(meta.folder.as_deref(), Some("work"));
+
+        // A copy older than bob's latest change is ignored.
+        frame.payload["pinned"] = false.into();
+        frame.payload["changedAt"] = 1.into();
+        respond(&bob, "daemon", &frame);
+        assert!(bob.meta("note-1").unwrap().pinned);
+    }
+
+    #[test]
+    fn lists_manifest_notes_and_asks_for_missing_content() {
+        let alice = NoteStore::in_memory();
+        let bob = NoteStore::in_memory();
+        alice
+            .apply_update("note-1", &update("# Offline"))
+            .expect("edit");
+
+        let replies = respond(&bob, "daemon", &manifest(&alice, "alice"));
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].frame_type, FrameType::StateVector);
+        assert_eq!(replies[0].note_id, "note-1");
+        let listed = bob.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].title, "Offline");
+
+        let state = alice.get("note-1").expect("note").yjs_state;
+        let frame = WsFrame::binary(FrameType::Update, "note-1", "alice", state);
+        respond(&bob, "daemon", &frame);
+        assert!(bob.missing_note_ids().is_empty());
+        assert_eq!(bob.get("note-1").expect("note").markdown, "# Offline");
+    }
+
+    #[test]
+    fn answers_hello_for_open_notes_it_knows() {
+        let store = NoteStore::in_memory();
+        store.apply_update("note-1", &update("hi")).expect("edit");
+
+        let greeting = WsFrame::new(
+            FrameType::Hello,
+            "",
+            "app",
+            serde_json::json!({ "openNoteIds": ["note-1", "note-unknown"] }),
+        );
+        let replies = respond(&store, "daemon", &greeting);
+
+        let kinds: Vec<_> = replies.iter().map(|frame| frame.frame_type).collect();
+        assert_eq!(kinds, vec![FrameType::Update, FrameType::StateVector]);
+        assert!(replies.iter().all(|frame| frame.note_id == "note-1"));
+        let state = replies[0].binary_payload().expect("update bytes");
+        assert_eq!(markdown_of(&state), "hi");
+
+        // A peer that already has the note only gets the store's state vector.
+        let replies = respond(&store, "daemon", &hello(&store, "app"));
+        let kinds: Vec<_> = replies.iter().map(|frame| frame.frame_type).collect();
+        assert_eq!(kinds, vec![FrameType::StateVector]);
+    }
+
+    #[test]
+    fn answers_state_vectors_with_a_diff() {
+        let store = NoteStore::in_memory();
+        store.apply_update("note-1", &update("hi")).expect("edit");
+
+        let vector = Doc::new().transact().state_vector().encode_v1();
+        let frame = WsFrame::binary(FrameType::StateVector, "note-1", "app", vector);
+        let replies = respond(&store, "daemon", &frame);
+
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].frame_type, FrameType::Update);
+        assert_eq!(replies[0].sender_id, "daemon");
+    }
+}
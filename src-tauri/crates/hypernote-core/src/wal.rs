@@ -0,0 +1,310 @@
+//! Write-ahead log of note updates.
+//!
+//! The store appends every update it accepts to `wal/<note-id>.log` instead of rewriting
+//! the note's container each time. Appends reach the OS before the update is acknowledged,
+//! so a crash of the process loses nothing; a background thread fsyncs logs at most
+//! [`SYNC_DELAY`] after they were written, which bounds what a power loss can take. Once a
+//! log has grown past [`COMPACT_BYTES`] the store folds it into the container (written to a
+//! temporary file, fsynced and renamed into place) and removes it. On startup any log left
+//! behind is replayed on top of the container; updates already in it merge as no-ops.
+//!
+//! A record is `len u32 | at i64 | crc32 u32 | update`, big-endian, with the checksum over
+//! `at` and the update. Reading stops at the first short or damaged record: a torn tail.
+//! Opening the log cuts torn tails off, so later appends stay readable.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use hypernote_protocol::is_valid_workspace_id;
+
+pub const WAL_DIR: &str = "wal";
+pub const LOG_EXTENSION: &str = "log";
+/// Longest an appended update waits to be fsynced.
+pub const SYNC_DELAY: Duration = Duration::from_millis(200);
+/// Log size from which a note's log is folded into its container.
+pub const COMPACT_BYTES: u64 = 256 * 1024;
+
+const HEADER_LEN: usize = 16;
+
+/// A logged update and the ms timestamp it was made at.
+pub type Record = (i64, Vec<u8>);
+
+#[derive(Default)]
+struct Files {
+    open: HashMap<String, File>,
+    /// Notes appended to since their last fsync.
+    dirty: HashSet<String>,
+    stopping: bool,
+}
+
+type Shared = Arc<(Mutex<Files>, Condvar)>;
+
+pub struct Wal {
+    dir: PathBuf,
+    files: Shared,
+    syncer: Option<JoinHandle<()>>,
+}
+
+impl Wal {
+    /// Log kept under `dir`, normally `<data-dir>/wal`.
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        cut_torn_tails(&dir);
+        let files: Shared = Arc::default();
+        let syncer = {
+            let files = Arc::clone(&files);
+            std::thread::Builder::new()
+                .name("hypernote-wal".to_string())
+                .spawn(move || run_syncer(&files))?
+        };
+        Ok(Self {
+            dir,
+            files,
+            syncer: Some(syncer),
+        })
+    }
+
+    /// Appends `update`, made at `at`, to the note's log and returns the log's new size.
+    pub fn append(&self, note_id: &str, update: &[u8], at: i64) -> io::Result<u64> {
+        let mut files = lock(&self.files);
+        let file = match files.open.entry(note_id.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(log_path(&self.dir, note_id))?,
+            ),
+        };
+        file.write_all(&encode_record(update, at))?;
+        let len = file.metadata()?.len();
+        if files.dirty.insert(note_id.to_string()) {
+            self.files.1.notify_one();
+        }
+        Ok(len)
+    }
+
+    /// Drops a note's log once its container holds everything in it.
+    pub fn clear(&self, note_id: &str) -> io::Result<()> {
+        let mut files = lock(&self.files);
+        files.open.remove(note_id);
+        files.dirty.remove(note_id);
+        match fs::remove_file(log_path(&self.dir, note_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Notes whose log was written this session and not yet cleared.
+    pub fn note_ids(&self) -> Vec<String> {
+        lock(&self.files).open.keys().cloned().collect()
+    }
+
+    /// Fsyncs every log written since its last sync, without waiting for the syncer.
+    pub fn sync(&self) -> io::Result<()> {
+        sync_dirty(&self.files)
+    }
+}
+
+impl Drop for Wal {
+    fn drop(&mut self) {
+        lock(&self.files).stopping = true;
+        self.files.1.notify_one();
+        if let Some(syncer) = self.syncer.take() {
+            let _ = syncer.join();
+        }
+    }
+}
+
+/// Updates in every log under `dir`, per note, as `(at, update)` in the order written.
+///
+/// Only reads; [`Wal::open`] is what cuts torn tails off.
+pub fn read_logs(dir: &Path) -> Vec<(String, Vec<Record>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut logs: Vec<(String, Vec<Record>)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != LOG_EXTENSION {
+                return None;
+            }
+            let note_id = path.file_stem()?.to_str()?.to_string();
+            if !is_valid_workspace_id(&note_id) {
+                return None;
+            }
+            let (records, _) = decode_records(&fs::read(&path).ok()?);
+            Some((note_id, records))
+        })
+        .filter(|(_, records)| !records.is_empty())
+        .collect();
+    logs.sort_by(|a, b| a.0.cmp(&b.0));
+    logs
+}
+
+/// Truncates every log under `dir` to its last whole record.
+fn cut_torn_tails(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != LOG_EXTENSION)
+        {
+            continue;
+        }
+        let cut = fs::read(&path).and_then(|bytes| {
+            let (_, valid) = decode_records(&bytes);
+            if valid == bytes.len() {
+                return Ok(());
+            }
+            eprintln!(
+                "[hypernote] dropping {} torn bytes from {}",
+                bytes.len() - valid,
+                path.display()
+            );
+            let file = OpenOptions::new().write(true).open(&path)?;
+            file.set_len(valid as u64)?;
+            file.sync_all()
+        });
+        if let Err(e) = cut {
+            eprintln!("[hypernote] cannot repair {}: {e}", path.display());
+        }
+    }
+}
+
+fn log_path(dir: &Path, note_id: &str) -> PathBuf {
+    dir.join(format!("{note_id}.{LOG_EXTENSION}"))
+}
+
+fn encode_record(update: &[u8], at: i64) -> Vec<u8> {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&at.to_be_bytes());
+    hasher.update(update);
+
+    let mut record = Vec::with_capacity(HEADER_LEN + update.len());
+    record.extend_from_slice(&(update.len() as u32).to_be_bytes());
+    record.extend_from_slice(&at.to_be_bytes());
+    record.extend_from_slice(&hasher.finalize().to_be_bytes());
+    record.extend_from_slice(update);
+    record
+}
+
+/// The whole records at the start of `bytes` and the number of bytes they take up.
+fn decode_records(bytes: &[u8]) -> (Vec<Record>, usize) {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + HEADER_LEN) {
+        let len = u32::from_be_bytes(header[0..4].try_into().unwrap_or_default()) as usize;
+        let at_bytes: [u8; 8] = header[4..12].try_into().unwrap_or_default();
+        let crc = u32::from_be_bytes(header[12..16].try_into().unwrap_or_default());
+        let Some(update) = bytes.get(offset + HEADER_LEN..offset + HEADER_LEN + len) else {
+            break;
+        };
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&at_bytes);
+        hasher.update(update);
+        if hasher.finalize() != crc {
+            break;
+        }
+        records.push((i64::from_be_bytes(at_bytes), update.to_vec()));
+        offset += HEADER_LEN + len;
+    }
+    (records, offset)
+}
+
+fn lock(files: &Shared) -> MutexGuard<'_, Files> {
+    files
+        .0
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Fsyncs dirty logs a short while after the first append, so a burst of edits costs one sync.
+fn run_syncer(files: &Shared) {
+    loop {
+        let mut guard = lock(files);
+        while guard.dirty.is_empty() && !guard.stopping {
+            guard = files
+                .1
+                .wait(guard)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        let stopping = guard.stopping;
+        drop(guard);
+
+        if !stopping {
+            std::thread::sleep(SYNC_DELAY);
+        }
+        if let Err(e) = sync_dirty(files) {
+            eprintln!("[hypernote] failed to sync update log: {e}");
+        }
+        if stopping {
+            return;
+        }
+    }
+}
+
+fn sync_dirty(files: &Shared) -> io::Result<()> {
+    // Sync clones outside the lock so appends don't wait for the disk.
+    let pending: Vec<File> = {
+        let mut guard = lock(files);
+        let Files { open, dirty, .. } = &mut *guard;
+        dirty
+            .drain()
+            .filter_map(|note_id| open.get(&note_id)?.try_clone().ok())
+            .collect()
+    };
+    pending.iter().try_for_each(File::sync_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_records_up_to_a_torn_tail_and_cuts_it_off() {
+        let dir = std::env::temp_dir().join(format!("hypernote-wal-{}", uuid::Uuid::new_v4()));
+        let wal = Wal::open(dir.clone()).expect("wal opens");
+        wal.append("note-1", b"first", 1).expect("append");
+        let len = wal.append("note-1", b"second", 2).expect("append");
+        wal.append("note-2", b"other", 3).expect("append");
+        wal.sync().expect("sync");
+        drop(wal);
+
+        // A crash mid-append leaves part of a record behind.
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(log_path(&dir, "note-1"))
+            .unwrap();
+        log.write_all(&encode_record(b"torn", 4)[..10]).unwrap();
+        assert_eq!(len, 2 * HEADER_LEN as u64 + 11);
+
+        let logs = read_logs(&dir);
+        assert_eq!(logs.len(), 2);
+        assert_eq!(
+            logs[0],
+            (
+                "note-1".to_string(),
+                vec![(1, b"first".to_vec()), (2, b"second".to_vec())]
+            )
+        );
+
+        // Reopening cuts the tail off, so the next append can be read back.
+        let wal = Wal::open(dir.clone()).expect("wal reopens");
+        wal.append("note-1", b"third", 5).expect("append");
+        assert_eq!(read_logs(&dir)[0].1.len(), 3);
+        wal.clear("note-1").expect("clear");
+        assert_eq!(read_logs(&dir).len(), 1);
+
+        fs::remove_dir_all(dir).expect("cleanup");
+    }
+}
@@ -0,0 +1,351 @@
+//! Wire types for the HyperNote WebSocket sync protocol.
+//!
+//! Mirrors `src/lib/contracts/protocol.ts`; see `docs/contracts.md` for the envelope.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Most payload bytes one `update` or `state_vector` frame carries; larger payloads, such
+/// as a whole note sent as a catch-up, travel as several chunks (see [`Chunk`]). Bytes
+/// cost up to four characters each, so a chunk stays well under a 16 MiB message.
+pub const MAX_CHUNK_BYTES: usize = 2 * 1024 * 1024;
+
+/// Longest workspace id accepted by the relay and by `join_workspace` relay targets.
+pub const MAX_WORKSPACE_ID_LEN: usize = 128;
+
+/// Note id under which peers exchange the workspace manifest (the list of every note).
+/// Generated note ids never start with `_`.
+pub const MANIFEST_NOTE_ID: &str = "_workspace";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameType {
+    Hello,
+    NoteList,
+    StateVector,
+    Update,
+    Presence,
+    Error,
+    /// Note organization (tags, pin, folder, color); see [`MetaPayload`].
+    Meta,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsFrame {
+    #[serde(rename = "type")]
+    pub frame_type: FrameType,
+    pub note_id: String,
+    pub sender_id: String,
+    pub protocol_version: u32,
+    pub payload: serde_json::Value,
+}
+
+/// Payload of `hello` frames.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HelloPayload {
+    #[serde(default)]
+    pub open_note_ids: Vec<String>,
+    /// The sender's state vector of each note it already has, so replies carry only the
+    /// changes it lacks instead of the whole note.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub state_vectors: HashMap<String, Vec<u8>>,
+}
+
+/// Payload of `update` and `state_vector` frames: raw Yjs bytes as a JSON number array.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryPayload {
+    pub bytes: Vec<u8>,
+    /// Set when `bytes` is one piece of a larger payload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<Chunk>,
+}
+
+/// Which piece of a split payload a frame carries. Pieces go out in order and the
+/// receiver joins them into one frame once the last (`index == count - 1`) arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    pub index: u32,
+    pub count: u32,
+}
+
+/// Payload of `meta` frames. The copy with the newest `changedAt` wins.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetaPayload {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    pub changed_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorPayload {
+    pub code: String,
+    pub message: String,
+}
+
+impl WsFrame {
+    pub fn new(
+        frame_type: FrameType,
+        note_id: impl Into<String>,
+        sender_id: impl Into<String>,
+        payload: serde_json::Value,
+    ) -> Self {
+        Self {
+            frame_type,
+            note_id: note_id.into(),
+            sender_id: sender_id.into(),
+            protocol_version: PROTOCOL_VERSION,
+            payload,
+        }
+    }
+
+    pub fn error(sender_id: impl Into<String>, code: &str, message: &str) -> Self {
+        let payload = ErrorPayload {
+            code: code.to_string(),
+            message: message.to_string(),
+        };
+
+        Self::new(
+            FrameType::Error,
+            "",
+            sender_id,
+            serde_json::to_value(payload).unwrap_or_default(),
+        )
+    }
+
+    pub fn binary(
+        frame_type: FrameType,
+        note_id: impl Into<String>,
+        sender_id: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        let payload =
+            serde_json::to_value(BinaryPayload { bytes, chunk: None }).unwrap_or_default();
+        Self::new(frame_type, note_id, sender_id, payload)
+    }
+
+    /// Bytes carried by an `update` or `state_vector` frame.
+    pub fn binary_payload(&self) -> Result<Vec<u8>, String> {
+        BinaryPayload::deserialize(&self.payload)
+            .map(|payload| payload.bytes)
+            .map_err(|e| format!("invalid binary payload: {e}"))
+    }
+
+    /// What a `hello` frame carries; empty when the payload does not fit.
+    pub fn hello_payload(&self) -> HelloPayload {
+        HelloPayload::deserialize(&self.payload).unwrap_or_default()
+    }
+
+    /// The piece of a split payload this frame carries, if it is one.
+    pub fn chunk(&self) -> Option<Chunk> {
+        let chunk = self.payload.get("chunk")?;
+        Chunk::deserialize(chunk).ok()
+    }
+
+    /// This frame as frames of at most `max_bytes` payload bytes each. Frames without a
+    /// binary payload, or with a short enough one, come back as they are.
+    pub fn split(self, max_bytes: usize) -> Vec<WsFrame> {
+        let max_bytes = max_bytes.max(1);
+        let bytes = match self.binary_payload() {
+            Ok(bytes) if bytes.len() > max_bytes => bytes,
+            _ => return vec![self],
+        };
+        let count = bytes.len().div_ceil(max_bytes) as u32;
+        bytes
+            .chunks(max_bytes)
+            .enumerate()
+            .map(|(index, piece)| {
+                let payload = BinaryPayload {
+                    bytes: piece.to_vec(),
+                    chunk: Some(Chunk {
+                        index: index as u32,
+                        count,
+                    }),
+                };
+                let payload = serde_json::to_value(payload).unwrap_or_default();
+                Self::new(self.frame_type, &self.note_id, &self.sender_id, payload)
+            })
+            .collect()
+    }
+
+    /// Parses a text frame and rejects envelopes from other protocol versions.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let frame: WsFrame =
+            serde_json::from_str(text).map_err(|e| format!("invalid frame envelope: {e}"))?;
+
+        if frame.protocol_version != PROTOCOL_VERSION {
+            return Err(format!(
+                "unsupported protocol version: {}",
+                frame.protocol_version
+            ));
+        }
+
+        Ok(frame)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Joins the chunks of split frames arriving on one connection.
+#[derive(Debug, Default)]
+pub struct Reassembly {
+    /// (type, note id, sender id) → the pieces so far.
+    pending: HashMap<(FrameType, String, String), Pending>,
+    held_bytes: usize,
+}
+
+#[derive(Debug)]
+struct Pending {
+    bytes: Vec<u8>,
+    next: u32,
+    count: u32,
+}
+
+impl Reassembly {
+    /// Passes frames that are not chunks straight back. Chunks are held until the last one
+    /// arrives, which returns the whole frame. Fails, dropping what was held for that
+    /// payload, when a piece is out of order or the held pieces would exceed `max_bytes`.
+    pub fn push(&mut self, frame: WsFrame, max_bytes: usize) -> Result<Option<WsFrame>, String> {
+        let Some(chunk) = frame.chunk() else {
+            return Ok(Some(frame));
+        };
+        let piece = frame.binary_payload()?;
+        let key = (
+            frame.frame_type,
+            frame.note_id.clone(),
+            frame.sender_id.clone(),
+        );
+        let pending = self.pending.remove(&key);
+        if let Some(pending) = &pending {
+            self.held_bytes -= pending.bytes.len();
+        }
+        let mut pending = match pending {
+            Some(pending) if pending.next == chunk.index && pending.count == chunk.count => pending,
+            None if chunk.index == 0 && chunk.count > 0 => Pending {
+                bytes: Vec::new(),
+                next: 0,
+                count: chunk.count,
+            },
+            _ => {
+                return Err(format!(
+                    "chunk {} of {} out of order",
+                    chunk.index, chunk.count
+                ))
+            }
+        };
+        if self.held_bytes + pending.bytes.len() + piece.len() > max_bytes {
+            return Err(format!(
+                "split frame for {} is over the {max_bytes} byte limit",
+                frame.note_id
+            ));
+        }
+
+        pending.bytes.extend_from_slice(&piece);
+        pending.next += 1;
+        if pending.next < pending.count {
+            self.held_bytes += pending.bytes.len();
+            self.pending.insert(key, pending);
+            return Ok(None);
+        }
+        Ok(Some(WsFrame::binary(
+            frame.frame_type,
+            frame.note_id,
+            frame.sender_id,
+            pending.bytes,
+        )))
+    }
+}
+
+/// Workspace ids travel in relay URL paths, so keep them to a URL-safe alphabet.
+pub fn is_valid_workspace_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_WORKSPACE_ID_LEN
+        && value
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_workspace_id, FrameType, Reassembly, WsFrame};
+
+    #[test]
+    fn parses_frames_produced_by_the_frontend() {
+        let text = r#"{"type":"state_vector","noteId":"n1","senderId":"p1","protocolVersion":1,"payload":{"bytes":[0]}}"#;
+        let frame = WsFrame::parse(text).expect("frame should parse");
+        assert_eq!(frame.frame_type, FrameType::StateVector);
+        assert_eq!(frame.note_id, "n1");
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_types() {
+        let future =
+            r#"{"type":"hello","noteId":"","senderId":"p1","protocolVersion":2,"payload":{}}"#;
+        assert!(WsFrame::parse(future).is_err());
+
+        let unknown =
+            r#"{"type":"gossip","noteId":"","senderId":"p1","protocolVersion":1,"payload":{}}"#;
+        assert!(WsFrame::parse(unknown).is_err());
+    }
+
+    #[test]
+    fn round_trips_binary_payloads() {
+        let frame = WsFrame::binary(FrameType::Update, "n1", "p1", vec![0, 7, 255]);
+        let parsed = WsFrame::parse(&frame.to_json()).expect("frame should parse");
+        assert_eq!(parsed.binary_payload().expect("bytes"), vec![0, 7, 255]);
+    }
+
+    #[test]
+    fn splits_large_payloads_and_joins_them_again() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let frame = WsFrame::binary(FrameType::Update, "n1", "p1", bytes.clone());
+        let pieces = frame.split(300);
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(pieces[3].binary_payload().unwrap().len(), 100);
+
+        let mut reassembly = Reassembly::default();
+        let mut joined = None;
+        for piece in &pieces {
+            let text = piece.to_json();
+            joined = reassembly
+                .push(WsFrame::parse(&text).unwrap(), 1000)
+                .unwrap();
+        }
+        let joined = joined.expect("whole once the last piece is in");
+        assert_eq!(joined.chunk(), None);
+        assert_eq!(joined.binary_payload().unwrap(), bytes);
+
+        // Too much to hold, or a piece missing.
+        assert!(reassembly.push(pieces[0].clone(), 1000).unwrap().is_none());
+        assert!(reassembly.push(pieces[2].clone(), 1000).is_err());
+        assert!(reassembly.push(pieces[0].clone(), 1000).unwrap().is_none());
+        assert!(reassembly.push(pieces[1].clone(), 500).is_err());
+
+        let small = WsFrame::binary(FrameType::Update, "n1", "p1", vec![1, 2]);
+        assert_eq!(small.split(300).len(), 1);
+    }
+
+    #[test]
+    fn validates_workspace_ids() {
+        assert!(is_valid_workspace_id("team-notes_01"));
+        assert!(!is_valid_workspace_id(""));
+        assert!(!is_valid_workspace_id("a/b"));
+        assert!(!is_valid_workspace_id(&"x".repeat(129)));
+    }
+}
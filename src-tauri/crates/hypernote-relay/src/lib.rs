@@ -0,0 +1,763 @@
+//! Internet relay for HyperNote.
+//!
+//! Instances connect to `ws://relay-host:port/<workspace-id>?secret=<secret>` and every
+//! valid sync frame one member sends is forwarded to the other members of the same
+//! workspace. Only the [`Workspaces`] the relay was given are served, each to members that
+//! present its secret. The relay keeps nothing on disk and holds a frame only for as long
+//! as it takes to hand it on, unless a [`Mailbox`] is attached for members that are offline
+//! when an edit happens.
+//!
+//! Each connection is held to [`RelayLimits`]: message size, frame rate, and how many
+//! messages may wait for it before it counts as fallen behind and is dropped.
+
+mod mailbox;
+mod workspaces;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use hypernote_protocol::{is_valid_workspace_id, Reassembly, WsFrame, MAX_CHUNK_BYTES};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{channel, error::TrySendError, Sender};
+use tokio::sync::oneshot;
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::{Message, WebSocketConfig};
+use tokio_tungstenite::tungstenite::Error as WsError;
+
+pub use mailbox::{parse_key, Mailbox, MailboxLimits};
+pub use workspaces::{Workspaces, MIN_SECRET_LEN};
+
+pub const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:4848";
+
+/// Sender id stamped on frames the relay itself originates.
+pub(crate) const RELAY_SENDER_ID: &str = "relay";
+
+/// Error codes of frames the relay sends before dropping a connection.
+const TOO_LARGE: &str = "TOO_LARGE";
+const RATE_LIMITED: &str = "RATE_LIMITED";
+const FELL_BEHIND: &str = "FELL_BEHIND";
+
+/// Caps on one connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayLimits {
+    pub max_message_bytes: usize,
+    pub max_frame_bytes: usize,
+    /// Sustained frames per second accepted from one member.
+    pub max_frames_per_second: u32,
+    /// Frames a member may send in a burst, e.g. a full catch-up after joining.
+    pub frame_burst: u32,
+    /// Messages waiting to go out to one member before it counts as fallen behind.
+    pub max_queued_messages: usize,
+    /// Longest one message may take to go out before the member counts as fallen behind.
+    pub send_timeout: Duration,
+}
+
+impl Default for RelayLimits {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: 16 * 1024 * 1024,
+            max_frame_bytes: 16 * 1024 * 1024,
+            max_frames_per_second: 200,
+            frame_burst: 2000,
+            max_queued_messages: 256,
+            send_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RelayLimits {
+    pub fn ws_config(&self) -> WebSocketConfig {
+        WebSocketConfig::default()
+            .max_message_size(Some(self.max_message_bytes))
+            .max_frame_size(Some(self.max_frame_bytes))
+    }
+}
+
+/// A connected member: the queue feeding its socket, and how to tell it that it fell behind.
+struct Member {
+    tx: Sender<Message>,
+    behind: Arc<Notify>,
+}
+
+/// connection id → member.
+type Members = HashMap<u64, Member>;
+
+pub struct Relay {
+    /// workspace id → connected members.
+    workspaces: Mutex<HashMap<String, Members>>,
+    next_connection_id: AtomicU64,
+    served: Workspaces,
+    mailbox: Option<Mailbox>,
+    limits: RelayLimits,
+}
+
+impl Relay {
+    /// A relay serving `workspaces` to members that present their secrets.
+    pub fn new(workspaces: Workspaces) -> Self {
+        Self {
+            workspaces: Mutex::new(HashMap::new()),
+            next_connection_id: AtomicU64::new(0),
+            served: workspaces,
+            mailbox: None,
+            limits: RelayLimits::default(),
+        }
+    }
+
+    /// The same relay, also keeping note state for members who are offline.
+    pub fn with_mailbox(mut self, mailbox: Mailbox) -> Self {
+        self.mailbox = Some(mailbox);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: RelayLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Number of members currently registered under `workspace_id`.
+    pub fn member_count(&self, workspace_id: &str) -> usize {
+        self.workspaces
+            .lock()
+            .map(|workspaces| workspaces.get(workspace_id).map_or(0, HashMap::len))
+            .unwrap_or(0)
+    }
+
+    fn register(&self, workspace_id: &str, member: Member) -> u64 {
+        let connection_id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut workspaces) = self.workspaces.lock() {
+            workspaces
+                .entry(workspace_id.to_string())
+                .or_default()
+                .insert(connection_id, member);
+        }
+        connection_id
+    }
+
+    fn unregister(&self, workspace_id: &str, connection_id: u64) {
+        if let Ok(mut workspaces) = self.workspaces.lock() {
+            if let Some(members) = workspaces.get_mut(workspace_id) {
+                members.remove(&connection_id);
+                if members.is_empty() {
+                    workspaces.remove(workspace_id);
+                }
+            }
+        }
+    }
+
+    /// Queues `text` for every other member of the workspace. A member whose queue is full
+    /// is dropped instead of holding up the rest or piling up messages.
+    fn forward(&self, workspace_id: &str, from: u64, text: &str) {
+        let Ok(mut workspaces) = self.workspaces.lock() else {
+            return;
+        };
+
+        let Some(members) = workspaces.get_mut(workspace_id) else {
+            return;
+        };
+
+        members.retain(|connection_id, member| {
+            if *connection_id == from {
+                return true;
+            }
+            match member.tx.try_send(Message::Text(text.into())) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    member.behind.notify_one();
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+}
+
+/// Accepts connections until the listener fails.
+pub async fn serve(relay: Arc<Relay>, listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                let relay = Arc::clone(&relay);
+                tokio::spawn(async move {
+                    handle_connection(relay, stream, addr.to_string()).await;
+                });
+            }
+            Err(e) => eprintln!("[hypernote-relay] accept error: {e}"),
+        }
+    }
+}
+
+async fn handle_connection(relay: Arc<Relay>, stream: TcpStream, addr: String) {
+    let mut workspace_id = String::new();
+    // The error type is fixed by tungstenite's handshake callback signature.
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| {
+        let value = workspace_id_from_path(request.uri().path())
+            .map_err(|reason| reject(StatusCode::BAD_REQUEST, reason))?;
+        let secret = secret_from_query(request.uri().query());
+        relay
+            .served
+            .admit(&value, secret.as_deref())
+            .map_err(|reason| reject(StatusCode::UNAUTHORIZED, reason))?;
+        workspace_id = value;
+        Ok(response)
+    };
+
+    let config = Some(relay.limits.ws_config());
+    let ws = match tokio_tungstenite::accept_hdr_async_with_config(stream, callback, config).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            eprintln!("[hypernote-relay] handshake with {addr} failed: {e}");
+            return;
+        }
+    };
+
+    let (mut sink, mut stream) = ws.split();
+    let (tx, mut rx) = channel::<Message>(relay.limits.max_queued_messages);
+    let behind = Arc::new(Notify::new());
+    let member = Member {
+        tx: tx.clone(),
+        behind: Arc::clone(&behind),
+    };
+    let connection_id = relay.register(&workspace_id, member);
+
+    // A member that stops reading is dropped once a message has waited too long for it.
+    // `last` carries the error frame sent when the relay drops the member; whatever is still
+    // queued then is discarded, and the member catches up on rejoining.
+    let send_timeout = relay.limits.send_timeout;
+    let sink_behind = Arc::clone(&behind);
+    let (last_tx, mut last_rx) = oneshot::channel::<Message>();
+    let sink_task = tokio::spawn(async move {
+        loop {
+            let message = tokio::select! {
+                biased;
+                last = &mut last_rx => {
+                    if let Ok(last) = last {
+                        let _ = tokio::time::timeout(send_timeout, async {
+                            sink.send(last).await?;
+                            sink.close().await
+                        })
+                        .await;
+                    }
+                    break;
+                }
+                message = rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+            match tokio::time::timeout(send_timeout, sink.send(message)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) => break,
+                Err(_) => {
+                    sink_behind.notify_one();
+                    break;
+                }
+            }
+        }
+    });
+
+    // Chunks are forwarded as they come; the mailbox needs them joined.
+    let mut reassembly = Reassembly::default();
+    let mut rate = FrameRate::new(&relay.limits);
+    let mut rejected = None;
+    'read: loop {
+        let msg_result = tokio::select! {
+            msg = stream.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            () = behind.notified() => {
+                rejected = Some((FELL_BEHIND, "not reading what it is sent".to_string()));
+                break;
+            }
+        };
+        if !rate.allow(&relay.limits, Instant::now()) {
+            let reason = format!(
+                "more than {} frames per second",
+                relay.limits.max_frames_per_second
+            );
+            rejected = Some((RATE_LIMITED, reason));
+            break;
+        }
+        match msg_result {
+            Ok(Message::Text(text)) => match WsFrame::parse(&text) {
+                Ok(frame) => {
+                    relay.forward(&workspace_id, connection_id, &text);
+                    let Some(mailbox) = &relay.mailbox else {
+                        continue;
+                    };
+                    let max_bytes = mailbox.limits().max_workspace_bytes;
+                    let frame = match reassembly.push(frame, max_bytes) {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => continue,
+                        Err(reason) => {
+                            eprintln!("[hypernote-relay] mailbox kept nothing: {reason}");
+                            continue;
+                        }
+                    };
+                    // Replies wait for room in the member's own queue.
+                    for reply in mailbox.respond(&workspace_id, &frame) {
+                        for piece in reply.split(MAX_CHUNK_BYTES) {
+                            let message = Message::Text(piece.to_json().into());
+                            if tx.send(message).await.is_err() {
+                                break 'read;
+                            }
+                        }
+                    }
+                }
+                Err(reason) => {
+                    let error = WsFrame::error(RELAY_SENDER_ID, "INVALID_FRAME", &reason);
+                    if tx
+                        .send(Message::Text(error.to_json().into()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            },
+            // Over the message or frame limit: the rest of the stream can't be trusted.
+            Err(WsError::Capacity(e)) => {
+                rejected = Some((TOO_LARGE, e.to_string()));
+                break;
+            }
+            Ok(Message::Close(_)) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    relay.unregister(&workspace_id, connection_id);
+    drop(tx);
+    match rejected {
+        Some((code, reason)) => {
+            eprintln!("[hypernote-relay] dropping {addr}: {reason}");
+            let error = WsFrame::error(RELAY_SENDER_ID, code, &reason);
+            let _ = last_tx.send(Message::Text(error.to_json().into()));
+        }
+        None => drop(last_tx),
+    }
+    let _ = sink_task.await;
+}
+
+/// Token bucket holding one member to [`RelayLimits::max_frames_per_second`].
+struct FrameRate {
+    tokens: f64,
+    last: Instant,
+}
+
+impl FrameRate {
+    fn new(limits: &RelayLimits) -> Self {
+        Self {
+            tokens: f64::from(limits.frame_burst),
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token for one frame received at `now`; `false` once the bucket is empty.
+    fn allow(&mut self, limits: &RelayLimits, now: Instant) -> bool {
+        let refill = now.saturating_duration_since(self.last).as_secs_f64()
+            * f64::from(limits.max_frames_per_second);
+        self.tokens = (self.tokens + refill).min(f64::from(limits.frame_burst));
+        self.last = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Extracts the workspace id from a request path such as `/team-notes`.
+pub fn workspace_id_from_path(path: &str) -> Result<String, String> {
+    let id = path.trim_matches('/');
+    if !is_valid_workspace_id(id) {
+        return Err("path must be /<workspace-id> using letters, digits, '-' or '_'".to_string());
+    }
+
+    Ok(id.to_string())
+}
+
+/// The `secret` parameter of a query string such as `secret=abc&x=1`.
+pub fn secret_from_query(query: Option<&str>) -> Option<String> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("secret="))
+        .map(str::to_string)
+}
+
+fn reject(status: StatusCode, reason: String) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(reason));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::{SinkExt, StreamExt};
+    use hypernote_core::{Limits, Node, NodeConfig, NoteStore};
+    use hypernote_protocol::{FrameType, WsFrame};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    use super::{
+        secret_from_query, serve, workspace_id_from_path, Mailbox, Relay, RelayLimits, Workspaces,
+    };
+
+    const SECRET: &str = "correct-horse-battery";
+
+    fn served() -> Workspaces {
+        Workspaces::parse(&format!("team-a {SECRET}\nteam-b {SECRET}")).unwrap()
+    }
+
+    async fn start_relay() -> (std::sync::Arc<Relay>, String) {
+        start(Relay::new(served())).await
+    }
+
+    async fn start(relay: Relay) -> (std::sync::Arc<Relay>, String) {
+        let relay = std::sync::Arc::new(relay);
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("local addr");
+        tokio::spawn(serve(std::sync::Arc::clone(&relay), listener));
+        (relay, format!("ws://{addr}"))
+    }
+
+    fn member_url(base: &str, workspace_id: &str) -> String {
+        format!("{base}/{workspace_id}?secret={SECRET}")
+    }
+
+    /// The error code of the next frame, skipping anything else the relay sends first.
+    async fn error_code<S>(client: &mut S) -> String
+    where
+        S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        while let Some(Ok(message)) = client.next().await {
+            if let Message::Text(text) = message {
+                let frame = WsFrame::parse(&text).expect("frame parses");
+                if frame.frame_type == FrameType::Error {
+                    return frame.payload["code"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string();
+                }
+            }
+        }
+        panic!("connection closed without an error frame");
+    }
+
+    async fn wait_for_members(relay: &Relay, workspace_id: &str, expected: usize) {
+        for _ in 0..100 {
+            if relay.member_count(workspace_id) == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("workspace {workspace_id} never reached {expected} members");
+    }
+
+    #[test]
+    fn reads_the_secret_from_the_query() {
+        assert_eq!(
+            secret_from_query(Some("x=1&secret=abc")).as_deref(),
+            Some("abc")
+        );
+        assert!(secret_from_query(Some("secretx=abc")).is_none());
+        assert!(secret_from_query(None).is_none());
+    }
+
+    #[test]
+    fn reads_workspace_id_from_path() {
+        assert_eq!(workspace_id_from_path("/team-a/").unwrap(), "team-a");
+        assert!(workspace_id_from_path("/").is_err());
+        assert!(workspace_id_from_path("/a/b").is_err());
+    }
+
+    struct NoEvents;
+
+    impl hypernote_core::EventSink for NoEvents {
+        fn emit(&self, _event: &str, _payload: serde_json::Value) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn node(peer_id: &str) -> std::sync::Arc<Node> {
+        node_with(peer_id, Limits::default())
+    }
+
+    fn node_with(peer_id: &str, limits: Limits) -> std::sync::Arc<Node> {
+        Node::new(
+            NodeConfig {
+                peer_id: peer_id.to_string(),
+                store: NoteStore::in_memory(),
+                serve_sync: true,
+                limits,
+            },
+            std::sync::Arc::new(NoEvents),
+            tokio::runtime::Handle::current(),
+        )
+    }
+
+    #[tokio::test]
+    async fn nodes_sync_notes_through_the_relay_within_their_workspace() {
+        let (relay, base) = start_relay().await;
+        let alice = node("alice");
+        let note = alice.create_note().expect("alice creates a note");
+        alice
+            .store()
+            .append_text(&note.id, "written before anyone joined")
+            .expect("alice writes");
+        let (bob, carol) = (node("bob"), node("carol"));
+
+        assert!(alice.join(&member_url(&base, "team-a")).accepted);
+        assert!(bob.join(&member_url(&base, "team-a")).accepted);
+        assert!(carol.join(&member_url(&base, "team-b")).accepted);
+        wait_for_members(&relay, "team-a", 2).await;
+        wait_for_members(&relay, "team-b", 1).await;
+
+        // Bob learns of the note from alice's manifest and asks for it by state vector.
+        let mut synced = None;
+        for _ in 0..200 {
+            synced = bob.open_note(&note.id).ok();
+            if synced.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let synced = synced.expect("bob received the note");
+        assert_eq!(synced.markdown, "written before anyone joined");
+        assert!(
+            carol.open_note(&note.id).is_err(),
+            "note leaked into team-b"
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_notes_larger_than_a_message_in_chunks() {
+        let (relay, base) = start_relay().await;
+        let limits = Limits {
+            max_ws_message_bytes: 128 * 1024,
+            ..Limits::default()
+        };
+        let alice = node_with("alice", limits);
+        let note = alice.create_note().expect("alice creates a note");
+        let text = "line of a long note\n".repeat(10_000);
+        alice
+            .store()
+            .append_text(&note.id, &text)
+            .expect("alice writes");
+        assert!(alice.store().state(&note.id).unwrap().len() > 4 * limits.chunk_bytes());
+
+        let bob = node_with("bob", limits);
+        assert!(alice.join(&member_url(&base, "team-a")).accepted);
+        assert!(bob.join(&member_url(&base, "team-a")).accepted);
+        wait_for_members(&relay, "team-a", 2).await;
+
+        let mut synced = None;
+        for _ in 0..300 {
+            synced = bob.open_note(&note.id).ok();
+            if synced.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(synced.expect("bob received the note").markdown, text);
+    }
+
+    #[tokio::test]
+    async fn answers_malformed_frames_with_an_error_frame() {
+        let (_relay, base) = start_relay().await;
+        let (mut client, _) = tokio_tungstenite::connect_async(member_url(&base, "team-a"))
+            .await
+            .expect("client connects");
+
+        client
+            .send(Message::Text("not json".into()))
+            .await
+            .expect("client sends");
+
+        let Message::Text(reply) = client.next().await.expect("reply").expect("frame") else {
+            panic!("expected a text frame");
+        };
+        let frame = WsFrame::parse(&reply).expect("error frame parses");
+        assert_eq!(frame.frame_type, FrameType::Error);
+        assert_eq!(frame.payload["code"], "INVALID_FRAME");
+    }
+
+    #[tokio::test]
+    async fn mailbox_delivers_edits_made_while_a_member_was_offline() {
+        let workspaces = Workspaces::parse(&format!("team-a {SECRET}")).unwrap();
+        let (relay, base) = start(Relay::new(workspaces).with_mailbox(Mailbox::in_memory())).await;
+        let member = member_url(&base, "team-a");
+        let (mut alice, _) = tokio_tungstenite::connect_async(&member)
+            .await
+            .expect("alice connects");
+        wait_for_members(&relay, "team-a", 1).await;
+
+        // A real Yjs update: the mailbox refuses bytes it cannot merge.
+        let update = {
+            use yrs::{Text, Transact};
+            let doc = yrs::Doc::new();
+            let content = doc.get_or_insert_text("content");
+            let mut txn = doc.transact_mut();
+            content.insert(&mut txn, 0, "written while bob was away");
+            txn.encode_update_v1()
+        };
+        let frame = WsFrame::binary(FrameType::Update, "note-1", "alice", update);
+        alice
+            .send(Message::Text(frame.to_json().into()))
+            .await
+            .expect("alice sends");
+        alice.close(None).await.expect("alice leaves");
+        wait_for_members(&relay, "team-a", 0).await;
+
+        assert!(tokio_tungstenite::connect_async(format!("{base}/team-a"))
+            .await
+            .is_err());
+        assert!(tokio_tungstenite::connect_async(format!(
+            "{base}/team-a?secret=guessed-wrongly-0"
+        ))
+        .await
+        .is_err());
+        assert!(tokio_tungstenite::connect_async(format!(
+            "{base}/team-b?secret=correct-horse-battery"
+        ))
+        .await
+        .is_err());
+        let (mut bob, _) = tokio_tungstenite::connect_async(&member)
+            .await
+            .expect("bob connects");
+        let hello = WsFrame::new(
+            FrameType::Hello,
+            "",
+            "bob",
+            serde_json::json!({ "openNoteIds": [] }),
+        );
+        bob.send(Message::Text(hello.to_json().into()))
+            .await
+            .expect("bob says hello");
+
+        let Message::Text(reply) = bob.next().await.expect("reply").expect("frame") else {
+            panic!("expected a text frame");
+        };
+        let delivered = WsFrame::parse(&reply).expect("update frame parses");
+        assert_eq!(delivered.frame_type, FrameType::Update);
+        assert_eq!(delivered.note_id, "note-1");
+        assert_eq!(delivered.sender_id, "relay");
+    }
+
+    #[tokio::test]
+    async fn drops_members_when_they_disconnect() {
+        let (relay, base) = start_relay().await;
+        let (mut client, _) = tokio_tungstenite::connect_async(member_url(&base, "team-a"))
+            .await
+            .expect("client connects");
+        wait_for_members(&relay, "team-a", 1).await;
+
+        client.close(None).await.expect("client closes");
+        wait_for_members(&relay, "team-a", 0).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_handshakes_without_a_workspace_id() {
+        let (_relay, base) = start_relay().await;
+        assert!(tokio_tungstenite::connect_async(format!("{base}/"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_members_without_the_secret_even_without_a_mailbox() {
+        let (_relay, base) = start_relay().await;
+        assert!(tokio_tungstenite::connect_async(format!("{base}/team-a"))
+            .await
+            .is_err());
+        assert!(
+            tokio_tungstenite::connect_async(format!("{base}/unlisted?secret={SECRET}"))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_connections_that_send_oversized_messages() {
+        let limits = RelayLimits {
+            max_message_bytes: 1024,
+            max_frame_bytes: 1024,
+            ..RelayLimits::default()
+        };
+        let (relay, base) = start(Relay::new(served()).with_limits(limits)).await;
+        let (mut client, _) = tokio_tungstenite::connect_async(member_url(&base, "team-a"))
+            .await
+            .expect("client connects");
+        wait_for_members(&relay, "team-a", 1).await;
+
+        let _ = client.send(Message::Text("x".repeat(4096).into())).await;
+        assert_eq!(error_code(&mut client).await, "TOO_LARGE");
+        wait_for_members(&relay, "team-a", 0).await;
+    }
+
+    #[tokio::test]
+    async fn drops_connections_that_send_too_fast() {
+        let limits = RelayLimits {
+            max_frames_per_second: 1,
+            frame_burst: 5,
+            ..RelayLimits::default()
+        };
+        let (relay, base) = start(Relay::new(served()).with_limits(limits)).await;
+        let (mut client, _) = tokio_tungstenite::connect_async(member_url(&base, "team-a"))
+            .await
+            .expect("client connects");
+        wait_for_members(&relay, "team-a", 1).await;
+
+        let hello = WsFrame::new(FrameType::Hello, "", "spammer", serde_json::json!({}));
+        for _ in 0..20 {
+            if client
+                .send(Message::Text(hello.to_json().into()))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+        assert_eq!(error_code(&mut client).await, "RATE_LIMITED");
+        wait_for_members(&relay, "team-a", 0).await;
+    }
+
+    #[tokio::test]
+    async fn drops_members_that_fall_behind_without_holding_up_the_rest() {
+        let limits = RelayLimits {
+            max_queued_messages: 4,
+            send_timeout: Duration::from_millis(200),
+            ..RelayLimits::default()
+        };
+        let (relay, base) = start(Relay::new(served()).with_limits(limits)).await;
+        let (mut sender, _) = tokio_tungstenite::connect_async(member_url(&base, "team-a"))
+            .await
+            .expect("sender connects");
+        // Connects and never reads.
+        let (_stalled, _) = tokio_tungstenite::connect_async(member_url(&base, "team-a"))
+            .await
+            .expect("stalled member connects");
+        wait_for_members(&relay, "team-a", 2).await;
+
+        let update = WsFrame::binary(FrameType::Update, "note-1", "sender", vec![0; 64 * 1024]);
+        let text = update.to_json();
+        for _ in 0..200 {
+            if relay.member_count("team-a") < 2 {
+                break;
+            }
+            sender
+                .send(Message::Text(text.clone().into()))
+                .await
+                .expect("sender is not held up");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        wait_for_members(&relay, "team-a", 1).await;
+    }
+}
@@ -0,0 +1,535 @@
+//! Store-and-forward mailbox.
+//!
+//! Keeps the merged Yjs state of every note that passes through a workspace so a member
+//! that was offline when an edit happened still receives it on reconnect. With a vault
+//! directory the mailbox survives restarts; each note is sealed with ChaCha20-Poly1305
+//! before it touches the disk.
+//!
+//! This is not end-to-end encryption. Members send plain Yjs updates and the relay reads
+//! them to merge them, so whoever runs the relay can read every note it keeps; the vault
+//! key, which the relay also holds, only protects copies of the files taken without it.
+//! Only members of workspaces provisioned in [`crate::Workspaces`] reach the mailbox.
+//! Stored state is capped per workspace and in total.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hypernote_protocol::{is_valid_workspace_id, FrameType, WsFrame};
+
+use crate::RELAY_SENDER_ID;
+
+/// lib0 v1 encoding of an update with no structs and no deletions.
+const EMPTY_UPDATE: &[u8] = &[0, 0];
+const NONCE_LEN: usize = 12;
+const VAULT_EXTENSION: &str = "ymb";
+
+/// Caps on the merged state the mailbox keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MailboxLimits {
+    pub max_workspace_bytes: usize,
+    pub max_total_bytes: usize,
+}
+
+impl Default for MailboxLimits {
+    fn default() -> Self {
+        Self {
+            max_workspace_bytes: 256 * 1024 * 1024,
+            max_total_bytes: 2 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+pub struct Mailbox {
+    stored: Mutex<Stored>,
+    limits: MailboxLimits,
+    vault: Option<Vault>,
+}
+
+#[derive(Default)]
+struct Stored {
+    /// workspace id → note id → merged lib0 v1 update.
+    notes: HashMap<String, HashMap<String, Vec<u8>>>,
+    total_bytes: usize,
+}
+
+/// Encrypted on-disk copy of the mailbox: `<dir>/<workspace-id>/<note-id>.ymb`.
+struct Vault {
+    dir: PathBuf,
+    cipher: ChaCha20Poly1305,
+    /// Serializes file writes so the newest state of a note is the one left on disk.
+    writes: Mutex<()>,
+}
+
+impl Mailbox {
+    pub fn in_memory() -> Self {
+        Self {
+            stored: Mutex::new(Stored::default()),
+            limits: MailboxLimits::default(),
+            vault: None,
+        }
+    }
+
+    /// Opens (or creates) a vault directory and loads every note it can decrypt.
+    pub fn persistent(dir: impl Into<PathBuf>, key: [u8; 32]) -> io::Result<Self> {
+        let vault = Vault {
+            dir: dir.into(),
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            writes: Mutex::new(()),
+        };
+        fs::create_dir_all(&vault.dir)?;
+        let stored = vault.load_all()?;
+
+        Ok(Self {
+            stored: Mutex::new(stored),
+            limits: MailboxLimits::default(),
+            vault: Some(vault),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: MailboxLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> MailboxLimits {
+        self.limits
+    }
+
+    /// Frames to send back to the member that sent `frame`.
+    ///
+    /// A `hello` gets, for every stored note, the changes missing from the state vector
+    /// the member sent for it (the whole note if it sent none) plus the mailbox's state
+    /// vector, so it can push whatever the mailbox lacks. A `state_vector` gets only the
+    /// changes that member is missing. Chunks of split frames
+    /// must be joined (see [`hypernote_protocol::Reassembly`]) before they get here.
+    pub fn respond(&self, workspace_id: &str, frame: &WsFrame) -> Vec<WsFrame> {
+        match frame.frame_type {
+            FrameType::Update => {
+                if let Err(reason) = frame
+                    .binary_payload()
+                    .and_then(|update| self.deposit(workspace_id, &frame.note_id, &update))
+                {
+                    eprintln!(
+                        "[hypernote-relay] mailbox kept nothing for {}: {reason}",
+                        frame.note_id
+                    );
+                }
+                Vec::new()
+            }
+            FrameType::Hello => {
+                let theirs = frame.hello_payload().state_vectors;
+                self.states(workspace_id)
+                    .into_iter()
+                    .flat_map(|(note_id, state)| {
+                        let vector = yrs::encode_state_vector_from_update_v1(&state).ok();
+                        let update = match theirs.get(&note_id) {
+                            Some(theirs) => self.missing(workspace_id, &note_id, theirs),
+                            None => Some(state),
+                        };
+                        let update = update.map(|update| {
+                            WsFrame::binary(
+                                FrameType::Update,
+                                note_id.clone(),
+                                RELAY_SENDER_ID,
+                                update,
+                            )
+                        });
+                        let vector = vector.map(|vector| {
+                            WsFrame::binary(
+                                FrameType::StateVector,
+                                note_id,
+                                RELAY_SENDER_ID,
+                                vector,
+                            )
+                        });
+                        update.into_iter().chain(vector)
+                    })
+                    .collect()
+            }
+            FrameType::StateVector => frame
+                .binary_payload()
+                .ok()
+                .and_then(|vector| self.missing(workspace_id, &frame.note_id, &vector))
+                .map(|update| {
+                    WsFrame::binary(
+                        FrameType::Update,
+                        frame.note_id.clone(),
+                        RELAY_SENDER_ID,
+                        update,
+                    )
+                })
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Merges `update` into the stored state of a note.
+    pub fn deposit(&self, workspace_id: &str, note_id: &str, update: &[u8]) -> Result<(), String> {
+        // Ids become vault file names, so hold them to the same alphabet as workspace ids.
+        if !is_valid_workspace_id(note_id) {
+            return Err("note id is not storable".to_string());
+        }
+
+        {
+            let mut stored = self
+                .stored
+                .lock()
+                .map_err(|_| "mailbox poisoned".to_string())?;
+            let Stored {
+                notes, total_bytes, ..
+            } = &mut *stored;
+            let workspace = notes.entry(workspace_id.to_string()).or_default();
+            let existing = workspace.get(note_id);
+            let merged = match existing {
+                Some(existing) => yrs::merge_updates_v1([existing.as_slice(), update]),
+                None => yrs::merge_updates_v1([update]),
+            }
+            .map_err(|e| format!("undecodable update: {e}"))?;
+
+            let existing_len = existing.map_or(0, Vec::len);
+            let growth = merged.len().saturating_sub(existing_len);
+            let workspace_bytes: usize = workspace.values().map(Vec::len).sum();
+            if workspace_bytes + growth > self.limits.max_workspace_bytes {
+                return Err("workspace mailbox is full".to_string());
+            }
+            if *total_bytes + growth > self.limits.max_total_bytes {
+                return Err("mailbox is full".to_string());
+            }
+            *total_bytes = *total_bytes + merged.len() - existing_len;
+            workspace.insert(note_id.to_string(), merged);
+        }
+
+        let Some(vault) = &self.vault else {
+            return Ok(());
+        };
+        // Write whatever is newest once it is our turn, so concurrent deposits to the same
+        // note cannot leave an older state on disk.
+        let _turn = vault
+            .writes
+            .lock()
+            .map_err(|_| "vault poisoned".to_string())?;
+        let Some(state) = self.state(workspace_id, note_id) else {
+            return Ok(());
+        };
+        vault
+            .write(workspace_id, note_id, &state)
+            .map_err(|e| format!("vault write failed: {e}"))
+    }
+
+    fn state(&self, workspace_id: &str, note_id: &str) -> Option<Vec<u8>> {
+        let stored = self.stored.lock().ok()?;
+        stored.notes.get(workspace_id)?.get(note_id).cloned()
+    }
+
+    /// Changes to a note that a member with `state_vector` has not seen, if any.
+    pub fn missing(
+        &self,
+        workspace_id: &str,
+        note_id: &str,
+        state_vector: &[u8],
+    ) -> Option<Vec<u8>> {
+        let state = self.state(workspace_id, note_id)?;
+        let diff = yrs::diff_updates_v1(&state, state_vector).ok()?;
+        (diff != EMPTY_UPDATE).then_some(diff)
+    }
+
+    /// Every stored note of a workspace as `(note id, merged update)`.
+    pub fn states(&self, workspace_id: &str) -> Vec<(String, Vec<u8>)> {
+        self.stored
+            .lock()
+            .ok()
+            .and_then(|stored| stored.notes.get(workspace_id).cloned())
+            .map(|workspace| workspace.into_iter().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Vault {
+    fn write(&self, workspace_id: &str, note_id: &str, state: &[u8]) -> io::Result<()> {
+        let file_name = format!("{note_id}.{VAULT_EXTENSION}");
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(&nonce, state)
+            .map_err(|_| io::Error::other("encryption failed"))?;
+
+        let dir = self.dir.join(workspace_id);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(&file_name);
+        let tmp = dir.join(format!("{file_name}.tmp"));
+
+        let mut bytes = nonce.to_vec();
+        bytes.extend_from_slice(&sealed);
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, path)
+    }
+
+    fn load_all(&self) -> io::Result<Stored> {
+        let mut stored = Stored::default();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let workspace_dir = entry?.path();
+            let Some(workspace_id) = file_stem(&workspace_dir) else {
+                continue;
+            };
+            if !workspace_dir.is_dir() || !is_valid_workspace_id(&workspace_id) {
+                continue;
+            }
+
+            for entry in fs::read_dir(&workspace_dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some(VAULT_EXTENSION) {
+                    continue;
+                }
+                let Some(note_id) = file_stem(&path) else {
+                    continue;
+                };
+
+                match self.read(&path) {
+                    Ok(state) => {
+                        stored.total_bytes += state.len();
+                        stored
+                            .notes
+                            .entry(workspace_id.clone())
+                            .or_default()
+                            .insert(note_id, state);
+                    }
+                    Err(e) => eprintln!("[hypernote-relay] skipping {}: {e}", path.display()),
+                }
+            }
+        }
+
+        Ok(stored)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(io::Error::other("vault file is truncated"));
+        }
+
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| io::Error::other("decryption failed (wrong key or tampered file)"))
+    }
+}
+
+fn file_stem(path: &Path) -> Option<String> {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(str::to_string)
+}
+
+/// Parses a 32-byte vault key given as 64 hex characters.
+pub fn parse_key(hex: &str) -> Result<[u8; 32], String> {
+    let hex = hex.trim();
+    if hex.len() != 64 {
+        return Err("key must be 64 hex characters".to_string());
+    }
+
+    let mut key = [0u8; 32];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .map_err(|_| "key must be 64 hex characters".to_string())?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
+
+    use hypernote_protocol::{FrameType, HelloPayload, WsFrame};
+
+    use super::{parse_key, Mailbox, MailboxLimits, EMPTY_UPDATE};
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn edit(doc: &Doc, text: &str) -> Vec<u8> {
+        let content = doc.get_or_insert_text("content");
+        let mut txn = doc.transact_mut();
+        let len = content.get_string(&txn).len() as u32;
+        content.insert(&mut txn, len, text);
+        txn.encode_update_v1()
+    }
+
+    fn text_of(update: &[u8]) -> String {
+        let doc = Doc::new();
+        let content = doc.get_or_insert_text("content");
+        doc.transact_mut()
+            .apply_update(Update::decode_v1(update).expect("decodable"))
+            .expect("applies");
+        let txn = doc.transact();
+        content.get_string(&txn)
+    }
+
+    #[test]
+    fn empty_update_constant_matches_yrs() {
+        assert_eq!(Update::new().encode_v1(), EMPTY_UPDATE);
+    }
+
+    #[test]
+    fn hands_a_reconnecting_peer_only_what_it_is_missing() {
+        let mailbox = Mailbox::in_memory();
+        let alice = Doc::new();
+
+        let first = edit(&alice, "hello");
+        mailbox.deposit("team", "note-1", &first).expect("deposit");
+
+        // Bob saw the first edit, then went offline while Alice kept typing.
+        let bob = Doc::new();
+        bob.transact_mut()
+            .apply_update(Update::decode_v1(&first).unwrap())
+            .unwrap();
+        let second = edit(&alice, " world");
+        mailbox.deposit("team", "note-1", &second).expect("deposit");
+
+        let bob_vector = bob.transact().state_vector().encode_v1();
+        let missing = mailbox
+            .missing("team", "note-1", &bob_vector)
+            .expect("bob is behind");
+        bob.transact_mut()
+            .apply_update(Update::decode_v1(&missing).unwrap())
+            .unwrap();
+        let content = bob.get_or_insert_text("content");
+        assert_eq!(content.get_string(&bob.transact()), "hello world");
+
+        let caught_up = bob.transact().state_vector().encode_v1();
+        assert!(mailbox.missing("team", "note-1", &caught_up).is_none());
+        assert!(mailbox
+            .missing("team", "note-1", &StateVector::default().encode_v1())
+            .is_some());
+    }
+
+    #[test]
+    fn answers_a_hello_with_only_what_the_member_lacks() {
+        let mailbox = Mailbox::in_memory();
+        let alice = Doc::new();
+        let first = edit(&alice, "hello");
+        mailbox.deposit("team", "note-1", &first).expect("deposit");
+        mailbox
+            .deposit("team", "note-2", &edit(&Doc::new(), "other"))
+            .expect("deposit");
+        let bob = Doc::new();
+        bob.transact_mut()
+            .apply_update(Update::decode_v1(&first).unwrap())
+            .unwrap();
+        mailbox
+            .deposit("team", "note-1", &edit(&alice, " world"))
+            .expect("deposit");
+
+        let payload = HelloPayload {
+            open_note_ids: vec!["note-1".to_string()],
+            state_vectors: [(
+                "note-1".to_string(),
+                bob.transact().state_vector().encode_v1(),
+            )]
+            .into(),
+        };
+        let greeting = WsFrame::new(
+            FrameType::Hello,
+            "",
+            "bob",
+            serde_json::to_value(payload).unwrap(),
+        );
+        let replies = mailbox.respond("team", &greeting);
+        let update = |note_id: &str| {
+            replies
+                .iter()
+                .find(|frame| frame.frame_type == FrameType::Update && frame.note_id == note_id)
+                .map(|frame| frame.binary_payload().unwrap())
+        };
+        // Only the edit bob missed, and in full the note it sent no vector for.
+        let missed = update("note-1").expect("bob is behind");
+        assert_eq!(text_of(&missed), "", "builds on what bob has");
+        bob.transact_mut()
+            .apply_update(Update::decode_v1(&missed).unwrap())
+            .unwrap();
+        let content = bob.get_or_insert_text("content");
+        assert_eq!(content.get_string(&bob.transact()), "hello world");
+        assert_eq!(text_of(&update("note-2").expect("whole note")), "other");
+        let vectors = replies
+            .iter()
+            .filter(|frame| frame.frame_type == FrameType::StateVector)
+            .count();
+        assert_eq!(vectors, 2);
+    }
+
+    #[test]
+    fn rejects_undecodable_updates_and_unsafe_note_ids() {
+        let mailbox = Mailbox::in_memory();
+        assert!(mailbox
+            .deposit("team", "note-1", &[0xff, 0xff, 0xff])
+            .is_err());
+        assert!(mailbox
+            .deposit("team", "../escape", &edit(&Doc::new(), "x"))
+            .is_err());
+        assert!(mailbox.states("team").is_empty());
+    }
+
+    #[test]
+    fn refuses_deposits_past_the_workspace_and_total_caps() {
+        let limits = MailboxLimits {
+            max_workspace_bytes: 200,
+            max_total_bytes: 300,
+        };
+        let mailbox = Mailbox::in_memory().with_limits(limits);
+        let doc = Doc::new();
+        mailbox
+            .deposit("team", "note-1", &edit(&doc, &"a".repeat(120)))
+            .expect("fits");
+        assert_eq!(
+            mailbox.deposit("team", "note-2", &edit(&Doc::new(), &"b".repeat(120))),
+            Err("workspace mailbox is full".to_string())
+        );
+        mailbox
+            .deposit("other", "note-1", &edit(&Doc::new(), &"c".repeat(120)))
+            .expect("fits");
+        assert_eq!(
+            mailbox.deposit("third", "note-1", &edit(&Doc::new(), &"d".repeat(120))),
+            Err("mailbox is full".to_string())
+        );
+        assert_eq!(mailbox.states("team").len(), 1);
+    }
+
+    #[test]
+    fn vault_survives_restart_and_never_stores_plaintext() {
+        let dir = std::env::temp_dir().join(format!("hypernote-vault-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mailbox = Mailbox::persistent(&dir, KEY).expect("open vault");
+        let update = edit(&Doc::new(), "top secret plan");
+        mailbox.deposit("team", "note-1", &update).expect("deposit");
+
+        let on_disk = std::fs::read(dir.join("team").join("note-1.ymb")).expect("vault file");
+        assert!(!on_disk
+            .windows(b"top secret".len())
+            .any(|window| window == b"top secret"));
+
+        let reopened = Mailbox::persistent(&dir, KEY).expect("reopen vault");
+        let states = reopened.states("team");
+        assert_eq!(states.len(), 1);
+        assert_eq!(text_of(&states[0].1), "top secret plan");
+
+        let wrong_key = Mailbox::persistent(&dir, [9; 32]).expect("open with wrong key");
+        assert!(wrong_key.states("team").is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parses_hex_keys() {
+        assert_eq!(parse_key(&"07".repeat(32)).unwrap(), KEY);
+        assert!(parse_key("abc").is_err());
+        assert!(parse_key(&"zz".repeat(32)).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use hypernote_relay::{
+    parse_key, serve, Mailbox, MailboxLimits, Relay, Workspaces, DEFAULT_LISTEN_ADDR,
+};
+use tokio::net::TcpListener;
+
+const USAGE: &str = "usage: hypernote-relay --workspaces <file> [--listen <host:port>]
+                       [--mailbox | --mailbox-dir <dir>]
+                       [--max-workspace-mb <n>] [--max-total-mb <n>]
+
+  --workspaces <file>    workspaces served, one \"<workspace-id> <secret>\" per
+                         line (secrets 16+ characters); members join with
+                         ?secret=<secret>
+  --mailbox              keep note state in memory for members that are offline;
+                         the relay can read it, so run it only where you would
+                         keep the notes themselves
+  --mailbox-dir <dir>    same, persisted to <dir> and sealed with the key in
+                         HYPERNOTE_RELAY_KEY (64 hex characters)
+  --max-workspace-mb <n> mailbox state kept per workspace (default 256)
+  --max-total-mb <n>     mailbox state kept in total (default 2048)";
+
+/// Environment variable holding the vault key; kept out of argv so it never shows in `ps`.
+const KEY_ENV: &str = "HYPERNOTE_RELAY_KEY";
+
+struct Options {
+    listen_addr: String,
+    mailbox: MailboxMode,
+    workspaces: PathBuf,
+    limits: MailboxLimits,
+}
+
+enum MailboxMode {
+    Off,
+    InMemory,
+    Vault(PathBuf),
+}
+
+#[tokio::main]
+async fn main() {
+    let Options {
+        listen_addr,
+        mailbox,
+        workspaces,
+        limits,
+    } = match parse_options(std::env::args().skip(1)) {
+        Ok(value) => value,
+        Err(reason) => {
+            eprintln!("{reason}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let relay = match open_relay(mailbox, workspaces, limits) {
+        Ok(relay) => relay,
+        Err(reason) => {
+            eprintln!("[hypernote-relay] {reason}");
+            std::process::exit(1);
+        }
+    };
+
+    let listener = match TcpListener::bind(&listen_addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("[hypernote-relay] bind failed on {listen_addr}: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    eprintln!("[hypernote-relay] listening on {listen_addr}");
+    serve(relay, listener).await;
+}
+
+fn open_relay(
+    mailbox: MailboxMode,
+    workspaces: PathBuf,
+    limits: MailboxLimits,
+) -> Result<Arc<Relay>, String> {
+    let workspaces = Workspaces::load(&workspaces)?;
+    eprintln!("[hypernote-relay] serving {} workspaces", workspaces.len());
+    let relay = Relay::new(workspaces);
+    Ok(Arc::new(match open_mailbox(mailbox)? {
+        Some(mailbox) => relay.with_mailbox(mailbox.with_limits(limits)),
+        None => relay,
+    }))
+}
+
+fn open_mailbox(mode: MailboxMode) -> Result<Option<Mailbox>, String> {
+    match mode {
+        MailboxMode::Off => Ok(None),
+        MailboxMode::InMemory => Ok(Some(Mailbox::in_memory())),
+        MailboxMode::Vault(dir) => {
+            let key = std::env::var(KEY_ENV).map_err(|_| format!("{KEY_ENV} is not set"))?;
+            let key = parse_key(&key).map_err(|reason| format!("{KEY_ENV}: {reason}"))?;
+            let mailbox = Mailbox::persistent(&dir, key)
+                .map_err(|e| format!("cannot open mailbox at {}: {e}", dir.display()))?;
+            eprintln!("[hypernote-relay] mailbox vault at {}", dir.display());
+            Ok(Some(mailbox))
+        }
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut listen_addr = DEFAULT_LISTEN_ADDR.to_string();
+    let mut mailbox = MailboxMode::Off;
+    let mut workspaces = None;
+    let mut limits = MailboxLimits::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => {
+                listen_addr = args
+                    .next()
+                    .ok_or_else(|| "--listen needs a value".to_string())?;
+            }
+            "--mailbox" => mailbox = MailboxMode::InMemory,
+            "--mailbox-dir" => {
+                let dir = args
+                    .next()
+                    .ok_or_else(|| "--mailbox-dir needs a value".to_string())?;
+                mailbox = MailboxMode::Vault(PathBuf::from(dir));
+            }
+            "--workspaces" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| "--workspaces needs a value".to_string())?;
+                workspaces = Some(PathBuf::from(path));
+            }
+            "--max-workspace-mb" => {
+                limits.max_workspace_bytes = megabytes(&arg, args.next())?;
+            }
+            "--max-total-mb" => limits.max_total_bytes = megabytes(&arg, args.next())?,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            other => return Err(format!("unknown argument: {other}")),
+        }
+    }
+
+    Ok(Options {
+        listen_addr,
+        mailbox,
+        workspaces: workspaces.ok_or_else(|| "--workspaces is required".to_string())?,
+        limits,
+    })
+}
+
+fn megabytes(flag: &str, value: Option<String>) -> Result<usize, String> {
+    value
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|&megabytes| megabytes > 0)
+        .and_then(|megabytes| megabytes.checked_mul(1024 * 1024))
+        .ok_or_else(|| format!("{flag} needs a positive number"))
+}
@@ -0,0 +1,137 @@
+//! Workspaces a relay serves and the secret members of each must present.
+//!
+//! Secrets are provisioned by whoever runs the relay, in a file of `<workspace-id> <secret>`
+//! lines; nobody can claim a workspace by being the first to connect to it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use hypernote_protocol::is_valid_workspace_id;
+
+/// Shortest secret a workspace can be provisioned with.
+pub const MIN_SECRET_LEN: usize = 16;
+
+#[derive(Debug, Default)]
+pub struct Workspaces {
+    /// workspace id → its secret.
+    secrets: HashMap<String, String>,
+}
+
+impl Workspaces {
+    /// Reads a workspaces file; see [`Self::parse`].
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|reason| format!("{}: {reason}", path.display()))
+    }
+
+    /// One `<workspace-id> <secret>` per line; blank lines and lines starting with `#` are
+    /// skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut secrets = HashMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_no = index + 1;
+            let (workspace_id, secret) = line
+                .split_once(char::is_whitespace)
+                .map(|(id, secret)| (id, secret.trim()))
+                .ok_or_else(|| format!("line {line_no}: expected <workspace-id> <secret>"))?;
+            if !is_valid_workspace_id(workspace_id) {
+                return Err(format!("line {line_no}: invalid workspace id"));
+            }
+            if secret.len() < MIN_SECRET_LEN || !secret.chars().all(url_safe) {
+                return Err(format!(
+                    "line {line_no}: the secret needs {MIN_SECRET_LEN}+ letters, digits, \
+                     '-', '_', '.' or '~'"
+                ));
+            }
+            if secrets
+                .insert(workspace_id.to_string(), secret.to_string())
+                .is_some()
+            {
+                return Err(format!("line {line_no}: {workspace_id} is listed twice"));
+            }
+        }
+        Ok(Self { secrets })
+    }
+
+    pub fn len(&self) -> usize {
+        self.secrets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    /// Lets a member presenting `secret` into a provisioned workspace.
+    pub fn admit(&self, workspace_id: &str, secret: Option<&str>) -> Result<(), String> {
+        let known = self
+            .secrets
+            .get(workspace_id)
+            .ok_or("workspace is not served by this relay")?;
+        match secret {
+            Some(secret) if same_secret(known, secret) => Ok(()),
+            Some(_) => Err("wrong workspace secret".to_string()),
+            None => Err("the workspace secret is required".to_string()),
+        }
+    }
+}
+
+/// Characters a secret can carry in a URL query unescaped.
+fn url_safe(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.' | '~')
+}
+
+/// Compares without stopping at the first differing byte.
+fn same_secret(known: &str, given: &str) -> bool {
+    known.len() == given.len()
+        && known
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Workspaces;
+
+    #[test]
+    fn admits_only_provisioned_secrets() {
+        let workspaces = Workspaces::parse(
+            "# id secret\n\nteam correct-horse-battery\nother another-long-secret\n",
+        )
+        .expect("parses");
+        assert_eq!(workspaces.len(), 2);
+
+        workspaces
+            .admit("team", Some("correct-horse-battery"))
+            .expect("right secret");
+        assert!(workspaces.admit("team", None).is_err());
+        assert!(workspaces
+            .admit("team", Some("correct-horse-battery!"))
+            .is_err());
+        assert!(workspaces
+            .admit("team", Some("another-long-secret"))
+            .is_err());
+        // Nobody claims a workspace by connecting first.
+        assert!(workspaces
+            .admit("unlisted", Some("correct-horse-battery"))
+            .is_err());
+    }
+
+    #[test]
+    fn refuses_malformed_files() {
+        assert!(Workspaces::parse("team").is_err());
+        assert!(Workspaces::parse("team short").is_err());
+        assert!(Workspaces::parse("team has spaces in it ok").is_err());
+        assert!(Workspaces::parse("../team correct-horse-battery").is_err());
+        assert!(
+            Workspaces::parse("team correct-horse-battery\nteam correct-horse-battery").is_err()
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hypernote_core::events::{self, EventSink};
+use hypernote_core::limits::LimitSettings;
+use hypernote_core::lock::DataDirLock;
+use hypernote_core::store::default_daemon_data_dir;
+use hypernote_core::{share_target, Limits, Node, NodeConfig, NoteStore};
+
+const USAGE: &str =
+    "usage: hypernoted [--data-dir <dir>] [--max-note-mb <n>] [--max-message-mb <n>]
+                  [--max-peers <n>]
+
+  --data-dir <dir>       directory holding notes/ and trash/
+                         (default: hypernoted/ in the user data directory)
+  --max-note-mb <n>      largest note or update accepted, in MB (default: 50)
+  --max-message-mb <n>   largest WebSocket message accepted, in MB (default: 16);
+                         larger updates travel in chunks
+  --max-peers <n>        concurrent peers served (default: 10)
+
+Backups follow <data-dir>/backups.json; they are off until it sets \"enabled\": true.
+
+Refuses to start on a data directory the desktop app or another hypernoted is using.";
+
+const SHUTDOWN_CLOSE_REASON: &str = "host shutting down";
+/// Upper bound on how long exit waits for close handshakes.
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(1500);
+
+struct Options {
+    data_dir: PathBuf,
+    limits: Limits,
+}
+
+/// Logs connection changes; frame traffic is too chatty for the console.
+struct LogEvents;
+
+impl EventSink for LogEvents {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        match event {
+            events::PEER_CONNECTED => {
+                eprintln!("[hypernoted] peer connected: {}", payload["addr"]);
+            }
+            events::PEER_DISCONNECTED => {
+                eprintln!("[hypernoted] peer disconnected: {}", payload["peerId"]);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let Options { data_dir, limits } = match parse_options(std::env::args().skip(1)) {
+        Ok(value) => value,
+        Err(reason) => {
+            eprintln!("{reason}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    // Held until exit; dropping it would let another process in.
+    let _lock = match DataDirLock::acquire(&data_dir) {
+        Ok(lock) => lock,
+        Err(reason) => {
+            eprintln!("[hypernoted] {reason}");
+            std::process::exit(1);
+        }
+    };
+
+    let store = match NoteStore::open(&data_dir) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("[hypernoted] cannot open {}: {e}", data_dir.display());
+            std::process::exit(1);
+        }
+    };
+    eprintln!(
+        "[hypernoted] serving {} note(s) from {}",
+        store.note_ids().len(),
+        data_dir.display()
+    );
+
+    let peer_id = store
+        .device_id()
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let node = Node::new(
+        NodeConfig {
+            peer_id,
+            store,
+            serve_sync: true,
+            limits,
+        },
+        Arc::new(LogEvents),
+        tokio::runtime::Handle::current(),
+    );
+    node.start_sync_services();
+    node.start_compaction();
+    node.start_backups();
+    eprintln!("[hypernoted] join with {}", share_target());
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        eprintln!("[hypernoted] cannot listen for ctrl-c: {e}");
+    }
+
+    eprintln!("[hypernoted] shutting down");
+    let mdns_withdrawn = node.stop_sync_services();
+    node.close_all_peers(SHUTDOWN_CLOSE_REASON);
+    let settled = async {
+        let _ = mdns_withdrawn.await;
+        node.wait_for_peers_drained().await;
+    };
+    let _ = tokio::time::timeout(SHUTDOWN_GRACE, settled).await;
+    node.store().compact_all();
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut data_dir = None;
+    let mut limits = Limits::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--data-dir" => {
+                let dir = args
+                    .next()
+                    .ok_or_else(|| "--data-dir needs a value".to_string())?;
+                data_dir = Some(PathBuf::from(dir));
+            }
+            "--max-note-mb" | "--max-message-mb" | "--max-peers" => {
+                let value = args
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|&value| value > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number"))?;
+                let settings = LimitSettings {
+                    max_note_mb: (arg == "--max-note-mb").then_some(value),
+                    max_message_mb: (arg == "--max-message-mb").then_some(value),
+                    max_peers: (arg == "--max-peers").then_some(value),
+                };
+                limits = settings.apply(limits)?;
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            other => return Err(format!("unknown argument: {other}")),
+        }
+    }
+
+    let data_dir = data_dir
+        .or_else(default_daemon_data_dir)
+        .ok_or_else(|| "no default data directory; pass --data-dir".to_string())?;
+
+    Ok(Options { data_dir, limits })
+}
+
+#[cfg(test)]
+mod tests {
+    use hypernote_core::store::default_data_dir;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults_to_its_own_data_directory() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(Some(options.data_dir), default_daemon_data_dir());
+        assert_ne!(default_daemon_data_dir(), default_data_dir());
+        assert_eq!(options.limits, Limits::default());
+    }
+
+    #[test]
+    fn reads_data_dir_and_limits() {
+        let options = parse(&["--max-peers", "3", "--data-dir", "/srv/notes"]).unwrap();
+        assert_eq!(options.data_dir, PathBuf::from("/srv/notes"));
+        assert_eq!(options.limits.max_peers, 3);
+
+        let options = parse(&[
+            "--max-message-mb",
+            "2",
+            "--max-note-mb",
+            "5",
+            "--max-peers",
+            "2",
+        ]);
+        let options = options.unwrap();
+        assert_eq!(options.limits.max_note_bytes, 5 * 1024 * 1024);
+        assert_eq!(options.limits.max_ws_message_bytes, 2 * 1024 * 1024);
+        assert_eq!(options.limits.max_peers, 2);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["--data-dir"]).is_err());
+        assert!(parse(&["--max-note-mb", "0"]).is_err());
+        assert!(parse(&["--max-peers", "many"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+}
@@ -1,86 +1,94 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod contracts;
-
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-
-use contracts::{
-    CommandAck, NoteDocument, NoteMeta, PeerConnectedEvent, PeerDisconnectedEvent, PeerInfo,
-    PeerUpdateEvent, WsMessageEvent,
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hypernote_core::backup::BackupSettings;
+use hypernote_core::contracts::{
+    AttributedRange, BackupInfo, BundleImportReport, CommandAck, CompactionReport, ExportManifest,
+    ImportReport, NoteDiff, NoteDocument, NoteFilter, NoteMeta, NoteVersion, PeerInfo,
+    QuickOpenHit, SearchHit, StorageIssue, SyncState, TagCount,
 };
+use hypernote_core::export::ExportOptions;
+use hypernote_core::import::ImportFormat;
+use hypernote_core::lock::DataDirLock;
+use hypernote_core::{EventSink, Limits, Node, NodeConfig, NoteStore};
+use hypernote_protocol::MetaPayload;
 use tauri::{Emitter, Manager};
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Notify;
 
 // ---------------------------------------------------------------------------
 // App state
 // ---------------------------------------------------------------------------
 
-/// peerId → channel to send outgoing WS commands to that peer.
-type WsPeers = Arc<Mutex<HashMap<String, UnboundedSender<WsPeerCommand>>>>;
-
-#[derive(Debug, Clone)]
-enum WsPeerCommand {
-    Text(String),
-    Close { reason: Option<String> },
-}
-
 struct AppState {
-    notes: Mutex<HashMap<String, NoteDocument>>,
-    peers: Mutex<HashMap<String, PeerInfo>>,
-    /// Shared (Arc) so async tasks can clone it cheaply without holding State<'_, ...>.
-    ws_peers: WsPeers,
-    /// Stable identity for this HyperNote instance (UUID, generated at startup).
-    peer_id: String,
+    /// Note store and LAN sync, shared with the `hypernoted` daemon.
+    node: Arc<Node>,
+    /// Set once the exit path has started so the final exit request is not intercepted.
+    shutting_down: AtomicBool,
+    /// Signalled by the frontend once its pending note saves reached the backend.
+    persistence_flushed: Notify,
+    /// Keeps `hypernoted` and `hypernote-cli` from writing the data directory meanwhile.
+    _data_dir_lock: Option<DataDirLock>,
 }
 
 impl AppState {
-    fn new(peer_id: String) -> Self {
+    fn new(node: Arc<Node>, data_dir_lock: Option<DataDirLock>) -> Self {
         Self {
-            notes: Default::default(),
-            peers: Default::default(),
-            ws_peers: Arc::new(Mutex::new(HashMap::new())),
-            peer_id,
+            node,
+            shutting_down: AtomicBool::new(false),
+            persistence_flushed: Notify::new(),
+            _data_dir_lock: data_dir_lock,
         }
     }
 }
 
+/// Forwards core events to the webview under the same names.
+struct WebviewEvents(tauri::AppHandle);
+
+impl EventSink for WebviewEvents {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        self.0.emit(event, payload).map_err(|e| e.to_string())
+    }
+}
+
 // ---------------------------------------------------------------------------
-// Note commands (unchanged from prior implementation)
+// Note commands
 // ---------------------------------------------------------------------------
 
 #[tauri::command]
-fn create_note(state: tauri::State<'_, AppState>) -> NoteMeta {
-    let now = unix_now_ms();
-    let meta = NoteMeta {
-        id: uuid::Uuid::new_v4().to_string(),
-        title: "Untitled".to_string(),
-        created_at: now,
-        updated_at: now,
-        deleted_at: None,
-    };
+fn create_note(state: tauri::State<'_, AppState>) -> Result<NoteMeta, String> {
+    state.node.create_note()
+}
 
-    let mut notes = state.notes.lock().expect("note store poisoned");
-    notes.insert(
-        meta.id.clone(),
-        NoteDocument {
-            meta: meta.clone(),
-            yjs_state: Vec::new(),
-            markdown: String::new(),
-        },
-    );
+#[tauri::command]
+fn open_note(note_id: String, state: tauri::State<'_, AppState>) -> Result<NoteDocument, String> {
+    state.node.open_note(&note_id)
+}
 
-    meta
+#[tauri::command]
+fn list_note_containers(trash: bool, state: tauri::State<'_, AppState>) -> Vec<Vec<u8>> {
+    state.node.note_containers(trash)
 }
 
 #[tauri::command]
-fn open_note(note_id: String, state: tauri::State<'_, AppState>) -> Result<NoteDocument, String> {
-    let notes = state.notes.lock().map_err(|_| "note store poisoned")?;
+fn read_note_container(
+    note_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<u8>, String> {
+    state.node.note_container(&note_id)
+}
 
-    notes
-        .get(&note_id)
-        .cloned()
-        .ok_or_else(|| format!("note not found: {note_id}"))
+#[tauri::command]
+fn save_note_container(bytes: Vec<u8>, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.save_note_container(&bytes)
+}
+
+#[tauri::command]
+fn purge_note(note_id: String, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.purge_note(&note_id)
 }
 
 #[tauri::command]
@@ -89,29 +97,7 @@ fn apply_local_edit(
     update: Vec<u8>,
     state: tauri::State<'_, AppState>,
 ) -> CommandAck {
-    let mut notes = match state.notes.lock() {
-        Ok(value) => value,
-        Err(_) => {
-            return CommandAck {
-                accepted: false,
-                reason: Some("note store poisoned".to_string()),
-            }
-        }
-    };
-
-    if let Some(note) = notes.get_mut(&note_id) {
-        note.yjs_state = update;
-        note.meta.updated_at = unix_now_ms();
-        return CommandAck {
-            accepted: true,
-            reason: None,
-        };
-    }
-
-    CommandAck {
-        accepted: false,
-        reason: Some(format!("note not found: {note_id}")),
-    }
+    state.node.apply_local_edit(&note_id, &update)
 }
 
 #[tauri::command]
@@ -119,656 +105,487 @@ fn apply_peer_update(
     note_id: String,
     update: Vec<u8>,
     state: tauri::State<'_, AppState>,
-    app: tauri::AppHandle,
 ) -> CommandAck {
-    let mut notes = match state.notes.lock() {
-        Ok(value) => value,
-        Err(_) => {
-            return CommandAck {
-                accepted: false,
-                reason: Some("note store poisoned".to_string()),
-            }
-        }
-    };
+    state.node.apply_peer_update(&note_id, update)
+}
 
-    if let Some(note) = notes.get_mut(&note_id) {
-        note.yjs_state = update.clone();
-        note.meta.updated_at = unix_now_ms();
+#[tauri::command]
+fn list_notes(state: tauri::State<'_, AppState>) -> Vec<NoteMeta> {
+    state.node.list_notes()
+}
 
-        let payload = PeerUpdateEvent { note_id, update };
+#[tauri::command]
+fn get_workspace_manifest(state: tauri::State<'_, AppState>) -> Vec<u8> {
+    state.node.workspace_manifest()
+}
 
-        if let Err(error) = app.emit("hypernote://peer-update", payload) {
-            return CommandAck {
-                accepted: false,
-                reason: Some(format!("emit failed: {error}")),
-            };
-        }
+#[tauri::command]
+fn list_missing_notes(state: tauri::State<'_, AppState>) -> Vec<String> {
+    state.node.list_missing_notes()
+}
 
-        return CommandAck {
-            accepted: true,
-            reason: None,
-        };
-    }
+/// Hits returned when the caller does not pass a limit.
+const DEFAULT_SEARCH_LIMIT: usize = 20;
 
-    CommandAck {
-        accepted: false,
-        reason: Some("note not found".to_string()),
-    }
+#[tauri::command]
+fn search_notes(
+    query: String,
+    limit: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Vec<SearchHit> {
+    state
+        .node
+        .search_notes(&query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
 }
 
 #[tauri::command]
-fn list_notes(state: tauri::State<'_, AppState>) -> Vec<NoteMeta> {
-    let notes = match state.notes.lock() {
-        Ok(value) => value,
-        Err(_) => return Vec::new(),
-    };
+fn quick_open(
+    query: String,
+    limit: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Vec<QuickOpenHit> {
+    state
+        .node
+        .quick_open(&query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+}
 
-    let mut metas: Vec<NoteMeta> = notes.values().map(|note| note.meta.clone()).collect();
-    metas.sort_by_key(|note| std::cmp::Reverse(note.updated_at));
-    metas
+#[tauri::command]
+fn record_note_opened(note_id: String, state: tauri::State<'_, AppState>) {
+    state.node.record_note_opened(&note_id);
 }
 
 #[tauri::command]
-fn delete_note_to_trash(note_id: String, state: tauri::State<'_, AppState>) -> CommandAck {
-    let mut notes = match state.notes.lock() {
-        Ok(value) => value,
-        Err(_) => {
-            return CommandAck {
-                accepted: false,
-                reason: Some("note store poisoned".to_string()),
-            }
-        }
-    };
+fn set_note_title(
+    note_id: String,
+    title: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> CommandAck {
+    state.node.set_note_title(&note_id, title.as_deref())
+}
 
-    if notes.remove(&note_id).is_some() {
-        return CommandAck {
-            accepted: true,
-            reason: None,
-        };
-    }
+#[tauri::command]
+fn delete_note_to_trash(note_id: String, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.delete_note_to_trash(&note_id)
+}
 
-    CommandAck {
-        accepted: false,
-        reason: Some("note not found".to_string()),
-    }
+#[tauri::command]
+fn restore_note(note_id: String, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.restore_note(&note_id)
 }
 
 #[tauri::command]
 fn list_peers(state: tauri::State<'_, AppState>) -> Vec<PeerInfo> {
-    let peers = match state.peers.lock() {
-        Ok(value) => value,
-        Err(_) => return Vec::new(),
-    };
-
-    peers.values().cloned().collect()
+    state.node.list_peers()
 }
 
 // ---------------------------------------------------------------------------
-// WebSocket / sync commands
+// Tags, pinning, folders
 // ---------------------------------------------------------------------------
 
 #[tauri::command]
-fn broadcast_update(payload: String, state: tauri::State<'_, AppState>) -> CommandAck {
-    let ws_peers = match state.ws_peers.lock() {
-        Ok(value) => value,
-        Err(_) => {
-            return CommandAck {
-                accepted: false,
-                reason: Some("ws_peers poisoned".to_string()),
-            }
-        }
-    };
-
-    let mut failed = 0usize;
-    for tx in ws_peers.values() {
-        if tx.send(WsPeerCommand::Text(payload.clone())).is_err() {
-            failed += 1;
-        }
-    }
-
-    CommandAck {
-        accepted: true,
-        reason: if failed > 0 {
-            Some(format!("{failed} peer(s) unreachable"))
-        } else {
-            None
-        },
-    }
+fn set_note_tags(
+    note_id: String,
+    tags: Vec<String>,
+    state: tauri::State<'_, AppState>,
+) -> CommandAck {
+    state.node.set_note_tags(&note_id, &tags)
 }
 
 #[tauri::command]
-fn send_to_peer(peer_id: String, payload: String, state: tauri::State<'_, AppState>) -> CommandAck {
-    let ws_peers = match state.ws_peers.lock() {
-        Ok(value) => value,
-        Err(_) => {
-            return CommandAck {
-                accepted: false,
-                reason: Some("ws_peers poisoned".to_string()),
-            }
-        }
-    };
-
-    if let Some(tx) = ws_peers.get(&peer_id) {
-        let ok = tx.send(WsPeerCommand::Text(payload)).is_ok();
-        CommandAck {
-            accepted: ok,
-            reason: if ok {
-                None
-            } else {
-                Some("channel closed".to_string())
-            },
-        }
-    } else {
-        CommandAck {
-            accepted: false,
-            reason: Some(format!("peer not found: {peer_id}")),
-        }
-    }
+fn set_note_pinned(note_id: String, pinned: bool, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.set_note_pinned(&note_id, pinned)
 }
 
 #[tauri::command]
-fn disconnect_peer(
-    peer_id: String,
-    reason: Option<String>,
+fn set_note_color(
+    note_id: String,
+    color: Option<String>,
     state: tauri::State<'_, AppState>,
 ) -> CommandAck {
-    let ws_peers = match state.ws_peers.lock() {
-        Ok(value) => value,
-        Err(_) => {
-            return CommandAck {
-                accepted: false,
-                reason: Some("ws_peers poisoned".to_string()),
-            }
-        }
-    };
+    state.node.set_note_color(&note_id, color.as_deref())
+}
 
-    if let Some(tx) = ws_peers.get(&peer_id) {
-        let ok = tx.send(WsPeerCommand::Close { reason }).is_ok();
-        CommandAck {
-            accepted: ok,
-            reason: if ok {
-                None
-            } else {
-                Some("channel closed".to_string())
-            },
-        }
-    } else {
-        CommandAck {
-            accepted: false,
-            reason: Some(format!("peer not found: {peer_id}")),
-        }
-    }
+#[tauri::command]
+fn move_note_to_folder(
+    note_id: String,
+    folder: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> CommandAck {
+    state.node.move_note_to_folder(&note_id, folder.as_deref())
 }
 
 #[tauri::command]
-fn get_peer_id(state: tauri::State<'_, AppState>) -> String {
-    state.peer_id.clone()
+fn list_tags(state: tauri::State<'_, AppState>) -> Vec<TagCount> {
+    state.node.list_tags()
 }
 
 #[tauri::command]
-fn get_share_target() -> String {
-    let host = detect_local_ipv4_host()
-        .map(|ip| ip.to_string())
-        .unwrap_or_else(default_share_host);
-    format!("ws://{host}:4747")
+fn list_notes_filtered(filter: NoteFilter, state: tauri::State<'_, AppState>) -> Vec<NoteMeta> {
+    state.node.list_notes_filtered(&filter)
 }
 
 #[tauri::command]
-fn join_workspace(
-    target: String,
+fn apply_peer_meta(
+    note_id: String,
+    payload: MetaPayload,
     state: tauri::State<'_, AppState>,
-    app: tauri::AppHandle,
 ) -> CommandAck {
-    let addr = match normalize_join_target(&target) {
-        Ok(value) => value,
-        Err(reason) => {
-            return CommandAck {
-                accepted: false,
-                reason: Some(reason),
-            }
-        }
-    };
-
-    if is_self_join_target(&addr) {
-        return CommandAck {
-            accepted: false,
-            reason: Some("cannot join your own workspace target".to_string()),
-        };
-    }
-
-    let ws_peers = Arc::clone(&state.ws_peers);
-    tauri::async_runtime::spawn(async move {
-        connect_to_peer_ws(addr, app, ws_peers).await;
-    });
+    state.node.apply_peer_meta(&note_id, &payload)
+}
 
-    CommandAck {
-        accepted: true,
-        reason: None,
-    }
+#[tauri::command]
+fn get_note_attribution(
+    note_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<AttributedRange>, String> {
+    state.node.get_note_attribution(&note_id)
 }
 
-// ---------------------------------------------------------------------------
-// WebSocket connection handler (shared by server-accept and client-connect)
-// ---------------------------------------------------------------------------
+#[tauri::command]
+fn list_note_versions(note_id: String, state: tauri::State<'_, AppState>) -> Vec<NoteVersion> {
+    state.node.list_note_versions(&note_id)
+}
 
-async fn handle_ws_connection<S>(
-    ws: tokio_tungstenite::WebSocketStream<S>,
-    addr: String,
-    app: tauri::AppHandle,
-    ws_peers: WsPeers,
-    outbound: bool,
-) where
-    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
-{
-    use futures_util::{SinkExt, StreamExt};
-    use tokio_tungstenite::tungstenite::{
-        protocol::{
-            frame::{coding::CloseCode, CloseFrame},
-            Message,
-        },
-        Utf8Bytes,
-    };
+#[tauri::command]
+fn open_note_version(
+    note_id: String,
+    version: u32,
+    state: tauri::State<'_, AppState>,
+) -> Result<NoteDocument, String> {
+    state.node.open_note_version(&note_id, version)
+}
 
-    let peer_id = uuid::Uuid::new_v4().to_string();
-    let (mut sink, mut stream) = ws.split();
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<WsPeerCommand>();
+#[tauri::command]
+fn list_storage_issues(state: tauri::State<'_, AppState>) -> Vec<StorageIssue> {
+    state.node.list_storage_issues()
+}
 
-    // Register peer sender — Arc<Mutex<...>> is 'static, safe across awaits.
-    if let Ok(mut peers) = ws_peers.lock() {
-        peers.insert(peer_id.clone(), tx);
-    }
+#[tauri::command]
+fn request_note_from_peers(note_id: String, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.request_note_from_peers(&note_id)
+}
 
-    let state = app.state::<AppState>();
-    if let Ok(mut peers) = state.peers.lock() {
-        peers.insert(
-            peer_id.clone(),
-            PeerInfo {
-                peer_id: peer_id.clone(),
-                ws_url: format!("ws://{addr}"),
-                status: "CONNECTED".to_string(),
-                note_ids: Vec::new(),
-            },
-        );
-    }
+#[tauri::command]
+fn compact_note(
+    note_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<CompactionReport, String> {
+    state.node.compact_note(&note_id)
+}
 
-    let _ = app.emit(
-        "hypernote://peer-connected",
-        PeerConnectedEvent {
-            peer_id: peer_id.clone(),
-            addr: addr.clone(),
-            outbound,
-        },
-    );
-
-    // Forward outgoing messages to the WS sink in a background task.
-    let sink_task = tauri::async_runtime::spawn(async move {
-        while let Some(cmd) = rx.recv().await {
-            match cmd {
-                WsPeerCommand::Text(msg) => {
-                    if sink.send(Message::Text(msg.into())).await.is_err() {
-                        break;
-                    }
-                }
-                WsPeerCommand::Close { reason } => {
-                    let close_reason = reason.unwrap_or_else(|| "peer disconnected".to_string());
-                    let _ = sink
-                        .send(Message::Close(Some(CloseFrame {
-                            code: CloseCode::Normal,
-                            reason: Utf8Bytes::from(close_reason),
-                        })))
-                        .await;
-                    break;
-                }
-            }
-        }
-    });
+#[tauri::command]
+fn import_markdown(paths: Vec<String>, state: tauri::State<'_, AppState>) -> ImportReport {
+    let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+    state.node.import_markdown(&paths)
+}
 
-    // Read incoming messages and forward to frontend.
-    while let Some(msg_result) = stream.next().await {
-        match msg_result {
-            Ok(Message::Text(text)) => {
-                let _ = app.emit(
-                    "hypernote://ws-message",
-                    WsMessageEvent {
-                        peer_id: peer_id.clone(),
-                        payload: text.to_string(),
-                    },
-                );
-            }
-            Ok(Message::Close(_)) | Err(_) => break,
-            _ => {}
-        }
-    }
+#[tauri::command]
+fn import_notes(
+    format: ImportFormat,
+    path: String,
+    dry_run: bool,
+    state: tauri::State<'_, AppState>,
+) -> ImportReport {
+    state.node.import_export(format, Path::new(&path), dry_run)
+}
 
-    sink_task.abort();
+#[tauri::command]
+fn export_workspace(
+    dest_path: String,
+    options: Option<ExportOptions>,
+    state: tauri::State<'_, AppState>,
+) -> Result<ExportManifest, String> {
+    state
+        .node
+        .export_workspace(Path::new(&dest_path), &options.unwrap_or_default())
+}
 
-    // Deregister peer.
-    if let Ok(mut peers) = ws_peers.lock() {
-        peers.remove(&peer_id);
-    }
+#[tauri::command]
+fn import_workspace_bundle(
+    path: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<BundleImportReport, String> {
+    state.node.import_workspace_bundle(Path::new(&path))
+}
 
-    let state = app.state::<AppState>();
-    if let Ok(mut peers) = state.peers.lock() {
-        peers.remove(&peer_id);
-    }
+#[tauri::command]
+fn list_backups(state: tauri::State<'_, AppState>) -> Vec<BackupInfo> {
+    state.node.list_backups()
+}
 
-    let _ = app.emit(
-        "hypernote://peer-disconnected",
-        PeerDisconnectedEvent { peer_id },
-    );
+#[tauri::command]
+fn restore_backup(
+    name: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<BundleImportReport, String> {
+    state.node.restore_backup(&name)
 }
 
-// ---------------------------------------------------------------------------
-// WebSocket server (TCP 4747)
-// ---------------------------------------------------------------------------
+#[tauri::command]
+fn get_backup_settings(state: tauri::State<'_, AppState>) -> Result<BackupSettings, String> {
+    state.node.backup_settings()
+}
 
-async fn run_ws_server(app: tauri::AppHandle, ws_peers: WsPeers) {
-    use tokio::net::TcpListener;
+#[tauri::command]
+fn set_backup_settings(settings: BackupSettings, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.set_backup_settings(&settings)
+}
 
-    let listener = match TcpListener::bind("0.0.0.0:4747").await {
-        Ok(l) => l,
-        Err(e) => {
-            eprintln!("[hypernote] WS server bind failed on 4747: {e}");
-            return;
-        }
-    };
+#[tauri::command]
+fn diff_note(
+    note_id: String,
+    from: Option<u32>,
+    to: Option<u32>,
+    state: tauri::State<'_, AppState>,
+) -> Result<NoteDiff, String> {
+    state.node.diff_note(&note_id, from, to)
+}
 
-    eprintln!("[hypernote] WS server listening on 0.0.0.0:4747");
-
-    loop {
-        match listener.accept().await {
-            Ok((stream, addr)) => {
-                let app = app.clone();
-                let ws_peers = Arc::clone(&ws_peers);
-                let addr_str = addr.to_string();
-                tauri::async_runtime::spawn(async move {
-                    match tokio_tungstenite::accept_async(stream).await {
-                        Ok(ws) => handle_ws_connection(ws, addr_str, app, ws_peers, false).await,
-                        Err(e) => eprintln!("[hypernote] WS handshake error: {e}"),
-                    }
-                });
-            }
-            Err(e) => eprintln!("[hypernote] WS accept error: {e}"),
-        }
-    }
+#[tauri::command]
+fn restore_note_version(
+    note_id: String,
+    version: u32,
+    state: tauri::State<'_, AppState>,
+) -> CommandAck {
+    state.node.restore_note_version(&note_id, version)
 }
 
 // ---------------------------------------------------------------------------
-// WebSocket client (connects to a discovered peer)
+// WebSocket / sync commands
 // ---------------------------------------------------------------------------
 
-async fn connect_to_peer_ws(addr: String, app: tauri::AppHandle, ws_peers: WsPeers) {
-    let url = format!("ws://{addr}/");
-
-    match tokio_tungstenite::connect_async(&url).await {
-        Ok((ws, _)) => handle_ws_connection(ws, addr, app, ws_peers, true).await,
-        Err(e) => eprintln!("[hypernote] WS connect to {addr} failed: {e}"),
-    }
+#[tauri::command]
+fn broadcast_update(payload: String, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.broadcast(payload)
 }
 
-// ---------------------------------------------------------------------------
-// mDNS registration + peer discovery
-// ---------------------------------------------------------------------------
-
-fn run_mdns(peer_id: String, app: tauri::AppHandle, ws_peers: WsPeers) {
-    use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+#[tauri::command]
+fn send_to_peer(peer_id: String, payload: String, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.send_to_peer(&peer_id, payload)
+}
 
-    let mdns = match ServiceDaemon::new() {
-        Ok(d) => d,
-        Err(e) => {
-            eprintln!("[hypernote] mDNS daemon start failed: {e}");
-            return;
-        }
-    };
+#[tauri::command]
+fn disconnect_peer(
+    peer_id: String,
+    reason: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> CommandAck {
+    state.node.disconnect_peer(&peer_id, reason)
+}
 
-    let host = format!("{}.local.", local_hostname());
-    let service_info = ServiceInfo::new(
-        "_hypernote._tcp.local.",
-        &peer_id,
-        &host,
-        "", // IP: auto-detect from bound interfaces
-        4747,
-        None,
-    );
-
-    match service_info {
-        Ok(info) => {
-            if let Err(e) = mdns.register(info) {
-                eprintln!("[hypernote] mDNS register failed: {e}");
-            } else {
-                eprintln!("[hypernote] mDNS registered as {peer_id}._hypernote._tcp.local.");
-            }
-        }
-        Err(e) => eprintln!("[hypernote] mDNS ServiceInfo build failed: {e}"),
-    }
+#[tauri::command]
+fn get_peer_id(state: tauri::State<'_, AppState>) -> String {
+    state.node.peer_id().to_string()
+}
 
-    let receiver = match mdns.browse("_hypernote._tcp.local.") {
-        Ok(r) => r,
-        Err(e) => {
-            eprintln!("[hypernote] mDNS browse failed: {e}");
-            return;
-        }
-    };
+#[tauri::command]
+fn get_share_target() -> String {
+    hypernote_core::share_target()
+}
 
-    loop {
-        match receiver.recv_timeout(std::time::Duration::from_secs(5)) {
-            Ok(ServiceEvent::ServiceResolved(info)) => {
-                let name = info.get_fullname();
+#[tauri::command]
+fn join_workspace(target: String, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.join(&target)
+}
 
-                // Skip self.
-                if name.contains(&peer_id) {
-                    continue;
-                }
+// ---------------------------------------------------------------------------
+// Sync pause / resume
+// ---------------------------------------------------------------------------
 
-                for addr in info.get_addresses() {
-                    let ws_addr = format!("{addr}:4747");
-                    let app = app.clone();
-                    let ws_peers = Arc::clone(&ws_peers);
-                    tauri::async_runtime::spawn(async move {
-                        connect_to_peer_ws(ws_addr, app, ws_peers).await;
-                    });
-                }
-            }
-            Ok(ServiceEvent::ServiceRemoved(_, name)) => {
-                eprintln!("[hypernote] mDNS peer removed: {name}");
-            }
-            Ok(_) => {}
-            Err(_) => {
-                // Timeout or channel closed — keep looping.
-            }
-        }
-    }
+#[tauri::command]
+fn set_sync_enabled(enabled: bool, state: tauri::State<'_, AppState>) -> CommandAck {
+    state.node.set_sync_enabled(enabled)
 }
 
-fn local_hostname() -> String {
-    std::process::Command::new("hostname")
-        .output()
-        .ok()
-        .and_then(|out| String::from_utf8(out.stdout).ok())
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .unwrap_or_else(|| "hypernote-host".to_string())
+#[tauri::command]
+fn get_sync_state(state: tauri::State<'_, AppState>) -> SyncState {
+    state.node.sync_state()
 }
 
-fn normalize_join_target(raw: &str) -> Result<String, String> {
-    let target = raw.trim();
-    if target.is_empty() {
-        return Err("target is empty".to_string());
-    }
-
-    if let Some(without_scheme) = target.strip_prefix("ws://") {
-        return parse_host_port_target(without_scheme, true);
-    }
+// ---------------------------------------------------------------------------
+// Shutdown
+// ---------------------------------------------------------------------------
 
-    if target.contains("://") {
-        return Err("only ws:// scheme is supported".to_string());
-    }
+const SHUTDOWN_CLOSE_REASON: &str = "host shutting down";
+/// Upper bound on how long exit waits for close handshakes and the frontend flush.
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(1500);
 
-    parse_host_port_target(target, false)
+#[tauri::command]
+fn confirm_persistence_flushed(state: tauri::State<'_, AppState>) {
+    state.persistence_flushed.notify_one();
 }
 
-fn parse_host_port_target(value: &str, require_port: bool) -> Result<String, String> {
-    if value.is_empty() {
-        return Err("target is empty".to_string());
-    }
+/// Stops networking and gives peers and the frontend a bounded window to settle.
+///
+/// Order matters: the accept loop stops first so no new peer slips in, then the
+/// mDNS record is withdrawn, then every open socket gets a close frame. The frontend
+/// flush is only awaited while a window is still there to answer.
+async fn shutdown(app: tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let webview_alive = !app.webview_windows().is_empty();
 
-    if value
-        .chars()
-        .any(|ch| ch.is_whitespace() || matches!(ch, '/' | '?' | '#'))
-    {
-        return Err("target contains unsupported characters".to_string());
+    if webview_alive {
+        let _ = app.emit("hypernote://shutdown", ());
     }
+    let mdns_withdrawn = state.node.stop_sync_services();
+    state.node.close_all_peers(SHUTDOWN_CLOSE_REASON);
 
-    if let Some((host, port_raw)) = value.rsplit_once(':') {
-        if host.is_empty() {
-            return Err("host is missing".to_string());
+    let settled = async {
+        if webview_alive {
+            state.persistence_flushed.notified().await;
         }
-
-        if host.contains(':') {
-            return Err("target host format is invalid".to_string());
-        }
-
-        let port = match port_raw.parse::<u16>() {
-            Ok(value) if value > 0 => value,
-            _ => return Err("port must be between 1 and 65535".to_string()),
-        };
-
-        return Ok(format!("{host}:{port}"));
-    }
-
-    if require_port {
-        return Err("target must be ws://host:port".to_string());
+        let _ = mdns_withdrawn.await;
+        state.node.wait_for_peers_drained().await;
+    };
+    if tokio::time::timeout(SHUTDOWN_GRACE, settled).await.is_err() {
+        eprintln!("[hypernote] shutdown grace period elapsed; exiting anyway");
     }
-
-    Ok(format!("{value}:4747"))
 }
 
-fn is_self_join_target(addr: &str) -> bool {
-    let (host, port_raw) = match addr.rsplit_once(':') {
-        Some(value) => value,
-        None => return false,
-    };
-
-    let port = match port_raw.parse::<u16>() {
-        Ok(value) => value,
-        Err(_) => return false,
-    };
-
-    if port != 4747 {
+/// Runs [`shutdown`] once, then exits. Returns `false` when it is already under way.
+fn begin_shutdown(app: &tauri::AppHandle) -> bool {
+    let state = app.state::<AppState>();
+    if state.shutting_down.swap(true, Ordering::SeqCst) {
         return false;
     }
 
-    let host_lower = host.to_ascii_lowercase();
-    if matches!(
-        host_lower.as_str(),
-        "localhost" | "127.0.0.1" | "0.0.0.0" | "::1"
-    ) {
-        return true;
-    }
-
-    let local_host = local_hostname().to_ascii_lowercase();
-    if host_lower == local_host || host_lower == format!("{local_host}.local") {
-        return true;
-    }
-
-    if let Some(local_ip) = detect_local_ipv4_host() {
-        if host == local_ip.to_string() {
-            return true;
-        }
-    }
-
-    false
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        shutdown(app.clone()).await;
+        app.exit(0);
+    });
+    true
 }
 
-fn detect_local_ipv4_host() -> Option<std::net::Ipv4Addr> {
-    use std::net::{IpAddr, UdpSocket};
-
-    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
-    socket.connect("8.8.8.8:80").ok()?;
-    match socket.local_addr().ok()?.ip() {
-        IpAddr::V4(ip) if !ip.is_loopback() => Some(ip),
-        _ => None,
+/// Holds the last window open until the frontend has flushed its saves; by the time
+/// `ExitRequested` fires the webview is already gone.
+fn handle_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    let tauri::WindowEvent::CloseRequested { api, .. } = event else {
+        return;
+    };
+    let app = window.app_handle();
+    if app.webview_windows().len() > 1 {
+        return;
     }
-}
-
-fn default_share_host() -> String {
-    let host = local_hostname();
-    if host.contains('.') {
-        host
-    } else {
-        format!("{host}.local")
+    if begin_shutdown(app) {
+        api.prevent_close();
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{is_self_join_target, normalize_join_target};
-
-    #[test]
-    fn blocks_loopback_self_join_targets() {
-        assert!(is_self_join_target("localhost:4747"));
-        assert!(is_self_join_target("127.0.0.1:4747"));
-        assert!(!is_self_join_target("127.0.0.1:4748"));
-    }
+fn handle_run_event(app: &tauri::AppHandle, event: tauri::RunEvent) {
+    let tauri::RunEvent::ExitRequested { api, .. } = event else {
+        return;
+    };
 
-    #[test]
-    fn normalizes_host_without_port() {
-        let normalized = normalize_join_target("peer-host").expect("normalize should succeed");
-        assert_eq!(normalized, "peer-host:4747");
+    // Our own `exit` after shutdown comes through here a second time — let it through.
+    if begin_shutdown(app) {
+        api.prevent_exit();
     }
 }
 
-// ---------------------------------------------------------------------------
-// Helpers
-// ---------------------------------------------------------------------------
-
-fn unix_now_ms() -> i64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis() as i64)
-        .unwrap_or(0)
-}
-
 // ---------------------------------------------------------------------------
 // Entry point
 // ---------------------------------------------------------------------------
 
 fn main() {
-    let peer_id = uuid::Uuid::new_v4().to_string();
-
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
-        .manage(AppState::new(peer_id))
         .setup(|app| {
-            // Clone the WsPeers Arc once; share it across WS server and mDNS tasks.
-            let state = app.state::<AppState>();
-            let ws_peers = Arc::clone(&state.ws_peers);
-            let peer_id = state.peer_id.clone();
-            drop(state);
-
-            // Start WebSocket server.
-            let app_handle = app.handle().clone();
-            let ws_peers_ws = Arc::clone(&ws_peers);
-            tauri::async_runtime::spawn(async move {
-                run_ws_server(app_handle, ws_peers_ws).await;
-            });
-
-            // Start mDNS in a blocking OS thread (mdns-sd uses sync channels).
-            let app_handle = app.handle().clone();
-            std::thread::spawn(move || {
-                run_mdns(peer_id, app_handle, ws_peers);
+            // The backend owns the note files: edits reach its update log before they are
+            // acknowledged, and the frontend loads and saves notes through it. Without
+            // the lock another process writes the directory, so only mirror it.
+            let data_dir = app.path().app_data_dir();
+            let data_dir_lock = data_dir.as_ref().ok().and_then(|dir| {
+                DataDirLock::acquire(dir)
+                    .map_err(|reason| eprintln!("[hypernote] {reason}; changes will not be saved"))
+                    .ok()
             });
-
+            let store = match data_dir {
+                Ok(dir) => match data_dir_lock {
+                    Some(_) => NoteStore::open(dir),
+                    None => NoteStore::mirror(dir),
+                }
+                .unwrap_or_else(|e| {
+                    eprintln!("[hypernote] cannot load notes: {e}");
+                    NoteStore::in_memory()
+                }),
+                Err(e) => {
+                    eprintln!("[hypernote] no app data directory: {e}");
+                    NoteStore::in_memory()
+                }
+            };
+            let limits = store
+                .data_dir()
+                .map(Limits::load)
+                .transpose()
+                .unwrap_or_else(|reason| {
+                    eprintln!("[hypernote] {reason}; using the default limits");
+                    None
+                })
+                .unwrap_or_default();
+            // Edits are attributed to this id, so keep it across launches when possible.
+            let peer_id = store
+                .device_id()
+                .map(str::to_string)
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            let node = Node::new(
+                NodeConfig {
+                    peer_id,
+                    store,
+                    serve_sync: false,
+                    limits,
+                },
+                Arc::new(WebviewEvents(app.handle().clone())),
+                tauri::async_runtime::handle().inner().clone(),
+            );
+            node.start_sync_services();
+            node.start_compaction();
+            node.start_backups();
+            app.manage(AppState::new(node, data_dir_lock));
             Ok(())
         })
+        .on_window_event(handle_window_event)
         .invoke_handler(tauri::generate_handler![
             create_note,
             open_note,
+            list_note_containers,
+            read_note_container,
+            save_note_container,
+            purge_note,
             apply_local_edit,
             apply_peer_update,
             list_notes,
+            get_workspace_manifest,
+            list_missing_notes,
+            search_notes,
+            quick_open,
+            record_note_opened,
+            set_note_title,
+            set_note_tags,
+            set_note_pinned,
+            set_note_color,
+            move_note_to_folder,
+            list_tags,
+            list_notes_filtered,
+            apply_peer_meta,
+            list_note_versions,
+            get_note_attribution,
+            open_note_version,
+            restore_note_version,
+            diff_note,
+            list_storage_issues,
+            request_note_from_peers,
+            compact_note,
+            import_markdown,
+            import_notes,
+            export_workspace,
+            import_workspace_bundle,
+            list_backups,
+            restore_backup,
+            get_backup_settings,
+            set_backup_settings,
             delete_note_to_trash,
+            restore_note,
             list_peers,
             broadcast_update,
             send_to_peer,
@@ -776,7 +593,11 @@ fn main() {
             get_peer_id,
             get_share_target,
             join_workspace,
+            confirm_persistence_flushed,
+            set_sync_enabled,
+            get_sync_state,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(handle_run_event);
 }
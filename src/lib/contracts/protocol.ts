@@ -1,7 +1,17 @@
-export type FrameType = 'hello' | 'note_list' | 'state_vector' | 'update' | 'presence' | 'error';
+export type FrameType =
+  | 'hello'
+  | 'note_list'
+  | 'state_vector'
+  | 'update'
+  | 'presence'
+  | 'error'
+  | 'meta';
 
 export const PROTOCOL_VERSION = 1;
 
+/** Note id under which peers exchange the workspace manifest (the list of every note). */
+export const MANIFEST_NOTE_ID = '_workspace';
+
 export interface WsFrame {
   type: FrameType;
   noteId: string;
@@ -17,6 +27,7 @@ const FRAME_TYPES: Set<FrameType> = new Set([
   'update',
   'presence',
   'error',
+  'meta',
 ]);
 
 export function isValidWsFrame(input: unknown): input is WsFrame {
@@ -7,6 +7,25 @@ export interface NoteMeta {
   updatedAt: number;
   deletedAt: number | null;
   body?: string;
+  /** Title was set by hand; otherwise it follows the first line of the note. */
+  titleOverride?: boolean;
+  tags?: string[];
+  pinned?: boolean;
+  /** Slash-separated folder path such as `work/clients`. */
+  folder?: string;
+  color?: string;
+}
+
+export interface NoteFilter {
+  tags?: string[];
+  folder?: string;
+  pinned?: boolean;
+  color?: string;
+}
+
+export interface TagCount {
+  tag: string;
+  count: number;
 }
 
 export interface NoteDocument {
@@ -15,6 +34,190 @@ export interface NoteDocument {
   markdown: string;
 }
 
+export interface SnippetPart {
+  text: string;
+  highlight: boolean;
+}
+
+export interface SearchHit {
+  noteId: string;
+  title: string;
+  score: number;
+  snippet: SnippetPart[];
+}
+
+export interface QuickOpenHit {
+  noteId: string;
+  title: string;
+  score: number;
+  titleParts: SnippetPart[];
+  recent: boolean;
+}
+
+export interface NoteVersion {
+  version: number;
+  savedAt: number;
+  title: string;
+}
+
+export type DiffKind = 'equal' | 'insert' | 'delete' | 'move';
+
+export interface DiffWord {
+  kind: DiffKind;
+  text: string;
+}
+
+/** 1-based line numbers; a `move` has both. */
+export interface DiffLine {
+  kind: DiffKind;
+  oldLine: number | null;
+  newLine: number | null;
+  text: string;
+  words: DiffWord[];
+}
+
+export interface NoteDiff {
+  lines: DiffLine[];
+  insertions: number;
+  deletions: number;
+  moves: number;
+  unified: string;
+}
+
+export interface CompactionReport {
+  noteId: string;
+  beforeBytes: number;
+  afterBytes: number;
+  collected: boolean;
+}
+
+/** Outcome of an import, one entry per source file. */
+export interface ImportReport {
+  /** Nothing was created; `imported` lists what would have been. */
+  dryRun: boolean;
+  imported: ImportedNote[];
+  /** Files whose title and text match an existing note; nothing was created for them. */
+  duplicates: ImportDuplicate[];
+  failures: ImportFailure[];
+}
+
+export interface ImportedNote {
+  path: string;
+  /** `null` in a dry run. */
+  noteId: string | null;
+  title: string;
+  folder: string | null;
+  tags: string[];
+  /** Files saved under `attachments/<noteId>/`. */
+  attachments: string[];
+}
+
+export interface ImportDuplicate {
+  path: string;
+  /** `null` when the match is another file of the same dry run. */
+  noteId: string | null;
+}
+
+/** Another app's export: an Obsidian vault, a Notion zip or Bear TextBundles. */
+export type ImportFormat = 'obsidian' | 'notion' | 'bear';
+
+export interface ImportFailure {
+  path: string;
+  reason: string;
+}
+
+/** `manifest.json` of a workspace export zip, from the webview or the backend. */
+export interface ExportManifest {
+  workspaceId: string;
+  /** ISO 8601. */
+  exportedAt: string;
+  noteCount: number;
+  files: ExportFileManifestEntry[];
+}
+
+export interface ExportFileManifestEntry {
+  path: string;
+  size: number;
+  /** Lower-case hex SHA-256 of the file's bytes. */
+  sha256: string;
+}
+
+/** Options for the backend's `export_workspace`; every field is optional. */
+export interface ExportOptions {
+  /** Defaults to `hypernote-workspace`. */
+  workspaceId?: string;
+  /** Also export trashed notes, under `trash/`. Defaults to `false`. */
+  includeTrash?: boolean;
+  /** Files imported notes refer to. Defaults to `true`. */
+  includeAttachments?: boolean;
+}
+
+/** Outcome of restoring a workspace export, by note id. */
+export interface BundleImportReport {
+  workspaceId: string;
+  exportedAt: string;
+  /** Notes the workspace did not have. */
+  created: string[];
+  /** Existing notes that took changes from the archive. */
+  merged: string[];
+  /** Existing notes that already had everything in the archive. */
+  unchanged: string[];
+  /** Merged notes that had also changed here since the archive's copy. */
+  conflicts: BundleConflict[];
+  /** Files that failed their checksum or could not be restored. */
+  failures: ImportFailure[];
+}
+
+export interface BundleConflict {
+  noteId: string;
+  /** The title after merging. */
+  title: string;
+  reason: string;
+}
+
+/** A backup archive, a workspace export written by the backup scheduler. */
+export interface BackupInfo {
+  /** File name, which `restoreBackup` takes. */
+  name: string;
+  path: string;
+  createdAt: number;
+  size: number;
+  /** `null` when the archive's manifest cannot be read. */
+  noteCount: number | null;
+}
+
+/** Saved as `backups.json` in the data directory. */
+export interface BackupSettings {
+  /** Off by default. */
+  enabled: boolean;
+  /** Absolute directory for the archives; `<data-dir>/backups` when `null`. */
+  dir: string | null;
+  intervalHours: number;
+  /** Newest backup of each of the last this many days with one. */
+  keepDaily: number;
+  /** Newest backup of each of the last this many weeks (Monday to Sunday, UTC) with one. */
+  keepWeekly: number;
+}
+
+/** A note container that failed validation when the backend store loaded. */
+export interface StorageIssue {
+  noteId: string;
+  path: string;
+  quarantinedTo: string | null;
+  reason: string;
+  restoredVersion: number | null;
+  replayedLog: boolean;
+  detectedAt: number;
+}
+
+/** UTF-16 offsets into a note's text; `end` is exclusive. */
+export interface AttributedRange {
+  start: number;
+  end: number;
+  author: string | null;
+  editedAt: number | null;
+}
+
 export interface CommandAck {
   accepted: boolean;
   reason: string | null;
@@ -44,4 +44,23 @@ describe('createDebouncedSaveScheduler', () => {
     vi.advanceTimersByTime(1);
     expect(calls).toEqual(['second']);
   });
+
+  it('runs pending saves immediately on flush', async () => {
+    vi.useFakeTimers();
+    const calls: string[] = [];
+    const scheduler = createDebouncedSaveScheduler(500);
+
+    scheduler.schedule('note-1', () => {
+      calls.push('note-1');
+    });
+    scheduler.schedule('note-2', () => {
+      calls.push('note-2');
+    });
+
+    await scheduler.flush();
+    expect(calls).toEqual(['note-1', 'note-2']);
+
+    vi.advanceTimersByTime(500);
+    expect(calls).toEqual(['note-1', 'note-2']);
+  });
 });
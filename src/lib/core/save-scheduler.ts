@@ -3,33 +3,49 @@ export type SaveTask = () => void | Promise<void>;
 export interface DebouncedSaveScheduler {
   schedule(key: string, task: SaveTask): void;
   cancel(key: string): void;
+  flush(): Promise<void>;
+}
+
+interface PendingSave {
+  timer: ReturnType<typeof setTimeout>;
+  task: SaveTask;
 }
 
 export function createDebouncedSaveScheduler(delayMs = 500): DebouncedSaveScheduler {
-  const timers = new Map<string, ReturnType<typeof setTimeout>>();
+  const pending = new Map<string, PendingSave>();
 
   return {
     schedule(key: string, task: SaveTask): void {
-      const activeTimer = timers.get(key);
-      if (activeTimer) {
-        clearTimeout(activeTimer);
+      const active = pending.get(key);
+      if (active) {
+        clearTimeout(active.timer);
       }
 
-      const nextTimer = setTimeout(() => {
-        timers.delete(key);
+      const timer = setTimeout(() => {
+        pending.delete(key);
         void task();
       }, delayMs);
 
-      timers.set(key, nextTimer);
+      pending.set(key, { timer, task });
     },
     cancel(key: string): void {
-      const activeTimer = timers.get(key);
-      if (!activeTimer) {
+      const active = pending.get(key);
+      if (!active) {
         return;
       }
 
-      clearTimeout(activeTimer);
-      timers.delete(key);
+      clearTimeout(active.timer);
+      pending.delete(key);
+    },
+    async flush(): Promise<void> {
+      const tasks = Array.from(pending.values());
+      pending.clear();
+
+      for (const { timer } of tasks) {
+        clearTimeout(timer);
+      }
+
+      await Promise.all(tasks.map(({ task }) => task()));
     },
   };
 }
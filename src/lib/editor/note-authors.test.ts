@@ -0,0 +1,43 @@
+import { describe, expect, it } from 'vitest';
+import * as Y from 'yjs';
+
+import { AUTHOR_CHECKPOINT_MS, AUTHORS_MAP, claimEdit, clientClock } from './note-authors';
+import { createTextareaYjsBridge } from './textarea-yjs-bridge';
+
+describe('claimEdit', () => {
+  it('adds a checkpoint at most once per interval', () => {
+    const doc = new Y.Doc();
+    const text = doc.getText('content');
+
+    claimEdit(doc, 'device-a', clientClock(doc), 1_000);
+    text.insert(0, 'hello');
+    claimEdit(doc, 'device-a', clientClock(doc), 2_000);
+    claimEdit(doc, 'device-a', clientClock(doc), 1_000 + AUTHOR_CHECKPOINT_MS);
+
+    const entry = doc.getMap(AUTHORS_MAP).get(String(doc.clientID));
+    expect(entry).toEqual({
+      device: 'device-a',
+      edits: [
+        [0, 1_000],
+        [expect.any(Number), 1_000 + AUTHOR_CHECKPOINT_MS],
+      ],
+    });
+  });
+
+  it('travels with the bridge update that made the edit', () => {
+    const author = createTextareaYjsBridge('note-1', { getAuthorId: () => 'device-a' });
+    const reader = createTextareaYjsBridge('note-1');
+
+    reader.applyPeerUpdate(author.setText('hello')!);
+
+    const doc = new Y.Doc();
+    Y.applyUpdate(doc, reader.encodeStateAsUpdate());
+    const authors = doc.getMap(AUTHORS_MAP).toJSON();
+    expect(Object.values(authors)).toEqual([
+      { device: 'device-a', edits: [[0, expect.any(Number)]] },
+    ]);
+
+    author.destroy();
+    reader.destroy();
+  });
+});
@@ -0,0 +1,35 @@
+import * as Y from 'yjs';
+
+/** Name of the Yjs map from client id to author, as in the backend's `attribution`. */
+export const AUTHORS_MAP = 'authors';
+/** Minimum time between two checkpoints of one client; the backend uses the same. */
+export const AUTHOR_CHECKPOINT_MS = 60_000;
+
+export interface AuthorEntry {
+  device: string;
+  /** `[clock, at]` pairs: text from `clock` on was written from time `at`. */
+  edits: [number, number][];
+}
+
+/** Next clock of `doc`'s own client, i.e. where its next edit starts. */
+export function clientClock(doc: Y.Doc): number {
+  return Y.decodeStateVector(Y.encodeStateVector(doc)).get(doc.clientID) ?? 0;
+}
+
+/**
+ * Attributes the edit `doc`'s client makes from `clock` on to `device`, adding a checkpoint
+ * unless the previous one is recent. Call inside the edit's transaction so the claim
+ * travels with the edit.
+ */
+export function claimEdit(doc: Y.Doc, device: string, clock: number, at = Date.now()): void {
+  const authors = doc.getMap<AuthorEntry>(AUTHORS_MAP);
+  const key = String(doc.clientID);
+  const entry = authors.get(key);
+  const edits = entry?.device === device ? entry.edits : [];
+  const last = edits[edits.length - 1];
+  if (last && at - last[1] < AUTHOR_CHECKPOINT_MS) {
+    return;
+  }
+
+  authors.set(key, { device, edits: [...edits, [clock, at]] });
+}
@@ -0,0 +1,39 @@
+import { describe, expect, it } from 'vitest';
+
+import type { NoteMeta } from '../contracts';
+import { withSyncedMeta } from './note-meta';
+
+const base: NoteMeta = {
+  id: 'note-1',
+  title: 'Kept title',
+  createdAt: 1,
+  updatedAt: 2,
+  deletedAt: null,
+  titleOverride: true,
+  tags: ['old'],
+};
+
+describe('withSyncedMeta', () => {
+  it('applies the keys present in the meta map', () => {
+    const meta = withSyncedMeta(
+      base,
+      { tags: ['work'], pinned: true, folder: 'a/b', color: null, deletedAt: 42 },
+      '# Heading',
+    );
+
+    expect(meta.tags).toEqual(['work']);
+    expect(meta.pinned).toBe(true);
+    expect(meta.folder).toBe('a/b');
+    expect(meta.color).toBeUndefined();
+    expect(meta.deletedAt).toBe(42);
+    expect(meta.title).toBe('Kept title');
+  });
+
+  it('derives the title again once the override is cleared', () => {
+    expect(withSyncedMeta(base, {}, '# Heading').title).toBe('Kept title');
+
+    const reset = withSyncedMeta(base, { title: null }, '# Heading');
+    expect(reset.titleOverride).toBe(false);
+    expect(reset.title).toBe('Heading');
+  });
+});
@@ -0,0 +1,51 @@
+import type { NoteMeta } from '../contracts';
+import { titleFromText } from './note-title';
+
+/** Name of the Yjs map holding a note's synced metadata, as in the backend's `meta_map`. */
+export const META_MAP = 'meta';
+
+/**
+ * `meta` with the fields of a note's `meta` map applied and its title derived from `text`
+ * unless set by hand. Keys the map does not have keep their current value, so notes
+ * saved before the map existed keep their metadata.
+ */
+export function withSyncedMeta(
+  meta: NoteMeta,
+  fields: Record<string, unknown>,
+  text: string,
+): NoteMeta {
+  const next: NoteMeta = { ...meta };
+
+  if ('title' in fields) {
+    next.titleOverride = typeof fields.title === 'string';
+    if (typeof fields.title === 'string') {
+      next.title = fields.title;
+    }
+  }
+  if ('deletedAt' in fields) {
+    next.deletedAt = typeof fields.deletedAt === 'number' ? fields.deletedAt : null;
+  }
+  if ('tags' in fields) {
+    next.tags = Array.isArray(fields.tags)
+      ? fields.tags.filter((tag): tag is string => typeof tag === 'string')
+      : [];
+  }
+  if ('pinned' in fields) {
+    next.pinned = fields.pinned === true;
+  }
+  if ('folder' in fields) {
+    next.folder = optionalString(fields.folder);
+  }
+  if ('color' in fields) {
+    next.color = optionalString(fields.color);
+  }
+
+  if (!next.titleOverride) {
+    next.title = titleFromText(text);
+  }
+  return next;
+}
+
+function optionalString(value: unknown): string | undefined {
+  return typeof value === 'string' ? value : undefined;
+}
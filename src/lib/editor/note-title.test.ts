@@ -0,0 +1,17 @@
+import { describe, expect, it } from 'vitest';
+
+import { titleFromText } from './note-title';
+
+describe('titleFromText', () => {
+  it('uses the first non-blank line without heading marks', () => {
+    expect(titleFromText('\n## Trip plan ##\nday one')).toBe('Trip plan');
+    expect(titleFromText('Groceries\n- milk')).toBe('Groceries');
+    expect(titleFromText('# C#')).toBe('C#');
+    expect(titleFromText('#hashtag')).toBe('#hashtag');
+  });
+
+  it('falls back to Untitled for blank notes', () => {
+    expect(titleFromText('  \n\n')).toBe('Untitled');
+    expect(titleFromText('#')).toBe('Untitled');
+  });
+});
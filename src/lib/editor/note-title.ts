@@ -0,0 +1,26 @@
+export const DEFAULT_NOTE_TITLE = 'Untitled';
+export const MAX_NOTE_TITLE_CHARS = 200;
+
+const ATX_OPEN_PATTERN = /^(#{1,6})(?:[ \t]+|$)/u;
+const ATX_CLOSE_PATTERN = /(?:^|[ \t]+)#+$/u;
+
+/**
+ * Title derived from note content: the first non-blank line with any heading marks
+ * removed. Mirrors `title_from_text` in the backend so both sides agree.
+ */
+export function titleFromText(text: string): string {
+  const line = text
+    .split('\n')
+    .map((value) => value.trim())
+    .find((value) => value.length > 0);
+  if (!line) {
+    return DEFAULT_NOTE_TITLE;
+  }
+
+  const open = ATX_OPEN_PATTERN.exec(line);
+  const title = open ? line.slice(open[0].length).replace(ATX_CLOSE_PATTERN, '').trim() : line;
+  if (title.length === 0) {
+    return DEFAULT_NOTE_TITLE;
+  }
+  return Array.from(title).slice(0, MAX_NOTE_TITLE_CHARS).join('');
+}
@@ -1,5 +1,8 @@
 import * as Y from 'yjs';
 
+import { claimEdit, clientClock } from './note-authors';
+import { META_MAP } from './note-meta';
+
 const LOCAL_ORIGIN = 'textarea-local-edit';
 const REMOTE_ORIGIN = 'peer-update';
 
@@ -14,11 +17,15 @@ export interface TextChangeEvent {
 export interface CreateTextareaBridgeOptions {
   initialText?: string;
   initialUpdate?: Uint8Array;
+  /** Device id local edits are attributed to; edits stay unattributed while it is empty. */
+  getAuthorId?: () => string;
 }
 
 export interface TextareaYjsBridge {
   noteId: string;
   getText(): string;
+  /** Fields of the note's synced `meta` map. */
+  getMeta(): Record<string, unknown>;
   setText(nextText: string): Uint8Array | null;
   applyPeerUpdate(update: Uint8Array): void;
   encodeStateVector(): Uint8Array;
@@ -65,6 +72,10 @@ export function createTextareaYjsBridge(
       return ytext.toString();
     },
 
+    getMeta() {
+      return doc.getMap(META_MAP).toJSON();
+    },
+
     setText(nextText) {
       const current = ytext.toString();
 
@@ -83,6 +94,7 @@ export function createTextareaYjsBridge(
 
       doc.on('update', captureLocalUpdate);
 
+      const clock = clientClock(doc);
       doc.transact(() => {
         if (deleteLen > 0) {
           ytext.delete(prefixLen, deleteLen);
@@ -91,6 +103,11 @@ export function createTextareaYjsBridge(
         if (insertText.length > 0) {
           ytext.insert(prefixLen, insertText);
         }
+
+        const authorId = options.getAuthorId?.();
+        if (authorId) {
+          claimEdit(doc, authorId, clock);
+        }
       }, LOCAL_ORIGIN);
 
       doc.off('update', captureLocalUpdate);
@@ -1,26 +1,15 @@
 import JSZip from 'jszip';
 import { jsPDF } from 'jspdf';
 
-import type { NoteMeta } from '../contracts';
+import type { ExportFileManifestEntry, ExportManifest, NoteMeta } from '../contracts';
+
+export type { ExportFileManifestEntry, ExportManifest } from '../contracts';
 
 export interface ExportNoteInput {
   meta: NoteMeta;
   text: string;
 }
 
-export interface ExportFileManifestEntry {
-  path: string;
-  size: number;
-  sha256: string;
-}
-
-export interface ExportManifest {
-  workspaceId: string;
-  exportedAt: string;
-  noteCount: number;
-  files: ExportFileManifestEntry[];
-}
-
 export type MarkdownBlock =
   | { type: 'heading'; level: number; text: string }
   | { type: 'paragraph'; text: string }
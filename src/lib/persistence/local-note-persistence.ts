@@ -27,6 +27,10 @@ export class LocalNotePersistence {
     this.saveScheduler.schedule(note.meta.id, () => this.saveNow(note));
   }
 
+  async flushPendingSaves(): Promise<void> {
+    await this.saveScheduler.flush();
+  }
+
   async saveNow(note: NoteSnapshot): Promise<void> {
     const encoded = encodeNoteContainer(note.meta, note.yjsState);
     await this.store.writeContainer(note.meta.id, encoded.bytes);
@@ -27,6 +27,18 @@ describe('sync frame helpers', () => {
     }
   });
 
+  it('carries state vectors of notes the sender already has', () => {
+    const frame = createHelloFrame('', 'peer-1', ['note-1'], {
+      'note-1': new Uint8Array([1, 2, 3]),
+    });
+    const decoded = decodeFrameMessage(serializeFrame(frame));
+
+    expect(decoded.ok).toBe(true);
+    if (decoded.ok && decoded.frame.type === 'hello') {
+      expect(decoded.frame.payload.stateVectors).toEqual({ 'note-1': [1, 2, 3] });
+    }
+  });
+
   it('round-trips update bytes payload', () => {
     const update = new Uint8Array([10, 20, 30]);
     const frame = createUpdateFrame('note-1', 'peer-1', update);
@@ -43,6 +55,23 @@ describe('sync frame helpers', () => {
     }
   });
 
+  it('decodes meta frames sent by the backend', () => {
+    const raw = JSON.stringify({
+      type: 'meta',
+      noteId: 'note-1',
+      senderId: 'peer-1',
+      protocolVersion: PROTOCOL_VERSION,
+      payload: { tags: ['work'], pinned: true, folder: null, color: '#f5a623', changedAt: 5 },
+    });
+    const decoded = decodeFrameMessage(raw);
+
+    expect(decoded.ok).toBe(true);
+    if (decoded.ok && decoded.frame.type === 'meta') {
+      expect(decoded.frame.payload.tags).toEqual(['work']);
+    }
+    expect(decodeFrameMessage(raw.replace('"pinned":true', '"pinned":"yes"')).ok).toBe(false);
+  });
+
   it('round-trips presence payload', () => {
     const frame = createPresenceFrame('note-1', 'peer-1', {
       cursorOffset: 42,
@@ -8,6 +8,8 @@ import {
 
 export interface HelloPayload {
   openNoteIds: string[];
+  /** State vector of each note the sender already has, so replies carry only what it lacks. */
+  stateVectors?: Record<string, number[]>;
 }
 
 export interface NoteListPayload {
@@ -32,6 +34,15 @@ export interface PresencePayload {
   emittedAt: number;
 }
 
+/** Tags, pin, folder and color of one note; the copy with the newest `changedAt` wins. */
+export interface MetaPayload {
+  tags: string[];
+  pinned: boolean;
+  folder: string | null;
+  color: string | null;
+  changedAt: number;
+}
+
 export interface FramePayloadByType {
   hello: HelloPayload;
   note_list: NoteListPayload;
@@ -39,6 +50,7 @@ export interface FramePayloadByType {
   update: BinaryPayload;
   presence: PresencePayload;
   error: ErrorPayload;
+  meta: MetaPayload;
 }
 
 export type TypedFrame<TType extends FrameType> = Omit<WsFrame, 'type' | 'payload'> & {
@@ -65,8 +77,15 @@ export function createHelloFrame(
   noteId: string,
   senderId: string,
   openNoteIds: string[],
+  stateVectors?: Record<string, Uint8Array>,
 ): TypedFrame<'hello'> {
-  return createTypedFrame('hello', noteId, senderId, { openNoteIds });
+  const payload: HelloPayload = { openNoteIds };
+  if (stateVectors) {
+    payload.stateVectors = Object.fromEntries(
+      Object.entries(stateVectors).map(([id, vector]) => [id, [...vector]]),
+    );
+  }
+  return createTypedFrame('hello', noteId, senderId, payload);
 }
 
 export function createNoteListFrame(
@@ -101,6 +120,14 @@ export function createPresenceFrame(
   return createTypedFrame('presence', noteId, senderId, payload);
 }
 
+export function createMetaFrame(
+  noteId: string,
+  senderId: string,
+  payload: MetaPayload,
+): TypedFrame<'meta'> {
+  return createTypedFrame('meta', noteId, senderId, payload);
+}
+
 export function createErrorFrame(
   noteId: string,
   senderId: string,
@@ -200,6 +227,8 @@ function isTypedFrame(frame: WsFrame): frame is AnyTypedFrame {
       return isPresencePayload(frame.payload);
     case 'error':
       return isErrorPayload(frame.payload);
+    case 'meta':
+      return isMetaPayload(frame.payload);
     default:
       return false;
   }
@@ -223,6 +252,25 @@ function isErrorPayload(payload: unknown): payload is ErrorPayload {
   return typeof value.code === 'string' && typeof value.message === 'string';
 }
 
+function isMetaPayload(payload: unknown): payload is MetaPayload {
+  if (!payload || typeof payload !== 'object') {
+    return false;
+  }
+
+  const value = payload as Record<string, unknown>;
+  const optionalString = (entry: unknown) =>
+    entry === null || entry === undefined || typeof entry === 'string';
+
+  return (
+    Array.isArray(value.tags) &&
+    value.tags.every((tag) => typeof tag === 'string') &&
+    typeof value.pinned === 'boolean' &&
+    optionalString(value.folder) &&
+    optionalString(value.color) &&
+    typeof value.changedAt === 'number'
+  );
+}
+
 function isBinaryPayload(payload: unknown): payload is BinaryPayload {
   if (!payload || typeof payload !== 'object') {
     return false;
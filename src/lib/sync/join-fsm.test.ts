@@ -31,6 +31,14 @@ describe('join fsm', () => {
     expect(allowsOutboundSync(approved)).toBe(true);
   });
 
+  it('treats relay links as approved from the start', () => {
+    const relay = createJoinPeerState('relay');
+
+    expect(isJoinApproved(relay)).toBe(true);
+    expect(allowsInboundFrame(relay, 'update')).toBe(true);
+    expect(peerStatusFromJoinState(relay)).toBe('CONNECTED');
+  });
+
   it('moves to rejected and then disconnected on close', () => {
     const pendingInbound = createJoinPeerState('inbound');
     const rejected = transitionJoinPeerState(pendingInbound, 'host_rejected');
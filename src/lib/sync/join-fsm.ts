@@ -1,6 +1,7 @@
 import type { FrameType, PeerStatus } from '../contracts';
 
-export type JoinDirection = 'inbound' | 'outbound';
+// Relay links are approved up front: knowing the workspace id is the invitation.
+export type JoinDirection = 'inbound' | 'outbound' | 'relay';
 
 export type JoinLifecycleState =
   | 'disconnected'
@@ -17,6 +18,10 @@ export interface JoinPeerState {
 export type JoinPeerEvent = 'host_approved' | 'host_rejected' | 'host_hello' | 'transport_closed';
 
 export function createJoinPeerState(direction: JoinDirection): JoinPeerState {
+  if (direction === 'relay') {
+    return { direction, lifecycle: 'approved' };
+  }
+
   return {
     direction,
     lifecycle: direction === 'inbound' ? 'pending_inbound_approval' : 'pending_outbound_approval',
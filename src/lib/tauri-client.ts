@@ -5,7 +5,30 @@ import {
   type UnlistenFn,
 } from '@tauri-apps/api/event';
 
-import type { CommandAck, NoteDocument, NoteMeta, PeerInfo, PeerStatus } from './contracts';
+import type {
+  AttributedRange,
+  BackupInfo,
+  BackupSettings,
+  BundleImportReport,
+  CommandAck,
+  CompactionReport,
+  ExportManifest,
+  ExportOptions,
+  ImportFormat,
+  ImportReport,
+  NoteDiff,
+  NoteDocument,
+  NoteFilter,
+  NoteMeta,
+  NoteVersion,
+  PeerInfo,
+  PeerStatus,
+  QuickOpenHit,
+  SearchHit,
+  StorageIssue,
+  TagCount,
+} from './contracts';
+import type { MetaPayload } from './sync/frame';
 
 const FALLBACK_EVENT_PEER_CONNECTED = 'hypernote:peer-connected';
 const FALLBACK_EVENT_PEER_DISCONNECTED = 'hypernote:peer-disconnected';
@@ -107,6 +130,16 @@ async function invokeOrFallback<T>(command: string, args: InvokeArgs, fallback:
   }
 }
 
+/** For callers that must see failures, like note persistence; throws without Tauri too. */
+async function invokeOrThrow<T>(command: string, args: InvokeArgs): Promise<T> {
+  const invoke = getInvoke();
+  if (!invoke) {
+    throw new Error(`${command} needs the Tauri runtime`);
+  }
+
+  return invoke<T>(command, args);
+}
+
 async function invokeWithStatus(command: string, args: InvokeArgs): Promise<boolean> {
   const invoke = getInvoke();
 
@@ -131,6 +164,32 @@ export async function openNote(noteId: string): Promise<NoteDocument | null> {
   return invokeOrFallback<NoteDocument | null>('open_note', { noteId }, null);
 }
 
+/** Containers of the notes outside the trash, or in it with `trash`. */
+export async function listNoteContainers(trash: boolean): Promise<Uint8Array[]> {
+  const containers = await invokeOrThrow<number[][]>('list_note_containers', { trash });
+  return containers.map((bytes) => Uint8Array.from(bytes));
+}
+
+export async function readNoteContainer(noteId: string): Promise<Uint8Array> {
+  return Uint8Array.from(await invokeOrThrow<number[]>('read_note_container', { noteId }));
+}
+
+/** Merges a saved container into the backend's copy of the note. */
+export async function saveNoteContainer(bytes: Uint8Array): Promise<void> {
+  const ack = await invokeOrThrow<CommandAck>('save_note_container', { bytes: Array.from(bytes) });
+  if (!ack.accepted) {
+    throw new Error(ack.reason ?? 'save_note_container rejected');
+  }
+}
+
+/** Deletes a trashed note from this device for good. */
+export async function purgeNote(noteId: string): Promise<void> {
+  const ack = await invokeOrThrow<CommandAck>('purge_note', { noteId });
+  if (!ack.accepted) {
+    throw new Error(ack.reason ?? 'purge_note rejected');
+  }
+}
+
 export async function applyLocalEdit(noteId: string, update: Uint8Array): Promise<boolean> {
   return invokeWithStatus('apply_local_edit', { noteId, update: Array.from(update) });
 }
@@ -143,10 +202,164 @@ export async function listNotes(): Promise<NoteMeta[]> {
   return invokeOrFallback<NoteMeta[]>('list_notes', undefined, []);
 }
 
+export async function getWorkspaceManifest(): Promise<Uint8Array | null> {
+  const bytes = await invokeOrFallback<number[] | null>('get_workspace_manifest', undefined, null);
+  return bytes ? new Uint8Array(bytes) : null;
+}
+
+export async function listMissingNotes(): Promise<string[]> {
+  return invokeOrFallback<string[]>('list_missing_notes', undefined, []);
+}
+
+export async function searchNotes(query: string, limit?: number): Promise<SearchHit[]> {
+  return invokeOrFallback<SearchHit[]>('search_notes', { query, limit }, []);
+}
+
+export async function quickOpen(query: string, limit?: number): Promise<QuickOpenHit[]> {
+  return invokeOrFallback<QuickOpenHit[]>('quick_open', { query, limit }, []);
+}
+
+export async function recordNoteOpened(noteId: string): Promise<void> {
+  await invokeOrFallback<void>('record_note_opened', { noteId }, undefined);
+}
+
+/** Sets a manual title; `null` goes back to deriving it from the content. */
+export async function setNoteTitle(noteId: string, title: string | null): Promise<boolean> {
+  return invokeWithStatus('set_note_title', { noteId, title });
+}
+
+export async function setNoteTags(noteId: string, tags: string[]): Promise<boolean> {
+  return invokeWithStatus('set_note_tags', { noteId, tags });
+}
+
+export async function setNotePinned(noteId: string, pinned: boolean): Promise<boolean> {
+  return invokeWithStatus('set_note_pinned', { noteId, pinned });
+}
+
+export async function setNoteColor(noteId: string, color: string | null): Promise<boolean> {
+  return invokeWithStatus('set_note_color', { noteId, color });
+}
+
+/** `null` moves the note back to the top level. */
+export async function moveNoteToFolder(noteId: string, folder: string | null): Promise<boolean> {
+  return invokeWithStatus('move_note_to_folder', { noteId, folder });
+}
+
+export async function listTags(): Promise<TagCount[]> {
+  return invokeOrFallback<TagCount[]>('list_tags', undefined, []);
+}
+
+export async function listNotesFiltered(filter: NoteFilter): Promise<NoteMeta[]> {
+  return invokeOrFallback<NoteMeta[]>('list_notes_filtered', { filter }, []);
+}
+
+export async function getNoteAttribution(noteId: string): Promise<AttributedRange[]> {
+  return invokeOrFallback<AttributedRange[]>('get_note_attribution', { noteId }, []);
+}
+
+export async function listNoteVersions(noteId: string): Promise<NoteVersion[]> {
+  return invokeOrFallback<NoteVersion[]>('list_note_versions', { noteId }, []);
+}
+
+export async function openNoteVersion(
+  noteId: string,
+  version: number,
+): Promise<NoteDocument | null> {
+  return invokeOrFallback<NoteDocument | null>('open_note_version', { noteId, version }, null);
+}
+
+/** `null` when the note is unknown or another process owns the data directory. */
+export async function compactNote(noteId: string): Promise<CompactionReport | null> {
+  return invokeOrFallback<CompactionReport | null>('compact_note', { noteId }, null);
+}
+
+/** Creates one note per Markdown file in `paths`; directories are walked into folders. */
+export async function importMarkdown(paths: string[]): Promise<ImportReport> {
+  return invokeOrFallback<ImportReport>(
+    'import_markdown',
+    { paths },
+    { dryRun: false, imported: [], duplicates: [], failures: [] },
+  );
+}
+
+/** Imports another app's export; with `dryRun` only reports what would be created. */
+export async function importNotes(
+  format: ImportFormat,
+  path: string,
+  dryRun: boolean,
+): Promise<ImportReport> {
+  return invokeOrFallback<ImportReport>(
+    'import_notes',
+    { format, path, dryRun },
+    { dryRun, imported: [], duplicates: [], failures: [] },
+  );
+}
+
+/** Writes every note as Markdown plus its `.yjs` container into a zip at `destPath`. */
+export async function exportWorkspace(
+  destPath: string,
+  options: ExportOptions = {},
+): Promise<ExportManifest | null> {
+  return invokeOrFallback<ExportManifest | null>('export_workspace', { destPath, options }, null);
+}
+
+/** Restores an `exportWorkspace` zip, merging each note into the note of the same id. */
+export async function importWorkspaceBundle(path: string): Promise<BundleImportReport | null> {
+  return invokeOrFallback<BundleImportReport | null>('import_workspace_bundle', { path }, null);
+}
+
+export async function listBackups(): Promise<BackupInfo[]> {
+  return invokeOrFallback<BackupInfo[]>('list_backups', {}, []);
+}
+
+/** Merges the backup called `name` back in, like `importWorkspaceBundle`. */
+export async function restoreBackup(name: string): Promise<BundleImportReport | null> {
+  return invokeOrFallback<BundleImportReport | null>('restore_backup', { name }, null);
+}
+
+export async function getBackupSettings(): Promise<BackupSettings | null> {
+  return invokeOrFallback<BackupSettings | null>('get_backup_settings', {}, null);
+}
+
+export async function setBackupSettings(settings: BackupSettings): Promise<boolean> {
+  return invokeWithStatus('set_backup_settings', { settings });
+}
+
+export async function listStorageIssues(): Promise<StorageIssue[]> {
+  return invokeOrFallback<StorageIssue[]>('list_storage_issues', {}, []);
+}
+
+/** Asks connected peers for whatever of the note this device lacks. */
+export async function requestNoteFromPeers(noteId: string): Promise<boolean> {
+  return invokeWithStatus('request_note_from_peers', { noteId });
+}
+
+/** `from`/`to` are version numbers; `null` is the note's current content. */
+export async function diffNote(
+  noteId: string,
+  from: number | null,
+  to: number | null,
+): Promise<NoteDiff | null> {
+  return invokeOrFallback<NoteDiff | null>('diff_note', { noteId, from, to }, null);
+}
+
+export async function restoreNoteVersion(noteId: string, version: number): Promise<boolean> {
+  return invokeWithStatus('restore_note_version', { noteId, version });
+}
+
+/** Applies a peer's `meta` frame; fields are taken only from a copy newer than ours. */
+export async function applyPeerMeta(noteId: string, payload: MetaPayload): Promise<boolean> {
+  return invokeWithStatus('apply_peer_meta', { noteId, payload });
+}
+
 export async function deleteNoteToTrash(noteId: string): Promise<boolean> {
   return invokeWithStatus('delete_note_to_trash', { noteId });
 }
 
+export async function restoreNote(noteId: string): Promise<boolean> {
+  return invokeWithStatus('restore_note', { noteId });
+}
+
 export async function listPeers(): Promise<PeerInfo[]> {
   const invoke = getInvoke();
   if (!invoke) {
@@ -256,10 +469,41 @@ export async function getLocalPeerId(): Promise<string> {
   return invokeOrFallback<string>('get_peer_id', undefined, '');
 }
 
+export interface PeerBan {
+  addr: string;
+  reason: string;
+  bannedAt: number;
+  until: number;
+}
+
+export interface SyncState {
+  enabled: boolean;
+  unsyncedNoteIds: string[];
+  /** Addresses refused for flooding frames or connections, until `until`. */
+  bans: PeerBan[];
+}
+
+export async function setSyncEnabled(enabled: boolean): Promise<boolean> {
+  return invokeWithStatus('set_sync_enabled', { enabled });
+}
+
+export async function getSyncState(): Promise<SyncState> {
+  return invokeOrFallback<SyncState>('get_sync_state', undefined, {
+    enabled: true,
+    unsyncedNoteIds: [],
+    bans: [],
+  });
+}
+
+export async function confirmPersistenceFlushed(): Promise<void> {
+  await invokeOrFallback<null>('confirm_persistence_flushed', undefined, null);
+}
+
 export interface PeerConnectedEvent {
   peerId: string;
   addr: string;
   outbound: boolean;
+  relay?: boolean;
 }
 
 export interface PeerDisconnectedEvent {
@@ -306,6 +550,30 @@ export function onWsMessage(listener: (event: WsMessageEvent) => void): () => vo
   return createWindowEventListener(FALLBACK_EVENT_WS_MESSAGE, listener, isWsMessageEvent);
 }
 
+/**
+ * Fires when the backend starts its exit path. Browser mode has no equivalent.
+ */
+export function onShutdown(listener: () => void): () => void {
+  return createTauriEventListener('hypernote://shutdown', () => listener(), isShutdownEvent);
+}
+
+export function onNoteMetaChanged(listener: (event: NoteMeta) => void): () => void {
+  return createTauriEventListener('hypernote://note-meta-changed', listener, isNoteMetaEvent);
+}
+
+/** Updates the backend made itself (metadata changes), to be saved and sent to peers. */
+export function onNoteUpdate(listener: (event: PeerUpdateEvent) => void): () => void {
+  return createTauriEventListener('hypernote://note-update', listener, isNoteUpdateEvent);
+}
+
+export function onStorageIssue(listener: (event: StorageIssue) => void): () => void {
+  return createTauriEventListener('hypernote://storage-issue', listener, isStorageIssueEvent);
+}
+
+export function onSyncStateChanged(listener: (event: SyncState) => void): () => void {
+  return createTauriEventListener('hypernote://sync-state-changed', listener, isSyncStateEvent);
+}
+
 function createTauriEventListener<T>(
   eventName: string,
   listener: (event: T) => void,
@@ -361,6 +629,37 @@ function createWindowEventListener<T>(
   };
 }
 
+function isShutdownEvent(payload: unknown): payload is null {
+  return payload === null;
+}
+
+function isSyncStateEvent(payload: unknown): payload is SyncState {
+  if (!payload || typeof payload !== 'object') return false;
+  const v = payload as Record<string, unknown>;
+  return (
+    typeof v.enabled === 'boolean' &&
+    Array.isArray(v.unsyncedNoteIds) &&
+    v.unsyncedNoteIds.every((entry) => typeof entry === 'string') &&
+    Array.isArray(v.bans)
+  );
+}
+
+function isStorageIssueEvent(payload: unknown): payload is StorageIssue {
+  if (!payload || typeof payload !== 'object') return false;
+  const v = payload as Record<string, unknown>;
+  return typeof v.noteId === 'string' && typeof v.reason === 'string';
+}
+
+function isNoteMetaEvent(payload: unknown): payload is NoteMeta {
+  if (!payload || typeof payload !== 'object') return false;
+  const v = payload as Record<string, unknown>;
+  return typeof v.id === 'string' && typeof v.title === 'string';
+}
+
+function isNoteUpdateEvent(payload: unknown): payload is PeerUpdateEvent {
+  return parsePeerUpdateEvent(payload) !== null;
+}
+
 function isPeerConnectedEvent(payload: unknown): payload is PeerConnectedEvent {
   if (!payload || typeof payload !== 'object') return false;
   const v = payload as Record<string, unknown>;
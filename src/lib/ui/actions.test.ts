@@ -1,6 +1,12 @@
 import { describe, expect, it } from 'vitest';
 
-import { formatModShortcut, formatShortcutForDisplay, matchesShortcut } from './actions';
+import {
+  PALETTE_ACTIONS,
+  filterActionByContext,
+  formatModShortcut,
+  formatShortcutForDisplay,
+  matchesShortcut,
+} from './actions';
 
 describe('shortcut labels', () => {
   it('renders mac shortcuts with command key only', () => {
@@ -53,3 +59,18 @@ describe('shortcut labels', () => {
     expect(matchesShortcut(koreanNew, 'new-note')).toBe(true);
   });
 });
+
+describe('filterActionByContext', () => {
+  const toggleSync = PALETTE_ACTIONS.find((action) => action.id === 'toggle-sync')!;
+  const context = { hasSelectedNote: true, trashCount: 0, connectedPeers: 0 };
+
+  it('offers pause while sync is running', () => {
+    expect(filterActionByContext(toggleSync, context)?.label).toBe('⏸ pause sync');
+  });
+
+  it('offers resume while sync is paused', () => {
+    expect(filterActionByContext(toggleSync, { ...context, syncEnabled: false })?.label).toBe(
+      '▶ resume sync',
+    );
+  });
+});
@@ -8,6 +8,7 @@ export type PaletteActionId =
   | 'peers'
   | 'join-workspace'
   | 'share-workspace'
+  | 'toggle-sync'
   | 'export-current'
   | 'export-workspace';
 
@@ -75,6 +76,12 @@ export const PALETTE_ACTIONS: readonly PaletteActionDescriptor[] = [
     shortcut: '⌘/Ctrl+Shift+S',
     keywords: ['share', 'workspace', 'invite'],
   },
+  {
+    id: 'toggle-sync',
+    label: '⏸ pause sync',
+    shortcut: '',
+    keywords: ['sync', 'pause', 'resume', 'offline', 'network'],
+  },
   {
     id: 'export-current',
     label: '⇩ export current note',
@@ -95,6 +102,7 @@ export function filterActionByContext(
     hasSelectedNote: boolean;
     trashCount: number;
     connectedPeers: number;
+    syncEnabled?: boolean;
   },
 ): PaletteActionDescriptor | null {
   const shortcut = formatShortcutForDisplay(action.shortcut);
@@ -115,6 +123,14 @@ export function filterActionByContext(
     };
   }
 
+  if (action.id === 'toggle-sync' && context.syncEnabled === false) {
+    return {
+      ...action,
+      label: '▶ resume sync',
+      shortcut,
+    };
+  }
+
   if (action.id === 'restore-mode') {
     return {
       ...action,